@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec4 colour;
+
+layout(location = 0) out vec4 varying_colour;
+
+layout(push_constant) uniform PushConstants {
+    mat4 projection;
+} push_constants;
+
+void main() {
+    varying_colour = colour;
+    gl_Position = push_constants.projection * vec4(position, 0.0, 1.0);
+}
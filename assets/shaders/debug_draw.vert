@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 colour;
+
+layout(location = 0) out vec4 varying_colour;
+
+layout(push_constant) uniform PushConstants {
+    mat4 view_projection;
+} push_constants;
+
+void main() {
+    varying_colour = colour;
+    gl_Position = push_constants.view_projection * vec4(position, 1.0);
+}
@@ -0,0 +1,11 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec2 varying_uv;
+
+// One triangle covering the whole target, with UVs running 0..1 across
+// the visible part. Draw with three vertices and no vertex buffer.
+void main() {
+    varying_uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(varying_uv * 2.0 - 1.0, 0.0, 1.0);
+}
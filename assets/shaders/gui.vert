@@ -0,0 +1,22 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 colour;
+
+layout(location = 0) out vec2 varying_uv;
+layout(location = 1) out vec4 varying_colour;
+
+layout(push_constant) uniform PushConstants {
+    vec2 scale;
+    vec2 translate;
+} push_constants;
+
+void main() {
+    varying_uv = uv;
+    // imgui's colours are sRGB, but we render to an sRGB framebuffer which
+    // expects linear values, so convert them (roughly) first.
+    varying_colour = vec4(pow(colour.rgb, vec3(2.2)), colour.a);
+    gl_Position = vec4(position * push_constants.scale + push_constants.translate, 0.0, 1.0);
+}
@@ -0,0 +1,37 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 varying_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PushConstants {
+    vec2 texel_size;
+    vec4 params[2];
+} push_constants;
+
+layout(location = 0) out vec4 target;
+
+// params[0]: threshold, knee
+//
+// The first step of bloom: keep only what's brighter than the threshold,
+// easing in over the knee rather than cutting off sharply. This writes a
+// half-size target, so average a 4x4 block of the source with four
+// bilinear taps on the way.
+void main() {
+    vec2 texel = push_constants.texel_size;
+    vec3 colour = 0.25 * (texture(source, varying_uv + vec2(-1.0, -1.0) * texel).rgb +
+                          texture(source, varying_uv + vec2(1.0, -1.0) * texel).rgb +
+                          texture(source, varying_uv + vec2(-1.0, 1.0) * texel).rgb +
+                          texture(source, varying_uv + vec2(1.0, 1.0) * texel).rgb);
+
+    float threshold = push_constants.params[0].x;
+    float knee = max(push_constants.params[0].y, 0.0001);
+
+    float brightness = max(colour.r, max(colour.g, colour.b));
+    float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    float contribution = max(soft, brightness - threshold) / max(brightness, 0.0001);
+
+    target = vec4(colour * contribution, 1.0);
+}
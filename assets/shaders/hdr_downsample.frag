@@ -0,0 +1,23 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 varying_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PushConstants {
+    vec2 texel_size;
+    vec4 params[2];
+} push_constants;
+
+layout(location = 0) out vec4 target;
+
+// Halve the resolution, averaging a 4x4 block of the source with four
+// bilinear taps.
+void main() {
+    vec2 texel = push_constants.texel_size;
+    target = 0.25 * (texture(source, varying_uv + vec2(-1.0, -1.0) * texel) +
+                     texture(source, varying_uv + vec2(1.0, -1.0) * texel) +
+                     texture(source, varying_uv + vec2(-1.0, 1.0) * texel) +
+                     texture(source, varying_uv + vec2(1.0, 1.0) * texel));
+}
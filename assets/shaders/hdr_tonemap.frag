@@ -0,0 +1,48 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 varying_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+layout(set = 0, binding = 1) uniform sampler2D bloom;
+
+layout(push_constant) uniform PushConstants {
+    vec2 texel_size;
+    vec4 params[2];
+} push_constants;
+
+layout(location = 0) out vec4 target;
+
+const int REINHARD = 0;
+const int ACES = 1;
+const int EXPOSURE = 2;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve.
+vec3 aces(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+// params[0]: exposure (in stops), operator, bloom intensity
+//
+// Bring the HDR scene, plus bloom, into 0..1. The output is linear; an
+// sRGB target does the encoding.
+void main() {
+    float exposure = push_constants.params[0].x;
+    int tone_map = int(push_constants.params[0].y + 0.5);
+    float bloom_intensity = push_constants.params[0].z;
+
+    vec4 colour = texture(scene, varying_uv);
+    vec3 hdr = colour.rgb + texture(bloom, varying_uv).rgb * bloom_intensity;
+    hdr = max(hdr * exp2(exposure), 0.0);
+
+    vec3 mapped;
+    if (tone_map == REINHARD) {
+        mapped = hdr / (1.0 + hdr);
+    } else if (tone_map == ACES) {
+        mapped = aces(hdr);
+    } else {
+        mapped = 1.0 - exp(-hdr);
+    }
+
+    target = vec4(mapped, colour.a);
+}
@@ -0,0 +1,35 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 varying_uv;
+
+// The downsampled level at this target's size, and the next smaller level
+// of the upsample chain.
+layout(set = 0, binding = 0) uniform sampler2D source;
+layout(set = 0, binding = 1) uniform sampler2D smaller;
+
+layout(push_constant) uniform PushConstants {
+    vec2 texel_size;
+    vec4 params[2];
+} push_constants;
+
+layout(location = 0) out vec4 target;
+
+// texel_size is the smaller level's.
+//
+// Add a 3x3 tent-filtered upsample of the smaller level to this one, so
+// each level carries the light of all the levels below it.
+void main() {
+    vec2 texel = push_constants.texel_size;
+    vec4 blurred = 4.0 * texture(smaller, varying_uv);
+    blurred += 2.0 * (texture(smaller, varying_uv + vec2(-1.0, 0.0) * texel) +
+                      texture(smaller, varying_uv + vec2(1.0, 0.0) * texel) +
+                      texture(smaller, varying_uv + vec2(0.0, -1.0) * texel) +
+                      texture(smaller, varying_uv + vec2(0.0, 1.0) * texel));
+    blurred += texture(smaller, varying_uv + vec2(-1.0, -1.0) * texel) +
+               texture(smaller, varying_uv + vec2(1.0, -1.0) * texel) +
+               texture(smaller, varying_uv + vec2(-1.0, 1.0) * texel) +
+               texture(smaller, varying_uv + vec2(1.0, 1.0) * texel);
+
+    target = texture(source, varying_uv) + blurred / 16.0;
+}
@@ -0,0 +1,12 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 varying_direction;
+
+layout(binding = 1) uniform samplerCube skybox;
+
+layout(location = 0) out vec4 target;
+
+void main() {
+    target = texture(skybox, varying_direction);
+}
@@ -0,0 +1,31 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec3 varying_direction;
+
+layout(binding = 0) uniform UniformBlock {
+    mat4 rotation;
+    vec4 lens;
+} uniform_block;
+
+// A single triangle that covers the whole screen.
+vec2 positions[3] = vec2[](
+    vec2(-1.0, -1.0),
+    vec2(3.0, -1.0),
+    vec2(-1.0, 3.0)
+);
+
+void main() {
+    vec2 position = positions[gl_VertexIndex];
+
+    // lens.x is the aspect ratio and lens.y is tan(fov / 2). Clip-space Y
+    // points down, so flip it to get an upward-facing world direction. The
+    // camera looks down -Z.
+    vec3 direction = vec3(position.x * uniform_block.lens.x * uniform_block.lens.y,
+                          -position.y * uniform_block.lens.y,
+                          -1.0);
+    varying_direction = (uniform_block.rotation * vec4(direction, 0.0)).xyz;
+
+    // Depth of 1.0 puts the sky as far back as possible.
+    gl_Position = vec4(position, 1.0, 1.0);
+}
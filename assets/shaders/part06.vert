@@ -0,0 +1,22 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+
+// These two come from the instance buffer, so they change once per
+// instance rather than once per vertex.
+layout(location = 2) in vec3 instance_position;
+layout(location = 3) in vec4 instance_tint;
+
+layout(location = 0) out vec4 varying_color;
+
+layout(binding = 0) uniform UniformBlock {
+    mat4 projection;
+} uniform_block;
+
+void main() {
+    varying_color = color * instance_tint;
+    gl_Position = uniform_block.projection
+        * vec4(position + instance_position, 1.0);
+}
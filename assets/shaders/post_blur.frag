@@ -0,0 +1,35 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 varying_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PushConstants {
+    vec2 texel_size;
+    vec4 params[2];
+} push_constants;
+
+layout(location = 0) out vec4 target;
+
+// params[0]: direction (one texel along the blur's axis), sigma in texels
+//
+// One axis of a separable gaussian blur, out to three standard deviations.
+const int MAX_RADIUS = 32;
+
+void main() {
+    vec2 offset = push_constants.params[0].xy * push_constants.texel_size;
+    float sigma = max(push_constants.params[0].z, 0.001);
+    int radius = min(int(ceil(sigma * 3.0)), MAX_RADIUS);
+
+    vec4 sum = texture(source, varying_uv);
+    float total = 1.0;
+    for (int i = 1; i <= radius; ++i) {
+        float weight = exp(-0.5 * float(i * i) / (sigma * sigma));
+        sum += weight * (texture(source, varying_uv + offset * float(i)) +
+                         texture(source, varying_uv - offset * float(i)));
+        total += 2.0 * weight;
+    }
+
+    target = sum / total;
+}
@@ -0,0 +1,31 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 varying_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PushConstants {
+    vec2 texel_size;
+    vec4 params[2];
+} push_constants;
+
+layout(location = 0) out vec4 target;
+
+// params[0]: exposure (in stops), contrast, saturation
+// params[1]: tint
+void main() {
+    vec4 colour = texture(source, varying_uv);
+    float exposure = push_constants.params[0].x;
+    float contrast = push_constants.params[0].y;
+    float saturation = push_constants.params[0].z;
+    vec3 tint = push_constants.params[1].rgb;
+
+    vec3 graded = colour.rgb * exp2(exposure) * tint;
+    // Contrast pivots around linear mid-grey.
+    graded = (graded - 0.18) * contrast + 0.18;
+    float luma = dot(graded, vec3(0.2126, 0.7152, 0.0722));
+    graded = mix(vec3(luma), graded, saturation);
+
+    target = vec4(max(graded, 0.0), colour.a);
+}
@@ -0,0 +1,59 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 varying_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PushConstants {
+    vec2 texel_size;
+    vec4 params[2];
+} push_constants;
+
+layout(location = 0) out vec4 target;
+
+// params[0]: maximum search span in texels, direction reduction multiplier,
+// minimum direction reduction
+//
+// The simplified FXAA from Timothy Lottes' original: find the direction of
+// the edge through this pixel from the luma of its diagonal neighbours,
+// then blur along it.
+
+float luma(vec3 colour) {
+    // The source is linear, and FXAA works best on perceptual brightness.
+    return sqrt(dot(colour, vec3(0.299, 0.587, 0.114)));
+}
+
+void main() {
+    vec2 texel = push_constants.texel_size;
+    float span_max = push_constants.params[0].x;
+    float reduce_mul = push_constants.params[0].y;
+    float reduce_min = push_constants.params[0].z;
+
+    vec4 centre = texture(source, varying_uv);
+    float luma_nw = luma(texture(source, varying_uv + vec2(-1.0, -1.0) * texel).rgb);
+    float luma_ne = luma(texture(source, varying_uv + vec2(1.0, -1.0) * texel).rgb);
+    float luma_sw = luma(texture(source, varying_uv + vec2(-1.0, 1.0) * texel).rgb);
+    float luma_se = luma(texture(source, varying_uv + vec2(1.0, 1.0) * texel).rgb);
+    float luma_m = luma(centre.rgb);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    vec2 dir = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)),
+                    (luma_nw + luma_sw) - (luma_ne + luma_se));
+    float dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * reduce_mul, reduce_min);
+    float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2(-span_max), vec2(span_max)) * texel;
+
+    vec3 near = 0.5 * (texture(source, varying_uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+                       texture(source, varying_uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 far = near * 0.5 + 0.25 * (texture(source, varying_uv - dir * 0.5).rgb +
+                                    texture(source, varying_uv + dir * 0.5).rgb);
+
+    // If the wider blur strays outside the local range, it crossed another
+    // edge, so fall back to the narrower one.
+    float luma_far = luma(far);
+    vec3 result = (luma_far < luma_min || luma_far > luma_max) ? near : far;
+    target = vec4(result, centre.a);
+}
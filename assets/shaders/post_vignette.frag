@@ -0,0 +1,31 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 varying_uv;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PushConstants {
+    vec2 texel_size;
+    vec4 params[2];
+} push_constants;
+
+layout(location = 0) out vec4 target;
+
+// params[0]: strength, radius, softness
+// params[1]: colour
+void main() {
+    vec4 colour = texture(source, varying_uv);
+    float strength = push_constants.params[0].x;
+    float radius = push_constants.params[0].y;
+    float softness = push_constants.params[0].z;
+
+    // Distance from the centre in units of the target's height, so the
+    // vignette is round whatever the aspect ratio.
+    vec2 offset = varying_uv - 0.5;
+    offset.x *= push_constants.texel_size.y / push_constants.texel_size.x;
+    float falloff = smoothstep(radius - softness, radius, length(offset));
+
+    vec3 shaded = mix(colour.rgb, push_constants.params[1].rgb, falloff * strength);
+    target = vec4(shaded, colour.a);
+}
@@ -0,0 +1,19 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 colour;
+
+layout(location = 0) out vec2 varying_uv;
+layout(location = 1) out vec4 varying_colour;
+
+layout(set = 0, binding = 0) uniform UniformBlock {
+    mat4 projection;
+} uniform_block;
+
+void main() {
+    varying_uv = uv;
+    varying_colour = colour;
+    gl_Position = uniform_block.projection * vec4(position, 0.0, 1.0);
+}
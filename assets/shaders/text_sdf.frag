@@ -0,0 +1,19 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 varying_uv;
+layout(location = 1) in vec4 varying_colour;
+
+layout(set = 1, binding = 0) uniform sampler2D glyph_texture;
+
+layout(location = 0) out vec4 target;
+
+void main() {
+    // Alpha holds the distance to the glyph outline, with 0.5 on the
+    // outline itself. Smoothing over one screen pixel's worth of distance
+    // keeps edges crisp at any scale.
+    float distance = texture(glyph_texture, varying_uv).a;
+    float width = fwidth(distance);
+    float coverage = smoothstep(0.5 - width, 0.5 + width, distance);
+    target = vec4(varying_colour.rgb, varying_colour.a * coverage);
+}
@@ -0,0 +1,13 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec2 varying_uv;
+layout(location = 1) in vec4 varying_colour;
+
+layout(set = 0, binding = 0) uniform sampler2D tile_texture;
+
+layout(location = 0) out vec4 target;
+
+void main() {
+    target = texture(tile_texture, varying_uv) * varying_colour;
+}
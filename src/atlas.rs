@@ -0,0 +1,547 @@
+// Texture atlas packing.
+//
+// Images are packed into fixed-size pages with the MaxRects algorithm,
+// using the best-short-side-fit rule, opening new pages as needed. Each
+// image can be surrounded by copies of its edge pixels ("extrusion"), which
+// stops filtering from bleeding neighbouring images in, and by empty
+// padding. Regions record where each image's own pixels ended up.
+//
+// The metadata serialises to JSON alongside one PNG per page.
+
+use image::{self, ImageError, RgbaImage};
+use serde_json;
+use sprite::UvRect;
+use texture::TextureData;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(io::Error),
+    Image(ImageError),
+    Json(serde_json::Error),
+    /// The named image doesn't fit on an empty page.
+    TooLarge(String),
+    DuplicateName(String),
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtlasError::Io(err) => write!(f, "I/O error: {}", err),
+            AtlasError::Image(err) => write!(f, "image error: {}", err),
+            AtlasError::Json(err) => write!(f, "JSON error: {}", err),
+            AtlasError::TooLarge(name) => write!(f, "image '{}' is too large for an atlas page", name),
+            AtlasError::DuplicateName(name) => write!(f, "more than one image named '{}'", name),
+        }
+    }
+}
+
+impl Error for AtlasError {}
+
+impl From<io::Error> for AtlasError {
+    fn from(err: io::Error) -> Self {
+        AtlasError::Io(err)
+    }
+}
+
+impl From<ImageError> for AtlasError {
+    fn from(err: ImageError) -> Self {
+        AtlasError::Image(err)
+    }
+}
+
+impl From<serde_json::Error> for AtlasError {
+    fn from(err: serde_json::Error) -> Self {
+        AtlasError::Json(err)
+    }
+}
+
+
+/// Where one image ended up, in pixels, not counting padding or extrusion.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AtlasRegion {
+    pub name: String,
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AtlasMeta {
+    pub page_width: u32,
+    pub page_height: u32,
+    pub padding: u32,
+    pub extrude: u32,
+    /// Page image file names, relative to the metadata file. Filled in by
+    /// `Atlas::save` and `Atlas::load`.
+    #[serde(default)]
+    pub pages: Vec<String>,
+    pub regions: Vec<AtlasRegion>,
+}
+
+impl AtlasMeta {
+    pub fn region(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.iter().find(|region| region.name == name)
+    }
+
+    /// The page and UV rectangle of the named image.
+    pub fn uv(&self, name: &str) -> Option<(usize, UvRect)> {
+        self.region(name).map(|region| {
+            let (w, h) = (self.page_width as f32, self.page_height as f32);
+            (region.page, UvRect::new(region.x as f32 / w,
+                                      region.y as f32 / h,
+                                      region.width as f32 / w,
+                                      region.height as f32 / h))
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, AtlasError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, AtlasError> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+
+pub struct Atlas {
+    pub pages: Vec<RgbaImage>,
+    pub meta: AtlasMeta,
+}
+
+impl Atlas {
+    pub fn uv(&self, name: &str) -> Option<(usize, UvRect)> {
+        self.meta.uv(name)
+    }
+
+    /// Pages ready for `Texture::upload`.
+    pub fn texture_data(&self, srgb: bool) -> Vec<TextureData> {
+        self.pages.iter().map(|page| TextureData::from_rgba8(page, srgb)).collect()
+    }
+
+    /// Write the metadata to `path` as JSON, and each page next to it as
+    /// `<stem>_<page>.png`.
+    pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<(), AtlasError> {
+        let path = path.as_ref();
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+        self.meta.pages = (0..self.pages.len()).map(|index| format!("{}_{}.png", stem, index)).collect();
+        for (page, file_name) in self.pages.iter().zip(&self.meta.pages) {
+            page.save(base.join(file_name))?;
+        }
+        fs::write(path, self.meta.to_json()?)?;
+        Ok(())
+    }
+
+    /// Load metadata written by `save`, along with its pages.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, AtlasError> {
+        let path = path.as_ref();
+        let meta = AtlasMeta::from_json(&fs::read_to_string(path)?)?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut pages = Vec::with_capacity(meta.pages.len());
+        for file_name in &meta.pages {
+            pages.push(image::open(base.join(file_name))?.to_rgba());
+        }
+        Ok(Atlas { pages, meta })
+    }
+}
+
+
+pub struct AtlasBuilder {
+    page_width: u32,
+    page_height: u32,
+    padding: u32,
+    extrude: u32,
+    images: Vec<(String, RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(page_width: u32, page_height: u32) -> Self {
+        AtlasBuilder {
+            page_width,
+            page_height,
+            padding: 0,
+            extrude: 0,
+            images: Vec::new(),
+        }
+    }
+
+    /// Empty pixels between images, and between images and page edges.
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Repeat each image's edge pixels this many times around it.
+    pub fn with_extrude(mut self, extrude: u32) -> Self {
+        self.extrude = extrude;
+        self
+    }
+
+    pub fn add<S: Into<String>>(&mut self, name: S, image: RgbaImage) {
+        self.images.push((name.into(), image));
+    }
+
+    /// Load and add an image file, named after its file stem.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), AtlasError> {
+        let path = path.as_ref();
+        let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let image = image::open(path)?.to_rgba();
+        self.add(name, image);
+        Ok(())
+    }
+
+    pub fn build(self) -> Result<Atlas, AtlasError> {
+        let AtlasBuilder { page_width, page_height, padding, extrude, mut images } = self;
+
+        // Bigger images first packs much more tightly.
+        images.sort_by(|(a_name, a), (b_name, b)| {
+            let a_side = a.width().max(a.height());
+            let b_side = b.width().max(b.height());
+            b_side.cmp(&a_side).then_with(|| a_name.cmp(b_name))
+        });
+        {
+            let mut names = HashSet::new();
+            for (name, _) in &images {
+                if !names.insert(name.as_str()) {
+                    return Err(AtlasError::DuplicateName(name.clone()));
+                }
+            }
+        }
+
+        // Each page's usable area excludes the padding along its top and
+        // left edges; each image's footprint includes padding on its
+        // bottom and right. Together that puts padding on every side.
+        let bin_width = page_width.saturating_sub(padding);
+        let bin_height = page_height.saturating_sub(padding);
+
+        let mut packers: Vec<MaxRects> = Vec::new();
+        let mut pages: Vec<RgbaImage> = Vec::new();
+        let mut regions = Vec::with_capacity(images.len());
+
+        for (name, image) in images {
+            let footprint_width = image.width() + extrude * 2 + padding;
+            let footprint_height = image.height() + extrude * 2 + padding;
+            if footprint_width > bin_width || footprint_height > bin_height {
+                return Err(AtlasError::TooLarge(name));
+            }
+
+            let placed = packers.iter_mut()
+                                .enumerate()
+                                .filter_map(|(page, packer)| {
+                                    packer.insert(footprint_width, footprint_height).map(|(x, y)| (page, x, y))
+                                })
+                                .next();
+            let (page, x, y) = match placed {
+                Some(placed) => placed,
+                None => {
+                    let mut packer = MaxRects::new(bin_width, bin_height);
+                    let (x, y) = packer.insert(footprint_width, footprint_height).unwrap();
+                    packers.push(packer);
+                    pages.push(RgbaImage::new(page_width, page_height));
+                    (packers.len() - 1, x, y)
+                }
+            };
+
+            let (x, y) = (x + padding + extrude, y + padding + extrude);
+            blit_extruded(&mut pages[page], &image, x, y, extrude);
+            regions.push(AtlasRegion {
+                name,
+                page,
+                x,
+                y,
+                width: image.width(),
+                height: image.height(),
+            });
+        }
+
+        regions.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Atlas {
+            pages,
+            meta: AtlasMeta {
+                page_width,
+                page_height,
+                padding,
+                extrude,
+                pages: Vec::new(),
+                regions,
+            },
+        })
+    }
+}
+
+
+// Copy `image` to (x, y) on `page`, and fill `extrude` pixels around it with
+// its nearest edge pixel.
+fn blit_extruded(page: &mut RgbaImage, image: &RgbaImage, x: u32, y: u32, extrude: u32) {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let extrude = extrude as i64;
+    for dy in -extrude..height + extrude {
+        for dx in -extrude..width + extrude {
+            let source = image.get_pixel(dx.max(0).min(width - 1) as u32, dy.max(0).min(height - 1) as u32);
+            page.put_pixel((x as i64 + dx) as u32, (y as i64 + dy) as u32, *source);
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Bin {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+impl Bin {
+    fn contains(&self, other: &Bin) -> bool {
+        other.x >= self.x && other.y >= self.y &&
+            other.x + other.w <= self.x + self.w && other.y + other.h <= self.y + self.h
+    }
+
+    fn intersects(&self, other: &Bin) -> bool {
+        self.x < other.x + other.w && other.x < self.x + self.w &&
+            self.y < other.y + other.h && other.y < self.y + self.h
+    }
+}
+
+// The free space of one page, as a list of maximal free rectangles (which
+// may overlap each other).
+struct MaxRects {
+    free: Vec<Bin>,
+}
+
+impl MaxRects {
+    fn new(width: u32, height: u32) -> Self {
+        MaxRects {
+            free: vec![Bin { x: 0, y: 0, w: width, h: height }],
+        }
+    }
+
+    fn insert(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        // Best short side fit: the free rectangle that leaves the smallest
+        // leftover along its tighter side, breaking ties on the other side.
+        let placed = self.free
+                         .iter()
+                         .filter(|free| free.w >= w && free.h >= h)
+                         .min_by_key(|free| {
+                             let (left_w, left_h) = (free.w - w, free.h - h);
+                             (left_w.min(left_h), left_w.max(left_h))
+                         })
+                         .map(|free| Bin { x: free.x, y: free.y, w, h })?;
+
+        // Carve the placed rectangle out of every free rectangle it
+        // overlaps, keeping the up-to-four maximal leftovers of each.
+        let mut free = Vec::with_capacity(self.free.len() + 4);
+        for bin in &self.free {
+            if !bin.intersects(&placed) {
+                free.push(*bin);
+                continue;
+            }
+            if placed.x > bin.x {
+                free.push(Bin { x: bin.x, y: bin.y, w: placed.x - bin.x, h: bin.h });
+            }
+            if placed.x + placed.w < bin.x + bin.w {
+                let x = placed.x + placed.w;
+                free.push(Bin { x, y: bin.y, w: bin.x + bin.w - x, h: bin.h });
+            }
+            if placed.y > bin.y {
+                free.push(Bin { x: bin.x, y: bin.y, w: bin.w, h: placed.y - bin.y });
+            }
+            if placed.y + placed.h < bin.y + bin.h {
+                let y = placed.y + placed.h;
+                free.push(Bin { x: bin.x, y, w: bin.w, h: bin.y + bin.h - y });
+            }
+        }
+
+        // Drop any free rectangle that's inside another.
+        let mut index = 0;
+        while index < free.len() {
+            let redundant = free.iter()
+                                .enumerate()
+                                .any(|(other, bin)| {
+                                    other != index && bin.contains(&free[index]) &&
+                                        (bin != &free[index] || other < index)
+                                });
+            if redundant {
+                free.swap_remove(index);
+            } else {
+                index += 1;
+            }
+        }
+
+        self.free = free;
+        Some((placed.x, placed.y))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    // Sizes from a fixed linear congruential sequence, so every run packs
+    // the same rectangles.
+    fn sizes(count: usize, max: u32) -> Vec<(u32, u32)> {
+        let mut state = 12345u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) % max + 1
+        };
+        (0..count).map(|_| (next(), next())).collect()
+    }
+
+    fn assert_disjoint(bins: &[Bin]) {
+        for (i, a) in bins.iter().enumerate() {
+            for b in &bins[i + 1..] {
+                assert!(!a.intersects(b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn max_rects_places_without_overlap() {
+        let mut packer = MaxRects::new(128, 96);
+        let mut placed = Vec::new();
+        for (w, h) in sizes(200, 24) {
+            if let Some((x, y)) = packer.insert(w, h) {
+                placed.push(Bin { x, y, w, h });
+            }
+        }
+
+        assert!(placed.len() > 20, "only placed {}", placed.len());
+        let bounds = Bin { x: 0, y: 0, w: 128, h: 96 };
+        assert!(placed.iter().all(|bin| bounds.contains(bin)));
+        assert_disjoint(&placed);
+        // No free rectangle hides inside another, or runs over a placed one.
+        for free in &packer.free {
+            assert!(bounds.contains(free));
+            assert!(placed.iter().all(|bin| !bin.intersects(free)), "{:?}", free);
+        }
+    }
+
+    #[test]
+    fn max_rects_returns_none_when_full() {
+        let mut packer = MaxRects::new(64, 64);
+        for _ in 0..4 {
+            assert!(packer.insert(32, 32).is_some());
+        }
+        assert!(packer.free.is_empty());
+        assert_eq!(packer.insert(1, 1), None);
+
+        // Too big to ever fit, even on an empty page.
+        assert_eq!(MaxRects::new(64, 64).insert(65, 1), None);
+
+        // Space left over, but none of it in one piece big enough.
+        let mut packer = MaxRects::new(64, 64);
+        assert_eq!(packer.insert(40, 40), Some((0, 0)));
+        assert_eq!(packer.insert(30, 30), None);
+        assert!(packer.insert(24, 64).is_some());
+    }
+
+    // An image whose every pixel is different, so misplaced copies show.
+    fn numbered(width: u32, height: u32, tag: u8) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, tag, 255]))
+    }
+
+    #[test]
+    fn build_respects_padding_and_extrusion() {
+        let (padding, extrude) = (2, 1);
+        let mut builder = AtlasBuilder::new(64, 64).with_padding(padding).with_extrude(extrude);
+        let sizes = sizes(30, 12);
+        for (tag, &(w, h)) in sizes.iter().enumerate() {
+            builder.add(format!("image{}", tag), numbered(w, h, tag as u8));
+        }
+        let atlas = builder.build().unwrap();
+        assert!(atlas.pages.len() > 1);
+        assert_eq!(atlas.meta.regions.len(), sizes.len());
+
+        // Each region, grown by its extrusion plus half the padding on every
+        // side, must stay clear of the others and the page edges.
+        let border = extrude + padding / 2;
+        for page in 0..atlas.pages.len() {
+            let grown: Vec<Bin> = atlas.meta.regions
+                                       .iter()
+                                       .filter(|region| region.page == page)
+                                       .map(|region| {
+                                           Bin {
+                                               x: region.x - border,
+                                               y: region.y - border,
+                                               w: region.width + border * 2,
+                                               h: region.height + border * 2,
+                                           }
+                                       })
+                                       .collect();
+            assert_disjoint(&grown);
+            let inner = Bin { x: padding / 2, y: padding / 2, w: 64 - padding, h: 64 - padding };
+            assert!(grown.iter().all(|bin| inner.contains(bin)), "{:?}", grown);
+        }
+
+        for region in &atlas.meta.regions {
+            let tag: u8 = region.name["image".len()..].parse().unwrap();
+            assert_eq!((region.width, region.height), sizes[tag as usize]);
+            let page = &atlas.pages[region.page];
+            let (w, h) = (region.width as i64, region.height as i64);
+            let extrude = extrude as i64;
+            for dy in -extrude..h + extrude {
+                for dx in -extrude..w + extrude {
+                    let expected = Rgba([dx.max(0).min(w - 1) as u8, dy.max(0).min(h - 1) as u8, tag, 255]);
+                    let pixel = page.get_pixel((region.x as i64 + dx) as u32, (region.y as i64 + dy) as u32);
+                    assert_eq!(*pixel, expected, "{} at ({}, {})", region.name, dx, dy);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn build_rejects_oversized_and_duplicate_images() {
+        let mut builder = AtlasBuilder::new(16, 16).with_padding(1);
+        builder.add("fits", numbered(14, 14, 0));
+        assert!(builder.build().is_ok());
+
+        let mut builder = AtlasBuilder::new(16, 16).with_padding(1);
+        builder.add("too_big", numbered(15, 14, 0));
+        match builder.build() {
+            Err(AtlasError::TooLarge(name)) => assert_eq!(name, "too_big"),
+            other => panic!("expected TooLarge, got {:?}", other.map(|atlas| atlas.meta)),
+        }
+
+        let mut builder = AtlasBuilder::new(16, 16);
+        builder.add("twice", numbered(2, 2, 0));
+        builder.add("twice", numbered(3, 3, 1));
+        match builder.build() {
+            Err(AtlasError::DuplicateName(name)) => assert_eq!(name, "twice"),
+            other => panic!("expected DuplicateName, got {:?}", other.map(|atlas| atlas.meta)),
+        }
+    }
+
+    #[test]
+    fn meta_gives_uvs_and_survives_json() {
+        let meta = AtlasMeta {
+            page_width: 64,
+            page_height: 32,
+            padding: 1,
+            extrude: 0,
+            pages: vec!["atlas_0.png".to_string()],
+            regions: vec![AtlasRegion { name: "a".to_string(), page: 0, x: 16, y: 8, width: 32, height: 16 }],
+        };
+        assert_eq!(meta.uv("a"), Some((0, UvRect::new(0.25, 0.25, 0.5, 0.5))));
+        assert_eq!(meta.uv("b"), None);
+        assert_eq!(AtlasMeta::from_json(&meta.to_json().unwrap()).unwrap(), meta);
+    }
+}
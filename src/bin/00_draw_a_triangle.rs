@@ -1,7 +1,9 @@
 extern crate haltut;
 
 use haltut::prelude::*;
+use haltut::readback;
 use haltut::backend;
+use haltut::tutorials::{TriangleScene, TutorialScene};
 
 
 fn main() {
@@ -93,66 +95,13 @@ fn main() {
         device.create_render_pass(&[colour_attachment], &[subpass], &[dependency])
     };
 
-    // The pipeline layout defines the shape of the data you can send to a
-    // shader.
-    // This includes the number of uniforms and push constants. We don't need
-    // them for now.
-    let pipeline_layout = device.create_pipeline_layout(&[], &[]);
-
-    // Shader modules are needed to create the pipeline definition.
-    // The shaders are loaded from SPIR-V binary files.
-    let vertex_shader_module = {
-        let spirv = include_bytes!("../../assets/gen/shaders/part00.vert.spv");
-        device.create_shader_module(spirv).unwrap()
-    };
-    let fragment_shader_module = {
-        let spirv = include_bytes!("../../assets/gen/shaders/part00.frag.spv");
-        device.create_shader_module(spirv).unwrap()
-    };
-
-    // A pipeline object encodes almost all the state you need in order to draw
-    // geometry on screen.
-    // For now, that's really only which shaders to use, what kind of blending
-    // to do, and what kind of primitives to draw.
-    let pipeline = {
-        let vs_entry = EntryPoint::<backend::Backend> {
-            entry: "main",
-            module: &vertex_shader_module,
-            specialization: Default::default(),
-        };
-
-        let fs_entry = EntryPoint::<backend::Backend> {
-            entry: "main",
-            module: &fragment_shader_module,
-            specialization: Default::default(),
-        };
-
-        let shader_entries = GraphicsShaderSet {
-            vertex: vs_entry,
-            hull: None,
-            domain: None,
-            geometry: None,
-            fragment: Some(fs_entry),
-        };
-
-        let subpass = Subpass {
-            index: 0,
-            main_pass: &render_pass
-        };
-
-        let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
-                                                          Primitive::TriangleList,
-                                                          Rasterizer::FILL,
-                                                          &pipeline_layout,
-                                                          subpass);
-
-        pipeline_desc.blender
-                     .targets
-                     .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
-
-        device.create_graphics_pipeline(&pipeline_desc, None)
-              .unwrap()
-    };
+    // The scene itself - the shaders, and the pipeline that uses them - lives
+    // in `haltut::tutorials::triangle`, so that the same frame can also be
+    // drawn without a window. Have a look there next: a pipeline object
+    // encodes almost all the state you need in order to draw geometry on
+    // screen.
+    let memory_types = physical_device.memory_properties().memory_types;
+    let scene = TriangleScene::<backend::Backend>::new(&device, &memory_types, &render_pass);
 
     // Initialize our swapchain, images, framebuffers etc.
     // We expect to have to rebuild these when the window is resized - however,
@@ -178,7 +127,9 @@ fn main() {
     //     image_layers: 1,
     //     image_usage: COLOR_ATTACHMENT
     //  }
-    let swap_config = SwapchainConfig::from_caps(&caps, surface_colour_format);
+    let mut swap_config = SwapchainConfig::from_caps(&caps, surface_colour_format);
+    // Screenshots are copied out of the swapchain images.
+    swap_config.image_usage |= img::Usage::TRANSFER_SRC;
 
     // Extent
     // {
@@ -206,11 +157,11 @@ fn main() {
     // Here we create an image view and a framebuffer for each image in our
     // swapchain.
 
-    let (frame_views, framebuffers) = match backbuffer {
+    let (frame_images, frame_views, framebuffers) = match backbuffer {
         // This arm is currently only used by the OpenGL backend,
         // which supplies an opaque framebuffer instead of giving us control
         // over individual images.
-        Backbuffer::Framebuffer(fbo) => (vec![], vec![fbo]),
+        Backbuffer::Framebuffer(fbo) => (vec![], vec![], vec![fbo]),
         Backbuffer::Images(images) => {
             let colour_range = SubresourceRange {
                 aspects: Aspects::COLOR,
@@ -234,7 +185,7 @@ fn main() {
                                     device.create_framebuffer(&render_pass, vec![image_view], extent)
                                           .unwrap()
                                   }).collect();
-            (image_views, fbos)
+            (images, image_views, fbos)
         }
     };
 
@@ -246,6 +197,9 @@ fn main() {
     // finished before attempting to display the image.
     let frame_fence = device.create_fence(false);
 
+    // F12 saves a screenshot of the next frame.
+    let mut take_screenshot = false;
+
     'main: loop {
         let mut quitting = false;
         events_loop.poll_events(|event| {
@@ -259,6 +213,14 @@ fn main() {
                         },
                         ..
                     } => quitting = true,
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F12),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => take_screenshot = true,
                     _ => ()
                 }
 
@@ -302,9 +264,6 @@ fn main() {
             command_buffer.set_viewports(0, &[viewport.clone()]);
             command_buffer.set_scissors(0, &[viewport.rect]);
 
-            // Choose a pipeline.
-            command_buffer.bind_graphics_pipeline(&pipeline);
-
             {
                 // Clear the screen and begin the render pass.
                 let mut encoder = command_buffer.begin_render_pass_inline(
@@ -314,15 +273,9 @@ fn main() {
                     &[ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0]))]
                 );
 
-                // Draw the geometry. In this case 0..3 indicates the range of
-                // vertices to be drawn. We have no vertex buffer as yet, so
-                // this really just tells our shader to draw one triangle. The
-                // specific vertices to draw at this point are encoded in the
-                // shader itself.
-                //
-                // The 0..1 is the range of instances to draw. This is
-                // irrelevant unless we're using instanced rendering.
-                encoder.draw(0..3, 0..1);
+                // Draw the scene: choose its pipeline, then draw one
+                // triangle.
+                scene.draw(&mut encoder);
             }
 
             // Finish building the command buffer; it is now ready to send to
@@ -342,6 +295,24 @@ fn main() {
         // signal frame_fence once rendering is completed.
         queue_group.queues[0].submit(submission, Some(&frame_fence));
 
+        if take_screenshot {
+            take_screenshot = false;
+            if let Some(image) = frame_images.get(frame_index as usize) {
+                match readback::save_screenshot(&device,
+                                                physical_device,
+                                                &mut command_pool,
+                                                &mut queue_group.queues[0],
+                                                image,
+                                                surface_colour_format,
+                                                extent.width,
+                                                extent.height,
+                                                Layout::Present) {
+                    Ok(path) => println!("Saved screenshot to {}", path.display()),
+                    Err(err) => println!("Screenshot failed: {}", err),
+                }
+            }
+        }
+
         // We first wait for rendering to complete...
         device.wait_for_fence(&frame_fence, !0);
 
@@ -350,8 +321,7 @@ fn main() {
                  .expect("Failed to present");
     }
 
-    device.destroy_graphics_pipeline(pipeline);
-    device.destroy_pipeline_layout(pipeline_layout);
+    scene.destroy(&device);
 
     for framebuffer in framebuffers {
         device.destroy_framebuffer(framebuffer);
@@ -364,9 +334,6 @@ fn main() {
     device.destroy_render_pass(render_pass);
     device.destroy_swapchain(swapchain);
 
-    device.destroy_shader_module(vertex_shader_module);
-    device.destroy_shader_module(fragment_shader_module);
-
     device.destroy_command_pool(command_pool.into_raw());
     device.destroy_fence(frame_fence);
     device.destroy_semaphore(frame_semaphore);
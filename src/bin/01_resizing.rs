@@ -1,10 +1,11 @@
 extern crate haltut;
 use haltut::prelude::*;
+use haltut::postprocess::{Pass, PostProcess};
+use haltut::readback;
 use haltut::backend;
+use haltut::tutorials::{TriangleScene, TutorialScene};
 
 static WIN_TITLE : &'static str = "Part 01: Resizing";
-static VERT_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part00.vert.spv");
-static FRAG_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part00.frag.spv");
 
 fn main() {
     let mut events_loop = EventsLoop::new();
@@ -68,54 +69,24 @@ fn main() {
         device.create_render_pass(&[colour_attachment], &[subpass], &[dependency])
     };
 
-    let pipeline_layout = device.create_pipeline_layout(&[], &[]);
-
-    let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
-    let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
-
-    // A pipeline object encodes almost all the state you need in order to draw
-    // geometry on screen.
-    // For now, that's really only which shaders to use, what kind of blending
-    // to do, and what kind of primitives to draw.
-    let pipeline = {
-        let vs_entry = EntryPoint::<backend::Backend> {
-            entry: "main",
-            module: &vertex_shader_module,
-            specialization: Default::default(),
-        };
-
-        let fs_entry = EntryPoint::<backend::Backend> {
-            entry: "main",
-            module: &fragment_shader_module,
-            specialization: Default::default(),
-        };
-
-        let shader_entries = GraphicsShaderSet {
-            vertex: vs_entry,
-            hull: None,
-            domain: None,
-            geometry: None,
-            fragment: Some(fs_entry),
-        };
-
-        let subpass = Subpass {
-            index: 0,
-            main_pass: &render_pass
-        };
-
-        let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
-                                                          Primitive::TriangleList,
-                                                          Rasterizer::FILL,
-                                                          &pipeline_layout,
-                                                          subpass);
-
-        pipeline_desc.blender
-                     .targets
-                     .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
-
-        device.create_graphics_pipeline(&pipeline_desc, None)
-              .unwrap()
-    };
+    // The same triangle as last time, but drawn into an offscreen target
+    // and post-processed on its way to the screen. The offscreen targets
+    // follow the window's size too, so they're resized along with the
+    // swapchain below.
+    let memory_types = physical_device.memory_properties().memory_types;
+    let mut post_process = PostProcess::<backend::Backend>::new(&device,
+                                                                &memory_types,
+                                                                Extent2D { width: 640, height: 480 },
+                                                                surface_colour_format,
+                                                                None,
+                                                                &render_pass);
+    let effects = vec![Pass::fxaa(),
+                       Pass::colour_grade(0.0, 1.1, 1.2, [1.0, 1.0, 1.0]),
+                       Pass::vignette(0.6, 0.35, 0.4, [0.0, 0.0, 0.0])];
+    post_process.passes = effects.clone();
+    let scene = TriangleScene::<backend::Backend>::new(&device,
+                                                       &memory_types,
+                                                       &post_process.scene_target().render_pass);
 
     let frame_semaphore = device.create_semaphore();
     let frame_fence = device.create_fence(false);
@@ -129,10 +100,14 @@ fn main() {
     // items simultaneously.
     // We also take advantage of type inference by withholding the types of
     // each tuple member at this point.
-    let mut swapchain_stuff : Option<(_, _, _, _)> = None;
+    let mut swapchain_stuff : Option<(_, _, _, _, _)> = None;
 
     let mut rebuild_swapchain = false;
 
+    // F12 saves a screenshot of the next frame, and P turns the effects on
+    // and off.
+    let mut take_screenshot = false;
+
     'main: loop {
         let mut quitting = false;
         events_loop.poll_events(|event| {
@@ -146,6 +121,29 @@ fn main() {
                         },
                         ..
                     } => quitting = true,
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F12),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => take_screenshot = true,
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::P),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => {
+                        // With no passes, the scene is copied across as it is.
+                        if post_process.passes.is_empty() {
+                            post_process.passes = effects.clone();
+                        } else {
+                            post_process.passes.clear();
+                        }
+                    }
                     // Set the rebuild flag if the window resizes.
                     WindowEvent::Resized(_) => rebuild_swapchain = true,
                     _ => ()
@@ -156,7 +154,7 @@ fn main() {
 
         if (rebuild_swapchain || quitting) && swapchain_stuff.is_some() {
             // Take ownership of swapchain_stuff contents.
-            let (swapchain, _extent, frame_views, framebuffers) = swapchain_stuff.take().unwrap();
+            let (swapchain, _extent, _frame_images, frame_views, framebuffers) = swapchain_stuff.take().unwrap();
 
             // Wait for all queues to be idle and reset the comand pool, so that
             // we know no commands are being executed while we destroy the
@@ -185,11 +183,16 @@ fn main() {
 
             // Here we just create the swapchain, image views, and framebuffers
             // like we did in part 00, and store them in swapchain_stuff.
-            let swap_config = SwapchainConfig::from_caps(&caps, surface_colour_format);
+            let mut swap_config = SwapchainConfig::from_caps(&caps, surface_colour_format);
+            // Screenshots are copied out of the swapchain images.
+            swap_config.image_usage |= img::Usage::TRANSFER_SRC;
             let extent = swap_config.extent.to_extent();
+            // Nothing is running on the GPU at this point, so the old
+            // offscreen targets can go.
+            post_process.resize(&device, &memory_types, swap_config.extent);
             let (swapchain, backbuffer) = device.create_swapchain(&mut surface, swap_config, None);
 
-            let (frame_views, framebuffers) = match backbuffer {
+            let (frame_images, frame_views, framebuffers) = match backbuffer {
                 Backbuffer::Images(images) => {
                     let color_range = SubresourceRange {
                         aspects: Aspects::COLOR,
@@ -218,18 +221,18 @@ fn main() {
                                 .unwrap()
                         }).collect();
 
-                    (image_views, fbos)
+                    (images, image_views, fbos)
                 }
-                Backbuffer::Framebuffer(fbo) => (Vec::new(), vec![fbo]),
+                Backbuffer::Framebuffer(fbo) => (Vec::new(), Vec::new(), vec![fbo]),
             };
 
-            swapchain_stuff = Some((swapchain, extent, frame_views, framebuffers));
+            swapchain_stuff = Some((swapchain, extent, frame_images, frame_views, framebuffers));
         }
 
         // To access the swapchain, we need to get a mutable reference to the
         // contents of swapchain_stuff. We know it's safe to unwrap because we just
         // checked it wasn't `None`.
-        let (swapchain, extent, _frame_views, framebuffers) = swapchain_stuff.as_mut().unwrap();
+        let (swapchain, extent, frame_images, _frame_views, framebuffers) = swapchain_stuff.as_mut().unwrap();
 
         // Begin rendering.
         //
@@ -261,31 +264,15 @@ fn main() {
                 depth: 0.0..1.0,
             };
 
-            command_buffer.set_viewports(0, &[viewport.clone()]);
-            command_buffer.set_scissors(0, &[viewport.rect]);
-
-            // Choose a pipeline.
-            command_buffer.bind_graphics_pipeline(&pipeline);
-
-            {
-                // Clear the screen and begin the render pass.
-                let mut encoder = command_buffer.begin_render_pass_inline(
-                    &render_pass,
-                    &framebuffers[frame_index as usize],
-                    viewport.rect,
-                    &[ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0]))]
-                );
-
-                // Draw the geometry. In this case 0..3 indicates the range of
-                // vertices to be drawn. We have no vertex buffer as yet, so
-                // this really just tells our shader to draw one triangle. The
-                // specific vertices to draw at this point are encoded in the
-                // shader itself.
-                //
-                // The 0..1 is the range of instances to draw. This is
-                // irrelevant unless we're using instanced rendering.
-                encoder.draw(0..3, 0..1);
-            }
+            // Draw the scene offscreen, then run the passes over it. The
+            // last one draws into the swapchain image, in our render pass,
+            // so it's given our clear values.
+            post_process.draw_scene(&mut command_buffer, [0.0, 0.0, 0.0, 1.0], |encoder| scene.draw(encoder));
+            post_process.apply(&mut command_buffer,
+                               &render_pass,
+                               &framebuffers[frame_index as usize],
+                               &viewport,
+                               &[ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0]))]);
 
             // Finish building the command buffer; it is now ready to send to
             // the GPU.
@@ -304,6 +291,24 @@ fn main() {
         // signal frame_fence once rendering is completed.
         queue_group.queues[0].submit(submission, Some(&frame_fence));
 
+        if take_screenshot {
+            take_screenshot = false;
+            if let Some(image) = frame_images.get(frame_index as usize) {
+                match readback::save_screenshot(&device,
+                                                physical_device,
+                                                &mut command_pool,
+                                                &mut queue_group.queues[0],
+                                                image,
+                                                surface_colour_format,
+                                                extent.width,
+                                                extent.height,
+                                                Layout::Present) {
+                    Ok(path) => println!("Saved screenshot to {}", path.display()),
+                    Err(err) => println!("Screenshot failed: {}", err),
+                }
+            }
+        }
+
         // We first wait for rendering to complete...
         device.wait_for_fence(&frame_fence, !0);
 
@@ -312,15 +317,11 @@ fn main() {
                  .expect("Failed to present");
     }
 
-    device.destroy_graphics_pipeline(pipeline);
-    device.destroy_pipeline_layout(pipeline_layout);
-
+    scene.destroy(&device);
+    post_process.destroy(&device);
 
     device.destroy_render_pass(render_pass);
 
-    device.destroy_shader_module(vertex_shader_module);
-    device.destroy_shader_module(fragment_shader_module);
-
     device.destroy_command_pool(command_pool.into_raw());
     device.destroy_fence(frame_fence);
     device.destroy_semaphore(frame_semaphore);
@@ -1,58 +1,13 @@
 extern crate haltut;
 
 use haltut::prelude::*;
+use haltut::readback;
 
 use haltut::backend;
-use backend::Backend;
-use haltut::utils;
+use haltut::tutorials::{TutorialScene, VertexBufferScene};
 
 
 static WIN_TITLE : &'static str = "Part 02: Vertex Buffers";
-#[cfg(windows)]
-static VERT_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part02.vert.spv");
-#[cfg(windows)]
-static FRAG_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part02.frag.spv");
-
-#[cfg(all(unix))]
-static VERT_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part02.vert.spv");
-#[cfg(all(unix))]
-static FRAG_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part02.frag.spv");
-
-// repr(C) ensures deterministic layout in memory.
-#[derive(Clone, Copy, Debug)]
-#[repr(C)]
-struct Vertex {
-    position: [f32; 3],
-    colour: [f32; 4]
-}
-
-
-const MESH: &[Vertex] = &[
-    Vertex {
-        position: [0.0, -1.0, 0.0],
-        colour: [1.0, 0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [-1.0, 0.0, 0.0],
-        colour: [0.0, 0.0, 1.0, 1.0],
-    },
-    Vertex {
-        position: [0.0, 1.0, 0.0],
-        colour: [0.0, 1.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [0.0, -1.0, 0.0],
-        colour: [1.0, 0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [0.0, 1.0, 0.0],
-        colour: [0.0, 1.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [1.0, 0.0, 0.0],
-        colour: [1.0, 1.0, 0.0, 1.0],
-    },
-];
 
 
 fn main() {
@@ -117,115 +72,22 @@ fn main() {
         device.create_render_pass(&[colour_attachment], &[subpass], &[dependency])
     };
 
-    let pipeline_layout = device.create_pipeline_layout(&[], &[]);
-
-    let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
-    let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
-
-    // A pipeline object encodes almost all the state you need in order to draw
-    // geometry on screen.
-    // For now, that's really only which shaders to use, what kind of blending
-    // to do, and what kind of primitives to draw.
-    let pipeline = {
-        let vs_entry = EntryPoint::<backend::Backend> {
-            entry: "main",
-            module: &vertex_shader_module,
-            specialization: Default::default(),
-        };
-
-        let fs_entry = EntryPoint::<backend::Backend> {
-            entry: "main",
-            module: &fragment_shader_module,
-            specialization: Default::default(),
-        };
-
-        let shader_entries = GraphicsShaderSet {
-            vertex: vs_entry,
-            hull: None,
-            domain: None,
-            geometry: None,
-            fragment: Some(fs_entry),
-        };
-
-        let subpass = Subpass {
-            index: 0,
-            main_pass: &render_pass
-        };
-
-        let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
-                                                          Primitive::TriangleList,
-                                                          Rasterizer::FILL,
-                                                          &pipeline_layout,
-                                                          subpass);
-
-        pipeline_desc.blender
-                     .targets
-                     .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
-
-        // We need to let the pipeline know about all the different formats
-        // of vertex buffer we're going to use.
-        // - The `binding` number is an ID for this entry.
-        // - The `stride` indicates the size of each vertex in bytes.
-        // - The `rate` is used for instanced rendering, and can be ignored at
-        // this point.
-        pipeline_desc.vertex_buffers.push(VertexBufferDesc {
-            binding: 0,
-            stride: std::mem::size_of::<Vertex>() as u32,
-            rate: 0
-        });
-
-        // We have to declare two vertex attributes: position, and colour.
-        // Note that their locaitons have to match the locations in the shader,
-        // and their format has to be appropriate for the data type in the
-        // shader.
-        //
-        // vec3 = Rgb32Float
-        // vec4 = Rgba32Float
-        //
-        // Additionally, the second attribute must have an offset of 12 bytes
-        // in the vertex, because this refers to the size of the first field.
-        // The `binding` parameter refers back to the ID we gave in the
-        // VertexBufferDesc above.
-        pipeline_desc.attributes.push(AttributeDesc {
-            location: 0,
-            binding: 0,
-            element: Element {
-                format: Format::Rgb32Float,
-                offset: 0
-            }
-        });
-        pipeline_desc.attributes.push(AttributeDesc {
-            location: 1,
-            binding: 0,
-            element: Element {
-                format: Format::Rgba32Float,
-                offset: 12
-            }
-        });
-
-        device.create_graphics_pipeline(&pipeline_desc, None)
-              .unwrap()
-    };
-
     let memory_types = physical_device.memory_properties().memory_types;
 
-    let mesh = MESH;
-
-    // Here's where we create the buffer itself, and the memory it uses.
-    let (vertex_buffer, vertex_buffer_memory) = utils::create_buffer::<Backend, Vertex>(
-        &device,
-        &memory_types,
-        Properties::CPU_VISIBLE,
-        buffer::Usage::VERTEX,
-        mesh
-    );
+    // This time the scene has a vertex buffer: see
+    // `haltut::tutorials::vertex_buffers` for the `Vertex` type, the mesh,
+    // and how the pipeline is told what the vertices look like.
+    let scene = VertexBufferScene::<backend::Backend>::new(&device, &memory_types, &render_pass);
 
     let frame_semaphore = device.create_semaphore();
     let frame_fence = device.create_fence(false);
 
-    let mut swapchain_stuff : Option<(_, _, _, _)> = None;
+    let mut swapchain_stuff : Option<(_, _, _, _, _)> = None;
     let mut rebuild_swapchain = false;
 
+    // F12 saves a screenshot of the next frame.
+    let mut take_screenshot = false;
+
     'main: loop {
         let mut quitting = false;
         events_loop.poll_events(|event| {
@@ -239,6 +101,14 @@ fn main() {
                         },
                         ..
                     } => quitting = true,
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F12),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => take_screenshot = true,
                     // Set the rebuild flag if the window resizes.
                     WindowEvent::Resized(_) => rebuild_swapchain = true,
                     _ => ()
@@ -249,7 +119,7 @@ fn main() {
 
         if (rebuild_swapchain || quitting) && swapchain_stuff.is_some() {
             // Take ownership of swapchain_stuff contents.
-            let (swapchain, _extent, frame_views, framebuffers) = swapchain_stuff.take().unwrap();
+            let (swapchain, _extent, _frame_images, frame_views, framebuffers) = swapchain_stuff.take().unwrap();
 
             // Wait for all queues to be idle and reset the comand pool, so that
             // we know no commands are being executed while we destroy the
@@ -278,11 +148,13 @@ fn main() {
 
             // Here we just create the swapchain, image views, and framebuffers
             // like we did in part 00, and store them in swapchain_stuff.
-            let swap_config = SwapchainConfig::from_caps(&caps, surface_colour_format);
+            let mut swap_config = SwapchainConfig::from_caps(&caps, surface_colour_format);
+            // Screenshots are copied out of the swapchain images.
+            swap_config.image_usage |= img::Usage::TRANSFER_SRC;
             let extent = swap_config.extent.to_extent();
             let (swapchain, backbuffer) = device.create_swapchain(&mut surface, swap_config, None);
 
-            let (frame_views, framebuffers) = match backbuffer {
+            let (frame_images, frame_views, framebuffers) = match backbuffer {
                 Backbuffer::Images(images) => {
                     let color_range = SubresourceRange {
                         aspects: Aspects::COLOR,
@@ -311,18 +183,18 @@ fn main() {
                                 .unwrap()
                         }).collect();
 
-                    (image_views, fbos)
+                    (images, image_views, fbos)
                 }
-                Backbuffer::Framebuffer(fbo) => (Vec::new(), vec![fbo]),
+                Backbuffer::Framebuffer(fbo) => (Vec::new(), Vec::new(), vec![fbo]),
             };
 
-            swapchain_stuff = Some((swapchain, extent, frame_views, framebuffers));
+            swapchain_stuff = Some((swapchain, extent, frame_images, frame_views, framebuffers));
         }
 
         // To access the swapchain, we need to get a mutable reference to the
         // contents of swapchain_stuff. We know it's safe to unwrap because we just
         // checked it wasn't `None`.
-        let (swapchain, extent, _frame_views, framebuffers) = swapchain_stuff.as_mut().unwrap();
+        let (swapchain, extent, frame_images, _frame_views, framebuffers) = swapchain_stuff.as_mut().unwrap();
 
         // Begin rendering.
         //
@@ -347,16 +219,6 @@ fn main() {
             command_buffer.set_viewports(0, &[viewport.clone()]);
             command_buffer.set_scissors(0, &[viewport.rect]);
 
-            command_buffer.bind_graphics_pipeline(&pipeline);
-
-            // This is where we tell our pipeline to use a specific vertex
-            // buffer.
-            // The first argument again refers to the vertex buffer `binding`.
-            // The second argument is a Vec of buffers in the form
-            //  (buffer, offset)
-            // where offset is relative to the binding number.
-            command_buffer.bind_vertex_buffers(0, vec![(&vertex_buffer, 0)]);
-
             {
                 // Clear the screen and begin the render pass.
                 let mut encoder = command_buffer.begin_render_pass_inline(
@@ -366,9 +228,9 @@ fn main() {
                     &[ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0]))]
                 );
 
-                // Draw the number of vertices in our mesh.
-                let num_vertices = mesh.len() as u32;
-                encoder.draw(0..num_vertices, 0..1);
+                // Bind the pipeline and the vertex buffer, and draw every
+                // vertex in the mesh.
+                scene.draw(&mut encoder);
             }
 
             command_buffer.finish()
@@ -383,6 +245,24 @@ fn main() {
         // signal frame_fence once rendering is completed.
         queue_group.queues[0].submit(submission, Some(&frame_fence));
 
+        if take_screenshot {
+            take_screenshot = false;
+            if let Some(image) = frame_images.get(frame_index as usize) {
+                match readback::save_screenshot(&device,
+                                                physical_device,
+                                                &mut command_pool,
+                                                &mut queue_group.queues[0],
+                                                image,
+                                                surface_colour_format,
+                                                extent.width,
+                                                extent.height,
+                                                Layout::Present) {
+                    Ok(path) => println!("Saved screenshot to {}", path.display()),
+                    Err(err) => println!("Screenshot failed: {}", err),
+                }
+            }
+        }
+
         // We first wait for rendering to complete...
         device.wait_for_fence(&frame_fence, !0);
 
@@ -391,18 +271,10 @@ fn main() {
                  .expect("Failed to present");
     }
 
-    device.destroy_graphics_pipeline(pipeline);
-    device.destroy_pipeline_layout(pipeline_layout);
-
+    scene.destroy(&device);
 
     device.destroy_render_pass(render_pass);
 
-    device.destroy_buffer(vertex_buffer);
-    device.free_memory(vertex_buffer_memory);
-
-    device.destroy_shader_module(vertex_shader_module);
-    device.destroy_shader_module(fragment_shader_module);
-
     device.destroy_command_pool(command_pool.into_raw());
     device.destroy_fence(frame_fence);
     device.destroy_semaphore(frame_semaphore);
@@ -1,75 +1,26 @@
 extern crate haltut;
+extern crate imgui;
 
 use haltut::backend;
+use haltut::gui::Gui;
+use haltut::math::Vec2;
 use haltut::prelude::*;
-use haltut::utils;
+use haltut::readback;
+use haltut::sampler::{SamplerCache, SamplerDesc};
+use haltut::sprite::SpriteBatch;
+use haltut::text::{self, Align, FontAtlas, GlyphMode, TextStyle};
+use haltut::texture::Texture;
+use haltut::tutorials::{self, PushConstantScene, TutorialScene};
 
 use std::time::Instant;
 
 static WIN_TITLE : &'static str = "Part 04: Push Constants";
 
 #[cfg(windows)]
-static VERT_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part04.vert.spv");
-#[cfg(windows)]
-static FRAG_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part04.frag.spv");
+static FONT : &'static [u8] = include_bytes!("..\\..\\assets\\fonts\\DejaVuSansMono.ttf");
 
 #[cfg(all(unix))]
-static VERT_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part04.vert.spv");
-#[cfg(all(unix))]
-static FRAG_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part04.frag.spv");
-
-#[derive(Clone, Copy, Debug)]
-#[repr(C)]
-struct Vertex {
-    position: [f32; 3],
-    colour: [f32; 4]
-}
-
-#[derive(Clone, Copy, Debug)]
-#[repr(C)]
-struct UniformBlock {
-    projection: [[f32; 4]; 4]
-}
-
-// Push constants are a simpler and faster way of providing data
-// to shaders than through the more complex bindings introduced
-// thus far. The traadeoff is limited size: the spec only mandates
-// that each shader stage should support 128 bytes of push constant
-// data (TODO: check this).
-#[derive(Clone, Copy, Debug)]
-#[repr(C)]
-struct PushConstants {
-    tint: [f32; 4],
-    position: [f32; 3],
-}
-
-
-const MESH: &[Vertex] = &[
-    Vertex {
-        position: [0.0, -1.0, 0.0],
-        colour: [1.0, 0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [-1.0, 0.0, 0.0],
-        colour: [0.0, 0.0, 1.0, 1.0],
-    },
-    Vertex {
-        position: [0.0, 1.0, 0.0],
-        colour: [0.0, 1.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [0.0, -1.0, 0.0],
-        colour: [1.0, 0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [0.0, 1.0, 0.0],
-        colour: [0.0, 1.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [1.0, 0.0, 0.0],
-        colour: [1.0, 1.0, 0.0, 1.0],
-    },
-];
+static FONT : &'static [u8] = include_bytes!("../../assets/fonts/DejaVuSansMono.ttf");
 
 
 fn main() {
@@ -133,179 +84,87 @@ fn main() {
     };
 
 
-    let set_layout = device.create_descriptor_set_layout(
-        &[DescriptorSetLayoutBinding {
-            binding: 0,
-            ty: DescriptorType::UniformBuffer,
-            count: 1,
-            stage_flags: ShaderStageFlags::VERTEX,
-            immutable_samplers: false,
-        }],
-        &[],
-    );
-
-    // We need to do a little transmutation later; push constants
-    // are u32, and we need to work out the size in 'push constants'
-    // both here for the pipeline layout, and later to unsafe-cast
-    // and upload the data for the draw-call.
-    let num_push_constants = {
-        let size_in_bytes = std::mem::size_of::<PushConstants>();
-        let size_of_push_constant = std::mem::size_of::<u32>();
-        size_in_bytes / size_of_push_constant
-    };
-
-    // As with the descriptor set layout, we add our push constants
-    // to the pipeline layout.
-    let pipeline_layout = device.create_pipeline_layout(
-        vec![&set_layout],
-        &[(ShaderStageFlags::VERTEX, 0..(num_push_constants as u32))]
-    );
-
-    let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
-    let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
-
-
-    let pipeline = {
-        let vs_entry = EntryPoint::<backend::Backend> {
-            entry: "main",
-            module: &vertex_shader_module,
-            specialization: Default::default(),
-        };
-
-        let fs_entry = EntryPoint::<backend::Backend> {
-            entry: "main",
-            module: &fragment_shader_module,
-            specialization: Default::default(),
-        };
-
-        let shader_entries = GraphicsShaderSet {
-            vertex: vs_entry,
-            hull: None,
-            domain: None,
-            geometry: None,
-            fragment: Some(fs_entry),
-        };
-
-        let subpass = Subpass {
-            index: 0,
-            main_pass: &render_pass
-        };
-
-        let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
-                                                          Primitive::TriangleList,
-                                                          Rasterizer::FILL,
-                                                          &pipeline_layout,
-                                                          subpass);
-
-        pipeline_desc.blender
-                     .targets
-                     .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
-
-        pipeline_desc.vertex_buffers.push(VertexBufferDesc {
-            binding: 0,
-            stride: std::mem::size_of::<Vertex>() as u32,
-            rate: 0
-        });
-        pipeline_desc.attributes.push(AttributeDesc {
-            location: 0,
-            binding: 0,
-            element: Element {
-                format: Format::Rgb32Float,
-                offset: 0
-            }
-        });
-        pipeline_desc.attributes.push(AttributeDesc {
-            location: 1,
-            binding: 0,
-            element: Element {
-                format: Format::Rgba32Float,
-                offset: 12
-            }
-        });
-
-        device.create_graphics_pipeline(&pipeline_desc, None)
-              .unwrap()
-    };
-
-    let mut desc_pool = device.create_descriptor_pool(
-        1,
-        &[DescriptorRangeDesc {
-            ty: DescriptorType::UniformBuffer,
-            count: 1
-        }]
-    );
-
-    let desc_set = desc_pool.allocate_set(&set_layout).unwrap();
-
     let memory_types = physical_device.memory_properties().memory_types;
 
-    let mesh = MESH;
-
-    let (vertex_buffer, vertex_buffer_memory) = utils::create_buffer::<backend::Backend, Vertex>(
-        &device,
-        &memory_types,
-        Properties::CPU_VISIBLE,
-        buffer::Usage::VERTEX,
-        &mesh
-    );
-
-    let (uniform_buffer, mut uniform_memory) = utils::create_buffer::<backend::Backend, UniformBlock>(
-        &device,
-        &memory_types,
-        Properties::CPU_VISIBLE,
-        buffer::Usage::UNIFORM,
-        &[UniformBlock {
-            projection: Default::default()
-        }]
-    );
-
-    device.write_descriptor_sets(vec![DescriptorSetWrite{
-        set: &desc_set,
-        binding: 0,
-        array_offset: 0,
-        descriptors: Some(Descriptor::Buffer(&uniform_buffer, None..None))
-    }]);
-
-    // One push constant for each draw call.
-    let diamonds = vec![
-        PushConstants {
-            position: [-1.0, -1.0, 0.0],
-            tint: [1.0, 0.0, 0.0, 1.0]
-        },
-        PushConstants {
-            position: [1.0, -1.0, 0.0],
-            tint: [0.0, 1.0, 0.0, 1.0],
-        },
-        PushConstants {
-            position: [-1.0, 1.0, 0.0],
-            tint: [0.0, 0.0, 1.0, 1.0],
-        },
-        PushConstants {
-            position: [1.0, 1.0, 0.0],
-            tint: [1.0, 1.0, 1.0, 1.0],
-        },
-    ];
-
+    // The diamonds are drawn by `haltut::tutorials::push_constants`, where
+    // each one's position and tint is handed to the shader as push
+    // constants. The scene's `diamonds` can be changed between frames.
+    let mut scene = PushConstantScene::<backend::Backend>::new(&device, &memory_types, &render_pass);
+
+
+    // Text is drawn from a font atlas through a sprite batch. The frame
+    // time uses a bitmap font, which is crisp at the size it was rasterised
+    // at; the title uses a signed distance field, which stays sharp as it
+    // zooms along with the diamonds.
+    let stats_font = FontAtlas::ascii(FONT.to_vec(), 16.0, GlyphMode::Bitmap).unwrap();
+    let title_font = FontAtlas::ascii(FONT.to_vec(), 48.0, GlyphMode::Sdf { spread: 6 }).unwrap();
+
+    let stats_texture = Texture::<backend::Backend>::upload(&device,
+                                                            physical_device,
+                                                            &mut command_pool,
+                                                            &mut queue_group.queues[0],
+                                                            &stats_font.texture_data()).unwrap();
+    let title_texture = Texture::<backend::Backend>::upload(&device,
+                                                            physical_device,
+                                                            &mut command_pool,
+                                                            &mut queue_group.queues[0],
+                                                            &title_font.texture_data()).unwrap();
+
+    let mut sampler_cache = SamplerCache::<backend::Backend>::new();
+    let font_sampler_desc = SamplerDesc::linear().with_wrap(WrapMode::Clamp,
+                                                            WrapMode::Clamp,
+                                                            WrapMode::Clamp);
+
+    let mut stats_batch = SpriteBatch::new(&device, &memory_types, &render_pass, 1);
+    let stats_texture_id = stats_batch.add_texture(&device,
+                                                   &stats_texture,
+                                                   sampler_cache.get(&device, font_sampler_desc));
+
+    let mut title_batch = text::sdf_sprite_batch(&device, &memory_types, &render_pass, 1);
+    let title_texture_id = title_batch.add_texture(&device,
+                                                   &title_texture,
+                                                   sampler_cache.get(&device, font_sampler_desc));
+
+    // A GUI for tweaking the diamonds while we run.
+    let mut gui = Gui::<backend::Backend>::new(&device,
+                                               physical_device,
+                                               &mut command_pool,
+                                               &mut queue_group.queues[0],
+                                               &render_pass,
+                                               &window);
+    let mut zoom_speed = 1.0;
+    let mut zoom_phase = 0.0f32;
 
     let frame_semaphore = device.create_semaphore();
     let present_semaphore = device.create_semaphore();
 
-    let mut swapchain_stuff : Option<(_, _, _, _)> = None;
+    // The sprite batches rewrite their buffers every frame, so wait for the
+    // previous frame to finish first. The fence starts signalled so the
+    // first frame doesn't wait forever.
+    let frame_fence = device.create_fence(true);
+
+    let mut swapchain_stuff : Option<(_, _, _, _, _)> = None;
     let mut rebuild_swapchain = false;
 
     // we have a timer now. fancy.
-    let start_time = Instant::now();
-    let mut last_time = start_time;
+    let mut last_time = Instant::now();
+    let mut frame_ms = 0.0;
+
+    // F12 saves a screenshot of the next frame.
+    let mut take_screenshot = false;
 
     'main: loop {
         let mut quitting = false;
 
         let now = Instant::now();
         let delta = now.duration_since(last_time);
-        println!("dt: {:?}", delta);
+        // Smooth the frame time a little so it's readable.
+        let delta_ms = delta.as_secs() as f32 * 1000.0 + delta.subsec_nanos() as f32 / 1_000_000.0;
+        frame_ms = frame_ms * 0.9 + delta_ms * 0.1;
         last_time = now;
         events_loop.poll_events(|event| {
             if let Event::WindowEvent { event, .. } = event {
+                gui.handle_event(&event);
                 match event {
                     WindowEvent::CloseRequested => quitting = true,
                     WindowEvent::KeyboardInput {
@@ -315,6 +174,14 @@ fn main() {
                         },
                         ..
                     } => quitting = true,
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F12),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => take_screenshot = true,
                     WindowEvent::Resized(_) => rebuild_swapchain = true,
                     _ => ()
                 }
@@ -323,7 +190,7 @@ fn main() {
         });
 
         if (rebuild_swapchain || quitting) && swapchain_stuff.is_some() {
-            let (swapchain, _extent, frame_views, framebuffers) = swapchain_stuff.take().unwrap();
+            let (swapchain, _extent, _frame_images, frame_views, framebuffers) = swapchain_stuff.take().unwrap();
 
             device.wait_idle().unwrap();
             command_pool.reset();
@@ -347,11 +214,13 @@ fn main() {
             rebuild_swapchain = false;
             let (caps, _, _) = surface.compatibility(physical_device);
 
-            let swap_config = SwapchainConfig::from_caps(&caps, surface_colour_format);
+            let mut swap_config = SwapchainConfig::from_caps(&caps, surface_colour_format);
+            // Screenshots are copied out of the swapchain images.
+            swap_config.image_usage |= img::Usage::TRANSFER_SRC;
             let extent = swap_config.extent.to_extent();
             let (swapchain, backbuffer) = device.create_swapchain(&mut surface, swap_config, None);
 
-            let (frame_views, framebuffers) = match backbuffer {
+            let (frame_images, frame_views, framebuffers) = match backbuffer {
                 Backbuffer::Images(images) => {
                     let color_range = SubresourceRange {
                         aspects: Aspects::COLOR,
@@ -380,43 +249,30 @@ fn main() {
                                 .unwrap()
                         }).collect();
 
-                    (image_views, fbos)
+                    (images, image_views, fbos)
                 }
-                Backbuffer::Framebuffer(fbo) => (Vec::new(), vec![fbo]),
+                Backbuffer::Framebuffer(fbo) => (Vec::new(), Vec::new(), vec![fbo]),
             };
 
-            swapchain_stuff = Some((swapchain, extent, frame_views, framebuffers));
+            swapchain_stuff = Some((swapchain, extent, frame_images, frame_views, framebuffers));
         }
 
-        let (swapchain, extent, _frame_views, framebuffers) = swapchain_stuff.as_mut().unwrap();
+        let (swapchain, extent, frame_images, _frame_views, framebuffers) = swapchain_stuff.as_mut().unwrap();
 
         let (width, height) = (extent.width, extent.height);
-        let aspect_corrected_x = height as f32 / width as f32;
-        let t = {
-            let elapsed = start_time.elapsed();
-            elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.0
-        };
-        let zoom = t.cos() * 0.33 + 0.67;
-        let x_scale = aspect_corrected_x * zoom;
-        let y_scale = zoom;
-
-        utils::fill_buffer::<backend::Backend, UniformBlock>(
-            &device,
-            &mut uniform_memory,
-            &[UniformBlock {
-                projection: [
-                    [x_scale, 0.0, 0.0, 0.0],
-                    [0.0, y_scale, 0.0, 0.0],
-                    [0.0, 0.0, 1.0, 0.0],
-                    [0.0, 0.0, 0.0, 1.0],
-                ]
-            }]
-        );
+        zoom_phase += delta_ms / 1000.0 * zoom_speed;
+        let zoom = tutorials::zoom(zoom_phase);
 
-        // Begin rendering.
+        // Begin rendering. The fence is only reset right before we submit,
+        // so if acquiring an image fails, next frame's wait still passes.
         //
+        device.wait_for_fence(&frame_fence, !0);
         command_pool.reset();
 
+        // The scene's uniform buffer can only be rewritten once the last
+        // frame has finished with it.
+        scene.update(&device, zoom_phase, Extent2D { width, height });
+
         let frame_index: SwapImageIndex = {
             match swapchain.acquire_image(!0, FrameSync::Semaphore(&frame_semaphore)) {
                 Ok(i) => i,
@@ -427,6 +283,20 @@ fn main() {
             }
         };
 
+        // Build the GUI for this frame. It's drawn later, from inside the
+        // render pass.
+        {
+            let ui = gui.frame(delta_ms / 1000.0);
+            ui.window("Parameters")
+              .size([280.0, 200.0], imgui::Condition::FirstUseEver)
+              .build(|| {
+                  ui.slider("Zoom speed", 0.0, 4.0, &mut zoom_speed);
+                  for (index, diamond) in scene.diamonds.iter_mut().enumerate() {
+                      ui.color_edit4(format!("Tint {}", index + 1), &mut diamond.tint);
+                  }
+              });
+        }
+
         let finished_command_buffer = {
             let mut command_buffer = command_pool.acquire_command_buffer(false);
 
@@ -442,12 +312,6 @@ fn main() {
             command_buffer.set_viewports(0, &[viewport.clone()]);
             command_buffer.set_scissors(0, &[viewport.rect]);
 
-            command_buffer.bind_graphics_pipeline(&pipeline);
-
-            command_buffer.bind_vertex_buffers(0, vec![(&vertex_buffer, 0)]);
-
-            command_buffer.bind_graphics_descriptor_sets(&pipeline_layout, 0, vec![&desc_set], &[]);
-
             {
                 let mut encoder = command_buffer.begin_render_pass_inline(
                     &render_pass,
@@ -456,28 +320,29 @@ fn main() {
                     &[ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0]))]
                 );
 
-
-                let num_vertices = mesh.len() as u32;
-
-                // now, for each of our push constants
-                // - cast the PushConstant data to &[u32]
-                // - upload the data to the vertex shader
-                // - draw our mesh with the vertex shader making use of the push constant data
-                for diamond in &diamonds {
-                    let push_constants = {
-                        let start_ptr = diamond as *const PushConstants as *const u32;
-                        unsafe {
-                            std::slice::from_raw_parts(start_ptr, num_push_constants)
-                        }
-                    };
-                    encoder.push_graphics_constants(
-                        &pipeline_layout,
-                        ShaderStageFlags::VERTEX,
-                        0,
-                        push_constants,
-                    );
-                    encoder.draw(0..num_vertices, 0..1);
-                }
+                // Draw each diamond with its own push constants.
+                scene.draw(&mut encoder);
+
+                let screen = SpriteBatch::<backend::Backend>::screen_projection(width as f32, height as f32);
+
+                let title_style = TextStyle::new(48.0 * zoom).with_align(Align::Centre)
+                                                             .with_max_width(width as f32);
+                title_font.draw_text(&mut title_batch,
+                                     title_texture_id,
+                                     "Push Constants",
+                                     Vec2::new(0.0, 16.0),
+                                     &title_style);
+                title_batch.flush(&device, &memory_types, &mut encoder, screen);
+
+                let stats_style = TextStyle::new(16.0).with_colour([1.0, 1.0, 0.5, 1.0]);
+                stats_font.draw_text(&mut stats_batch,
+                                     stats_texture_id,
+                                     &format!("frame: {:.2} ms ({:.0} fps)", frame_ms, 1000.0 / frame_ms),
+                                     Vec2::new(8.0, height as f32 - 24.0),
+                                     &stats_style);
+                stats_batch.flush(&device, &memory_types, &mut encoder, screen);
+
+                gui.render(&device, &memory_types, &mut encoder);
             }
 
             command_buffer.finish()
@@ -488,7 +353,26 @@ fn main() {
             .signal(&[&present_semaphore])
             .submit(vec![finished_command_buffer]);
 
-        queue_group.queues[0].submit(submission, None);
+        device.reset_fence(&frame_fence);
+        queue_group.queues[0].submit(submission, Some(&frame_fence));
+
+        if take_screenshot {
+            take_screenshot = false;
+            if let Some(image) = frame_images.get(frame_index as usize) {
+                match readback::save_screenshot(&device,
+                                                physical_device,
+                                                &mut command_pool,
+                                                &mut queue_group.queues[0],
+                                                image,
+                                                surface_colour_format,
+                                                extent.width,
+                                                extent.height,
+                                                Layout::Present) {
+                    Ok(path) => println!("Saved screenshot to {}", path.display()),
+                    Err(err) => println!("Screenshot failed: {}", err),
+                }
+            }
+        }
 
         let result = swapchain.present(
             &mut queue_group.queues[0],
@@ -501,23 +385,20 @@ fn main() {
         }
     }
 
-    device.destroy_graphics_pipeline(pipeline);
-    device.destroy_pipeline_layout(pipeline_layout);
+    device.wait_idle().unwrap();
 
-    device.destroy_render_pass(render_pass);
+    gui.destroy(&device);
+    stats_batch.destroy(&device);
+    title_batch.destroy(&device);
+    stats_texture.destroy(&device);
+    title_texture.destroy(&device);
+    sampler_cache.destroy(&device);
 
-    device.destroy_descriptor_pool(desc_pool);
-    device.destroy_descriptor_set_layout(set_layout);
-    device.destroy_buffer(uniform_buffer);
-    device.free_memory(uniform_memory);
+    scene.destroy(&device);
 
-
-    device.destroy_buffer(vertex_buffer);
-    device.free_memory(vertex_buffer_memory);
-
-    device.destroy_shader_module(vertex_shader_module);
-    device.destroy_shader_module(fragment_shader_module);
+    device.destroy_render_pass(render_pass);
 
     device.destroy_command_pool(command_pool.into_raw());
     device.destroy_semaphore(frame_semaphore);
+    device.destroy_fence(frame_fence);
 }
@@ -0,0 +1,683 @@
+extern crate haltut;
+extern crate image;
+
+use haltut::backend;
+use haltut::camera::{Camera, CameraController, FlyController};
+use haltut::math::{Mat4, Vec3};
+use haltut::prelude::*;
+use haltut::readback;
+use haltut::sampler::{SamplerCache, SamplerDesc};
+use haltut::texture::{Texture, TextureData};
+use haltut::utils;
+
+use image::{Rgba, RgbaImage};
+use std::time::Instant;
+
+static WIN_TITLE : &'static str = "Part 05: Skybox";
+
+#[cfg(windows)]
+static SKY_VERT_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part05_skybox.vert.spv");
+#[cfg(windows)]
+static SKY_FRAG_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part05_skybox.frag.spv");
+#[cfg(windows)]
+static VERT_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part03.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part03.frag.spv");
+
+#[cfg(all(unix))]
+static SKY_VERT_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part05_skybox.vert.spv");
+#[cfg(all(unix))]
+static SKY_FRAG_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part05_skybox.frag.spv");
+#[cfg(all(unix))]
+static VERT_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part03.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part03.frag.spv");
+
+const FACE_SIZE: u32 = 256;
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct Vertex {
+    position: [f32; 3],
+    colour: [f32; 4]
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct UniformBlock {
+    projection: [[f32; 4]; 4]
+}
+
+// The skybox needs to know which way the camera is facing, plus enough
+// about the lens to turn a screen position into a view direction:
+// `lens` is (aspect ratio, tan(fov / 2), unused, unused).
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct SkyUniformBlock {
+    rotation: [[f32; 4]; 4],
+    lens: [f32; 4],
+}
+
+
+const MESH: &[Vertex] = &[
+    Vertex {
+        position: [0.0, -1.0, 0.0],
+        colour: [1.0, 0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [-1.0, 0.0, 0.0],
+        colour: [0.0, 0.0, 1.0, 1.0],
+    },
+    Vertex {
+        position: [0.0, 1.0, 0.0],
+        colour: [0.0, 1.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [0.0, -1.0, 0.0],
+        colour: [1.0, 0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [0.0, 1.0, 0.0],
+        colour: [0.0, 1.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [1.0, 0.0, 0.0],
+        colour: [1.0, 1.0, 0.0, 1.0],
+    },
+];
+
+
+// We don't ship any skybox images, so paint one: a sky gradient above the
+// horizon, darker ground below, and a grid so you can see it turning.
+// Faces are in cubemap order: +X, -X, +Y, -Y, +Z, -Z.
+fn generate_sky_faces() -> Vec<RgbaImage> {
+    let face_direction = |face: usize, u: f32, v: f32| -> [f32; 3] {
+        match face {
+            0 => [1.0, -v, -u],
+            1 => [-1.0, -v, u],
+            2 => [u, 1.0, v],
+            3 => [u, -1.0, -v],
+            4 => [u, -v, 1.0],
+            _ => [-u, -v, -1.0],
+        }
+    };
+
+    (0..6).map(|face| {
+        RgbaImage::from_fn(FACE_SIZE, FACE_SIZE, |x, y| {
+            let u = (x as f32 + 0.5) / FACE_SIZE as f32 * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / FACE_SIZE as f32 * 2.0 - 1.0;
+            let d = face_direction(face, u, v);
+            let len = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+            let up = d[1] / len;
+
+            let (low, high, t) = if up >= 0.0 {
+                ([0.75, 0.85, 1.0], [0.15, 0.35, 0.8], up)
+            } else {
+                ([0.4, 0.35, 0.3], [0.15, 0.12, 0.1], -up)
+            };
+            let mut colour = [0.0; 3];
+            for i in 0..3 {
+                colour[i] = low[i] + (high[i] - low[i]) * t;
+            }
+
+            let on_grid = |c: f32| (c * 4.0).fract().abs() < 0.02;
+            if on_grid(u) || on_grid(v) {
+                for c in colour.iter_mut() {
+                    *c *= 0.8;
+                }
+            }
+
+            Rgba([(colour[0] * 255.0) as u8,
+                  (colour[1] * 255.0) as u8,
+                  (colour[2] * 255.0) as u8,
+                  255])
+        })
+    }).collect()
+}
+
+
+fn create_pipeline<B: Backend>(device: &B::Device,
+                               render_pass: &B::RenderPass,
+                               pipeline_layout: &B::PipelineLayout,
+                               vertex_shader_module: &B::ShaderModule,
+                               fragment_shader_module: &B::ShaderModule,
+                               with_vertices: bool) -> B::GraphicsPipeline {
+    let vs_entry = EntryPoint::<B> {
+        entry: "main",
+        module: vertex_shader_module,
+        specialization: Default::default(),
+    };
+
+    let fs_entry = EntryPoint::<B> {
+        entry: "main",
+        module: fragment_shader_module,
+        specialization: Default::default(),
+    };
+
+    let shader_entries = GraphicsShaderSet {
+        vertex: vs_entry,
+        hull: None,
+        domain: None,
+        geometry: None,
+        fragment: Some(fs_entry),
+    };
+
+    let subpass = Subpass {
+        index: 0,
+        main_pass: render_pass
+    };
+
+    let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                      Primitive::TriangleList,
+                                                      Rasterizer::FILL,
+                                                      pipeline_layout,
+                                                      subpass);
+
+    pipeline_desc.blender
+                 .targets
+                 .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+    // The skybox generates its own positions from gl_VertexIndex, so it
+    // doesn't take any vertex input at all.
+    if with_vertices {
+        pipeline_desc.vertex_buffers.push(VertexBufferDesc {
+            binding: 0,
+            stride: std::mem::size_of::<Vertex>() as u32,
+            rate: 0
+        });
+        pipeline_desc.attributes.push(AttributeDesc {
+            location: 0,
+            binding: 0,
+            element: Element {
+                format: Format::Rgb32Float,
+                offset: 0
+            }
+        });
+        pipeline_desc.attributes.push(AttributeDesc {
+            location: 1,
+            binding: 0,
+            element: Element {
+                format: Format::Rgba32Float,
+                offset: 12
+            }
+        });
+    }
+
+    device.create_graphics_pipeline(&pipeline_desc, None)
+          .unwrap()
+}
+
+
+fn main() {
+    let mut events_loop = EventsLoop::new();
+    let window = WindowBuilder::new()
+                    .with_title(WIN_TITLE)
+                    .with_dimensions((640, 480).into())
+                    .with_decorations(true)
+                    .build(&events_loop)
+                    .unwrap();
+
+    let instance = backend::Instance::create(WIN_TITLE, 1);
+
+    let mut surface = instance.create_surface(&window);
+    let mut adapter = instance.enumerate_adapters().remove(0);
+    let (device, mut queue_group) = adapter
+        .open_with::<_, Graphics>(1, |family| surface.supports_queue_family(family))
+        .unwrap();
+
+    let mut command_pool = device.create_command_pool_typed(&queue_group,
+                                                            CommandPoolCreateFlags::empty(),
+                                                            16);
+
+    let physical_device = &adapter.physical_device;
+
+    let (_caps, formats, _) = surface.compatibility(physical_device);
+
+    let surface_colour_format = {
+        match formats {
+            Some(choices) => choices.into_iter()
+                                    .find(|format| format.base_format().1 == ChannelType::Srgb)
+                                    .unwrap(),
+            None => Format::Rgba8Srgb,
+        }
+    };
+
+    let render_pass = {
+        let colour_attachment = Attachment {
+            format: Some(surface_colour_format),
+            samples: 1,
+            ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+            stencil_ops: AttachmentOps::DONT_CARE,
+            layouts: Layout::Undefined..Layout::Present
+        };
+
+        let subpass = SubpassDesc {
+            colors: &[(0, Layout::ColorAttachmentOptimal)],
+            depth_stencil: None,
+            inputs: &[],
+            preserves: &[],
+            resolves: &[]
+        };
+
+        let dependency = SubpassDependency {
+            passes: SubpassRef::External..SubpassRef::Pass(0),
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+            accesses: Access::empty()..(Access::COLOR_ATTACHMENT_READ | Access::COLOR_ATTACHMENT_WRITE),
+        };
+
+        device.create_render_pass(&[colour_attachment], &[subpass], &[dependency])
+    };
+
+    // The cubemap is six square images uploaded as the six array layers of
+    // a single image. Texture::upload notices that there are six faces,
+    // creates the image with ViewCapabilities::KIND_CUBE and gives us back
+    // a ViewKind::Cube view, which is what a samplerCube expects.
+    let sky_texture = {
+        let faces = generate_sky_faces();
+        let data = TextureData::cube_from_faces(&faces, true).unwrap();
+        Texture::<backend::Backend>::upload(&device,
+                                            physical_device,
+                                            &mut command_pool,
+                                            &mut queue_group.queues[0],
+                                            &data).unwrap()
+    };
+
+    // Clamp to the edge so we don't get seams where the faces meet.
+    let mut sampler_cache = SamplerCache::<backend::Backend>::new();
+    let sky_sampler_desc = SamplerDesc::linear().with_wrap(WrapMode::Clamp,
+                                                           WrapMode::Clamp,
+                                                           WrapMode::Clamp);
+
+    // The skybox pipeline has a uniform for the camera and a combined
+    // image-sampler for the cubemap...
+    let sky_set_layout = device.create_descriptor_set_layout(
+        &[
+            DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::UniformBuffer,
+                count: 1,
+                stage_flags: ShaderStageFlags::VERTEX,
+                immutable_samplers: false,
+            },
+            DescriptorSetLayoutBinding {
+                binding: 1,
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+                stage_flags: ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            },
+        ],
+        &[],
+    );
+
+    // ... and the diamond is drawn exactly as in part 03.
+    let set_layout = device.create_descriptor_set_layout(
+        &[DescriptorSetLayoutBinding {
+            binding: 0,
+            ty: DescriptorType::UniformBuffer,
+            count: 1,
+            stage_flags: ShaderStageFlags::VERTEX,
+            immutable_samplers: false,
+        }],
+        &[],
+    );
+
+    let sky_pipeline_layout = device.create_pipeline_layout(vec![&sky_set_layout], &[]);
+    let pipeline_layout = device.create_pipeline_layout(vec![&set_layout], &[]);
+
+    let sky_vertex_shader_module = device.create_shader_module(SKY_VERT_SPIRV).unwrap();
+    let sky_fragment_shader_module = device.create_shader_module(SKY_FRAG_SPIRV).unwrap();
+    let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+    let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
+
+    let sky_pipeline = create_pipeline::<backend::Backend>(&device,
+                                       &render_pass,
+                                       &sky_pipeline_layout,
+                                       &sky_vertex_shader_module,
+                                       &sky_fragment_shader_module,
+                                       false);
+    let pipeline = create_pipeline::<backend::Backend>(&device,
+                                   &render_pass,
+                                   &pipeline_layout,
+                                   &vertex_shader_module,
+                                   &fragment_shader_module,
+                                   true);
+
+    let mut desc_pool = device.create_descriptor_pool(
+        2,
+        &[
+            DescriptorRangeDesc {
+                ty: DescriptorType::UniformBuffer,
+                count: 2
+            },
+            DescriptorRangeDesc {
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1
+            },
+        ]
+    );
+
+    let sky_desc_set = desc_pool.allocate_set(&sky_set_layout).unwrap();
+    let desc_set = desc_pool.allocate_set(&set_layout).unwrap();
+
+    let memory_types = physical_device.memory_properties().memory_types;
+
+    let mesh = MESH;
+
+    let (vertex_buffer, vertex_buffer_memory) = utils::create_buffer::<backend::Backend, Vertex>(
+        &device,
+        &memory_types,
+        Properties::CPU_VISIBLE,
+        buffer::Usage::VERTEX,
+        &mesh
+    );
+
+    let (sky_uniform_buffer, mut sky_uniform_memory) = utils::create_buffer::<backend::Backend, SkyUniformBlock>(
+        &device,
+        &memory_types,
+        Properties::CPU_VISIBLE,
+        buffer::Usage::UNIFORM,
+        &[SkyUniformBlock {
+            rotation: Default::default(),
+            lens: Default::default(),
+        }]
+    );
+
+    let (uniform_buffer, mut uniform_memory) = utils::create_buffer::<backend::Backend, UniformBlock>(
+        &device,
+        &memory_types,
+        Properties::CPU_VISIBLE,
+        buffer::Usage::UNIFORM,
+        &[UniformBlock {
+            projection: Default::default()
+        }]
+    );
+
+    device.write_descriptor_sets(vec![
+        DescriptorSetWrite {
+            set: &sky_desc_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::Buffer(&sky_uniform_buffer, None..None))
+        },
+        DescriptorSetWrite {
+            set: &sky_desc_set,
+            binding: 1,
+            array_offset: 0,
+            descriptors: Some(Descriptor::CombinedImageSampler(
+                &sky_texture.view,
+                Layout::ShaderReadOnlyOptimal,
+                sampler_cache.get(&device, sky_sampler_desc),
+            ))
+        },
+        DescriptorSetWrite {
+            set: &desc_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::Buffer(&uniform_buffer, None..None))
+        },
+    ]);
+
+    let frame_semaphore = device.create_semaphore();
+    let present_semaphore = device.create_semaphore();
+
+    let mut swapchain_stuff : Option<(_, _, _, _, _)> = None;
+    let mut rebuild_swapchain = false;
+
+    // Hold the right mouse button and drag to look around. The camera never
+    // moves, since the sky is infinitely far away anyway.
+    let fov_y = std::f32::consts::PI / 2.0;
+    let mut camera = Camera::perspective(fov_y, 0.1, 100.0);
+    let mut controller = FlyController::new(0.0);
+
+    let mut last_time = Instant::now();
+
+    // F12 saves a screenshot of the next frame.
+    let mut take_screenshot = false;
+
+    'main: loop {
+        let mut quitting = false;
+
+        let now = Instant::now();
+        let delta = now.duration_since(last_time);
+        last_time = now;
+
+        events_loop.poll_events(|event| {
+            if let Event::WindowEvent { event, .. } = event {
+                camera.handle_event(&event);
+                controller.handle_event(&event);
+                match event {
+                    WindowEvent::CloseRequested => quitting = true,
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
+                        ..
+                    } => quitting = true,
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F12),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => take_screenshot = true,
+                    WindowEvent::Resized(_) => rebuild_swapchain = true,
+                    _ => ()
+                }
+
+            }
+        });
+
+        if (rebuild_swapchain || quitting) && swapchain_stuff.is_some() {
+            let (swapchain, _extent, _frame_images, frame_views, framebuffers) = swapchain_stuff.take().unwrap();
+
+            device.wait_idle().unwrap();
+            command_pool.reset();
+
+            for framebuffer in framebuffers {
+                device.destroy_framebuffer(framebuffer);
+            }
+
+            for image_view in frame_views {
+                device.destroy_image_view(image_view);
+            }
+
+            device.destroy_swapchain(swapchain);
+        }
+
+        if quitting {
+            break 'main;
+        }
+
+        if swapchain_stuff.is_none() {
+            rebuild_swapchain = false;
+            let (caps, _, _) = surface.compatibility(physical_device);
+
+            let mut swap_config = SwapchainConfig::from_caps(&caps, surface_colour_format);
+            // Screenshots are copied out of the swapchain images.
+            swap_config.image_usage |= img::Usage::TRANSFER_SRC;
+            let extent = swap_config.extent.to_extent();
+            let (swapchain, backbuffer) = device.create_swapchain(&mut surface, swap_config, None);
+
+            let (frame_images, frame_views, framebuffers) = match backbuffer {
+                Backbuffer::Images(images) => {
+                    let color_range = SubresourceRange {
+                        aspects: Aspects::COLOR,
+                        levels: 0..1,
+                        layers: 0..1,
+                    };
+
+                    let image_views = images
+                        .iter()
+                        .map(|image| {
+                            device
+                                .create_image_view(
+                                    image,
+                                    ViewKind::D2,
+                                    surface_colour_format,
+                                    Swizzle::NO,
+                                    color_range.clone(),
+                                ).unwrap()
+                        }).collect::<Vec<_>>();
+
+                    let fbos = image_views
+                        .iter()
+                        .map(|image_view| {
+                            device
+                                .create_framebuffer(&render_pass, vec![image_view], extent)
+                                .unwrap()
+                        }).collect();
+
+                    (images, image_views, fbos)
+                }
+                Backbuffer::Framebuffer(fbo) => (Vec::new(), Vec::new(), vec![fbo]),
+            };
+
+            swapchain_stuff = Some((swapchain, extent, frame_images, frame_views, framebuffers));
+        }
+
+        let (swapchain, extent, frame_images, _frame_views, framebuffers) = swapchain_stuff.as_mut().unwrap();
+
+        let (width, height) = (extent.width, extent.height);
+        let aspect_corrected_x = height as f32 / width as f32;
+        let dt = delta.as_secs() as f32 + delta.subsec_nanos() as f32 / 1_000_000_000.0;
+
+        // The sky only needs the camera's orientation, not its position.
+        camera.set_viewport(width, height);
+        controller.update(&mut camera, dt);
+        utils::fill_buffer::<backend::Backend, SkyUniformBlock>(
+            &device,
+            &mut sky_uniform_memory,
+            &[SkyUniformBlock {
+                rotation: Mat4::from_quat(camera.rotation).into(),
+                lens: [camera.aspect, (fov_y / 2.0).tan(), 0.0, 0.0],
+            }]
+        );
+
+        let scale = 0.5;
+        utils::fill_buffer::<backend::Backend, UniformBlock>(
+            &device,
+            &mut uniform_memory,
+            &[UniformBlock {
+                projection: Mat4::from_scale(Vec3::new(aspect_corrected_x * scale, scale, 1.0)).into()
+            }]
+        );
+
+        command_pool.reset();
+
+        let frame_index: SwapImageIndex = {
+            match swapchain.acquire_image(!0, FrameSync::Semaphore(&frame_semaphore)) {
+                Ok(i) => i,
+                Err(_) => {
+                    rebuild_swapchain = true;
+                    continue;
+                }
+            }
+        };
+
+        let finished_command_buffer = {
+            let mut command_buffer = command_pool.acquire_command_buffer(false);
+
+            let viewport = Viewport {
+                rect: Rect {
+                    x: 0, y: 0,
+                    w: extent.width as i16,
+                    h: extent.height as i16,
+                },
+                depth: 0.0..1.0,
+            };
+
+            command_buffer.set_viewports(0, &[viewport.clone()]);
+            command_buffer.set_scissors(0, &[viewport.rect]);
+
+            {
+                let mut encoder = command_buffer.begin_render_pass_inline(
+                    &render_pass,
+                    &framebuffers[frame_index as usize],
+                    viewport.rect,
+                    &[ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0]))]
+                );
+
+                // Draw the sky first, so everything else lands on top of it.
+                // One triangle, three vertices, no vertex buffer.
+                encoder.bind_graphics_pipeline(&sky_pipeline);
+                encoder.bind_graphics_descriptor_sets(&sky_pipeline_layout, 0, vec![&sky_desc_set], &[]);
+                encoder.draw(0..3, 0..1);
+
+                encoder.bind_graphics_pipeline(&pipeline);
+                encoder.bind_vertex_buffers(0, vec![(&vertex_buffer, 0)]);
+                encoder.bind_graphics_descriptor_sets(&pipeline_layout, 0, vec![&desc_set], &[]);
+                encoder.draw(0..mesh.len() as u32, 0..1);
+            }
+
+            command_buffer.finish()
+        };
+
+        let submission = Submission::new()
+            .wait_on(&[(&frame_semaphore, PipelineStage::BOTTOM_OF_PIPE)])
+            .signal(&[&present_semaphore])
+            .submit(vec![finished_command_buffer]);
+
+        queue_group.queues[0].submit(submission, None);
+
+        if take_screenshot {
+            take_screenshot = false;
+            if let Some(image) = frame_images.get(frame_index as usize) {
+                match readback::save_screenshot(&device,
+                                                physical_device,
+                                                &mut command_pool,
+                                                &mut queue_group.queues[0],
+                                                image,
+                                                surface_colour_format,
+                                                extent.width,
+                                                extent.height,
+                                                Layout::Present) {
+                    Ok(path) => println!("Saved screenshot to {}", path.display()),
+                    Err(err) => println!("Screenshot failed: {}", err),
+                }
+            }
+        }
+
+        let result = swapchain.present(
+            &mut queue_group.queues[0],
+            frame_index,
+            vec![&present_semaphore],
+        );
+
+        if result.is_err() {
+            rebuild_swapchain = true;
+        }
+    }
+
+    device.destroy_graphics_pipeline(sky_pipeline);
+    device.destroy_graphics_pipeline(pipeline);
+    device.destroy_pipeline_layout(sky_pipeline_layout);
+    device.destroy_pipeline_layout(pipeline_layout);
+
+    device.destroy_render_pass(render_pass);
+
+    device.destroy_descriptor_pool(desc_pool);
+    device.destroy_descriptor_set_layout(sky_set_layout);
+    device.destroy_descriptor_set_layout(set_layout);
+    device.destroy_buffer(sky_uniform_buffer);
+    device.free_memory(sky_uniform_memory);
+    device.destroy_buffer(uniform_buffer);
+    device.free_memory(uniform_memory);
+
+    device.destroy_buffer(vertex_buffer);
+    device.free_memory(vertex_buffer_memory);
+
+    sky_texture.destroy(&device);
+    sampler_cache.destroy(&device);
+
+    device.destroy_shader_module(sky_vertex_shader_module);
+    device.destroy_shader_module(sky_fragment_shader_module);
+    device.destroy_shader_module(vertex_shader_module);
+    device.destroy_shader_module(fragment_shader_module);
+
+    device.destroy_command_pool(command_pool.into_raw());
+    device.destroy_semaphore(frame_semaphore);
+    device.destroy_semaphore(present_semaphore);
+}
@@ -0,0 +1,652 @@
+extern crate haltut;
+
+use haltut::backend;
+use haltut::dynamic_buffer::DynamicBuffer;
+use haltut::instancing::{self, InstanceData, PER_INSTANCE, PER_VERTEX};
+use haltut::math::{Mat4, Vec3};
+use haltut::prelude::*;
+use haltut::readback;
+use haltut::utils;
+
+use std::time::{Duration, Instant};
+
+static WIN_TITLE : &'static str = "Part 06: Instancing";
+
+#[cfg(windows)]
+static PUSH_VERT_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part04.vert.spv");
+#[cfg(windows)]
+static INSTANCED_VERT_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part06.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV : &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part04.frag.spv");
+
+#[cfg(all(unix))]
+static PUSH_VERT_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part04.vert.spv");
+#[cfg(all(unix))]
+static INSTANCED_VERT_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part06.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV : &'static [u8] = include_bytes!("../../assets/gen/shaders/part04.frag.spv");
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct Vertex {
+    position: [f32; 3],
+    colour: [f32; 4]
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct UniformBlock {
+    projection: [[f32; 4]; 4]
+}
+
+// The same push constants as part 04, for the one-draw-per-diamond path.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct PushConstants {
+    tint: [f32; 4],
+    position: [f32; 3],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    PushConstants,
+    Instanced,
+}
+
+// A GRID x GRID square of diamonds - ten thousand of them.
+const GRID: usize = 100;
+const SPACING: f32 = 2.5;
+
+
+const MESH: &[Vertex] = &[
+    Vertex {
+        position: [0.0, -1.0, 0.0],
+        colour: [1.0, 0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [-1.0, 0.0, 0.0],
+        colour: [0.0, 0.0, 1.0, 1.0],
+    },
+    Vertex {
+        position: [0.0, 1.0, 0.0],
+        colour: [0.0, 1.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [0.0, -1.0, 0.0],
+        colour: [1.0, 0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [0.0, 1.0, 0.0],
+        colour: [0.0, 1.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [1.0, 0.0, 0.0],
+        colour: [1.0, 1.0, 0.0, 1.0],
+    },
+];
+
+
+fn create_pipeline<B: Backend>(device: &B::Device,
+                               render_pass: &B::RenderPass,
+                               pipeline_layout: &B::PipelineLayout,
+                               vertex_shader_module: &B::ShaderModule,
+                               fragment_shader_module: &B::ShaderModule,
+                               instanced: bool) -> B::GraphicsPipeline {
+    let vs_entry = EntryPoint::<B> {
+        entry: "main",
+        module: vertex_shader_module,
+        specialization: Default::default(),
+    };
+
+    let fs_entry = EntryPoint::<B> {
+        entry: "main",
+        module: fragment_shader_module,
+        specialization: Default::default(),
+    };
+
+    let shader_entries = GraphicsShaderSet {
+        vertex: vs_entry,
+        hull: None,
+        domain: None,
+        geometry: None,
+        fragment: Some(fs_entry),
+    };
+
+    let subpass = Subpass {
+        index: 0,
+        main_pass: render_pass
+    };
+
+    let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                      Primitive::TriangleList,
+                                                      Rasterizer::FILL,
+                                                      pipeline_layout,
+                                                      subpass);
+
+    pipeline_desc.blender
+                 .targets
+                 .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+    pipeline_desc.vertex_buffers.push(instancing::vertex_buffer_desc::<Vertex>(0, PER_VERTEX));
+    pipeline_desc.attributes.push(AttributeDesc {
+        location: 0,
+        binding: 0,
+        element: Element {
+            format: Format::Rgb32Float,
+            offset: 0
+        }
+    });
+    pipeline_desc.attributes.push(AttributeDesc {
+        location: 1,
+        binding: 0,
+        element: Element {
+            format: Format::Rgba32Float,
+            offset: 12
+        }
+    });
+
+    // The instanced pipeline reads two more attributes, an offset and a
+    // tint, from binding 1. That buffer's rate is "per instance": the GPU
+    // moves on to the next one after drawing each whole copy of the mesh,
+    // rather than after each vertex.
+    if instanced {
+        pipeline_desc.vertex_buffers.push(instancing::vertex_buffer_desc::<InstanceData>(1, PER_INSTANCE));
+        pipeline_desc.attributes.extend(InstanceData::attributes(1, 2));
+    }
+
+    device.create_graphics_pipeline(&pipeline_desc, None)
+          .unwrap()
+}
+
+
+fn seconds(duration: Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 / 1_000_000_000.0
+}
+
+
+fn main() {
+    let mut events_loop = EventsLoop::new();
+    let window = WindowBuilder::new()
+                    .with_title(WIN_TITLE)
+                    .with_dimensions((640, 480).into())
+                    .with_decorations(true)
+                    .build(&events_loop)
+                    .unwrap();
+
+    let instance = backend::Instance::create(WIN_TITLE, 1);
+
+    let mut surface = instance.create_surface(&window);
+    let mut adapter = instance.enumerate_adapters().remove(0);
+    let (device, mut queue_group) = adapter
+        .open_with::<_, Graphics>(1, |family| surface.supports_queue_family(family))
+        .unwrap();
+
+    let mut command_pool = device.create_command_pool_typed(&queue_group,
+                                                            CommandPoolCreateFlags::empty(),
+                                                            16);
+
+    let physical_device = &adapter.physical_device;
+
+    let (_caps, formats, _) = surface.compatibility(physical_device);
+
+    let surface_colour_format = {
+        match formats {
+            Some(choices) => choices.into_iter()
+                                    .find(|format| format.base_format().1 == ChannelType::Srgb)
+                                    .unwrap(),
+            None => Format::Rgba8Srgb,
+        }
+    };
+
+    let render_pass = {
+        let colour_attachment = Attachment {
+            format: Some(surface_colour_format),
+            samples: 1,
+            ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+            stencil_ops: AttachmentOps::DONT_CARE,
+            layouts: Layout::Undefined..Layout::Present
+        };
+
+        let subpass = SubpassDesc {
+            colors: &[(0, Layout::ColorAttachmentOptimal)],
+            depth_stencil: None,
+            inputs: &[],
+            preserves: &[],
+            resolves: &[]
+        };
+
+        let dependency = SubpassDependency {
+            passes: SubpassRef::External..SubpassRef::Pass(0),
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+            accesses: Access::empty()..(Access::COLOR_ATTACHMENT_READ | Access::COLOR_ATTACHMENT_WRITE),
+        };
+
+        device.create_render_pass(&[colour_attachment], &[subpass], &[dependency])
+    };
+
+
+    let set_layout = device.create_descriptor_set_layout(
+        &[DescriptorSetLayoutBinding {
+            binding: 0,
+            ty: DescriptorType::UniformBuffer,
+            count: 1,
+            stage_flags: ShaderStageFlags::VERTEX,
+            immutable_samplers: false,
+        }],
+        &[],
+    );
+
+    let num_push_constants = {
+        let size_in_bytes = std::mem::size_of::<PushConstants>();
+        let size_of_push_constant = std::mem::size_of::<u32>();
+        size_in_bytes / size_of_push_constant
+    };
+
+    // Both pipelines share this layout. The instanced one just never uses
+    // the push constants.
+    let pipeline_layout = device.create_pipeline_layout(
+        vec![&set_layout],
+        &[(ShaderStageFlags::VERTEX, 0..(num_push_constants as u32))]
+    );
+
+    let push_vertex_shader_module = device.create_shader_module(PUSH_VERT_SPIRV).unwrap();
+    let instanced_vertex_shader_module = device.create_shader_module(INSTANCED_VERT_SPIRV).unwrap();
+    let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
+
+    let push_pipeline = create_pipeline::<backend::Backend>(&device,
+                                                            &render_pass,
+                                                            &pipeline_layout,
+                                                            &push_vertex_shader_module,
+                                                            &fragment_shader_module,
+                                                            false);
+    let instanced_pipeline = create_pipeline::<backend::Backend>(&device,
+                                                                 &render_pass,
+                                                                 &pipeline_layout,
+                                                                 &instanced_vertex_shader_module,
+                                                                 &fragment_shader_module,
+                                                                 true);
+
+    let mut desc_pool = device.create_descriptor_pool(
+        1,
+        &[DescriptorRangeDesc {
+            ty: DescriptorType::UniformBuffer,
+            count: 1
+        }]
+    );
+
+    let desc_set = desc_pool.allocate_set(&set_layout).unwrap();
+
+    let memory_types = physical_device.memory_properties().memory_types;
+
+    let mesh = MESH;
+
+    let (vertex_buffer, vertex_buffer_memory) = utils::create_buffer::<backend::Backend, Vertex>(
+        &device,
+        &memory_types,
+        Properties::CPU_VISIBLE,
+        buffer::Usage::VERTEX,
+        &mesh
+    );
+
+    let (uniform_buffer, mut uniform_memory) = utils::create_buffer::<backend::Backend, UniformBlock>(
+        &device,
+        &memory_types,
+        Properties::CPU_VISIBLE,
+        buffer::Usage::UNIFORM,
+        &[UniformBlock {
+            projection: Default::default()
+        }]
+    );
+
+    device.write_descriptor_sets(vec![DescriptorSetWrite{
+        set: &desc_set,
+        binding: 0,
+        array_offset: 0,
+        descriptors: Some(Descriptor::Buffer(&uniform_buffer, None..None))
+    }]);
+
+    // The instance buffer is rewritten every frame, since the diamonds move.
+    let mut instance_buffer = DynamicBuffer::<backend::Backend, InstanceData>::new(&device,
+                                                                                  &memory_types,
+                                                                                  buffer::Usage::VERTEX,
+                                                                                  GRID * GRID);
+    let mut instances = Vec::with_capacity(GRID * GRID);
+
+    let frame_semaphore = device.create_semaphore();
+    let present_semaphore = device.create_semaphore();
+
+    // We rewrite buffers the GPU reads from every frame, so wait for the
+    // previous frame to finish first. The fence starts signalled so the
+    // first frame doesn't wait forever.
+    let frame_fence = device.create_fence(true);
+
+    let mut swapchain_stuff : Option<(_, _, _, _, _)> = None;
+    let mut rebuild_swapchain = false;
+
+    // Press space to switch between one draw per diamond and a single
+    // instanced draw. Timings are averaged and shown in the title bar.
+    let mut mode = Mode::Instanced;
+    let mut frame_count = 0;
+    let mut frame_time = 0.0;
+    let mut record_time = 0.0;
+    let mut last_report = Instant::now();
+
+    let start_time = Instant::now();
+    let mut last_time = start_time;
+
+    // F12 saves a screenshot of the next frame.
+    let mut take_screenshot = false;
+
+    'main: loop {
+        let mut quitting = false;
+
+        let now = Instant::now();
+        frame_time += seconds(now.duration_since(last_time));
+        last_time = now;
+        events_loop.poll_events(|event| {
+            if let Event::WindowEvent { event, .. } = event {
+                match event {
+                    WindowEvent::CloseRequested => quitting = true,
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
+                        ..
+                    } => quitting = true,
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::F12),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => take_screenshot = true,
+                    WindowEvent::KeyboardInput {
+                        input: KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Space),
+                            ..
+                        },
+                        ..
+                    } => {
+                        mode = match mode {
+                            Mode::PushConstants => Mode::Instanced,
+                            Mode::Instanced => Mode::PushConstants,
+                        };
+                    }
+                    WindowEvent::Resized(_) => rebuild_swapchain = true,
+                    _ => ()
+                }
+
+            }
+        });
+
+        if (rebuild_swapchain || quitting) && swapchain_stuff.is_some() {
+            let (swapchain, _extent, _frame_images, frame_views, framebuffers) = swapchain_stuff.take().unwrap();
+
+            device.wait_idle().unwrap();
+            command_pool.reset();
+
+            for framebuffer in framebuffers {
+                device.destroy_framebuffer(framebuffer);
+            }
+
+            for image_view in frame_views {
+                device.destroy_image_view(image_view);
+            }
+
+            device.destroy_swapchain(swapchain);
+        }
+
+        if quitting {
+            break 'main;
+        }
+
+        if swapchain_stuff.is_none() {
+            rebuild_swapchain = false;
+            let (caps, _, _) = surface.compatibility(physical_device);
+
+            let mut swap_config = SwapchainConfig::from_caps(&caps, surface_colour_format);
+            // Screenshots are copied out of the swapchain images.
+            swap_config.image_usage |= img::Usage::TRANSFER_SRC;
+            let extent = swap_config.extent.to_extent();
+            let (swapchain, backbuffer) = device.create_swapchain(&mut surface, swap_config, None);
+
+            let (frame_images, frame_views, framebuffers) = match backbuffer {
+                Backbuffer::Images(images) => {
+                    let color_range = SubresourceRange {
+                        aspects: Aspects::COLOR,
+                        levels: 0..1,
+                        layers: 0..1,
+                    };
+
+                    let image_views = images
+                        .iter()
+                        .map(|image| {
+                            device
+                                .create_image_view(
+                                    image,
+                                    ViewKind::D2,
+                                    surface_colour_format,
+                                    Swizzle::NO,
+                                    color_range.clone(),
+                                ).unwrap()
+                        }).collect::<Vec<_>>();
+
+                    let fbos = image_views
+                        .iter()
+                        .map(|image_view| {
+                            device
+                                .create_framebuffer(&render_pass, vec![image_view], extent)
+                                .unwrap()
+                        }).collect();
+
+                    (images, image_views, fbos)
+                }
+                Backbuffer::Framebuffer(fbo) => (Vec::new(), Vec::new(), vec![fbo]),
+            };
+
+            swapchain_stuff = Some((swapchain, extent, frame_images, frame_views, framebuffers));
+        }
+
+        let (swapchain, extent, frame_images, _frame_views, framebuffers) = swapchain_stuff.as_mut().unwrap();
+
+        // Begin rendering. The fence is only reset right before we submit,
+        // so if acquiring an image fails, next frame's wait still passes.
+        //
+        device.wait_for_fence(&frame_fence, !0);
+        command_pool.reset();
+
+        let frame_index: SwapImageIndex = {
+            match swapchain.acquire_image(!0, FrameSync::Semaphore(&frame_semaphore)) {
+                Ok(i) => i,
+                Err(_) => {
+                    rebuild_swapchain = true;
+                    continue;
+                }
+            }
+        };
+
+        // Fit the whole grid on screen.
+        let (width, height) = (extent.width, extent.height);
+        let aspect_corrected_x = height as f32 / width as f32;
+        let half_extent = (GRID - 1) as f32 * SPACING * 0.5;
+        let scale = 1.0 / (half_extent + 1.0);
+
+        utils::fill_buffer::<backend::Backend, UniformBlock>(
+            &device,
+            &mut uniform_memory,
+            &[UniformBlock {
+                projection: Mat4::from_scale(Vec3::new(aspect_corrected_x * scale, scale, 1.0)).into()
+            }]
+        );
+
+        // Ripple the diamonds so there's something to update every frame.
+        let t = seconds(start_time.elapsed());
+        instances.clear();
+        for row in 0..GRID {
+            for column in 0..GRID {
+                let (u, v) = (column as f32 / (GRID - 1) as f32, row as f32 / (GRID - 1) as f32);
+                let x = column as f32 * SPACING - half_extent;
+                let y = row as f32 * SPACING - half_extent;
+                let ripple = ((x * x + y * y).sqrt() * 0.1 - t * 3.0).sin() * 0.5;
+                instances.push(InstanceData {
+                    offset: [x, y + ripple, 0.0],
+                    tint: [u, v, 1.0 - u, 1.0],
+                });
+            }
+        }
+        if mode == Mode::Instanced {
+            instance_buffer.write(&device, &memory_types, &instances);
+        }
+
+        // Recording is where the two approaches differ most on the CPU.
+        let record_start = Instant::now();
+
+        let finished_command_buffer = {
+            let mut command_buffer = command_pool.acquire_command_buffer(false);
+
+            let viewport = Viewport {
+                rect: Rect {
+                    x: 0, y: 0,
+                    w: extent.width as i16,
+                    h: extent.height as i16,
+                },
+                depth: 0.0..1.0,
+            };
+
+            command_buffer.set_viewports(0, &[viewport.clone()]);
+            command_buffer.set_scissors(0, &[viewport.rect]);
+
+            command_buffer.bind_graphics_descriptor_sets(&pipeline_layout, 0, vec![&desc_set], &[]);
+
+            {
+                let mut encoder = command_buffer.begin_render_pass_inline(
+                    &render_pass,
+                    &framebuffers[frame_index as usize],
+                    viewport.rect,
+                    &[ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0]))]
+                );
+
+                let num_vertices = mesh.len() as u32;
+
+                match mode {
+                    // As in part 04: a push constant upload and a draw call
+                    // for every single diamond.
+                    Mode::PushConstants => {
+                        encoder.bind_graphics_pipeline(&push_pipeline);
+                        encoder.bind_vertex_buffers(0, vec![(&vertex_buffer, 0)]);
+                        for instance in &instances {
+                            let diamond = PushConstants {
+                                tint: instance.tint,
+                                position: instance.offset,
+                            };
+                            let push_constants = {
+                                let start_ptr = &diamond as *const PushConstants as *const u32;
+                                unsafe {
+                                    std::slice::from_raw_parts(start_ptr, num_push_constants)
+                                }
+                            };
+                            encoder.push_graphics_constants(
+                                &pipeline_layout,
+                                ShaderStageFlags::VERTEX,
+                                0,
+                                push_constants,
+                            );
+                            encoder.draw(0..num_vertices, 0..1);
+                        }
+                    }
+                    // Bind the mesh and the instance data side by side, and
+                    // draw every diamond with a single call.
+                    Mode::Instanced => {
+                        encoder.bind_graphics_pipeline(&instanced_pipeline);
+                        instancing::draw_instanced(&mut encoder, 0, &vertex_buffer, 0..num_vertices, &instance_buffer);
+                    }
+                }
+            }
+
+            command_buffer.finish()
+        };
+
+        record_time += seconds(record_start.elapsed());
+
+        let submission = Submission::new()
+            .wait_on(&[(&frame_semaphore, PipelineStage::BOTTOM_OF_PIPE)])
+            .signal(&[&present_semaphore])
+            .submit(vec![finished_command_buffer]);
+
+        device.reset_fence(&frame_fence);
+        queue_group.queues[0].submit(submission, Some(&frame_fence));
+
+        if take_screenshot {
+            take_screenshot = false;
+            if let Some(image) = frame_images.get(frame_index as usize) {
+                match readback::save_screenshot(&device,
+                                                physical_device,
+                                                &mut command_pool,
+                                                &mut queue_group.queues[0],
+                                                image,
+                                                surface_colour_format,
+                                                extent.width,
+                                                extent.height,
+                                                Layout::Present) {
+                    Ok(path) => println!("Saved screenshot to {}", path.display()),
+                    Err(err) => println!("Screenshot failed: {}", err),
+                }
+            }
+        }
+
+        let result = swapchain.present(
+            &mut queue_group.queues[0],
+            frame_index,
+            vec![&present_semaphore],
+        );
+
+        if result.is_err() {
+            rebuild_swapchain = true;
+        }
+
+        frame_count += 1;
+        if last_report.elapsed() >= Duration::from_secs(1) {
+            let title = format!("{} - {:?}: {:.2} ms/frame, {:.3} ms recording",
+                                WIN_TITLE,
+                                mode,
+                                frame_time * 1000.0 / frame_count as f32,
+                                record_time * 1000.0 / frame_count as f32);
+            println!("{}", title);
+            window.set_title(&title);
+            frame_count = 0;
+            frame_time = 0.0;
+            record_time = 0.0;
+            last_report = Instant::now();
+        }
+    }
+
+    device.wait_idle().unwrap();
+
+    device.destroy_graphics_pipeline(push_pipeline);
+    device.destroy_graphics_pipeline(instanced_pipeline);
+    device.destroy_pipeline_layout(pipeline_layout);
+
+    device.destroy_render_pass(render_pass);
+
+    device.destroy_descriptor_pool(desc_pool);
+    device.destroy_descriptor_set_layout(set_layout);
+    device.destroy_buffer(uniform_buffer);
+    device.free_memory(uniform_memory);
+
+    instance_buffer.destroy(&device);
+
+    device.destroy_buffer(vertex_buffer);
+    device.free_memory(vertex_buffer_memory);
+
+    device.destroy_shader_module(push_vertex_shader_module);
+    device.destroy_shader_module(instanced_vertex_shader_module);
+    device.destroy_shader_module(fragment_shader_module);
+
+    device.destroy_command_pool(command_pool.into_raw());
+    device.destroy_fence(frame_fence);
+    device.destroy_semaphore(frame_semaphore);
+    device.destroy_semaphore(present_semaphore);
+}
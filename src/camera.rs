@@ -0,0 +1,573 @@
+// Cameras and the controllers that move them.
+//
+// A `Camera` is a position and orientation plus a projection; it looks down
+// its local -Z with +Y up, as in `math`. Controllers turn winit window
+// events into camera movement: feed every `WindowEvent` to both the camera
+// (so it can follow the window's aspect ratio) and the controller, then call
+// the controller's `update` once per frame before reading the matrices.
+
+use math::{Mat4, Quat, Vec2, Vec3};
+use winit::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use std::f32::consts::PI;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// `fov_y` is the vertical field of view in radians.
+    Perspective { fov_y: f32, near: f32, far: f32 },
+    /// `height` is how much of the view-space Y axis fits on screen; the
+    /// width follows from the aspect ratio.
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub projection: Projection,
+    /// Width / height of the viewport.
+    pub aspect: f32,
+}
+
+impl Camera {
+    pub fn perspective(fov_y: f32, near: f32, far: f32) -> Self {
+        Camera {
+            position: Vec3::zero(),
+            rotation: Quat::identity(),
+            projection: Projection::Perspective { fov_y, near, far },
+            aspect: 1.0,
+        }
+    }
+
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Self {
+        Camera {
+            position: Vec3::zero(),
+            rotation: Quat::identity(),
+            projection: Projection::Orthographic { height, near, far },
+            aspect: 1.0,
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * -Vec3::unit_z()
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.rotation * Vec3::unit_x()
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::unit_y()
+    }
+
+    /// Turn to face `target` from the current position.
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let view = Mat4::look_at(self.position, target, up);
+        self.rotation = Quat::from_mat3(&view.to_mat3().transpose());
+    }
+
+    /// Set the aspect ratio from a viewport size in pixels.
+    pub fn set_viewport(&mut self, width: u32, height: u32) {
+        if height > 0 {
+            self.aspect = width as f32 / height as f32;
+        }
+    }
+
+    /// Keep the aspect ratio in step with the window.
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        if let WindowEvent::Resized(size) = *event {
+            if size.height > 0.0 {
+                self.aspect = (size.width / size.height) as f32;
+            }
+        }
+    }
+
+    /// World to view space.
+    pub fn view(&self) -> Mat4 {
+        Mat4::from_quat(self.rotation.conjugate()) * Mat4::from_translation(-self.position)
+    }
+
+    /// View to clip space.
+    pub fn projection(&self) -> Mat4 {
+        match self.projection {
+            Projection::Perspective { fov_y, near, far } => Mat4::perspective(fov_y, self.aspect, near, far),
+            Projection::Orthographic { height, near, far } => {
+                let (half_width, half_height) = (height * self.aspect * 0.5, height * 0.5);
+                Mat4::orthographic(-half_width, half_width, -half_height, half_height, near, far)
+            }
+        }
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+}
+
+
+pub trait CameraController {
+    /// Record input from a window event. Nothing moves until `update`.
+    fn handle_event(&mut self, event: &WindowEvent);
+
+    /// Apply the input gathered since the last update. `dt` is in seconds.
+    fn update(&mut self, camera: &mut Camera, dt: f32);
+}
+
+
+// Mouse state shared by the controllers. Motion and scrolling accumulate
+// between updates.
+#[derive(Clone, Debug, Default)]
+struct MouseInput {
+    cursor: Option<Vec2>,
+    buttons: [bool; 3],
+    motion: Vec2,
+    scroll: f32,
+}
+
+impl MouseInput {
+    fn handle_event(&mut self, event: &WindowEvent) {
+        match *event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let cursor = Vec2::new(position.x as f32, position.y as f32);
+                if let Some(last) = self.cursor {
+                    self.motion += cursor - last;
+                }
+                self.cursor = Some(cursor);
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            WindowEvent::MouseInput { state, button, .. } => {
+                let index = match button {
+                    MouseButton::Left => 0,
+                    MouseButton::Right => 1,
+                    MouseButton::Middle => 2,
+                    MouseButton::Other(_) => return,
+                };
+                self.buttons[index] = state == ElementState::Pressed;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    // Roughly one line per 20 pixels.
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+            }
+            WindowEvent::Focused(false) => self.buttons = [false; 3],
+            _ => {}
+        }
+    }
+
+    fn left(&self) -> bool {
+        self.buttons[0]
+    }
+
+    fn right(&self) -> bool {
+        self.buttons[1]
+    }
+
+    fn middle(&self) -> bool {
+        self.buttons[2]
+    }
+
+    // Take the accumulated motion and scroll.
+    fn take(&mut self) -> (Vec2, f32) {
+        let taken = (self.motion, self.scroll);
+        self.motion = Vec2::zero();
+        self.scroll = 0.0;
+        taken
+    }
+}
+
+// Keep pitch just short of straight up or down, where yaw stops making sense.
+fn clamp_pitch(pitch: f32) -> f32 {
+    let limit = PI * 0.5 - 0.01;
+    pitch.max(-limit).min(limit)
+}
+
+
+/// Orbits a target point: drag with the left button to rotate, the right
+/// or middle button to pan, and scroll to zoom.
+#[derive(Clone, Debug)]
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Radians per pixel dragged.
+    pub rotate_speed: f32,
+    /// Fraction of the distance moved per scroll line.
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    mouse: MouseInput,
+}
+
+impl OrbitController {
+    pub fn new(target: Vec3, distance: f32) -> Self {
+        OrbitController {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: 0.0,
+            rotate_speed: 0.01,
+            zoom_speed: 0.1,
+            min_distance: 0.01,
+            max_distance: ::std::f32::INFINITY,
+            mouse: MouseInput::default(),
+        }
+    }
+}
+
+impl CameraController for OrbitController {
+    fn handle_event(&mut self, event: &WindowEvent) {
+        self.mouse.handle_event(event);
+    }
+
+    fn update(&mut self, camera: &mut Camera, _dt: f32) {
+        let (motion, scroll) = self.mouse.take();
+
+        if self.mouse.left() {
+            self.yaw -= motion.x * self.rotate_speed;
+            self.pitch = clamp_pitch(self.pitch - motion.y * self.rotate_speed);
+        } else if self.mouse.right() || self.mouse.middle() {
+            // Move the target with the cursor, scaled so that points at the
+            // target's depth roughly follow it.
+            let pan_speed = self.distance * 0.002;
+            self.target += camera.right() * (-motion.x * pan_speed) + camera.up() * (motion.y * pan_speed);
+        }
+
+        self.distance *= (1.0 - self.zoom_speed).powf(scroll);
+        self.distance = self.distance.max(self.min_distance).min(self.max_distance);
+
+        camera.rotation = Quat::from_yaw_pitch_roll(self.yaw, self.pitch, 0.0);
+        camera.position = self.target + camera.rotation * Vec3::new(0.0, 0.0, self.distance);
+    }
+}
+
+
+/// First-person flying: WASD to move, Q and E to go down and up, and hold
+/// the right mouse button to look around. Holding shift moves faster.
+#[derive(Clone, Debug)]
+pub struct FlyController {
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Units per second.
+    pub speed: f32,
+    /// Radians per pixel.
+    pub look_speed: f32,
+    keys: [bool; 7],
+    mouse: MouseInput,
+}
+
+// Indices into `FlyController::keys`.
+const KEY_FORWARD: usize = 0;
+const KEY_BACK: usize = 1;
+const KEY_LEFT: usize = 2;
+const KEY_RIGHT: usize = 3;
+const KEY_DOWN: usize = 4;
+const KEY_UP: usize = 5;
+const KEY_FAST: usize = 6;
+
+impl FlyController {
+    pub fn new(speed: f32) -> Self {
+        FlyController {
+            yaw: 0.0,
+            pitch: 0.0,
+            speed,
+            look_speed: 0.005,
+            keys: [false; 7],
+            mouse: MouseInput::default(),
+        }
+    }
+}
+
+impl CameraController for FlyController {
+    fn handle_event(&mut self, event: &WindowEvent) {
+        self.mouse.handle_event(event);
+        match *event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => {
+                let index = match key {
+                    VirtualKeyCode::W => KEY_FORWARD,
+                    VirtualKeyCode::S => KEY_BACK,
+                    VirtualKeyCode::A => KEY_LEFT,
+                    VirtualKeyCode::D => KEY_RIGHT,
+                    VirtualKeyCode::Q => KEY_DOWN,
+                    VirtualKeyCode::E => KEY_UP,
+                    VirtualKeyCode::LShift | VirtualKeyCode::RShift => KEY_FAST,
+                    _ => return,
+                };
+                self.keys[index] = state == ElementState::Pressed;
+            }
+            WindowEvent::Focused(false) => self.keys = [false; 7],
+            _ => {}
+        }
+    }
+
+    fn update(&mut self, camera: &mut Camera, dt: f32) {
+        let (motion, _) = self.mouse.take();
+        if self.mouse.right() {
+            self.yaw -= motion.x * self.look_speed;
+            self.pitch = clamp_pitch(self.pitch - motion.y * self.look_speed);
+        }
+        camera.rotation = Quat::from_yaw_pitch_roll(self.yaw, self.pitch, 0.0);
+
+        let axis = |negative: usize, positive: usize| {
+            (self.keys[positive] as i32 - self.keys[negative] as i32) as f32
+        };
+        let direction = camera.forward() * axis(KEY_BACK, KEY_FORWARD) +
+            camera.right() * axis(KEY_LEFT, KEY_RIGHT) +
+            Vec3::unit_y() * axis(KEY_DOWN, KEY_UP);
+        let speed = if self.keys[KEY_FAST] { self.speed * 4.0 } else { self.speed };
+        camera.position += direction.normalize() * (speed * dt);
+    }
+}
+
+
+/// 2D panning and zooming for an orthographic camera: drag with any button
+/// to pan, and scroll to zoom about the cursor.
+#[derive(Clone, Debug)]
+pub struct PanZoomController {
+    /// Fraction of the visible height zoomed per scroll line.
+    pub zoom_speed: f32,
+    pub min_height: f32,
+    pub max_height: f32,
+    viewport: Vec2,
+    mouse: MouseInput,
+}
+
+impl PanZoomController {
+    /// `width` and `height` are the window's size in logical pixels; the
+    /// controller follows `Resized` events after that.
+    pub fn new(width: f32, height: f32) -> Self {
+        PanZoomController {
+            zoom_speed: 0.1,
+            min_height: 0.0,
+            max_height: ::std::f32::INFINITY,
+            viewport: Vec2::new(width, height),
+            mouse: MouseInput::default(),
+        }
+    }
+}
+
+impl CameraController for PanZoomController {
+    fn handle_event(&mut self, event: &WindowEvent) {
+        self.mouse.handle_event(event);
+        if let WindowEvent::Resized(size) = *event {
+            self.viewport = Vec2::new(size.width as f32, size.height as f32);
+        }
+    }
+
+    fn update(&mut self, camera: &mut Camera, _dt: f32) {
+        let (motion, scroll) = self.mouse.take();
+        let height = match camera.projection {
+            Projection::Orthographic { height, .. } => height,
+            // Nothing sensible to do for a perspective camera.
+            Projection::Perspective { .. } => return,
+        };
+        if self.viewport.y <= 0.0 {
+            return;
+        }
+
+        // Screen Y runs down, view-space Y runs up.
+        let units_per_pixel = height / self.viewport.y;
+        if self.mouse.left() || self.mouse.right() || self.mouse.middle() {
+            camera.position += camera.right() * (-motion.x * units_per_pixel) +
+                camera.up() * (motion.y * units_per_pixel);
+        }
+
+        if scroll != 0.0 {
+            let new_height = (height * (1.0 - self.zoom_speed).powf(scroll))
+                .max(self.min_height)
+                .min(self.max_height);
+            let new_units_per_pixel = new_height / self.viewport.y;
+
+            // Keep the point under the cursor where it is.
+            let cursor = self.mouse.cursor.unwrap_or(self.viewport * 0.5);
+            let offset = cursor - self.viewport * 0.5;
+            let shift = units_per_pixel - new_units_per_pixel;
+            camera.position += camera.right() * (offset.x * shift) - camera.up() * (offset.y * shift);
+            if let Projection::Orthographic { ref mut height, .. } = camera.projection {
+                *height = new_height;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::dpi::{LogicalPosition, LogicalSize};
+    use winit::{DeviceId, ModifiersState, TouchPhase};
+
+    fn approx_eq(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    fn assert_vec3(a: Vec3, b: Vec3) {
+        assert!(approx_eq(a.x, b.x) && approx_eq(a.y, b.y) && approx_eq(a.z, b.z),
+                "{:?} != {:?}",
+                a,
+                b);
+    }
+
+    fn device_id() -> DeviceId {
+        unsafe { DeviceId::dummy() }
+    }
+
+    fn key(key: VirtualKeyCode, state: ElementState) -> WindowEvent {
+        WindowEvent::KeyboardInput {
+            device_id: device_id(),
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(key),
+                modifiers: ModifiersState::default(),
+            },
+        }
+    }
+
+    fn cursor(x: f64, y: f64) -> WindowEvent {
+        WindowEvent::CursorMoved {
+            device_id: device_id(),
+            position: LogicalPosition::new(x, y),
+            modifiers: ModifiersState::default(),
+        }
+    }
+
+    fn button(button: MouseButton, state: ElementState) -> WindowEvent {
+        WindowEvent::MouseInput {
+            device_id: device_id(),
+            state,
+            button,
+            modifiers: ModifiersState::default(),
+        }
+    }
+
+    fn scroll(lines: f32) -> WindowEvent {
+        WindowEvent::MouseWheel {
+            device_id: device_id(),
+            delta: MouseScrollDelta::LineDelta(0.0, lines),
+            phase: TouchPhase::Moved,
+            modifiers: ModifiersState::default(),
+        }
+    }
+
+    // Where `point` lands on a `width` by `height` window, in pixels from
+    // the top left.
+    fn to_screen(camera: &Camera, point: Vec3, width: f32, height: f32) -> Vec2 {
+        let clip = camera.view_projection() * point.extend(1.0);
+        Vec2::new((clip.x / clip.w + 1.0) * 0.5 * width, (clip.y / clip.w + 1.0) * 0.5 * height)
+    }
+
+    #[test]
+    fn camera_follows_resizes() {
+        let mut camera = Camera::perspective(1.0, 0.1, 100.0);
+        camera.handle_event(&WindowEvent::Resized(LogicalSize::new(800.0, 400.0)));
+        assert_eq!(camera.aspect, 2.0);
+
+        // Minimising reports a zero height; keep the last aspect ratio.
+        camera.handle_event(&WindowEvent::Resized(LogicalSize::new(800.0, 0.0)));
+        assert_eq!(camera.aspect, 2.0);
+
+        camera.set_viewport(300, 600);
+        assert_eq!(camera.aspect, 0.5);
+    }
+
+    #[test]
+    fn orbit_clamps_distance() {
+        let target = Vec3::new(1.0, 2.0, 3.0);
+        let mut camera = Camera::perspective(1.0, 0.1, 100.0);
+        let mut orbit = OrbitController::new(target, 5.0);
+        orbit.min_distance = 2.0;
+        orbit.max_distance = 10.0;
+
+        // Each line zooms in by 10%.
+        orbit.handle_event(&scroll(2.0));
+        orbit.update(&mut camera, 0.1);
+        assert!(approx_eq(orbit.distance, 4.05));
+        assert_vec3(camera.position, target + Vec3::new(0.0, 0.0, 4.05));
+
+        orbit.handle_event(&scroll(50.0));
+        orbit.update(&mut camera, 0.1);
+        assert_eq!(orbit.distance, 2.0);
+        assert_vec3(camera.position, target + Vec3::new(0.0, 0.0, 2.0));
+
+        orbit.handle_event(&scroll(-50.0));
+        orbit.update(&mut camera, 0.1);
+        assert_eq!(orbit.distance, 10.0);
+        assert_vec3(camera.position, target + Vec3::new(0.0, 0.0, 10.0));
+        assert_vec3(camera.forward(), Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn fly_moves_with_held_keys() {
+        let mut camera = Camera::perspective(1.0, 0.1, 100.0);
+        let mut fly = FlyController::new(2.0);
+
+        fly.handle_event(&key(VirtualKeyCode::W, ElementState::Pressed));
+        fly.update(&mut camera, 0.5);
+        assert_vec3(camera.position, Vec3::new(0.0, 0.0, -1.0));
+
+        // Still held, so it keeps going; shift makes it four times faster.
+        fly.handle_event(&key(VirtualKeyCode::W, ElementState::Released));
+        fly.handle_event(&key(VirtualKeyCode::D, ElementState::Pressed));
+        fly.handle_event(&key(VirtualKeyCode::LShift, ElementState::Pressed));
+        fly.update(&mut camera, 0.25);
+        assert_vec3(camera.position, Vec3::new(2.0, 0.0, -1.0));
+
+        // Diagonals aren't faster than moving along one axis.
+        fly.handle_event(&key(VirtualKeyCode::LShift, ElementState::Released));
+        fly.handle_event(&key(VirtualKeyCode::E, ElementState::Pressed));
+        fly.update(&mut camera, 1.0);
+        let step = 2.0 / 2.0f32.sqrt();
+        assert_vec3(camera.position, Vec3::new(2.0 + step, step, -1.0));
+
+        // Losing focus lets go of everything.
+        fly.handle_event(&WindowEvent::Focused(false));
+        fly.update(&mut camera, 1.0);
+        assert_vec3(camera.position, Vec3::new(2.0 + step, step, -1.0));
+    }
+
+    #[test]
+    fn pan_zoom_keeps_the_point_under_the_cursor() {
+        let mut camera = Camera::orthographic(10.0, -1.0, 1.0);
+        camera.set_viewport(200, 100);
+        let mut pan_zoom = PanZoomController::new(100.0, 50.0);
+        // The controller follows the window's size too.
+        pan_zoom.handle_event(&WindowEvent::Resized(LogicalSize::new(200.0, 100.0)));
+
+        // 10 units across 100 pixels: the cursor is 5 units right of and
+        // 2.5 above the centre.
+        pan_zoom.handle_event(&cursor(150.0, 25.0));
+        let point = Vec3::new(5.0, 2.5, 0.0);
+        let screen = to_screen(&camera, point, 200.0, 100.0);
+        assert!(approx_eq(screen.x, 150.0) && approx_eq(screen.y, 25.0));
+
+        pan_zoom.handle_event(&scroll(1.0));
+        pan_zoom.update(&mut camera, 0.1);
+        assert_eq!(camera.projection, Projection::Orthographic { height: 9.0, near: -1.0, far: 1.0 });
+        let screen = to_screen(&camera, point, 200.0, 100.0);
+        assert!(approx_eq(screen.x, 150.0) && approx_eq(screen.y, 25.0), "{:?}", screen);
+
+        // Dragging moves the view with the cursor.
+        let position = camera.position;
+        pan_zoom.handle_event(&button(MouseButton::Left, ElementState::Pressed));
+        pan_zoom.handle_event(&cursor(160.0, 35.0));
+        pan_zoom.update(&mut camera, 0.1);
+        assert_vec3(camera.position, position + Vec3::new(-0.9, 0.9, 0.0));
+
+        // Zoom stops at the limit.
+        pan_zoom.min_height = 4.0;
+        pan_zoom.handle_event(&scroll(50.0));
+        pan_zoom.update(&mut camera, 0.1);
+        assert_eq!(camera.projection, Projection::Orthographic { height: 4.0, near: -1.0, far: 1.0 });
+    }
+}
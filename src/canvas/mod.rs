@@ -0,0 +1,223 @@
+// 2D vector drawing.
+//
+// Shapes are described as `Path`s, flattened into polylines and then
+// tessellated into triangles on the CPU, with thin fading edges for
+// anti-aliasing. Like `DebugDraw`, a `Canvas` is immediate mode: shapes are
+// queued over a frame and `flush` uploads and draws them in order, then
+// clears the queue.
+
+pub mod path;
+pub mod tessellate;
+
+pub use self::path::{Contour, Path, PathCommand};
+pub use self::tessellate::{CanvasVertex, Geometry, LineCap, LineJoin, StrokeStyle};
+
+use dynamic_buffer::DynamicBuffer;
+use gfx_hal::Backend;
+use math::{Mat4, Rect2, Vec2};
+use prelude::*;
+
+#[cfg(windows)]
+static VERT_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\canvas.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\canvas.frag.spv");
+
+#[cfg(all(unix))]
+static VERT_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/canvas.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/canvas.frag.spv");
+
+
+pub struct Canvas<B: Backend> {
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::GraphicsPipeline,
+    vertex_shader_module: B::ShaderModule,
+    fragment_shader_module: B::ShaderModule,
+    vertices: DynamicBuffer<B, CanvasVertex>,
+    indices: DynamicBuffer<B, u32>,
+    geometry: Geometry,
+    tolerance: f32,
+    aa_width: f32,
+}
+
+impl<B: Backend> Canvas<B> {
+    /// Create a canvas for the first subpass of `render_pass`. It doesn't
+    /// use depth testing.
+    pub fn new(device: &B::Device, memory_types: &[MemoryType], render_pass: &B::RenderPass) -> Self {
+        let pipeline_layout = device.create_pipeline_layout(
+            Vec::<&B::DescriptorSetLayout>::new(),
+            &[(ShaderStageFlags::VERTEX, 0..16)],
+        );
+
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+        let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
+
+        let pipeline = {
+            let vs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &vertex_shader_module,
+                specialization: Default::default(),
+            };
+
+            let fs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &fragment_shader_module,
+                specialization: Default::default(),
+            };
+
+            let shader_entries = GraphicsShaderSet {
+                vertex: vs_entry,
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(fs_entry),
+            };
+
+            let subpass = Subpass {
+                index: 0,
+                main_pass: render_pass,
+            };
+
+            let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                              Primitive::TriangleList,
+                                                              Rasterizer::FILL,
+                                                              &pipeline_layout,
+                                                              subpass);
+
+            pipeline_desc.blender
+                         .targets
+                         .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+            pipeline_desc.vertex_buffers.push(VertexBufferDesc {
+                binding: 0,
+                stride: ::std::mem::size_of::<CanvasVertex>() as u32,
+                rate: 0,
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 0,
+                binding: 0,
+                element: Element {
+                    format: Format::Rg32Float,
+                    offset: 0,
+                },
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 1,
+                binding: 0,
+                element: Element {
+                    format: Format::Rgba32Float,
+                    offset: 8,
+                },
+            });
+
+            device.create_graphics_pipeline(&pipeline_desc, None)
+                  .unwrap()
+        };
+
+        Canvas {
+            pipeline_layout,
+            pipeline,
+            vertex_shader_module,
+            fragment_shader_module,
+            vertices: DynamicBuffer::new(device, memory_types, buffer::Usage::VERTEX, 1024),
+            indices: DynamicBuffer::new(device, memory_types, buffer::Usage::INDEX, 4096),
+            geometry: Geometry::new(),
+            tolerance: 0.25,
+            aa_width: 1.0,
+        }
+    }
+
+    /// How many pixels one canvas unit covers, which sets how finely curves
+    /// are flattened and how wide the anti-aliased edges are. Defaults to
+    /// 1.0, for drawing in pixels.
+    pub fn set_pixel_scale(&mut self, scale: f32) {
+        let scale = scale.max(1.0e-6);
+        self.tolerance = 0.25 / scale;
+        self.aa_width = 1.0 / scale;
+    }
+
+    pub fn fill_path(&mut self, path: &Path, colour: [f32; 4]) {
+        let contours = path.flatten(self.tolerance);
+        tessellate::fill(&contours, colour, self.aa_width, &mut self.geometry);
+    }
+
+    pub fn stroke_path(&mut self, path: &Path, style: &StrokeStyle, colour: [f32; 4]) {
+        let contours = path.flatten(self.tolerance);
+        tessellate::stroke(&contours, style, colour, self.aa_width, &mut self.geometry);
+    }
+
+    pub fn fill_rect(&mut self, rect: Rect2, colour: [f32; 4]) {
+        let mut path = Path::new();
+        path.rect(rect);
+        self.fill_path(&path, colour);
+    }
+
+    pub fn fill_rounded_rect(&mut self, rect: Rect2, radius: f32, colour: [f32; 4]) {
+        let mut path = Path::new();
+        path.rounded_rect(rect, radius);
+        self.fill_path(&path, colour);
+    }
+
+    pub fn fill_circle(&mut self, centre: Vec2, radius: f32, colour: [f32; 4]) {
+        let mut path = Path::new();
+        path.circle(centre, radius);
+        self.fill_path(&path, colour);
+    }
+
+    pub fn stroke_line(&mut self, from: Vec2, to: Vec2, style: &StrokeStyle, colour: [f32; 4]) {
+        let mut path = Path::new();
+        path.move_to(from).line_to(to);
+        self.stroke_path(&path, style, colour);
+    }
+
+    /// The triangles queued since the last flush.
+    pub fn geometry(&self) -> &Geometry {
+        &self.geometry
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.geometry.is_empty()
+    }
+
+    /// Upload every queued shape and record the draw call, then clear the
+    /// queue. For pixel coordinates, use `SpriteBatch::screen_projection`.
+    ///
+    /// This rewrites buffers the GPU reads from, so flush at most once per
+    /// frame, after the previous frame using this canvas has finished.
+    pub fn flush(&mut self,
+                 device: &B::Device,
+                 memory_types: &[MemoryType],
+                 encoder: &mut RenderPassInlineEncoder<B>,
+                 projection: Mat4) {
+        if self.geometry.is_empty() {
+            return;
+        }
+
+        self.vertices.write(device, memory_types, &self.geometry.vertices);
+        self.indices.write(device, memory_types, &self.geometry.indices);
+        let index_count = self.geometry.indices.len() as u32;
+        self.geometry.clear();
+
+        let matrix: [[f32; 4]; 4] = projection.into();
+        let push_constants = unsafe { ::std::slice::from_raw_parts(matrix.as_ptr() as *const u32, 16) };
+
+        encoder.bind_graphics_pipeline(&self.pipeline);
+        encoder.push_graphics_constants(&self.pipeline_layout, ShaderStageFlags::VERTEX, 0, push_constants);
+        encoder.bind_vertex_buffers(0, vec![(self.vertices.buffer(), 0)]);
+        encoder.bind_index_buffer(IndexBufferView {
+            buffer: self.indices.buffer(),
+            offset: 0,
+            index_type: IndexType::U32,
+        });
+        encoder.draw_indexed(0..index_count, 0, 0..1);
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        self.vertices.destroy(device);
+        self.indices.destroy(device);
+        device.destroy_graphics_pipeline(self.pipeline);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+        device.destroy_shader_module(self.vertex_shader_module);
+        device.destroy_shader_module(self.fragment_shader_module);
+    }
+}
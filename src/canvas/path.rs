@@ -0,0 +1,382 @@
+// Vector paths, and flattening them into polylines.
+
+use math::{Rect2, Vec2};
+
+use std::f32::consts::PI;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathCommand {
+    MoveTo(Vec2),
+    LineTo(Vec2),
+    QuadTo(Vec2, Vec2),
+    CubicTo(Vec2, Vec2, Vec2),
+    /// Angles in radians, clockwise on screen when Y points down.
+    Arc {
+        centre: Vec2,
+        radius: Vec2,
+        start: f32,
+        end: f32,
+    },
+    Close,
+}
+
+
+/// A flattened subpath.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Contour {
+    pub points: Vec<Vec2>,
+    pub closed: bool,
+}
+
+
+/// A sequence of subpaths made of lines and curves. Each `move_to` starts a
+/// new subpath; drawing without one starts at the origin.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path {
+    commands: Vec<PathCommand>,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Path::default()
+    }
+
+    pub fn commands(&self) -> &[PathCommand] {
+        &self.commands
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn move_to(&mut self, to: Vec2) -> &mut Self {
+        self.commands.push(PathCommand::MoveTo(to));
+        self
+    }
+
+    pub fn line_to(&mut self, to: Vec2) -> &mut Self {
+        self.commands.push(PathCommand::LineTo(to));
+        self
+    }
+
+    /// A quadratic bezier curve.
+    pub fn quad_to(&mut self, control: Vec2, to: Vec2) -> &mut Self {
+        self.commands.push(PathCommand::QuadTo(control, to));
+        self
+    }
+
+    /// A cubic bezier curve.
+    pub fn cubic_to(&mut self, control1: Vec2, control2: Vec2, to: Vec2) -> &mut Self {
+        self.commands.push(PathCommand::CubicTo(control1, control2, to));
+        self
+    }
+
+    /// An elliptical arc from `start` to `end` radians, joined to the
+    /// current subpath by a straight line.
+    pub fn arc(&mut self, centre: Vec2, radius: Vec2, start: f32, end: f32) -> &mut Self {
+        self.commands.push(PathCommand::Arc {
+            centre,
+            radius,
+            start,
+            end,
+        });
+        self
+    }
+
+    /// Join the end of the current subpath back to its start.
+    pub fn close(&mut self) -> &mut Self {
+        self.commands.push(PathCommand::Close);
+        self
+    }
+
+    pub fn rect(&mut self, rect: Rect2) -> &mut Self {
+        self.move_to(Vec2::new(rect.x, rect.y))
+            .line_to(Vec2::new(rect.x + rect.w, rect.y))
+            .line_to(Vec2::new(rect.x + rect.w, rect.y + rect.h))
+            .line_to(Vec2::new(rect.x, rect.y + rect.h))
+            .close()
+    }
+
+    /// A rectangle with its corners rounded off. The radius is clamped to
+    /// half the shorter side.
+    pub fn rounded_rect(&mut self, rect: Rect2, radius: f32) -> &mut Self {
+        let radius = radius.min(rect.w.abs() * 0.5).min(rect.h.abs() * 0.5).max(0.0);
+        if radius == 0.0 {
+            return self.rect(rect);
+        }
+        let r = Vec2::new(radius, radius);
+        let (left, top) = (rect.x + radius, rect.y + radius);
+        let (right, bottom) = (rect.x + rect.w - radius, rect.y + rect.h - radius);
+        self.move_to(Vec2::new(left, rect.y))
+            .arc(Vec2::new(right, top), r, -PI * 0.5, 0.0)
+            .arc(Vec2::new(right, bottom), r, 0.0, PI * 0.5)
+            .arc(Vec2::new(left, bottom), r, PI * 0.5, PI)
+            .arc(Vec2::new(left, top), r, PI, PI * 1.5)
+            .close()
+    }
+
+    pub fn ellipse(&mut self, centre: Vec2, radius: Vec2) -> &mut Self {
+        self.move_to(centre + Vec2::new(radius.x, 0.0))
+            .arc(centre, radius, 0.0, 2.0 * PI)
+            .close()
+    }
+
+    pub fn circle(&mut self, centre: Vec2, radius: f32) -> &mut Self {
+        self.ellipse(centre, Vec2::new(radius, radius))
+    }
+
+    /// Turn curves into line segments, each within `tolerance` of the true
+    /// curve, and split the path into its subpaths. Consecutive duplicate
+    /// points are dropped.
+    pub fn flatten(&self, tolerance: f32) -> Vec<Contour> {
+        let tolerance = tolerance.max(1.0e-4);
+        let mut contours = Vec::new();
+        let mut current = Contour::default();
+        let mut pen = Vec2::zero();
+
+        fn push_point(contour: &mut Contour, point: Vec2) {
+            if contour.points.last() != Some(&point) {
+                contour.points.push(point);
+            }
+        }
+
+        fn finish(contours: &mut Vec<Contour>, contour: &mut Contour) {
+            let mut contour = ::std::mem::replace(contour, Contour::default());
+            if contour.closed && contour.points.len() > 1 && contour.points.first() == contour.points.last() {
+                contour.points.pop();
+            }
+            if !contour.points.is_empty() {
+                contours.push(contour);
+            }
+        }
+
+        for command in &self.commands {
+            match *command {
+                PathCommand::MoveTo(to) => {
+                    finish(&mut contours, &mut current);
+                    push_point(&mut current, to);
+                    pen = to;
+                }
+                PathCommand::LineTo(to) => {
+                    push_point(&mut current, pen);
+                    push_point(&mut current, to);
+                    pen = to;
+                }
+                PathCommand::QuadTo(control, to) => {
+                    push_point(&mut current, pen);
+                    // Uniform steps are within `d / (4 n^2)` of the curve.
+                    let d = (pen - control * 2.0 + to).length();
+                    let steps = ((d / (4.0 * tolerance)).sqrt().ceil() as usize).max(1);
+                    for i in 1..=steps {
+                        let t = i as f32 / steps as f32;
+                        let u = 1.0 - t;
+                        push_point(&mut current, pen * (u * u) + control * (2.0 * u * t) + to * (t * t));
+                    }
+                    pen = to;
+                }
+                PathCommand::CubicTo(control1, control2, to) => {
+                    push_point(&mut current, pen);
+                    // Uniform steps are within `3 d / (4 n^2)` of the curve.
+                    let d = (pen - control1 * 2.0 + control2).length()
+                                                             .max((control1 - control2 * 2.0 + to).length());
+                    let steps = ((0.75 * d / tolerance).sqrt().ceil() as usize).max(1);
+                    for i in 1..=steps {
+                        let t = i as f32 / steps as f32;
+                        let u = 1.0 - t;
+                        push_point(&mut current,
+                                   pen * (u * u * u) + control1 * (3.0 * u * u * t) + control2 * (3.0 * u * t * t) +
+                                       to * (t * t * t));
+                    }
+                    pen = to;
+                }
+                PathCommand::Arc { centre, radius, start, end } => {
+                    // Each step's chord stays within `tolerance` of a circle
+                    // of the larger radius.
+                    let r = radius.x.abs().max(radius.y.abs());
+                    let max_step = if r > tolerance { 2.0 * (1.0 - tolerance / r).acos() } else { PI };
+                    let steps = (((end - start).abs() / max_step).ceil() as usize).max(1);
+                    let point = |angle: f32| centre + Vec2::new(angle.cos() * radius.x, angle.sin() * radius.y);
+                    for i in 0..=steps {
+                        push_point(&mut current, point(start + (end - start) * i as f32 / steps as f32));
+                    }
+                    pen = point(end);
+                }
+                PathCommand::Close => {
+                    if let Some(&first) = current.points.first() {
+                        current.closed = true;
+                        finish(&mut contours, &mut current);
+                        push_point(&mut current, first);
+                        pen = first;
+                    }
+                }
+            }
+        }
+        finish(&mut contours, &mut current);
+
+        // A subpath left with nothing after a `close` is just its start
+        // point, which isn't worth keeping.
+        contours.retain(|contour| contour.closed || contour.points.len() > 1);
+        contours
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1.0e-4;
+
+    fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+        let ab = b - a;
+        let t = if ab.length_squared() > 0.0 { ((p - a).dot(ab) / ab.length_squared()).max(0.0).min(1.0) } else { 0.0 };
+        (p - (a + ab * t)).length()
+    }
+
+    // Check that `curve`, sampled finely over 0..1, never strays further
+    // than `tolerance` from the flattened polyline, and that the polyline
+    // runs from the curve's start to its end.
+    fn assert_within_tolerance<F: Fn(f32) -> Vec2>(path: &Path, tolerance: f32, curve: F) -> usize {
+        let contours = path.flatten(tolerance);
+        assert_eq!(contours.len(), 1);
+        let points = &contours[0].points;
+        assert!((points[0] - curve(0.0)).length() < EPSILON);
+        assert!((points[points.len() - 1] - curve(1.0)).length() < EPSILON);
+        for i in 0..=1000 {
+            let p = curve(i as f32 / 1000.0);
+            let distance = points.windows(2)
+                                 .map(|segment| distance_to_segment(p, segment[0], segment[1]))
+                                 .fold(::std::f32::MAX, f32::min);
+            assert!(distance <= tolerance + EPSILON, "{:?} is {} away", p, distance);
+        }
+        points.len()
+    }
+
+    #[test]
+    fn flatten_quads_within_tolerance() {
+        let (start, control, end) = (Vec2::new(0.0, 0.0), Vec2::new(50.0, 100.0), Vec2::new(100.0, 0.0));
+        let mut path = Path::new();
+        path.move_to(start).quad_to(control, end);
+        let curve = |t: f32| start * ((1.0 - t) * (1.0 - t)) + control * (2.0 * t * (1.0 - t)) + end * (t * t);
+
+        let coarse = assert_within_tolerance(&path, 1.0, curve);
+        let fine = assert_within_tolerance(&path, 0.01, curve);
+        assert!(fine > coarse);
+    }
+
+    #[test]
+    fn flatten_cubics_within_tolerance() {
+        let points = [Vec2::new(0.0, 0.0), Vec2::new(0.0, 100.0), Vec2::new(100.0, -50.0), Vec2::new(100.0, 50.0)];
+        let mut path = Path::new();
+        path.move_to(points[0]).cubic_to(points[1], points[2], points[3]);
+        let curve = |t: f32| {
+            let u = 1.0 - t;
+            points[0] * (u * u * u) + points[1] * (3.0 * u * u * t) + points[2] * (3.0 * u * t * t) + points[3] * (t * t * t)
+        };
+
+        let coarse = assert_within_tolerance(&path, 1.0, curve);
+        let fine = assert_within_tolerance(&path, 0.01, curve);
+        assert!(fine > coarse);
+    }
+
+    #[test]
+    fn flatten_arcs_within_tolerance() {
+        let (centre, radius) = (Vec2::new(10.0, 20.0), Vec2::new(40.0, 25.0));
+        let (start, end) = (-0.5f32, 4.0f32);
+        let mut path = Path::new();
+        path.move_to(centre + Vec2::new(start.cos() * radius.x, start.sin() * radius.y))
+            .arc(centre, radius, start, end);
+        let curve = |t: f32| {
+            let angle = start + (end - start) * t;
+            centre + Vec2::new(angle.cos() * radius.x, angle.sin() * radius.y)
+        };
+
+        assert_within_tolerance(&path, 0.5, curve);
+        assert_within_tolerance(&path, 0.01, curve);
+        // Every point lies on the ellipse.
+        for point in &path.flatten(0.5)[0].points {
+            let offset = *point - centre;
+            let on_unit_circle = Vec2::new(offset.x / radius.x, offset.y / radius.y).length();
+            assert!((on_unit_circle - 1.0).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn close_ends_the_contour_at_its_start() {
+        let (a, b, c, d) = (Vec2::new(0.0, 0.0), Vec2::new(10.0, 0.0), Vec2::new(10.0, 10.0), Vec2::new(-5.0, 5.0));
+        let mut path = Path::new();
+        path.move_to(a).line_to(b).line_to(c).line_to(a).close().line_to(d);
+        let contours = path.flatten(0.25);
+
+        // Closing drops the repeated start point, and drawing on carries on
+        // from the start in a new contour.
+        assert_eq!(contours, vec![
+            Contour { points: vec![a, b, c], closed: true },
+            Contour { points: vec![a, d], closed: false },
+        ]);
+
+        // Without a `move_to`, drawing starts at the origin.
+        let mut path = Path::new();
+        path.line_to(b).move_to(c).move_to(d).line_to(a);
+        assert_eq!(path.flatten(0.25), vec![
+            Contour { points: vec![Vec2::zero(), b], closed: false },
+            Contour { points: vec![d, a], closed: false },
+        ]);
+    }
+
+    #[test]
+    fn rect_is_four_closed_corners() {
+        let mut path = Path::new();
+        path.rect(Rect2::new(1.0, 2.0, 3.0, 4.0));
+        assert_eq!(path.flatten(0.25), vec![Contour {
+            points: vec![Vec2::new(1.0, 2.0), Vec2::new(4.0, 2.0), Vec2::new(4.0, 6.0), Vec2::new(1.0, 6.0)],
+            closed: true,
+        }]);
+    }
+
+    #[test]
+    fn rounded_rect_rounds_only_the_corners() {
+        let rect = Rect2::new(10.0, 20.0, 60.0, 30.0);
+        let radius = 8.0;
+        let mut path = Path::new();
+        path.rounded_rect(rect, radius);
+        let contours = path.flatten(0.1);
+        assert_eq!(contours.len(), 1);
+        assert!(contours[0].closed);
+        let points = &contours[0].points;
+
+        let (left, right) = (rect.x + radius, rect.x + rect.w - radius);
+        let (top, bottom) = (rect.y + radius, rect.y + rect.h - radius);
+        for point in points {
+            // Each point is on a straight edge, or on a corner's circle.
+            let nearest = Vec2::new(point.x.max(left).min(right), point.y.max(top).min(bottom));
+            let from_corner = (*point - nearest).length();
+            assert!((from_corner - radius).abs() < EPSILON, "{:?}", point);
+        }
+        // It fills out the whole rectangle, and goes clockwise on screen.
+        let min_x = points.iter().map(|p| p.x).fold(::std::f32::MAX, f32::min);
+        let max_x = points.iter().map(|p| p.x).fold(::std::f32::MIN, f32::max);
+        let min_y = points.iter().map(|p| p.y).fold(::std::f32::MAX, f32::min);
+        let max_y = points.iter().map(|p| p.y).fold(::std::f32::MIN, f32::max);
+        assert_eq!((min_x, max_x, min_y, max_y), (10.0, 70.0, 20.0, 50.0));
+        assert_eq!(points[0], Vec2::new(left, rect.y));
+        assert!(points[1].x > points[0].x);
+    }
+
+    #[test]
+    fn rounded_rect_clamps_the_radius() {
+        // Half the shorter side at most, making a stadium.
+        let mut clamped = Path::new();
+        clamped.rounded_rect(Rect2::new(0.0, 0.0, 20.0, 10.0), 100.0);
+        let mut exact = Path::new();
+        exact.rounded_rect(Rect2::new(0.0, 0.0, 20.0, 10.0), 5.0);
+        assert_eq!(clamped, exact);
+
+        let mut square = Path::new();
+        square.rounded_rect(Rect2::new(0.0, 0.0, 20.0, 10.0), -1.0);
+        let mut rect = Path::new();
+        rect.rect(Rect2::new(0.0, 0.0, 20.0, 10.0));
+        assert_eq!(square, rect);
+    }
+}
@@ -0,0 +1,673 @@
+// Turning flattened paths into anti-aliased triangles.
+//
+// Anti-aliasing is done with geometry rather than multisampling: every
+// edge gets a thin "fringe" strip, `aa_width` wide and centred on the true
+// edge, whose alpha fades from the shape's alpha to zero. With `aa_width`
+// at one pixel that gives smooth edges at any angle.
+
+use math::Vec2;
+
+use super::path::Contour;
+
+use std::f32::consts::PI;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct CanvasVertex {
+    pub position: [f32; 2],
+    pub colour: [f32; 4],
+}
+
+
+/// Indexed triangles, ready for upload.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Geometry {
+    pub vertices: Vec<CanvasVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Geometry {
+    pub fn new() -> Self {
+        Geometry::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn vertex(&mut self, position: Vec2, colour: [f32; 4]) -> u32 {
+        self.vertices.push(CanvasVertex {
+            position: position.into(),
+            colour,
+        });
+        self.vertices.len() as u32 - 1
+    }
+
+    fn quad(&mut self, a: u32, b: u32, c: u32, d: u32) {
+        self.indices.extend_from_slice(&[a, b, c, a, c, d]);
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /// Miter joins longer than this many half-widths become bevels.
+    pub miter_limit: f32,
+}
+
+impl StrokeStyle {
+    pub fn new(width: f32) -> Self {
+        StrokeStyle {
+            width,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+        }
+    }
+
+    pub fn with_join(self, join: LineJoin) -> Self {
+        StrokeStyle { join, ..self }
+    }
+
+    pub fn with_cap(self, cap: LineCap) -> Self {
+        StrokeStyle { cap, ..self }
+    }
+
+    pub fn with_miter_limit(self, miter_limit: f32) -> Self {
+        StrokeStyle { miter_limit, ..self }
+    }
+}
+
+
+fn with_alpha(colour: [f32; 4], alpha: f32) -> [f32; 4] {
+    [colour[0], colour[1], colour[2], alpha]
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+fn signed_area(points: &[Vec2]) -> f32 {
+    let mut area = 0.0;
+    for (i, &a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        area += cross(a, b);
+    }
+    area * 0.5
+}
+
+// The contour's points without near-duplicates, including between its last
+// and first points if it's closed.
+fn clean_points(contour: &Contour) -> Vec<Vec2> {
+    const EPSILON: f32 = 1.0e-5;
+    let mut points: Vec<Vec2> = Vec::with_capacity(contour.points.len());
+    for &point in &contour.points {
+        if points.last().map_or(true, |&last| (point - last).length_squared() > EPSILON * EPSILON) {
+            points.push(point);
+        }
+    }
+    if contour.closed {
+        while points.len() > 1 && (points[0] - points[points.len() - 1]).length_squared() <= EPSILON * EPSILON {
+            points.pop();
+        }
+    }
+    points
+}
+
+
+/// Fill each closed contour as its own simple polygon; open contours are
+/// closed implicitly. Contours don't cut holes in each other, and
+/// self-intersecting contours are filled, but not necessarily correctly.
+pub fn fill(contours: &[Contour], colour: [f32; 4], aa_width: f32, out: &mut Geometry) {
+    for contour in contours {
+        let points = clean_points(contour);
+        if points.len() < 3 {
+            continue;
+        }
+        let area = signed_area(&points);
+        if area == 0.0 {
+            continue;
+        }
+
+        if aa_width <= 0.0 {
+            let base = out.vertices.len() as u32;
+            for &point in &points {
+                out.vertex(point, colour);
+            }
+            triangulate(&points, base, &mut out.indices);
+            continue;
+        }
+
+        // Outward normals of each edge, then averaged at each vertex into
+        // the direction that moves both adjacent edges out by one unit.
+        let count = points.len();
+        let outward = if area > 0.0 { -1.0 } else { 1.0 };
+        let edge_normals: Vec<Vec2> = (0..count).map(|i| {
+                                                    let edge = (points[(i + 1) % count] - points[i]).normalize();
+                                                    edge.perpendicular() * outward
+                                                })
+                                                .collect();
+        let half = aa_width * 0.5;
+        let mut inner = Vec::with_capacity(count);
+        let base = out.vertices.len() as u32;
+        for i in 0..count {
+            let normal = miter(edge_normals[(i + count - 1) % count], edge_normals[i]);
+            let point = points[i] - normal * half;
+            inner.push(point);
+            out.vertex(point, colour);
+            out.vertex(points[i] + normal * half, with_alpha(colour, 0.0));
+        }
+
+        // Interior vertices are the even ones.
+        let mut interior = Vec::new();
+        triangulate(&inner, 0, &mut interior);
+        out.indices.extend(interior.into_iter().map(|i| base + i * 2));
+
+        for i in 0..count as u32 {
+            let j = (i + 1) % count as u32;
+            out.quad(base + i * 2, base + i * 2 + 1, base + j * 2 + 1, base + j * 2);
+        }
+    }
+}
+
+// The offset direction for a corner between edges with unit normals `a`
+// and `b`, scaled so that offsetting by it moves both edges by one unit.
+// Very sharp corners are limited, as they'd otherwise shoot off far away.
+fn miter(a: Vec2, b: Vec2) -> Vec2 {
+    let average = (a + b) * 0.5;
+    let length_squared = average.length_squared();
+    if length_squared < 1.0e-6 {
+        return a;
+    }
+    average * (1.0 / length_squared).min(16.0)
+}
+
+// Ear clipping. Pushes indices (offset by `base`) for triangles covering a
+// simple polygon, in either winding.
+fn triangulate(points: &[Vec2], base: u32, indices: &mut Vec<u32>) {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    // Work counter-clockwise (positive area), so ears are convex corners.
+    if signed_area(points) < 0.0 {
+        remaining.reverse();
+    }
+
+    let is_ear = |remaining: &[usize], i: usize| {
+        let len = remaining.len();
+        let (a, b, c) = (remaining[(i + len - 1) % len], remaining[i], remaining[(i + 1) % len]);
+        let (pa, pb, pc) = (points[a], points[b], points[c]);
+        if cross(pb - pa, pc - pb) <= 0.0 {
+            return false;
+        }
+        remaining.iter().all(|&other| {
+            if other == a || other == b || other == c {
+                return true;
+            }
+            let p = points[other];
+            // Outside (or on the edge of) the triangle.
+            cross(pb - pa, p - pa) <= 0.0 || cross(pc - pb, p - pb) <= 0.0 || cross(pa - pc, p - pc) <= 0.0
+        })
+    };
+
+    let mut i = 0;
+    let mut misses = 0;
+    while remaining.len() > 3 {
+        let len = remaining.len();
+        i %= len;
+        // If nothing is an ear (the polygon isn't simple, or rounding got
+        // in the way), clip something anyway so we always finish.
+        if is_ear(&remaining, i) || misses >= len {
+            let (a, b, c) = (remaining[(i + len - 1) % len], remaining[i], remaining[(i + 1) % len]);
+            indices.extend_from_slice(&[base + a as u32, base + b as u32, base + c as u32]);
+            remaining.remove(i);
+            misses = 0;
+        } else {
+            i += 1;
+            misses += 1;
+        }
+    }
+    if remaining.len() == 3 {
+        indices.extend(remaining.iter().map(|&index| base + index as u32));
+    }
+}
+
+
+// A cross-section of a stroke: a line through `centre` along `normal`,
+// with solid colour out to `core` either side and fading to nothing at
+// `outer`. Consecutive cross-sections are joined with quads.
+#[derive(Clone, Copy, Debug)]
+struct Section {
+    centre: Vec2,
+    normal: Vec2,
+    core: f32,
+    outer: f32,
+    alpha: f32,
+}
+
+/// Stroke each contour. Overlapping parts of a stroke (at joins, or where
+/// it crosses itself) are drawn more than once, which shows if the colour
+/// is translucent.
+pub fn stroke(contours: &[Contour], style: &StrokeStyle, colour: [f32; 4], aa_width: f32, out: &mut Geometry) {
+    let half_width = style.width.max(0.0) * 0.5;
+    // Strokes thinner than the fringe fade out instead of getting thinner.
+    let (core, alpha) = if style.width >= aa_width {
+        (half_width - aa_width * 0.5, colour[3])
+    } else {
+        (0.0, colour[3] * style.width / aa_width.max(1.0e-6))
+    };
+    let outer = core + aa_width.max(0.0);
+    // Segments in each round join or cap, enough to keep each one short.
+    let round_steps = |angle: f32| ((angle * half_width.max(1.0).sqrt()).ceil() as usize).max(1);
+
+    for contour in contours {
+        let points = clean_points(contour);
+        if points.len() < 2 {
+            continue;
+        }
+        let closed = contour.closed && points.len() > 2;
+        let count = points.len();
+        let segment_count = if closed { count } else { count - 1 };
+        let directions: Vec<Vec2> = (0..segment_count).map(|i| (points[(i + 1) % count] - points[i]).normalize())
+                                                      .collect();
+
+        let section = |centre: Vec2, normal: Vec2| {
+            Section {
+                centre,
+                normal,
+                core,
+                outer,
+                alpha,
+            }
+        };
+
+        let mut sections = Vec::new();
+        if !closed {
+            let direction = directions[0];
+            cap(points[0], -direction, direction.perpendicular(), style.cap, half_width, aa_width, alpha, &round_steps, &mut sections);
+            sections.reverse();
+            sections.push(section(points[0], direction.perpendicular()));
+        }
+
+        let (first_join, last_join) = if closed { (0, count) } else { (1, count - 1) };
+        for i in first_join..last_join {
+            let incoming = directions[(i + segment_count - 1) % segment_count];
+            let outgoing = directions[i % segment_count];
+            let (n0, n1) = (incoming.perpendicular(), outgoing.perpendicular());
+            let p = points[i];
+
+            // Nearly straight on: no join needed.
+            if incoming.dot(outgoing) > 0.9999 {
+                sections.push(section(p, n0));
+                continue;
+            }
+
+            let turn = n0.dot(n1).max(-1.0).min(1.0).acos();
+            let bisector = (n0 + n1).normalize();
+            let cos_half = bisector.dot(n0);
+            let miter_fits = cos_half > 1.0e-3 && 1.0 / cos_half <= style.miter_limit;
+            match style.join {
+                LineJoin::Miter if miter_fits => sections.push(section(p, bisector * (1.0 / cos_half))),
+                LineJoin::Round => {
+                    // Sweep the normal round the outside of the corner.
+                    let steps = round_steps(turn);
+                    let sign = if cross(incoming, outgoing) > 0.0 { 1.0 } else { -1.0 };
+                    for step in 0..=steps {
+                        let angle = turn * step as f32 / steps as f32 * sign;
+                        let (sin, cos) = angle.sin_cos();
+                        sections.push(section(p, Vec2::new(n0.x * cos - n0.y * sin, n0.x * sin + n0.y * cos)));
+                    }
+                }
+                _ => {
+                    sections.push(section(p, n0));
+                    sections.push(section(p, n1));
+                }
+            }
+        }
+
+        if !closed {
+            let direction = directions[segment_count - 1];
+            let end = points[count - 1];
+            sections.push(section(end, direction.perpendicular()));
+            cap(end, direction, direction.perpendicular(), style.cap, half_width, aa_width, alpha, &round_steps, &mut sections);
+        }
+
+        emit_ribbon(&sections, closed, colour, out);
+    }
+}
+
+// Push the cross-sections for a cap at `end`, facing `outward`, from the
+// end outwards, finishing with the invisible tip.
+fn cap<F>(end: Vec2,
+          outward: Vec2,
+          normal: Vec2,
+          cap: LineCap,
+          half_width: f32,
+          aa_width: f32,
+          alpha: f32,
+          round_steps: &F,
+          sections: &mut Vec<Section>)
+    where F: Fn(f32) -> usize
+{
+    let fade = aa_width * 0.5;
+    let section = |distance: f32, half: f32, alpha: f32| {
+        Section {
+            centre: end + outward * distance,
+            normal,
+            core: (half - fade).max(0.0),
+            outer: half + fade,
+            alpha,
+        }
+    };
+    match cap {
+        LineCap::Butt => sections.push(section(fade, half_width, 0.0)),
+        LineCap::Square => {
+            sections.push(section(half_width, half_width, alpha));
+            sections.push(section(half_width + fade, half_width, 0.0));
+        }
+        LineCap::Round => {
+            // Cross-sections of a semicircle, narrowing towards the tip.
+            let steps = round_steps(PI * 0.5);
+            for step in 1..=steps {
+                let angle = step as f32 / steps as f32 * PI * 0.5;
+                let (sin, cos) = angle.sin_cos();
+                sections.push(section(half_width * sin, half_width * cos, alpha));
+            }
+            sections.push(section(half_width + fade, 0.0, 0.0));
+        }
+    }
+}
+
+fn emit_ribbon(sections: &[Section], closed: bool, colour: [f32; 4], out: &mut Geometry) {
+    let base = out.vertices.len() as u32;
+    for section in sections {
+        let solid = with_alpha(colour, section.alpha);
+        let clear = with_alpha(colour, 0.0);
+        out.vertex(section.centre - section.normal * section.outer, clear);
+        out.vertex(section.centre - section.normal * section.core, solid);
+        out.vertex(section.centre + section.normal * section.core, solid);
+        out.vertex(section.centre + section.normal * section.outer, clear);
+    }
+
+    let count = sections.len() as u32;
+    let joins = if closed { count } else { count.saturating_sub(1) };
+    for i in 0..joins {
+        let a = base + i * 4;
+        let b = base + (i + 1) % count * 4;
+        for k in 0..3 {
+            out.quad(a + k, a + k + 1, b + k + 1, b + k);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1.0e-4;
+    const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+    fn polygon(points: &[(f32, f32)]) -> Vec<Vec2> {
+        points.iter().map(|&(x, y)| Vec2::new(x, y)).collect()
+    }
+
+    fn closed(points: Vec<Vec2>) -> Contour {
+        Contour { points, closed: true }
+    }
+
+    fn open(points: Vec<Vec2>) -> Contour {
+        Contour { points, closed: false }
+    }
+
+    fn position(vertex: &CanvasVertex) -> Vec2 {
+        Vec2::new(vertex.position[0], vertex.position[1])
+    }
+
+    fn triangle_area(points: &[Vec2], triangle: &[u32]) -> f32 {
+        signed_area(&[points[triangle[0] as usize], points[triangle[1] as usize], points[triangle[2] as usize]])
+    }
+
+    fn hexagon() -> Vec<Vec2> {
+        (0..6).map(|i| {
+                  let angle = i as f32 * PI / 3.0;
+                  Vec2::new(angle.cos() * 3.0, angle.sin() * 3.0)
+              })
+              .collect()
+    }
+
+    // An arrow pointing right, with a notch in its tail: concave at the
+    // two shoulders and in the notch.
+    fn arrow() -> Vec<Vec2> {
+        polygon(&[(0.0, 1.0), (4.0, 1.0), (4.0, 0.0), (6.0, 2.0), (4.0, 4.0), (4.0, 3.0), (0.0, 3.0), (1.0, 2.0)])
+    }
+
+    #[test]
+    fn triangulate_covers_convex_and_concave_polygons() {
+        for points in vec![hexagon(), arrow()] {
+            for &reversed in &[false, true] {
+                let mut points = points.clone();
+                if reversed {
+                    points.reverse();
+                }
+                let mut indices = Vec::new();
+                triangulate(&points, 0, &mut indices);
+
+                assert_eq!(indices.len(), (points.len() - 2) * 3);
+                // No triangle is clockwise, so none cancel out, and between
+                // them they cover exactly the polygon's area. (Clipping can
+                // leave collinear points, so some may have no area at all.)
+                let mut total = 0.0;
+                for triangle in indices.chunks(3) {
+                    let area = triangle_area(&points, triangle);
+                    assert!(area >= 0.0, "{:?} in {:?}", triangle, points);
+                    total += area;
+                }
+                assert!((total - signed_area(&points).abs()).abs() < EPSILON, "{} in {:?}", total, points);
+            }
+        }
+    }
+
+    #[test]
+    fn triangulate_offsets_indices_by_base() {
+        let mut indices = vec![7];
+        triangulate(&hexagon(), 10, &mut indices);
+        assert_eq!(indices[0], 7);
+        assert!(indices[1..].iter().all(|&index| index >= 10 && index < 16));
+    }
+
+    #[test]
+    fn fill_without_fringe_is_solid() {
+        let mut out = Geometry::new();
+        fill(&[closed(arrow())], [1.0, 0.0, 0.0, 0.5], 0.0, &mut out);
+        assert_eq!(out.vertices.len(), 8);
+        assert_eq!(out.indices.len(), 6 * 3);
+        assert!(out.vertices.iter().all(|vertex| vertex.colour == [1.0, 0.0, 0.0, 0.5]));
+    }
+
+    #[test]
+    fn fill_fringe_fades_across_the_edge() {
+        let square = polygon(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        for &reversed in &[false, true] {
+            let mut square = square.clone();
+            if reversed {
+                square.reverse();
+            }
+            let mut out = Geometry::new();
+            fill(&[open(square.clone())], [0.2, 0.4, 0.6, 0.8], 1.0, &mut out);
+
+            // Each corner gets an inner vertex at the shape's alpha and an
+            // outer one at zero, half the fringe width either side of the
+            // true edge in both directions.
+            assert_eq!(out.vertices.len(), 8);
+            for (corner, pair) in square.iter().zip(out.vertices.chunks(2)) {
+                let inward = (Vec2::new(5.0, 5.0) - *corner).normalize() * 0.5f32.sqrt();
+                assert!((position(&pair[0]) - (*corner + inward)).length() < EPSILON, "{:?}", pair);
+                assert!((position(&pair[1]) - (*corner - inward)).length() < EPSILON, "{:?}", pair);
+                assert_eq!(pair[0].colour, [0.2, 0.4, 0.6, 0.8]);
+                assert_eq!(pair[1].colour, [0.2, 0.4, 0.6, 0.0]);
+            }
+
+            // Two interior triangles plus a quad per edge.
+            assert_eq!(out.indices.len(), 2 * 3 + 4 * 6);
+            let points: Vec<Vec2> = out.vertices.iter().map(position).collect();
+            let interior: f32 = out.indices[..6].chunks(3).map(|triangle| triangle_area(&points, triangle).abs()).sum();
+            assert!((interior - 81.0).abs() < EPSILON);
+            let total: f32 = out.indices.chunks(3).map(|triangle| triangle_area(&points, triangle).abs()).sum();
+            assert!((total - 121.0).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn fill_skips_degenerate_contours() {
+        let mut out = Geometry::new();
+        fill(&[closed(polygon(&[(0.0, 0.0), (1.0, 1.0)])),
+               closed(polygon(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)])),
+               closed(polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 0.0), (0.0, 0.0)]))],
+             WHITE,
+             1.0,
+             &mut out);
+        assert!(out.is_empty());
+    }
+
+    // An L-shaped polyline with a right-angle turn at (10, 0).
+    fn corner() -> Contour {
+        open(polygon(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]))
+    }
+
+    fn stroked(contour: Contour, style: StrokeStyle) -> Geometry {
+        let mut out = Geometry::new();
+        stroke(&[contour], &style, WHITE, 1.0, &mut out);
+        out
+    }
+
+    // Vertices at the corner, the solid and the faded ones separately.
+    fn corner_vertices(out: &Geometry) -> (Vec<Vec2>, Vec<Vec2>) {
+        let at_corner = |vertex: &&CanvasVertex| (position(vertex) - Vec2::new(10.0, 0.0)).length() < 5.0;
+        let solid = out.vertices.iter().filter(at_corner).filter(|vertex| vertex.colour[3] > 0.0).map(position).collect();
+        let clear = out.vertices.iter().filter(at_corner).filter(|vertex| vertex.colour[3] == 0.0).map(position).collect();
+        (solid, clear)
+    }
+
+    // Ribbon geometry: four vertices per cross-section, three quads between
+    // each pair of them.
+    fn assert_sections(out: &Geometry, sections: usize) {
+        assert_eq!(out.vertices.len(), sections * 4);
+        assert_eq!(out.indices.len(), (sections - 1) * 3 * 6);
+    }
+
+    #[test]
+    fn stroke_miter_join_meets_at_a_point() {
+        let out = stroked(corner(), StrokeStyle::new(4.0));
+        // Butt cap, start, join, end, butt cap.
+        assert_sections(&out, 5);
+        // The solid core is 1.5 either side; the outside of the corner is
+        // mitred out to (8.5, 1.5) and (11.5, -1.5).
+        let (solid, clear) = corner_vertices(&out);
+        assert!(solid.iter().any(|&p| (p - Vec2::new(11.5, -1.5)).length() < EPSILON), "{:?}", solid);
+        assert!(solid.iter().any(|&p| (p - Vec2::new(8.5, 1.5)).length() < EPSILON), "{:?}", solid);
+        assert!(clear.iter().any(|&p| (p - Vec2::new(12.5, -2.5)).length() < EPSILON), "{:?}", clear);
+    }
+
+    #[test]
+    fn stroke_bevel_join_cuts_the_corner() {
+        let out = stroked(corner(), StrokeStyle::new(4.0).with_join(LineJoin::Bevel));
+        assert_sections(&out, 6);
+        let (solid, _) = corner_vertices(&out);
+        // Nothing solid reaches past either edge of the stroke.
+        assert!(solid.iter().all(|p| p.x <= 11.5 + EPSILON && p.y >= -1.5 - EPSILON), "{:?}", solid);
+        assert!(solid.iter().any(|&p| (p - Vec2::new(10.0, -1.5)).length() < EPSILON));
+        assert!(solid.iter().any(|&p| (p - Vec2::new(11.5, 0.0)).length() < EPSILON));
+    }
+
+    #[test]
+    fn stroke_round_join_stays_on_the_circle() {
+        let out = stroked(corner(), StrokeStyle::new(4.0).with_join(LineJoin::Round));
+        // A quarter turn with a half width of 2 takes three steps.
+        assert_sections(&out, 8);
+        let (solid, clear) = corner_vertices(&out);
+        let centre = Vec2::new(10.0, 0.0);
+        assert!(solid.iter().all(|&p| ((p - centre).length() - 1.5).abs() < EPSILON), "{:?}", solid);
+        assert!(clear.iter().all(|&p| ((p - centre).length() - 2.5).abs() < EPSILON), "{:?}", clear);
+    }
+
+    #[test]
+    fn stroke_miter_falls_back_to_bevel_past_the_limit() {
+        // A right angle's miter is sqrt(2) half-widths long.
+        let limited = StrokeStyle::new(4.0).with_miter_limit(1.4);
+        assert_eq!(stroked(corner(), limited), stroked(corner(), limited.with_join(LineJoin::Bevel)));
+        let allowed = StrokeStyle::new(4.0).with_miter_limit(1.5);
+        assert_sections(&stroked(corner(), allowed), 5);
+
+        // A near-reversal would need a miter many times the width.
+        let hairpin = open(polygon(&[(0.0, 0.0), (10.0, 0.0), (0.0, 0.5)]));
+        assert_eq!(stroked(hairpin.clone(), StrokeStyle::new(4.0)),
+                   stroked(hairpin, StrokeStyle::new(4.0).with_join(LineJoin::Bevel)));
+    }
+
+    #[test]
+    fn stroke_caps_extend_past_the_ends() {
+        let line = || open(polygon(&[(0.0, 0.0), (10.0, 0.0)]));
+        // (sections, furthest vertex past the end, furthest solid vertex)
+        let cases = [
+            (LineCap::Butt, 4, 0.5, 0.0),
+            (LineCap::Square, 6, 2.5, 2.0),
+            (LineCap::Round, 2 + 2 * 4, 2.5, 2.0),
+        ];
+        for &(cap, sections, reach, solid_reach) in &cases {
+            let out = stroked(line(), StrokeStyle::new(4.0).with_cap(cap));
+            assert_sections(&out, sections);
+            let max_x = out.vertices.iter().map(|vertex| vertex.position[0]).fold(0.0, f32::max);
+            let min_x = out.vertices.iter().map(|vertex| vertex.position[0]).fold(0.0, f32::min);
+            assert!((max_x - (10.0 + reach)).abs() < EPSILON, "{:?}: {}", cap, max_x);
+            assert!((min_x + reach).abs() < EPSILON, "{:?}: {}", cap, min_x);
+            let solid_max_x = out.vertices
+                                 .iter()
+                                 .filter(|vertex| vertex.colour[3] > 0.0)
+                                 .map(|vertex| vertex.position[0])
+                                 .fold(0.0, f32::max);
+            assert!((solid_max_x - (10.0 + solid_reach)).abs() < EPSILON, "{:?}: {}", cap, solid_max_x);
+            // Nothing wider than the stroke plus its fringe.
+            assert!(out.vertices.iter().all(|vertex| vertex.position[1].abs() <= 2.5 + EPSILON));
+        }
+    }
+
+    #[test]
+    fn stroke_closed_contours_wrap_around() {
+        let square = closed(polygon(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]));
+        let out = stroked(square, StrokeStyle::new(2.0));
+        // One mitred section per corner, and a quad strip back to the start.
+        assert_eq!(out.vertices.len(), 4 * 4);
+        assert_eq!(out.indices.len(), 4 * 3 * 6);
+    }
+
+    #[test]
+    fn thin_strokes_fade_instead_of_narrowing() {
+        let out = stroked(open(polygon(&[(0.0, 0.0), (10.0, 0.0)])), StrokeStyle::new(0.25));
+        let alphas: Vec<f32> = out.vertices.iter().map(|vertex| vertex.colour[3]).filter(|&alpha| alpha > 0.0).collect();
+        assert!(!alphas.is_empty());
+        assert!(alphas.iter().all(|&alpha| (alpha - 0.25).abs() < EPSILON), "{:?}", alphas);
+        assert!(out.vertices.iter().all(|vertex| vertex.position[1].abs() <= 1.0 + EPSILON));
+    }
+}
@@ -0,0 +1,390 @@
+// Immediate-mode debug drawing.
+//
+// Shapes are queued as coloured line segments over the course of a frame,
+// then `flush` uploads them all into one vertex buffer, records the draw
+// calls and clears the queue ready for the next frame. Nothing is retained
+// between frames, so anything that should stay visible has to be drawn
+// again every frame.
+//
+// Lines are depth tested by default, if the render pass has a depth
+// attachment; `set_depth_test(false)` makes the following shapes draw on
+// top of everything instead.
+
+use dynamic_buffer::DynamicBuffer;
+use gfx_hal::Backend;
+use math::{Mat4, Vec2, Vec3};
+use prelude::*;
+use sprite::{SpriteBatch, TextureId};
+use text::{FontAtlas, TextStyle};
+
+use std::f32::consts::PI;
+
+#[cfg(windows)]
+static VERT_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\debug_draw.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\debug_draw.frag.spv");
+
+#[cfg(all(unix))]
+static VERT_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/debug_draw.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/debug_draw.frag.spv");
+
+/// Line segments used for each circle.
+const CIRCLE_SEGMENTS: usize = 32;
+
+pub const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub colour: [f32; 4],
+}
+
+
+struct Label {
+    position: Vec3,
+    text: String,
+    colour: [f32; 4],
+}
+
+
+pub struct DebugDraw<B: Backend> {
+    pipeline_layout: B::PipelineLayout,
+    // `None` if the render pass has no depth attachment.
+    depth_pipeline: Option<B::GraphicsPipeline>,
+    overlay_pipeline: B::GraphicsPipeline,
+    vertex_shader_module: B::ShaderModule,
+    fragment_shader_module: B::ShaderModule,
+    vertices: DynamicBuffer<B, DebugVertex>,
+    depth_tested: Vec<DebugVertex>,
+    overlay: Vec<DebugVertex>,
+    labels: Vec<Label>,
+    depth_test: bool,
+}
+
+impl<B: Backend> DebugDraw<B> {
+    /// Create a debug drawer for the first subpass of `render_pass`. Pass
+    /// `has_depth` if that subpass has a depth attachment, to allow depth
+    /// testing.
+    pub fn new(device: &B::Device,
+               memory_types: &[MemoryType],
+               render_pass: &B::RenderPass,
+               has_depth: bool) -> Self {
+        // The only input is the view-projection matrix, which is small
+        // enough to be a push constant.
+        let pipeline_layout = device.create_pipeline_layout(
+            Vec::<&B::DescriptorSetLayout>::new(),
+            &[(ShaderStageFlags::VERTEX, 0..16)],
+        );
+
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+        let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
+
+        let (depth_pipeline, overlay_pipeline) = {
+            let create_pipeline = |depth: DepthTest| {
+                let vs_entry = EntryPoint::<B> {
+                    entry: "main",
+                    module: &vertex_shader_module,
+                    specialization: Default::default(),
+                };
+
+                let fs_entry = EntryPoint::<B> {
+                    entry: "main",
+                    module: &fragment_shader_module,
+                    specialization: Default::default(),
+                };
+
+                let shader_entries = GraphicsShaderSet {
+                    vertex: vs_entry,
+                    hull: None,
+                    domain: None,
+                    geometry: None,
+                    fragment: Some(fs_entry),
+                };
+
+                let subpass = Subpass {
+                    index: 0,
+                    main_pass: render_pass,
+                };
+
+                let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                                  Primitive::LineList,
+                                                                  Rasterizer::FILL,
+                                                                  &pipeline_layout,
+                                                                  subpass);
+
+                pipeline_desc.blender
+                             .targets
+                             .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+                pipeline_desc.depth_stencil = DepthStencilDesc {
+                    depth,
+                    depth_bounds: false,
+                    stencil: StencilTest::Off,
+                };
+
+                pipeline_desc.vertex_buffers.push(VertexBufferDesc {
+                    binding: 0,
+                    stride: ::std::mem::size_of::<DebugVertex>() as u32,
+                    rate: 0,
+                });
+                pipeline_desc.attributes.push(AttributeDesc {
+                    location: 0,
+                    binding: 0,
+                    element: Element {
+                        format: Format::Rgb32Float,
+                        offset: 0,
+                    },
+                });
+                pipeline_desc.attributes.push(AttributeDesc {
+                    location: 1,
+                    binding: 0,
+                    element: Element {
+                        format: Format::Rgba32Float,
+                        offset: 12,
+                    },
+                });
+
+                device.create_graphics_pipeline(&pipeline_desc, None)
+                      .unwrap()
+            };
+
+            // Test against the scene, but don't write, so overlapping debug
+            // lines don't hide each other.
+            let depth_pipeline = if has_depth {
+                Some(create_pipeline(DepthTest::On {
+                    fun: Comparison::LessEqual,
+                    write: false,
+                }))
+            } else {
+                None
+            };
+            (depth_pipeline, create_pipeline(DepthTest::Off))
+        };
+
+        DebugDraw {
+            pipeline_layout,
+            depth_pipeline,
+            overlay_pipeline,
+            vertex_shader_module,
+            fragment_shader_module,
+            vertices: DynamicBuffer::new(device, memory_types, buffer::Usage::VERTEX, 1024),
+            depth_tested: Vec::new(),
+            overlay: Vec::new(),
+            labels: Vec::new(),
+            depth_test: true,
+        }
+    }
+
+    /// Whether shapes drawn from now on are hidden behind the scene. Has no
+    /// effect without a depth attachment. Resets to `true` after a flush.
+    pub fn set_depth_test(&mut self, depth_test: bool) {
+        self.depth_test = depth_test;
+    }
+
+    pub fn line(&mut self, from: Vec3, to: Vec3, colour: [f32; 4]) {
+        let vertices = if self.depth_test && self.depth_pipeline.is_some() {
+            &mut self.depth_tested
+        } else {
+            &mut self.overlay
+        };
+        vertices.push(DebugVertex {
+            position: from.into(),
+            colour,
+        });
+        vertices.push(DebugVertex {
+            position: to.into(),
+            colour,
+        });
+    }
+
+    /// A line with a four-pronged head at `to`.
+    pub fn arrow(&mut self, from: Vec3, to: Vec3, colour: [f32; 4]) {
+        self.line(from, to, colour);
+
+        let length = (to - from).length();
+        if length == 0.0 {
+            return;
+        }
+        let direction = (to - from) / length;
+        let (side, up) = perpendiculars(direction);
+        let head = length.min(1.0) * 0.2;
+        let base = to - direction * head;
+        for &offset in &[side, -side, up, -up] {
+            self.line(to, base + offset * (head * 0.5), colour);
+        }
+    }
+
+    /// An axis-aligned box.
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, colour: [f32; 4]) {
+        let corner = |i: usize| {
+            Vec3::new(if i & 1 == 0 { min.x } else { max.x },
+                      if i & 2 == 0 { min.y } else { max.y },
+                      if i & 4 == 0 { min.z } else { max.z })
+        };
+        // Each edge joins two corners that differ in one bit.
+        for i in 0..8 {
+            for &bit in &[1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), colour);
+                }
+            }
+        }
+    }
+
+    /// A circle facing along `normal`. Nothing is drawn if `normal` is zero,
+    /// as there's no plane to draw it in.
+    pub fn circle(&mut self, centre: Vec3, normal: Vec3, radius: f32, colour: [f32; 4]) {
+        let normal = normal.normalize();
+        if normal == Vec3::zero() {
+            return;
+        }
+        let (u, v) = perpendiculars(normal);
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+            centre + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), colour);
+        }
+    }
+
+    /// A sphere, drawn as a circle around each axis.
+    pub fn sphere(&mut self, centre: Vec3, radius: f32, colour: [f32; 4]) {
+        self.circle(centre, Vec3::unit_x(), radius, colour);
+        self.circle(centre, Vec3::unit_y(), radius, colour);
+        self.circle(centre, Vec3::unit_z(), radius, colour);
+    }
+
+    /// A square grid on the XZ plane, `size` across and split into
+    /// `divisions` cells each way.
+    pub fn grid(&mut self, centre: Vec3, size: f32, divisions: u32, colour: [f32; 4]) {
+        let half = size * 0.5;
+        let divisions = divisions.max(1);
+        for i in 0..=divisions {
+            let offset = i as f32 / divisions as f32 * size - half;
+            self.line(centre + Vec3::new(offset, 0.0, -half),
+                      centre + Vec3::new(offset, 0.0, half),
+                      colour);
+            self.line(centre + Vec3::new(-half, 0.0, offset),
+                      centre + Vec3::new(half, 0.0, offset),
+                      colour);
+        }
+    }
+
+    /// The X, Y and Z axes of `transform` as red, green and blue arrows,
+    /// `size` long before the transform's scale.
+    pub fn axes(&mut self, transform: &Mat4, size: f32) {
+        let origin = transform.transform_point(Vec3::zero());
+        self.arrow(origin, transform.transform_point(Vec3::unit_x() * size), RED);
+        self.arrow(origin, transform.transform_point(Vec3::unit_y() * size), GREEN);
+        self.arrow(origin, transform.transform_point(Vec3::unit_z() * size), BLUE);
+    }
+
+    /// Text at a point in the world. Labels aren't drawn by `flush`, but
+    /// are cleared by it: call `draw_labels` first to show them.
+    pub fn label<S: Into<String>>(&mut self, position: Vec3, text: S, colour: [f32; 4]) {
+        self.labels.push(Label {
+            position,
+            text: text.into(),
+            colour,
+        });
+    }
+
+    /// Number of line segments queued since the last flush.
+    pub fn len(&self) -> usize {
+        (self.depth_tested.len() + self.overlay.len()) / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.depth_tested.is_empty() && self.overlay.is_empty()
+    }
+
+    /// Upload every queued line and record the draw calls, then clear the
+    /// queue, along with any labels `draw_labels` hasn't drawn.
+    ///
+    /// This rewrites a buffer the GPU reads from, so flush at most once per
+    /// frame, after the previous frame using this drawer has finished.
+    pub fn flush(&mut self,
+                 device: &B::Device,
+                 memory_types: &[MemoryType],
+                 encoder: &mut RenderPassInlineEncoder<B>,
+                 view_projection: Mat4) {
+        self.depth_test = true;
+        self.labels.clear();
+        if self.is_empty() {
+            return;
+        }
+
+        let depth_count = self.depth_tested.len() as u32;
+        let overlay_count = self.overlay.len() as u32;
+        self.depth_tested.append(&mut self.overlay);
+        self.vertices.write(device, memory_types, &self.depth_tested);
+        self.depth_tested.clear();
+
+        let matrix: [[f32; 4]; 4] = view_projection.into();
+        let push_constants = unsafe { ::std::slice::from_raw_parts(matrix.as_ptr() as *const u32, 16) };
+
+        encoder.bind_vertex_buffers(0, vec![(self.vertices.buffer(), 0)]);
+        if let Some(ref pipeline) = self.depth_pipeline {
+            if depth_count > 0 {
+                encoder.bind_graphics_pipeline(pipeline);
+                encoder.push_graphics_constants(&self.pipeline_layout, ShaderStageFlags::VERTEX, 0, push_constants);
+                encoder.draw(0..depth_count, 0..1);
+            }
+        }
+        if overlay_count > 0 {
+            encoder.bind_graphics_pipeline(&self.overlay_pipeline);
+            encoder.push_graphics_constants(&self.pipeline_layout, ShaderStageFlags::VERTEX, 0, push_constants);
+            encoder.draw(depth_count..depth_count + overlay_count, 0..1);
+        }
+    }
+
+    /// Queue the labels drawn since the last call or `flush` in `batch`,
+    /// projected to a `width` by `height` screen, centred on their points.
+    /// Labels behind the camera are skipped. `batch` should be flushed with
+    /// `SpriteBatch::screen_projection`.
+    pub fn draw_labels(&mut self,
+                       font: &FontAtlas,
+                       batch: &mut SpriteBatch<B>,
+                       texture: TextureId,
+                       view_projection: Mat4,
+                       width: f32,
+                       height: f32) {
+        for label in self.labels.drain(..) {
+            let clip = view_projection * label.position.extend(1.0);
+            if clip.w <= 0.0 {
+                continue;
+            }
+            let screen = Vec2::new((clip.x / clip.w + 1.0) * 0.5 * width,
+                                   (clip.y / clip.w + 1.0) * 0.5 * height);
+            let style = TextStyle::new(font.px_size()).with_colour(label.colour);
+            let size = font.measure(&label.text, &style);
+            font.draw_text(batch, texture, &label.text, screen - size * 0.5, &style);
+        }
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        self.vertices.destroy(device);
+        if let Some(pipeline) = self.depth_pipeline {
+            device.destroy_graphics_pipeline(pipeline);
+        }
+        device.destroy_graphics_pipeline(self.overlay_pipeline);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+        device.destroy_shader_module(self.vertex_shader_module);
+        device.destroy_shader_module(self.fragment_shader_module);
+    }
+}
+
+
+// Two unit vectors perpendicular to `direction` and each other.
+fn perpendiculars(direction: Vec3) -> (Vec3, Vec3) {
+    let reference = if direction.y.abs() < 0.99 { Vec3::unit_y() } else { Vec3::unit_x() };
+    let side = direction.cross(reference).normalize();
+    (side, side.cross(direction))
+}
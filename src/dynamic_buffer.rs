@@ -0,0 +1,86 @@
+use gfx_hal::Backend;
+use prelude::*;
+use utils;
+
+use std::marker::PhantomData;
+
+
+/// A CPU-visible buffer that's rewritten as often as every frame, for data
+/// like instance attributes or batched vertices. It grows as needed, but
+/// never shrinks.
+///
+/// Writing replaces the contents (and growing replaces the buffer), so the
+/// GPU must be done with any previous contents first - for example by
+/// waiting on the frame's fence, or with `device.wait_idle()`.
+pub struct DynamicBuffer<B: Backend, T> {
+    buffer: B::Buffer,
+    memory: B::Memory,
+    usage: buffer::Usage,
+    capacity: usize,
+    len: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<B: Backend, T: Copy> DynamicBuffer<B, T> {
+    pub fn new(device: &B::Device,
+               memory_types: &[MemoryType],
+               usage: buffer::Usage,
+               capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (buffer, memory) = utils::empty_buffer::<B, T>(device,
+                                                           memory_types,
+                                                           Properties::CPU_VISIBLE,
+                                                           usage,
+                                                           capacity);
+        DynamicBuffer {
+            buffer,
+            memory,
+            usage,
+            capacity,
+            len: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Replace the contents with `items`, reallocating if they don't fit.
+    pub fn write(&mut self, device: &B::Device, memory_types: &[MemoryType], items: &[T]) {
+        if items.len() > self.capacity {
+            let capacity = items.len().next_power_of_two();
+            let (buffer, memory) = utils::empty_buffer::<B, T>(device,
+                                                               memory_types,
+                                                               Properties::CPU_VISIBLE,
+                                                               self.usage,
+                                                               capacity);
+            device.destroy_buffer(::std::mem::replace(&mut self.buffer, buffer));
+            device.free_memory(::std::mem::replace(&mut self.memory, memory));
+            self.capacity = capacity;
+        }
+
+        if !items.is_empty() {
+            utils::fill_buffer::<B, T>(device, &mut self.memory, items);
+        }
+        self.len = items.len();
+    }
+
+    pub fn buffer(&self) -> &B::Buffer {
+        &self.buffer
+    }
+
+    /// Number of items from the last write.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        device.destroy_buffer(self.buffer);
+        device.free_memory(self.memory);
+    }
+}
@@ -0,0 +1,508 @@
+// glTF 2.0 import, for both `.gltf` (with external or embedded buffers) and
+// binary `.glb` files.
+//
+// Everything is read into plain CPU-side structures: primitives become
+// `Mesh`es plus any extra vertex attributes, images become `TextureData`,
+// and the node hierarchy is flattened into a list indexed the same way as
+// the file. Uploading is left to `Mesh::create_buffers` and
+// `Texture::upload` (or `upload_images` below).
+
+use gltf;
+use gltf::mesh::Mode;
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+use mesh::{Mesh, Vertex};
+use prelude::*;
+use sampler::SamplerDesc;
+use texture::{MipLevel, Texture, TextureData, TextureError};
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// Extensions we can safely load. Anything else listed in
+/// `extensionsRequired` is rejected rather than rendered incorrectly.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["KHR_materials_unlit"];
+
+
+#[derive(Debug)]
+pub enum GltfError {
+    Gltf(gltf::Error),
+    UnsupportedExtension(String),
+    /// A feature of the file we can't represent, e.g. a primitive that
+    /// isn't a triangle list.
+    Unsupported(String),
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GltfError::Gltf(err) => write!(f, "glTF error: {}", err),
+            GltfError::UnsupportedExtension(name) => write!(f, "unsupported required extension {}", name),
+            GltfError::Unsupported(what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+impl Error for GltfError {}
+
+impl From<gltf::Error> for GltfError {
+    fn from(err: gltf::Error) -> Self {
+        GltfError::Gltf(err)
+    }
+}
+
+
+/// Vertex data beyond position, normal, tangent and the first UV set.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttributeData {
+    Float2(Vec<[f32; 2]>),
+    Float4(Vec<[f32; 4]>),
+    UShort4(Vec<[u16; 4]>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfPrimitive {
+    pub mesh: Mesh,
+    /// Extra attributes keyed by their glTF semantic name: `COLOR_0`,
+    /// `TEXCOORD_1`, `JOINTS_0` and `WEIGHTS_0`.
+    pub attributes: Vec<(String, AttributeData)>,
+    pub material: Option<usize>,
+}
+
+impl GltfPrimitive {
+    pub fn attribute(&self, semantic: &str) -> Option<&AttributeData> {
+        self.attributes
+            .iter()
+            .find(|(name, _)| name == semantic)
+            .map(|(_, data)| data)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GltfTexture {
+    /// Index into `GltfScene::images`.
+    pub image: usize,
+    pub sampler: SamplerDesc,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+/// Metallic-roughness material parameters. Texture fields index into
+/// `GltfScene::textures`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfMaterial {
+    pub name: Option<String>,
+    pub base_colour: [f32; 4],
+    pub base_colour_texture: Option<usize>,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub metallic_roughness_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<usize>,
+    pub occlusion_strength: f32,
+    pub emissive: [f32; 3],
+    pub emissive_texture: Option<usize>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+    pub unlit: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GltfNode {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub translation: [f32; 3],
+    /// Quaternion as (x, y, z, w).
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    pub mesh: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<GltfMaterial>,
+    pub textures: Vec<GltfTexture>,
+    pub images: Vec<TextureData>,
+    pub nodes: Vec<GltfNode>,
+    /// Root nodes of the default scene (or the first scene, if there's no
+    /// default).
+    pub roots: Vec<usize>,
+}
+
+impl GltfScene {
+    /// Upload every image, in order, so `GltfTexture::image` can index the
+    /// result directly.
+    pub fn upload_images<B: Backend>(&self,
+                                     device: &B::Device,
+                                     physical_device: &B::PhysicalDevice,
+                                     command_pool: &mut CommandPool<B, Graphics>,
+                                     queue: &mut CommandQueue<B, Graphics>) -> Result<Vec<Texture<B>>, TextureError> {
+        let mut textures = Vec::with_capacity(self.images.len());
+        for image in &self.images {
+            match Texture::upload(device, physical_device, command_pool, queue, image) {
+                Ok(texture) => textures.push(texture),
+                Err(err) => {
+                    for texture in textures {
+                        texture.destroy(device);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(textures)
+    }
+}
+
+
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<GltfScene, GltfError> {
+    let path = path.as_ref();
+
+    // Check the required extensions from the JSON alone, before spending
+    // time loading and decoding buffers and images we can't use. gltf 0.15
+    // doesn't expose loading those for an already-parsed document, so the
+    // import below reads the JSON a second time.
+    let gltf::Gltf { document, .. } = gltf::Gltf::open(path)?;
+    for extension in document.extensions_required() {
+        if !SUPPORTED_EXTENSIONS.contains(&extension) {
+            return Err(GltfError::UnsupportedExtension(extension.to_owned()));
+        }
+    }
+
+    let (document, buffers, images) = gltf::import(path)?;
+
+    let mut meshes = Vec::new();
+    for mesh in document.meshes() {
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                return Err(GltfError::Unsupported(format!("{:?} primitives", primitive.mode())));
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            let positions = match reader.read_positions() {
+                Some(positions) => positions.collect::<Vec<_>>(),
+                None => return Err(GltfError::Unsupported("primitive without positions".to_owned())),
+            };
+            let normals = reader.read_normals().map(|normals| normals.collect::<Vec<_>>());
+            let uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect::<Vec<_>>());
+            let tangents = reader.read_tangents().map(|tangents| tangents.collect::<Vec<_>>());
+
+            let vertices = positions.iter()
+                                    .enumerate()
+                                    .map(|(i, &position)| Vertex {
+                                        position,
+                                        normal: normals.as_ref().map(|n| n[i]).unwrap_or([0.0; 3]),
+                                        uv: uvs.as_ref().map(|t| t[i]).unwrap_or([0.0; 2]),
+                                        tangent: tangents.as_ref().map(|t| t[i]).unwrap_or([0.0; 4]),
+                                    })
+                                    .collect::<Vec<_>>();
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+
+            let mut attributes = Vec::new();
+            if let Some(colours) = reader.read_colors(0) {
+                attributes.push(("COLOR_0".to_owned(), AttributeData::Float4(colours.into_rgba_f32().collect())));
+            }
+            if let Some(uvs) = reader.read_tex_coords(1) {
+                attributes.push(("TEXCOORD_1".to_owned(), AttributeData::Float2(uvs.into_f32().collect())));
+            }
+            if let Some(joints) = reader.read_joints(0) {
+                attributes.push(("JOINTS_0".to_owned(), AttributeData::UShort4(joints.into_u16().collect())));
+            }
+            if let Some(weights) = reader.read_weights(0) {
+                attributes.push(("WEIGHTS_0".to_owned(), AttributeData::Float4(weights.into_f32().collect())));
+            }
+
+            primitives.push(GltfPrimitive {
+                mesh: Mesh { vertices, indices },
+                attributes,
+                material: primitive.material().index(),
+            });
+        }
+
+        meshes.push(GltfMesh {
+            name: mesh.name().map(|name| name.to_owned()),
+            primitives,
+        });
+    }
+
+    let texture_index = |info: Option<gltf::texture::Info>| info.map(|info| info.texture().index());
+    let materials = document.materials()
+                            .map(|material| {
+                                let pbr = material.pbr_metallic_roughness();
+                                GltfMaterial {
+                                    name: material.name().map(|name| name.to_owned()),
+                                    base_colour: pbr.base_color_factor(),
+                                    base_colour_texture: texture_index(pbr.base_color_texture()),
+                                    metallic: pbr.metallic_factor(),
+                                    roughness: pbr.roughness_factor(),
+                                    metallic_roughness_texture: texture_index(pbr.metallic_roughness_texture()),
+                                    normal_texture: material.normal_texture().map(|t| t.texture().index()),
+                                    normal_scale: material.normal_texture().map(|t| t.scale()).unwrap_or(1.0),
+                                    occlusion_texture: material.occlusion_texture().map(|t| t.texture().index()),
+                                    occlusion_strength: material.occlusion_texture()
+                                                                .map(|t| t.strength())
+                                                                .unwrap_or(1.0),
+                                    emissive: material.emissive_factor(),
+                                    emissive_texture: texture_index(material.emissive_texture()),
+                                    alpha_mode: match material.alpha_mode() {
+                                        gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                                        gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+                                        gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+                                    },
+                                    alpha_cutoff: material.alpha_cutoff(),
+                                    double_sided: material.double_sided(),
+                                    unlit: material.unlit(),
+                                }
+                            })
+                            .collect::<Vec<_>>();
+
+    let textures = document.textures()
+                           .map(|texture| GltfTexture {
+                               image: texture.source().index(),
+                               sampler: sampler_desc(&texture.sampler()),
+                           })
+                           .collect::<Vec<_>>();
+
+    // Colour textures are authored in sRGB; everything else (normals,
+    // metallic-roughness, occlusion) holds linear data.
+    let srgb_images = materials.iter()
+                               .flat_map(|material| vec![material.base_colour_texture, material.emissive_texture])
+                               .filter_map(|texture| texture.map(|texture| textures[texture].image))
+                               .collect::<HashSet<_>>();
+    let images = images.into_iter()
+                       .enumerate()
+                       .map(|(index, image)| texture_data(image, srgb_images.contains(&index)))
+                       .collect::<Result<Vec<_>, _>>()?;
+
+    let mut nodes = document.nodes()
+                            .map(|node| {
+                                let (translation, rotation, scale) = node.transform().decomposed();
+                                GltfNode {
+                                    name: node.name().map(|name| name.to_owned()),
+                                    parent: None,
+                                    children: node.children().map(|child| child.index()).collect(),
+                                    translation,
+                                    rotation,
+                                    scale,
+                                    mesh: node.mesh().map(|mesh| mesh.index()),
+                                }
+                            })
+                            .collect::<Vec<_>>();
+    for index in 0..nodes.len() {
+        for child in nodes[index].children.clone() {
+            nodes[child].parent = Some(index);
+        }
+    }
+
+    let roots = document.default_scene()
+                        .or_else(|| document.scenes().next())
+                        .map(|scene| scene.nodes().map(|node| node.index()).collect())
+                        .unwrap_or_else(|| (0..nodes.len()).filter(|&i| nodes[i].parent.is_none()).collect());
+
+    Ok(GltfScene {
+        meshes,
+        materials,
+        textures,
+        images,
+        nodes,
+        roots,
+    })
+}
+
+
+fn sampler_desc(sampler: &gltf::texture::Sampler) -> SamplerDesc {
+    let wrap = |mode: WrappingMode| match mode {
+        WrappingMode::ClampToEdge => WrapMode::Clamp,
+        WrappingMode::MirroredRepeat => WrapMode::Mirror,
+        WrappingMode::Repeat => WrapMode::Tile,
+    };
+    let (min_filter, mip_filter) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::NearestMipmapNearest) => (Filter::Nearest, Filter::Nearest),
+        Some(MinFilter::NearestMipmapLinear) => (Filter::Nearest, Filter::Linear),
+        Some(MinFilter::LinearMipmapNearest) => (Filter::Linear, Filter::Nearest),
+        Some(MinFilter::Linear) | Some(MinFilter::LinearMipmapLinear) | None => (Filter::Linear, Filter::Linear),
+    };
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => Filter::Nearest,
+        _ => Filter::Linear,
+    };
+
+    let mut desc = SamplerDesc::linear().with_wrap(wrap(sampler.wrap_s()),
+                                                   wrap(sampler.wrap_t()),
+                                                   WrapMode::Tile);
+    desc.min_filter = min_filter;
+    desc.mag_filter = mag_filter;
+    desc.mip_filter = mip_filter;
+    desc
+}
+
+// Expand whatever the image decoded to into 8-bit RGBA.
+fn texture_data(image: gltf::image::Data, srgb: bool) -> Result<TextureData, GltfError> {
+    use gltf::image::Format as Source;
+
+    let pixel_count = image.width as usize * image.height as usize;
+    let channels = match image.format {
+        Source::R8 => 1,
+        Source::R8G8 => 2,
+        Source::R8G8B8 | Source::B8G8R8 => 3,
+        Source::R8G8B8A8 | Source::B8G8R8A8 => 4,
+        other => return Err(GltfError::Unsupported(format!("{:?} images", other))),
+    };
+    let bgr = match image.format {
+        Source::B8G8R8 | Source::B8G8R8A8 => true,
+        _ => false,
+    };
+
+    let mut data = Vec::with_capacity(pixel_count * 4);
+    for pixel in image.pixels.chunks(channels).take(pixel_count) {
+        let rgba = match channels {
+            1 => [pixel[0], pixel[0], pixel[0], 255],
+            2 => [pixel[0], pixel[1], 0, 255],
+            3 => [pixel[0], pixel[1], pixel[2], 255],
+            _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+        };
+        if bgr {
+            data.extend_from_slice(&[rgba[2], rgba[1], rgba[0], rgba[3]]);
+        } else {
+            data.extend_from_slice(&rgba);
+        }
+    }
+
+    Ok(TextureData {
+        format: if srgb { Format::Rgba8Srgb } else { Format::Rgba8Unorm },
+        width: image.width,
+        height: image.height,
+        depth: 1,
+        layers: 1,
+        faces: 1,
+        levels: vec![MipLevel {
+            width: image.width,
+            height: image.height,
+            depth: 1,
+            data,
+        }],
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("gltf").join(name)
+    }
+
+    fn approx_eq(a: &[f32], b: &[f32]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5)
+    }
+
+    fn check_quad_scene(scene: &GltfScene) {
+        // "root" has two children; "unlit quad" is a second root.
+        assert_eq!(scene.roots, vec![0, 3]);
+        let names = scene.nodes.iter().map(|node| node.name.as_ref().unwrap().as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["root", "quad", "scaled", "unlit quad"]);
+        assert_eq!(scene.nodes[0].children, vec![1, 2]);
+        assert_eq!(scene.nodes.iter().map(|node| node.parent).collect::<Vec<_>>(),
+                   vec![None, Some(0), Some(0), None]);
+        assert_eq!(scene.nodes.iter().map(|node| node.mesh).collect::<Vec<_>>(),
+                   vec![None, Some(0), Some(0), Some(1)]);
+
+        assert_eq!(scene.nodes[0].translation, [1.0, 2.0, 3.0]);
+        assert!(approx_eq(&scene.nodes[1].rotation, &[0.0, 0.0, 0.70710678, 0.70710678]));
+        assert_eq!(scene.nodes[2].scale, [2.0, 2.0, 2.0]);
+        // Matrices are decomposed.
+        assert!(approx_eq(&scene.nodes[3].translation, &[4.0, 5.0, 6.0]));
+        assert!(approx_eq(&scene.nodes[3].rotation, &[0.0, 0.0, 0.0, 1.0]));
+        assert!(approx_eq(&scene.nodes[3].scale, &[1.0, 1.0, 1.0]));
+
+        assert_eq!(scene.meshes.len(), 2);
+        assert_eq!(scene.meshes[0].name, Some("quad".to_owned()));
+        let quad = &scene.meshes[0].primitives[0];
+        assert_eq!(quad.mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(quad.mesh.vertices.len(), 4);
+        assert_eq!(quad.mesh.vertices[2].position, [1.0, 1.0, 0.0]);
+        assert_eq!(quad.mesh.vertices[2].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(quad.mesh.vertices[2].uv, [1.0, 0.0]);
+        assert_eq!(quad.mesh.vertices[2].tangent, [0.0; 4]);
+        assert_eq!(quad.material, Some(0));
+        match quad.attribute("COLOR_0") {
+            Some(AttributeData::Float4(colours)) => assert_eq!(colours[3], [1.0, 1.0, 1.0, 0.5]),
+            other => panic!("expected float colours, got {:?}", other),
+        }
+        assert_eq!(quad.attribute("TEXCOORD_1"), None);
+
+        // Missing attributes are zeroed.
+        let unlit = &scene.meshes[1].primitives[0];
+        assert_eq!(unlit.mesh.vertices[1].normal, [0.0; 3]);
+        assert!(unlit.attributes.is_empty());
+
+        let painted = &scene.materials[0];
+        assert_eq!(painted.name, Some("painted".to_owned()));
+        assert_eq!(painted.base_colour, [0.5, 0.25, 1.0, 1.0]);
+        assert_eq!((painted.metallic, painted.roughness), (0.25, 0.75));
+        assert!(approx_eq(&painted.emissive, &[0.1, 0.2, 0.3]));
+        assert_eq!(painted.alpha_mode, AlphaMode::Mask);
+        assert!(approx_eq(&[painted.alpha_cutoff], &[0.3]));
+        assert!(painted.double_sided && !painted.unlit);
+        assert_eq!(painted.base_colour_texture, None);
+        assert_eq!((painted.normal_scale, painted.occlusion_strength), (1.0, 1.0));
+
+        // Defaults, and the unlit extension.
+        let flat = &scene.materials[1];
+        assert_eq!(flat.base_colour, [1.0; 4]);
+        assert_eq!((flat.metallic, flat.roughness), (1.0, 1.0));
+        assert_eq!(flat.alpha_mode, AlphaMode::Blend);
+        assert!(flat.unlit && !flat.double_sided);
+
+        assert!(scene.textures.is_empty() && scene.images.is_empty());
+    }
+
+    #[test]
+    fn loads_gltf_with_external_buffer() {
+        check_quad_scene(&load_gltf(fixture("quad.gltf")).unwrap());
+    }
+
+    #[test]
+    fn loads_glb() {
+        check_quad_scene(&load_gltf(fixture("quad.glb")).unwrap());
+    }
+
+    #[test]
+    fn rejects_unsupported_required_extensions() {
+        match load_gltf(fixture("draco_required.gltf")) {
+            Err(GltfError::UnsupportedExtension(ref name)) if name == "KHR_draco_mesh_compression" => {}
+            other => panic!("expected an unsupported extension error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn rejects_non_triangle_primitives() {
+        match load_gltf(fixture("lines.gltf")) {
+            Err(GltfError::Unsupported(ref what)) if what.contains("Lines") => {}
+            other => panic!("expected an unsupported primitive error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
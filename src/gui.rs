@@ -0,0 +1,453 @@
+// Dear ImGui integration.
+//
+// `Gui` owns an imgui context and everything needed to draw it: winit
+// events are translated into imgui input with `handle_event`, `frame`
+// starts a frame and hands back the `Ui` to build windows with, and
+// `render` uploads that frame's meshes and draws them, clipped to imgui's
+// scissor rectangles. Draw it last in the render pass so it sits on top of
+// the scene.
+//
+// Only imgui's font texture is supported; `Ui::image` and friends with
+// other texture IDs aren't drawn.
+
+use dynamic_buffer::DynamicBuffer;
+use gfx_hal::Backend;
+use imgui::{self, DrawCmd, DrawIdx, DrawVert, Key};
+use image::RgbaImage;
+use prelude::*;
+use sampler::SamplerDesc;
+use texture::{Texture, TextureData};
+use winit::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, Window};
+
+#[cfg(windows)]
+static VERT_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\gui.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\gui.frag.spv");
+
+#[cfg(all(unix))]
+static VERT_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/gui.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/gui.frag.spv");
+
+/// The texture ID imgui is given for its font atlas.
+const FONT_TEXTURE_ID: usize = 0;
+
+
+// Maps imgui's logical coordinates to clip space.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct PushConstants {
+    scale: [f32; 2],
+    translate: [f32; 2],
+}
+
+
+pub struct Gui<B: Backend> {
+    context: imgui::Context,
+    set_layout: B::DescriptorSetLayout,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::GraphicsPipeline,
+    vertex_shader_module: B::ShaderModule,
+    fragment_shader_module: B::ShaderModule,
+    desc_pool: B::DescriptorPool,
+    desc_set: B::DescriptorSet,
+    font_texture: Texture<B>,
+    font_sampler: B::Sampler,
+    vertices: DynamicBuffer<B, DrawVert>,
+    indices: DynamicBuffer<B, DrawIdx>,
+    vertex_data: Vec<DrawVert>,
+    index_data: Vec<DrawIdx>,
+}
+
+impl<B: Backend> Gui<B> {
+    /// Create a GUI drawn in the first subpass of `render_pass`, sized to
+    /// fit `window`. This uploads the font texture, so it blocks until the
+    /// copy has finished.
+    pub fn new(device: &B::Device,
+               physical_device: &B::PhysicalDevice,
+               command_pool: &mut CommandPool<B, Graphics>,
+               queue: &mut CommandQueue<B, Graphics>,
+               render_pass: &B::RenderPass,
+               window: &Window) -> Self {
+        let mut context = imgui::Context::create();
+        // Don't litter the working directory with window positions.
+        context.set_ini_filename(None);
+        // `render` honours each command's vertex offset, which lets imgui
+        // use 16-bit indices for big meshes.
+        context.io_mut().backend_flags.insert(imgui::BackendFlags::RENDERER_HAS_VTX_OFFSET);
+
+        {
+            let io = context.io_mut();
+            let size = window.get_inner_size().unwrap_or_else(|| (1, 1).into());
+            let hidpi_factor = window.get_hidpi_factor() as f32;
+            io.display_size = [size.width as f32, size.height as f32];
+            io.display_framebuffer_scale = [hidpi_factor, hidpi_factor];
+        }
+
+        let font_texture = {
+            let fonts = context.fonts();
+            let font_data = {
+                let atlas = fonts.build_rgba32_texture();
+                let image = RgbaImage::from_raw(atlas.width, atlas.height, atlas.data.to_vec()).unwrap();
+                TextureData::from_rgba8(&image, false)
+            };
+            fonts.tex_id = imgui::TextureId::new(FONT_TEXTURE_ID);
+            Texture::upload(device, physical_device, command_pool, queue, &font_data).unwrap()
+        };
+        let font_sampler = device.create_sampler(
+            SamplerDesc::linear().with_wrap(WrapMode::Clamp, WrapMode::Clamp, WrapMode::Clamp)
+                                 .to_info(),
+        );
+
+        let set_layout = device.create_descriptor_set_layout(
+            &[DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+                stage_flags: ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            }],
+            &[],
+        );
+
+        let num_push_constants = ::std::mem::size_of::<PushConstants>() / ::std::mem::size_of::<u32>();
+        let pipeline_layout = device.create_pipeline_layout(
+            vec![&set_layout],
+            &[(ShaderStageFlags::VERTEX, 0..num_push_constants as u32)],
+        );
+
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+        let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
+
+        let pipeline = {
+            let vs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &vertex_shader_module,
+                specialization: Default::default(),
+            };
+
+            let fs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &fragment_shader_module,
+                specialization: Default::default(),
+            };
+
+            let shader_entries = GraphicsShaderSet {
+                vertex: vs_entry,
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(fs_entry),
+            };
+
+            let subpass = Subpass {
+                index: 0,
+                main_pass: render_pass,
+            };
+
+            let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                              Primitive::TriangleList,
+                                                              Rasterizer::FILL,
+                                                              &pipeline_layout,
+                                                              subpass);
+
+            pipeline_desc.blender
+                         .targets
+                         .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+            pipeline_desc.vertex_buffers.push(VertexBufferDesc {
+                binding: 0,
+                stride: ::std::mem::size_of::<DrawVert>() as u32,
+                rate: 0,
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 0,
+                binding: 0,
+                element: Element {
+                    format: Format::Rg32Float,
+                    offset: 0,
+                },
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 1,
+                binding: 0,
+                element: Element {
+                    format: Format::Rg32Float,
+                    offset: 8,
+                },
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 2,
+                binding: 0,
+                element: Element {
+                    format: Format::Rgba8Unorm,
+                    offset: 16,
+                },
+            });
+
+            device.create_graphics_pipeline(&pipeline_desc, None)
+                  .unwrap()
+        };
+
+        let mut desc_pool = device.create_descriptor_pool(
+            1,
+            &[DescriptorRangeDesc {
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+            }],
+        );
+        let desc_set = desc_pool.allocate_set(&set_layout).unwrap();
+
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &desc_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::CombinedImageSampler(&font_texture.view,
+                                                               Layout::ShaderReadOnlyOptimal,
+                                                               &font_sampler)),
+        }]);
+
+        let memory_types = physical_device.memory_properties().memory_types;
+
+        Gui {
+            context,
+            set_layout,
+            pipeline_layout,
+            pipeline,
+            vertex_shader_module,
+            fragment_shader_module,
+            desc_pool,
+            desc_set,
+            font_texture,
+            font_sampler,
+            vertices: DynamicBuffer::new(device, &memory_types, buffer::Usage::VERTEX, 4096),
+            indices: DynamicBuffer::new(device, &memory_types, buffer::Usage::INDEX, 8192),
+            vertex_data: Vec::new(),
+            index_data: Vec::new(),
+        }
+    }
+
+    pub fn context(&mut self) -> &mut imgui::Context {
+        &mut self.context
+    }
+
+    /// Whether imgui is using the mouse, so the application should ignore
+    /// it. Only meaningful after `frame`.
+    pub fn wants_mouse(&self) -> bool {
+        self.context.io().want_capture_mouse
+    }
+
+    /// Whether imgui is using the keyboard, so the application should
+    /// ignore it. Only meaningful after `frame`.
+    pub fn wants_keyboard(&self) -> bool {
+        self.context.io().want_capture_keyboard
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        let io = self.context.io_mut();
+        match *event {
+            WindowEvent::Resized(size) => {
+                io.display_size = [size.width as f32, size.height as f32];
+            }
+            WindowEvent::HiDpiFactorChanged(factor) => {
+                io.display_framebuffer_scale = [factor as f32, factor as f32];
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                io.add_mouse_pos_event([position.x as f32, position.y as f32]);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                io.add_mouse_pos_event([-::std::f32::MAX, -::std::f32::MAX]);
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => imgui::MouseButton::Left,
+                    MouseButton::Right => imgui::MouseButton::Right,
+                    MouseButton::Middle => imgui::MouseButton::Middle,
+                    MouseButton::Other(_) => return,
+                };
+                io.add_mouse_button_event(button, state == ElementState::Pressed);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (x, y) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (x, y),
+                    // Roughly one line's worth of pixels per notch.
+                    MouseScrollDelta::PixelDelta(position) => (position.x as f32 / 20.0, position.y as f32 / 20.0),
+                };
+                io.add_mouse_wheel_event([x, y]);
+            }
+            WindowEvent::ReceivedCharacter(c) => {
+                // Control characters arrive as key presses instead.
+                if !c.is_control() {
+                    io.add_input_character(c);
+                }
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                let pressed = input.state == ElementState::Pressed;
+                io.add_key_event(Key::ModCtrl, input.modifiers.ctrl);
+                io.add_key_event(Key::ModShift, input.modifiers.shift);
+                io.add_key_event(Key::ModAlt, input.modifiers.alt);
+                io.add_key_event(Key::ModSuper, input.modifiers.logo);
+                if let Some(key) = input.virtual_keycode.and_then(imgui_key) {
+                    io.add_key_event(key, pressed);
+                }
+            }
+            WindowEvent::Focused(false) => {
+                // We won't hear about keys released while unfocused.
+                io.add_key_event(Key::ModCtrl, false);
+                io.add_key_event(Key::ModShift, false);
+                io.add_key_event(Key::ModAlt, false);
+                io.add_key_event(Key::ModSuper, false);
+            }
+            _ => (),
+        }
+    }
+
+    /// Start a frame, `dt` seconds after the last one.
+    pub fn frame(&mut self, dt: f32) -> &mut imgui::Ui {
+        // imgui asserts that time moves forwards.
+        self.context.io_mut().delta_time = dt.max(1.0e-6);
+        self.context.new_frame()
+    }
+
+    /// Finish the frame started by `frame`, upload its meshes, and record
+    /// the draw calls.
+    ///
+    /// This rewrites buffers the GPU reads from, so render at most once per
+    /// frame, after the previous frame has finished. It leaves the scissor
+    /// rectangle covering the whole display.
+    pub fn render(&mut self,
+                  device: &B::Device,
+                  memory_types: &[MemoryType],
+                  encoder: &mut RenderPassInlineEncoder<B>) {
+        let draw_data = self.context.render();
+        let [scale_x, scale_y] = draw_data.framebuffer_scale;
+        let [display_w, display_h] = draw_data.display_size;
+        let [origin_x, origin_y] = draw_data.display_pos;
+        if draw_data.total_vtx_count == 0 || display_w <= 0.0 || display_h <= 0.0 {
+            return;
+        }
+
+        // Put every draw list into one buffer, and record where each
+        // command's scissor and indices ended up.
+        self.vertex_data.clear();
+        self.index_data.clear();
+        // (scissor, first index, index count, base vertex) per draw call.
+        let mut draws: Vec<(Rect, u32, u32, i32)> = Vec::new();
+        for draw_list in draw_data.draw_lists() {
+            let first_vertex = self.vertex_data.len();
+            let first_index = self.index_data.len();
+            self.vertex_data.extend_from_slice(draw_list.vtx_buffer());
+            self.index_data.extend_from_slice(draw_list.idx_buffer());
+
+            for command in draw_list.commands() {
+                if let DrawCmd::Elements { count, cmd_params } = command {
+                    if cmd_params.texture_id.id() != FONT_TEXTURE_ID {
+                        continue;
+                    }
+                    let [x0, y0, x1, y1] = cmd_params.clip_rect;
+                    let x0 = ((x0 - origin_x) * scale_x).max(0.0);
+                    let y0 = ((y0 - origin_y) * scale_y).max(0.0);
+                    let x1 = ((x1 - origin_x) * scale_x).min(display_w * scale_x);
+                    let y1 = ((y1 - origin_y) * scale_y).min(display_h * scale_y);
+                    if x1 <= x0 || y1 <= y0 {
+                        continue;
+                    }
+                    let scissor = Rect {
+                        x: x0 as i16,
+                        y: y0 as i16,
+                        w: (x1 - x0).ceil() as i16,
+                        h: (y1 - y0).ceil() as i16,
+                    };
+                    let first = (first_index + cmd_params.idx_offset) as u32;
+                    let base_vertex = (first_vertex + cmd_params.vtx_offset) as i32;
+                    draws.push((scissor, first, count as u32, base_vertex));
+                }
+            }
+        }
+
+        self.vertices.write(device, memory_types, &self.vertex_data);
+        self.indices.write(device, memory_types, &self.index_data);
+
+        let push_constants = PushConstants {
+            scale: [2.0 / display_w, 2.0 / display_h],
+            translate: [-1.0 - origin_x * 2.0 / display_w, -1.0 - origin_y * 2.0 / display_h],
+        };
+        let push_constants = unsafe {
+            ::std::slice::from_raw_parts(&push_constants as *const PushConstants as *const u32,
+                                         ::std::mem::size_of::<PushConstants>() / ::std::mem::size_of::<u32>())
+        };
+
+        encoder.bind_graphics_pipeline(&self.pipeline);
+        encoder.bind_graphics_descriptor_sets(&self.pipeline_layout, 0, vec![&self.desc_set], &[]);
+        encoder.push_graphics_constants(&self.pipeline_layout, ShaderStageFlags::VERTEX, 0, push_constants);
+        encoder.bind_vertex_buffers(0, vec![(self.vertices.buffer(), 0)]);
+        encoder.bind_index_buffer(IndexBufferView {
+            buffer: self.indices.buffer(),
+            offset: 0,
+            index_type: IndexType::U16,
+        });
+
+        for &(scissor, first, count, base_vertex) in &draws {
+            encoder.set_scissors(0, &[scissor]);
+            encoder.draw_indexed(first..first + count, base_vertex, 0..1);
+        }
+
+        encoder.set_scissors(0, &[Rect {
+            x: 0,
+            y: 0,
+            w: (display_w * scale_x) as i16,
+            h: (display_h * scale_y) as i16,
+        }]);
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        self.vertices.destroy(device);
+        self.indices.destroy(device);
+        self.font_texture.destroy(device);
+        device.destroy_sampler(self.font_sampler);
+        device.destroy_descriptor_pool(self.desc_pool);
+        device.destroy_graphics_pipeline(self.pipeline);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+        device.destroy_descriptor_set_layout(self.set_layout);
+        device.destroy_shader_module(self.vertex_shader_module);
+        device.destroy_shader_module(self.fragment_shader_module);
+    }
+}
+
+
+fn imgui_key(key: VirtualKeyCode) -> Option<Key> {
+    Some(match key {
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Left => Key::LeftArrow,
+        VirtualKeyCode::Right => Key::RightArrow,
+        VirtualKeyCode::Up => Key::UpArrow,
+        VirtualKeyCode::Down => Key::DownArrow,
+        VirtualKeyCode::PageUp => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::Insert => Key::Insert,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Return => Key::Enter,
+        VirtualKeyCode::NumpadEnter => Key::KeypadEnter,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::LControl => Key::LeftCtrl,
+        VirtualKeyCode::RControl => Key::RightCtrl,
+        VirtualKeyCode::LShift => Key::LeftShift,
+        VirtualKeyCode::RShift => Key::RightShift,
+        VirtualKeyCode::LAlt => Key::LeftAlt,
+        VirtualKeyCode::RAlt => Key::RightAlt,
+        VirtualKeyCode::LWin => Key::LeftSuper,
+        VirtualKeyCode::RWin => Key::RightSuper,
+        // Shortcuts for text editing.
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Y => Key::Y,
+        VirtualKeyCode::Z => Key::Z,
+        _ => return None,
+    })
+}
@@ -0,0 +1,422 @@
+// High dynamic range rendering.
+//
+// The scene is drawn with `draw_scene` into a floating-point target, where
+// colours can go well past 1.0. `resolve` then optionally adds bloom and
+// tone-maps the result into the caller's render pass - usually the sRGB
+// swapchain's, or a `PostProcess` scene target to run further passes on.
+//
+// Bloom works on a chain of targets at 1/2, 1/4, 1/8... of the window's
+// size. A bright pass keeps only what's over the threshold while writing
+// the first, and each later one is a downsample of the one before. Then,
+// from the smallest up, each level is blurred up into the next larger one
+// and added to it, so the largest ends up holding a wide, smooth glow made
+// from all of them.
+//
+// Every pass is a full-screen triangle drawn with the helpers in
+// `postprocess`, and gets the same push constants as its passes do.
+
+use gfx_hal::command::CommandBuffer;
+use gfx_hal::format::ImageFeature;
+use gfx_hal::Backend;
+use postprocess::{self, NUM_PUSH_CONSTANTS, VERT_SPIRV};
+use prelude::*;
+use render_target::{RenderTarget, TargetSize};
+
+#[cfg(windows)]
+static BRIGHT_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\hdr_bright.frag.spv");
+#[cfg(windows)]
+static DOWNSAMPLE_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\hdr_downsample.frag.spv");
+#[cfg(windows)]
+static UPSAMPLE_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\hdr_upsample.frag.spv");
+#[cfg(windows)]
+static TONEMAP_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\hdr_tonemap.frag.spv");
+
+#[cfg(all(unix))]
+static BRIGHT_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/hdr_bright.frag.spv");
+#[cfg(all(unix))]
+static DOWNSAMPLE_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/hdr_downsample.frag.spv");
+#[cfg(all(unix))]
+static UPSAMPLE_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/hdr_upsample.frag.spv");
+#[cfg(all(unix))]
+static TONEMAP_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/hdr_tonemap.frag.spv");
+
+/// The format of the scene and bloom targets.
+pub const HDR_FORMAT: Format = Format::Rgba16Float;
+
+/// Whether `HDR_FORMAT` can be rendered to and sampled from.
+pub fn supports_hdr<B: Backend>(physical_device: &B::PhysicalDevice) -> bool {
+    physical_device.format_properties(Some(HDR_FORMAT))
+                   .optimal_tiling
+                   .contains(ImageFeature::COLOR_ATTACHMENT | ImageFeature::SAMPLED)
+}
+
+
+/// How `resolve` brings HDR colours into the 0..1 range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMap {
+    /// `c / (1 + c)`: gentle, but washes out bright colours.
+    Reinhard,
+    /// A fit of the ACES filmic curve, with more contrast and a soft
+    /// shoulder.
+    Aces,
+    /// `1 - exp(-c)`, as if exposing film.
+    Exposure,
+}
+
+impl ToneMap {
+    // Matches the constants in hdr_tonemap.frag.
+    fn shader_index(self) -> f32 {
+        match self {
+            ToneMap::Reinhard => 0.0,
+            ToneMap::Aces => 1.0,
+            ToneMap::Exposure => 2.0,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bloom {
+    /// Brightness (the largest of R, G and B) above which pixels glow.
+    pub threshold: f32,
+    /// How far below the threshold the glow starts fading in.
+    pub knee: f32,
+    /// How much of the glow is added back to the scene. Each level adds to
+    /// it, so with more levels, less is needed.
+    pub intensity: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom {
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.2,
+        }
+    }
+}
+
+
+pub struct HdrRenderer<B: Backend> {
+    pub tone_map: ToneMap,
+    /// In stops: +1 doubles the brightness before tone mapping.
+    pub exposure: f32,
+    /// `None` to skip bloom entirely.
+    pub bloom: Option<Bloom>,
+    set_layout: B::DescriptorSetLayout,
+    pipeline_layout: B::PipelineLayout,
+    desc_pool: B::DescriptorPool,
+    sampler: B::Sampler,
+    vertex_shader_module: B::ShaderModule,
+    fragment_shader_modules: Vec<B::ShaderModule>,
+    bright_pipeline: B::GraphicsPipeline,
+    downsample_pipeline: B::GraphicsPipeline,
+    upsample_pipeline: B::GraphicsPipeline,
+    tonemap_pipeline: B::GraphicsPipeline,
+    scene: RenderTarget<B>,
+    // The bloom chain, largest first. `up[i]` is the same size as
+    // `down[i]`; the smallest level has no `up`, as there's nothing to add
+    // to it.
+    down: Vec<RenderTarget<B>>,
+    up: Vec<RenderTarget<B>>,
+    // `down_sets[i]` reads the input to `down[i]`, and `up_sets[i]` the
+    // inputs to `up[i]`.
+    down_sets: Vec<B::DescriptorSet>,
+    up_sets: Vec<B::DescriptorSet>,
+    tonemap_set: B::DescriptorSet,
+    // With bloom off, the bloom targets may never have been written, so
+    // this reads the scene in their place.
+    tonemap_no_bloom_set: B::DescriptorSet,
+}
+
+impl<B: Backend> HdrRenderer<B> {
+    /// Create a renderer for a window of `window` pixels, with
+    /// `bloom_levels` bloom targets (at least one). The scene target gets
+    /// a depth image if `depth_format` is given. Tone mapping draws in the
+    /// first subpass of `output_render_pass`.
+    pub fn new(device: &B::Device,
+               memory_types: &[MemoryType],
+               window: Extent2D,
+               depth_format: Option<Format>,
+               bloom_levels: usize,
+               output_render_pass: &B::RenderPass) -> Self {
+        assert!(bloom_levels > 0, "bloom needs at least one level");
+
+        // Binding 1 is only read by the upsample and tone mapping shaders.
+        let bindings = [0, 1].iter()
+                             .map(|&binding| DescriptorSetLayoutBinding {
+                                 binding,
+                                 ty: DescriptorType::CombinedImageSampler,
+                                 count: 1,
+                                 stage_flags: ShaderStageFlags::FRAGMENT,
+                                 immutable_samplers: false,
+                             })
+                             .collect::<Vec<_>>();
+        let set_layout = device.create_descriptor_set_layout(&bindings, &[]);
+
+        let pipeline_layout = device.create_pipeline_layout(
+            vec![&set_layout],
+            &[(ShaderStageFlags::FRAGMENT, 0..NUM_PUSH_CONSTANTS)]
+        );
+
+        let num_sets = 2 * bloom_levels + 1;
+        let mut desc_pool = device.create_descriptor_pool(
+            num_sets,
+            &[DescriptorRangeDesc {
+                ty: DescriptorType::CombinedImageSampler,
+                count: num_sets * 2,
+            }]
+        );
+        let down_sets = (0..bloom_levels).map(|_| desc_pool.allocate_set(&set_layout).unwrap())
+                                         .collect();
+        let up_sets = (1..bloom_levels).map(|_| desc_pool.allocate_set(&set_layout).unwrap())
+                                       .collect();
+        let tonemap_set = desc_pool.allocate_set(&set_layout).unwrap();
+        let tonemap_no_bloom_set = desc_pool.allocate_set(&set_layout).unwrap();
+
+        let sampler = device.create_sampler(SamplerInfo::new(Filter::Linear, WrapMode::Clamp));
+
+        let scene = RenderTarget::new(device,
+                                      memory_types,
+                                      TargetSize::Relative(1.0),
+                                      window,
+                                      HDR_FORMAT,
+                                      depth_format);
+        let level_target = |level: usize| {
+            let scale = 0.5f32.powi(level as i32 + 1);
+            RenderTarget::new(device, memory_types, TargetSize::Relative(scale), window, HDR_FORMAT, None)
+        };
+        let down: Vec<RenderTarget<B>> = (0..bloom_levels).map(&level_target).collect();
+        let up = (0..bloom_levels - 1).map(&level_target).collect();
+
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+        let fragment_shader_modules = [BRIGHT_SPIRV, DOWNSAMPLE_SPIRV, UPSAMPLE_SPIRV, TONEMAP_SPIRV]
+            .iter()
+            .map(|spirv| device.create_shader_module(spirv).unwrap())
+            .collect::<Vec<_>>();
+
+        // Every bloom target has the same format, so pipelines made for one
+        // of their render passes work with all of them.
+        let (bright_pipeline, downsample_pipeline, upsample_pipeline) = {
+            let bloom_pass = &down[0].render_pass;
+            let create = |module: &B::ShaderModule| {
+                postprocess::fullscreen_pipeline::<B>(device, bloom_pass, &pipeline_layout, &vertex_shader_module, module)
+            };
+            (create(&fragment_shader_modules[0]),
+             create(&fragment_shader_modules[1]),
+             create(&fragment_shader_modules[2]))
+        };
+        let tonemap_pipeline = postprocess::fullscreen_pipeline::<B>(device,
+                                                                     output_render_pass,
+                                                                     &pipeline_layout,
+                                                                     &vertex_shader_module,
+                                                                     &fragment_shader_modules[3]);
+
+        let renderer = HdrRenderer {
+            tone_map: ToneMap::Aces,
+            exposure: 0.0,
+            bloom: Some(Bloom::default()),
+            set_layout,
+            pipeline_layout,
+            desc_pool,
+            sampler,
+            vertex_shader_module,
+            fragment_shader_modules,
+            bright_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            tonemap_pipeline,
+            scene,
+            down,
+            up,
+            down_sets,
+            up_sets,
+            tonemap_set,
+            tonemap_no_bloom_set,
+        };
+        renderer.write_sets(device);
+        renderer
+    }
+
+    /// The target `draw_scene` draws into. Pipelines for the scene should
+    /// be created against its render pass.
+    pub fn scene_target(&self) -> &RenderTarget<B> {
+        &self.scene
+    }
+
+    /// Call when the window has been resized to `window`.
+    ///
+    /// Nothing using the old targets may still be running on the GPU.
+    pub fn resize(&mut self, device: &B::Device, memory_types: &[MemoryType], window: Extent2D) {
+        let mut changed = self.scene.resize(device, memory_types, window);
+        for target in self.down.iter_mut().chain(self.up.iter_mut()) {
+            changed |= target.resize(device, memory_types, window);
+        }
+        if changed {
+            self.write_sets(device);
+        }
+    }
+
+    fn write_sets(&self, device: &B::Device) {
+        let levels = self.down.len();
+
+        // (set, binding 0, binding 1)
+        let mut sources = Vec::with_capacity(self.down_sets.len() + self.up_sets.len() + 2);
+        sources.push((&self.down_sets[0], &self.scene, &self.scene));
+        for i in 1..levels {
+            sources.push((&self.down_sets[i], &self.down[i - 1], &self.down[i - 1]));
+        }
+        for i in 0..levels - 1 {
+            let smaller = if i + 1 == levels - 1 { &self.down[i + 1] } else { &self.up[i + 1] };
+            sources.push((&self.up_sets[i], &self.down[i], smaller));
+        }
+        sources.push((&self.tonemap_set, &self.scene, self.bloom_result()));
+        sources.push((&self.tonemap_no_bloom_set, &self.scene, &self.scene));
+
+        let writes = sources.iter().flat_map(|&(set, first, second)| {
+            vec![(set, 0, first), (set, 1, second)]
+        }).map(|(set, binding, target)| DescriptorSetWrite {
+            set,
+            binding,
+            array_offset: 0,
+            descriptors: Some(Descriptor::CombinedImageSampler(&target.texture().view,
+                                                               Layout::ShaderReadOnlyOptimal,
+                                                               &self.sampler)),
+        });
+        device.write_descriptor_sets(writes);
+    }
+
+    // The largest level of the upsample chain, or the only level there is.
+    fn bloom_result(&self) -> &RenderTarget<B> {
+        self.up.first().unwrap_or(&self.down[0])
+    }
+
+    /// Record a render pass drawing the scene into the scene target with
+    /// `draw`, after clearing it to `clear_colour`. The viewport and
+    /// scissor cover the whole target.
+    pub fn draw_scene<F>(&self, command_buffer: &mut CommandBuffer<B, Graphics>, clear_colour: [f32; 4], draw: F)
+        where F: FnOnce(&mut RenderPassInlineEncoder<B>)
+    {
+        let viewport = self.scene.viewport();
+        command_buffer.set_viewports(0, &[viewport.clone()]);
+        command_buffer.set_scissors(0, &[viewport.rect]);
+
+        let mut encoder = command_buffer.begin_render_pass_inline(&self.scene.render_pass,
+                                                                  self.scene.framebuffer(),
+                                                                  viewport.rect,
+                                                                  &self.scene.clear_values(clear_colour));
+        draw(&mut encoder);
+    }
+
+    /// Record the bloom passes, if bloom is on, then tone-map the scene into
+    /// `output_framebuffer` (which must belong to the `output_render_pass`
+    /// given to `new`) over `output_viewport`, beginning the output pass
+    /// with `output_clear_values`. Call after `draw_scene`.
+    pub fn resolve(&self,
+                   command_buffer: &mut CommandBuffer<B, Graphics>,
+                   output_render_pass: &B::RenderPass,
+                   output_framebuffer: &B::Framebuffer,
+                   output_viewport: &Viewport,
+                   output_clear_values: &[ClearValue]) {
+        let levels = self.down.len();
+
+        let bloom_intensity = match self.bloom {
+            Some(bloom) => {
+                self.draw_into(command_buffer,
+                               &self.down[0],
+                               &self.bright_pipeline,
+                               &self.down_sets[0],
+                               self.scene.extent(),
+                               [bloom.threshold, bloom.knee, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+                for i in 1..levels {
+                    self.draw_into(command_buffer,
+                                   &self.down[i],
+                                   &self.downsample_pipeline,
+                                   &self.down_sets[i],
+                                   self.down[i - 1].extent(),
+                                   [0.0; 8]);
+                }
+                for i in (0..levels - 1).rev() {
+                    self.draw_into(command_buffer,
+                                   &self.up[i],
+                                   &self.upsample_pipeline,
+                                   &self.up_sets[i],
+                                   self.down[i + 1].extent(),
+                                   [0.0; 8]);
+                }
+                bloom.intensity
+            }
+            None => 0.0,
+        };
+
+        let set = if self.bloom.is_some() { &self.tonemap_set } else { &self.tonemap_no_bloom_set };
+        let params = [self.exposure, self.tone_map.shader_index(), bloom_intensity, 0.0, 0.0, 0.0, 0.0, 0.0];
+        postprocess::draw_fullscreen_pass(command_buffer,
+                                          output_render_pass,
+                                          output_framebuffer,
+                                          output_viewport,
+                                          output_clear_values,
+                                          &self.pipeline_layout,
+                                          &self.tonemap_pipeline,
+                                          set,
+                                          self.scene.extent(),
+                                          &params);
+    }
+
+    fn draw_into(&self,
+                 command_buffer: &mut CommandBuffer<B, Graphics>,
+                 target: &RenderTarget<B>,
+                 pipeline: &B::GraphicsPipeline,
+                 set: &B::DescriptorSet,
+                 source_extent: Extent2D,
+                 params: [f32; 8]) {
+        postprocess::draw_fullscreen_pass(command_buffer,
+                                          &target.render_pass,
+                                          target.framebuffer(),
+                                          &target.viewport(),
+                                          &target.clear_values(postprocess::BLACK),
+                                          &self.pipeline_layout,
+                                          pipeline,
+                                          set,
+                                          source_extent,
+                                          &params);
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        let HdrRenderer {
+            set_layout,
+            pipeline_layout,
+            desc_pool,
+            sampler,
+            vertex_shader_module,
+            fragment_shader_modules,
+            bright_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            tonemap_pipeline,
+            scene,
+            down,
+            up,
+            ..
+        } = self;
+
+        device.destroy_graphics_pipeline(bright_pipeline);
+        device.destroy_graphics_pipeline(downsample_pipeline);
+        device.destroy_graphics_pipeline(upsample_pipeline);
+        device.destroy_graphics_pipeline(tonemap_pipeline);
+        for module in fragment_shader_modules {
+            device.destroy_shader_module(module);
+        }
+        device.destroy_shader_module(vertex_shader_module);
+
+        scene.destroy(device);
+        for target in down.into_iter().chain(up) {
+            target.destroy(device);
+        }
+
+        device.destroy_sampler(sampler);
+        device.destroy_descriptor_pool(desc_pool);
+        device.destroy_pipeline_layout(pipeline_layout);
+        device.destroy_descriptor_set_layout(set_layout);
+    }
+}
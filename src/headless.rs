@@ -0,0 +1,216 @@
+// Rendering without a window.
+//
+// `HeadlessContext` opens a device with a graphics queue but no surface,
+// and `OffscreenTarget` is a fixed-size `RenderTarget` to draw into in place
+// of a swapchain. Pipelines are created against the target's render pass
+// exactly as they would be against a swapchain's, so everything else in
+// the library works unchanged. After rendering, `read_pixels` copies the
+// colour image back to the CPU.
+
+use backend;
+use gfx_hal::error::DeviceCreationError;
+use gfx_hal::queue::QueueFamily;
+use gfx_hal::{Adapter, Backend, QueueGroup};
+use image::RgbaImage;
+use prelude::*;
+use readback::{self, ReadbackError};
+use render_target::{RenderTarget, TargetSize};
+
+use std::error::Error;
+use std::fmt;
+
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    /// No adapter with a graphics queue was found.
+    NoAdapter,
+    DeviceCreation(DeviceCreationError),
+}
+
+impl fmt::Display for HeadlessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeadlessError::NoAdapter => write!(f, "no graphics adapter available"),
+            HeadlessError::DeviceCreation(err) => write!(f, "could not create device: {:?}", err),
+        }
+    }
+}
+
+impl Error for HeadlessError {}
+
+
+/// A device and graphics queue opened without a surface.
+pub struct HeadlessContext {
+    pub adapter: Adapter<backend::Backend>,
+    pub device: <backend::Backend as Backend>::Device,
+    pub queue_group: QueueGroup<backend::Backend, Graphics>,
+    pub command_pool: CommandPool<backend::Backend, Graphics>,
+    pub memory_types: Vec<MemoryType>,
+    // Dropped last, after everything created from it.
+    _instance: backend::Instance,
+}
+
+impl HeadlessContext {
+    /// Open the first adapter that has a graphics queue.
+    pub fn new(name: &str) -> Result<Self, HeadlessError> {
+        let instance = backend::Instance::create(name, 1);
+
+        let mut adapters = instance.enumerate_adapters();
+        let index = adapters.iter()
+                            .position(|adapter| {
+                                adapter.queue_families
+                                       .iter()
+                                       .any(|family| family.supports_graphics())
+                            })
+                            .ok_or(HeadlessError::NoAdapter)?;
+        let mut adapter = adapters.remove(index);
+
+        let (device, queue_group) = adapter.open_with::<_, Graphics>(1, |_family| true)
+                                           .map_err(HeadlessError::DeviceCreation)?;
+        let command_pool = device.create_command_pool_typed(&queue_group,
+                                                            CommandPoolCreateFlags::empty(),
+                                                            16);
+        let memory_types = adapter.physical_device.memory_properties().memory_types;
+
+        Ok(HeadlessContext {
+            adapter,
+            device,
+            queue_group,
+            command_pool,
+            memory_types,
+            _instance: instance,
+        })
+    }
+
+    pub fn physical_device(&self) -> &<backend::Backend as Backend>::PhysicalDevice {
+        &self.adapter.physical_device
+    }
+
+    pub fn queue(&mut self) -> &mut CommandQueue<backend::Backend, Graphics> {
+        &mut self.queue_group.queues[0]
+    }
+
+    /// Destroy the command pool, once everything else made with the device
+    /// has been destroyed.
+    pub fn destroy(self) {
+        self.device.wait_idle().unwrap();
+        self.device.destroy_command_pool(self.command_pool.into_raw());
+    }
+}
+
+
+/// An image to render into instead of a swapchain image: a fixed-size
+/// `RenderTarget`, with helpers to render into it once and read it back.
+///
+/// Its render pass clears every attachment, and leaves the colour image in
+/// `ShaderReadOnlyOptimal`.
+pub struct OffscreenTarget<B: Backend> {
+    target: RenderTarget<B>,
+}
+
+impl<B: Backend> OffscreenTarget<B> {
+    /// A `width` by `height` target.
+    pub fn new(device: &B::Device,
+               memory_types: &[MemoryType],
+               width: u32,
+               height: u32,
+               colour_format: Format,
+               depth_format: Option<Format>) -> Self {
+        // The window size only matters for relative targets.
+        let extent = Extent2D { width, height };
+        let target = RenderTarget::new(device,
+                                       memory_types,
+                                       TargetSize::Fixed(width, height),
+                                       extent,
+                                       colour_format,
+                                       depth_format);
+        OffscreenTarget { target }
+    }
+
+    /// The target this renders into, e.g. to sample its colour image.
+    pub fn target(&self) -> &RenderTarget<B> {
+        &self.target
+    }
+
+    pub fn render_pass(&self) -> &B::RenderPass {
+        &self.target.render_pass
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.target.extent()
+    }
+
+    pub fn colour_image(&self) -> &B::Image {
+        &self.target.texture().image
+    }
+
+    /// A viewport covering the whole target.
+    pub fn viewport(&self) -> Viewport {
+        self.target.viewport()
+    }
+
+    /// Record one render pass into the target with `draw`, after clearing
+    /// it to `clear_colour` (and the depth to 1.0), then submit it and wait
+    /// for it to finish. The viewport and scissor cover the whole target.
+    ///
+    /// `command_pool` is reset afterwards, so it shouldn't have any other
+    /// command buffers in use.
+    pub fn render<F>(&self,
+                     device: &B::Device,
+                     command_pool: &mut CommandPool<B, Graphics>,
+                     queue: &mut CommandQueue<B, Graphics>,
+                     clear_colour: [f32; 4],
+                     draw: F)
+        where F: FnOnce(&mut RenderPassInlineEncoder<B>)
+    {
+        let finished_command_buffer = {
+            let mut command_buffer = command_pool.acquire_command_buffer(false);
+
+            let viewport = self.viewport();
+            command_buffer.set_viewports(0, &[viewport.clone()]);
+            command_buffer.set_scissors(0, &[viewport.rect]);
+
+            {
+                let mut encoder = command_buffer.begin_render_pass_inline(&self.target.render_pass,
+                                                                          self.target.framebuffer(),
+                                                                          viewport.rect,
+                                                                          &self.target.clear_values(clear_colour));
+                draw(&mut encoder);
+            }
+
+            command_buffer.finish()
+        };
+
+        let fence = device.create_fence(false);
+        queue.submit(Submission::new().submit(vec![finished_command_buffer]), Some(&fence));
+        device.wait_for_fence(&fence, !0);
+        device.destroy_fence(fence);
+        command_pool.reset();
+    }
+
+    /// Copy the colour image back to the CPU as RGBA8. Call after
+    /// `render`. See `readback::read_image` for the formats supported.
+    pub fn read_pixels(&self,
+                       device: &B::Device,
+                       physical_device: &B::PhysicalDevice,
+                       command_pool: &mut CommandPool<B, Graphics>,
+                       queue: &mut CommandQueue<B, Graphics>) -> Result<RgbaImage, ReadbackError> {
+        let extent = self.target.extent();
+        let pixels = readback::read_image(device,
+                                          physical_device,
+                                          command_pool,
+                                          queue,
+                                          self.colour_image(),
+                                          self.target.colour_format,
+                                          extent.width,
+                                          extent.height,
+                                          Layout::ShaderReadOnlyOptimal);
+        command_pool.reset();
+        pixels
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        self.target.destroy(device);
+    }
+}
+
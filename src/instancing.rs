@@ -0,0 +1,77 @@
+// Instanced drawing: one draw call for many copies of a mesh, with
+// per-copy data read from a second vertex buffer that advances once per
+// instance instead of once per vertex.
+
+use dynamic_buffer::DynamicBuffer;
+use gfx_hal::Backend;
+use prelude::*;
+
+use std::ops::Range;
+
+
+/// `VertexBufferDesc::rate` for data that advances once per vertex.
+pub const PER_VERTEX: u8 = 0;
+/// `VertexBufferDesc::rate` for data that advances once per instance.
+pub const PER_INSTANCE: u8 = 1;
+
+/// Describe a vertex buffer binding holding `T`s.
+pub fn vertex_buffer_desc<T>(binding: u32, rate: u8) -> VertexBufferDesc {
+    VertexBufferDesc {
+        binding,
+        stride: ::std::mem::size_of::<T>() as u32,
+        rate,
+    }
+}
+
+
+/// Per-instance data for drawing offset, tinted copies of a mesh, as
+/// `part06.vert` reads it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct InstanceData {
+    /// Added to every vertex position of the copy.
+    pub offset: [f32; 3],
+    /// Multiplied with every vertex colour of the copy.
+    pub tint: [f32; 4],
+}
+
+impl InstanceData {
+    /// Attribute descriptions starting at `first_location`: the offset, then
+    /// the tint.
+    pub fn attributes(binding: u32, first_location: u32) -> Vec<AttributeDesc> {
+        vec![
+            AttributeDesc {
+                location: first_location,
+                binding,
+                element: Element {
+                    format: Format::Rgb32Float,
+                    offset: 0,
+                },
+            },
+            AttributeDesc {
+                location: first_location + 1,
+                binding,
+                element: Element {
+                    format: Format::Rgba32Float,
+                    offset: 12,
+                },
+            },
+        ]
+    }
+}
+
+
+/// Draw `vertices` from `vertex_buffer` once per item in `instances`, which
+/// is bound after it: the vertices at `first_binding`, the instances at
+/// `first_binding + 1`.
+pub fn draw_instanced<B: Backend, T: Copy>(encoder: &mut RenderPassInlineEncoder<B>,
+                                           first_binding: u32,
+                                           vertex_buffer: &B::Buffer,
+                                           vertices: Range<u32>,
+                                           instances: &DynamicBuffer<B, T>) {
+    if instances.is_empty() {
+        return;
+    }
+    encoder.bind_vertex_buffers(first_binding, vec![(vertex_buffer, 0), (instances.buffer(), 0)]);
+    encoder.draw(vertices, 0..instances.len() as u32);
+}
@@ -6,8 +6,39 @@ pub extern crate gfx_backend_metal as gfx_backend;
 pub extern crate gfx_backend_vulkan as gfx_backend;
 
 extern crate gfx_hal;
+extern crate gltf;
+extern crate image;
+extern crate imgui;
+extern crate rusttype;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 extern crate winit;
 
+pub mod atlas;
+pub mod camera;
+pub mod canvas;
+pub mod debug_draw;
+pub mod dynamic_buffer;
+pub mod gltf_import;
+pub mod gui;
+pub mod hdr;
+pub mod headless;
+pub mod instancing;
+pub mod math;
+pub mod mesh;
+pub mod postprocess;
 pub mod prelude;
+pub mod raster;
+pub mod readback;
+pub mod render_target;
+pub mod sampler;
+pub mod scene;
+pub mod sprite;
+pub mod text;
+pub mod texture;
+pub mod tilemap;
+pub mod tutorials;
 pub mod utils;
 pub use gfx_backend as backend;
@@ -0,0 +1,836 @@
+// Small linear algebra types for building transforms and uploading them.
+//
+// Matrices are column-major, matching GLSL, and convert straight into the
+// `[[f32; 4]; 4]` fields the tutorials' uniform and push constant structs
+// use. The vector types are `repr(C)` so they can also be used directly in
+// those structs and in vertex data.
+//
+// World space is right-handed with Y up, and cameras look down -Z. gfx-hal
+// follows Vulkan's clip space on every backend: Y points down the screen and
+// depth runs from 0 at the near plane to 1 at the far plane. `perspective`
+// and `orthographic` account for both, so meshes don't need flipping.
+
+use std::ops::{Add, AddAssign, Div, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign};
+
+
+macro_rules! impl_vector {
+    ($name:ident { $($field:ident),+ }, $count:expr) => {
+        impl $name {
+            pub fn new($($field: f32),+) -> Self {
+                $name { $($field),+ }
+            }
+
+            pub fn zero() -> Self {
+                $name { $($field: 0.0),+ }
+            }
+
+            pub fn splat(value: f32) -> Self {
+                $name { $($field: value),+ }
+            }
+
+            pub fn dot(self, other: Self) -> f32 {
+                0.0 $(+ self.$field * other.$field)+
+            }
+
+            pub fn length_squared(self) -> f32 {
+                self.dot(self)
+            }
+
+            pub fn length(self) -> f32 {
+                self.dot(self).sqrt()
+            }
+
+            /// Unit vector in the same direction, or zero for a zero vector.
+            pub fn normalize(self) -> Self {
+                let length = self.length();
+                if length > 0.0 {
+                    self / length
+                } else {
+                    self
+                }
+            }
+
+            pub fn lerp(self, other: Self, t: f32) -> Self {
+                self + (other - self) * t
+            }
+
+            pub fn min(self, other: Self) -> Self {
+                $name { $($field: self.$field.min(other.$field)),+ }
+            }
+
+            pub fn max(self, other: Self) -> Self {
+                $name { $($field: self.$field.max(other.$field)),+ }
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+            fn add(self, other: $name) -> $name {
+                $name { $($field: self.$field + other.$field),+ }
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+            fn sub(self, other: $name) -> $name {
+                $name { $($field: self.$field - other.$field),+ }
+            }
+        }
+
+        /// Component-wise product.
+        impl Mul for $name {
+            type Output = $name;
+            fn mul(self, other: $name) -> $name {
+                $name { $($field: self.$field * other.$field),+ }
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = $name;
+            fn mul(self, s: f32) -> $name {
+                $name { $($field: self.$field * s),+ }
+            }
+        }
+
+        impl Mul<$name> for f32 {
+            type Output = $name;
+            fn mul(self, v: $name) -> $name {
+                v * self
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = $name;
+            fn div(self, s: f32) -> $name {
+                $name { $($field: self.$field / s),+ }
+            }
+        }
+
+        impl Neg for $name {
+            type Output = $name;
+            fn neg(self) -> $name {
+                $name { $($field: -self.$field),+ }
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: $name) {
+                *self = *self + other;
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, other: $name) {
+                *self = *self - other;
+            }
+        }
+
+        impl MulAssign<f32> for $name {
+            fn mul_assign(&mut self, s: f32) {
+                *self = *self * s;
+            }
+        }
+
+        impl From<[f32; $count]> for $name {
+            fn from(array: [f32; $count]) -> $name {
+                let [$($field),+] = array;
+                $name { $($field),+ }
+            }
+        }
+
+        impl From<$name> for [f32; $count] {
+            fn from(v: $name) -> [f32; $count] {
+                [$(v.$field),+]
+            }
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl_vector!(Vec2 { x, y }, 2);
+
+impl Vec2 {
+    /// The vector rotated a quarter turn, from +X towards +Y.
+    pub fn perpendicular(self) -> Self {
+        Vec2::new(-self.y, self.x)
+    }
+
+    pub fn extend(self, z: f32) -> Vec3 {
+        Vec3::new(self.x, self.y, z)
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl_vector!(Vec3 { x, y, z }, 3);
+
+impl Vec3 {
+    pub fn unit_x() -> Self {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    pub fn unit_y() -> Self {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+
+    pub fn unit_z() -> Self {
+        Vec3::new(0.0, 0.0, 1.0)
+    }
+
+    pub fn cross(self, other: Self) -> Self {
+        Vec3::new(self.y * other.z - self.z * other.y,
+                  self.z * other.x - self.x * other.z,
+                  self.x * other.y - self.y * other.x)
+    }
+
+    pub fn extend(self, w: f32) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, w)
+    }
+
+    pub fn truncate(self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl_vector!(Vec4 { x, y, z, w }, 4);
+
+impl Vec4 {
+    pub fn truncate(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+}
+
+
+/// An axis-aligned rectangle, for geometry: canvas shapes, tilemap bounds
+/// and the like. `x` and `y` are its minimum corner.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect2 {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rect2 {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Rect2 { x, y, w, h }
+    }
+
+    pub fn from_corners(min: Vec2, max: Vec2) -> Self {
+        Rect2::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+
+    /// Whether the two rectangles share any area; touching edges don't
+    /// count.
+    pub fn overlaps(self, other: Rect2) -> bool {
+        self.x < other.x + other.w && other.x < self.x + self.w && self.y < other.y + other.h &&
+            other.y < self.y + self.h
+    }
+}
+
+
+/// Rotation quaternion. Only unit quaternions represent rotations; the
+/// constructors here all produce them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Quat::identity()
+    }
+}
+
+impl Quat {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Quat { x, y, z, w }
+    }
+
+    pub fn identity() -> Self {
+        Quat::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// Rotation by `angle` radians about `axis`, counter-clockwise when
+    /// looking back along the axis.
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Quat::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    /// Yaw about Y, then pitch about X, then roll about Z, all in radians.
+    /// This is the usual order for a camera or character.
+    pub fn from_yaw_pitch_roll(yaw: f32, pitch: f32, roll: f32) -> Self {
+        Quat::from_axis_angle(Vec3::unit_y(), yaw) *
+            Quat::from_axis_angle(Vec3::unit_x(), pitch) *
+            Quat::from_axis_angle(Vec3::unit_z(), roll)
+    }
+
+    /// Extract the rotation from a matrix whose upper 3x3 is a pure rotation.
+    pub fn from_mat3(m: &Mat3) -> Self {
+        let m = &m.0;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quat::new((m[1][2] - m[2][1]) / s, (m[2][0] - m[0][2]) / s, (m[0][1] - m[1][0]) / s, 0.25 * s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            Quat::new(0.25 * s, (m[1][0] + m[0][1]) / s, (m[2][0] + m[0][2]) / s, (m[1][2] - m[2][1]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            Quat::new((m[1][0] + m[0][1]) / s, 0.25 * s, (m[2][1] + m[1][2]) / s, (m[2][0] - m[0][2]) / s)
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            Quat::new((m[2][0] + m[0][2]) / s, (m[2][1] + m[1][2]) / s, 0.25 * s, (m[0][1] - m[1][0]) / s)
+        };
+        q.normalize()
+    }
+
+    pub fn dot(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn normalize(self) -> Self {
+        let length = self.dot(self).sqrt();
+        if length > 0.0 {
+            Quat::new(self.x / length, self.y / length, self.z / length, self.w / length)
+        } else {
+            Quat::identity()
+        }
+    }
+
+    /// The inverse rotation.
+    pub fn conjugate(self) -> Self {
+        Quat::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn rotate(self, v: Vec3) -> Vec3 {
+        let axis = Vec3::new(self.x, self.y, self.z);
+        let t = axis.cross(v) * 2.0;
+        v + t * self.w + axis.cross(t)
+    }
+
+    /// Spherical interpolation along the shorter arc.
+    pub fn slerp(self, other: Self, t: f32) -> Self {
+        let mut cos = self.dot(other);
+        let other = if cos < 0.0 {
+            cos = -cos;
+            Quat::new(-other.x, -other.y, -other.z, -other.w)
+        } else {
+            other
+        };
+
+        // Nearly parallel; a plain lerp avoids dividing by a tiny sine.
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+
+        Quat::new(self.x * a + other.x * b,
+                  self.y * a + other.y * b,
+                  self.z * a + other.z * b,
+                  self.w * a + other.w * b).normalize()
+    }
+}
+
+/// Composition: `a * b` rotates by `b`, then by `a`.
+impl Mul for Quat {
+    type Output = Quat;
+    fn mul(self, o: Quat) -> Quat {
+        Quat::new(self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+                  self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+                  self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+                  self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z)
+    }
+}
+
+impl Mul<Vec3> for Quat {
+    type Output = Vec3;
+    fn mul(self, v: Vec3) -> Vec3 {
+        self.rotate(v)
+    }
+}
+
+
+/// Column-major 3x3 matrix: `self.0[column][row]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat3(pub [[f32; 3]; 3]);
+
+impl Default for Mat3 {
+    fn default() -> Self {
+        Mat3::identity()
+    }
+}
+
+impl Mat3 {
+    pub fn identity() -> Self {
+        Mat3([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+    }
+
+    pub fn from_cols(x: Vec3, y: Vec3, z: Vec3) -> Self {
+        Mat3([x.into(), y.into(), z.into()])
+    }
+
+    pub fn from_quat(q: Quat) -> Self {
+        let (x2, y2, z2) = (q.x + q.x, q.y + q.y, q.z + q.z);
+        let (xx, xy, xz) = (q.x * x2, q.x * y2, q.x * z2);
+        let (yy, yz, zz) = (q.y * y2, q.y * z2, q.z * z2);
+        let (wx, wy, wz) = (q.w * x2, q.w * y2, q.w * z2);
+        Mat3([
+            [1.0 - (yy + zz), xy + wz, xz - wy],
+            [xy - wz, 1.0 - (xx + zz), yz + wx],
+            [xz + wy, yz - wx, 1.0 - (xx + yy)],
+        ])
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Mat3([[scale.x, 0.0, 0.0], [0.0, scale.y, 0.0], [0.0, 0.0, scale.z]])
+    }
+
+    pub fn col(&self, index: usize) -> Vec3 {
+        self.0[index].into()
+    }
+
+    pub fn transpose(&self) -> Self {
+        let m = &self.0;
+        Mat3([
+            [m[0][0], m[1][0], m[2][0]],
+            [m[0][1], m[1][1], m[2][1]],
+            [m[0][2], m[1][2], m[2][2]],
+        ])
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.col(0).dot(self.col(1).cross(self.col(2)))
+    }
+
+    /// The inverse, or `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det == 0.0 {
+            return None;
+        }
+        let (a, b, c) = (self.col(0), self.col(1), self.col(2));
+        // Rows of the inverse are the cross products of pairs of columns.
+        Some(Mat3::from_cols(b.cross(c) / det, c.cross(a) / det, a.cross(b) / det).transpose())
+    }
+
+    /// Columns padded to vec4s, which is how std140 lays out a `mat3`.
+    pub fn to_std140(&self) -> [[f32; 4]; 3] {
+        let m = &self.0;
+        [
+            [m[0][0], m[0][1], m[0][2], 0.0],
+            [m[1][0], m[1][1], m[1][2], 0.0],
+            [m[2][0], m[2][1], m[2][2], 0.0],
+        ]
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Mat3;
+    fn mul(self, other: Mat3) -> Mat3 {
+        Mat3::from_cols(self * other.col(0), self * other.col(1), self * other.col(2))
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+    fn mul(self, v: Vec3) -> Vec3 {
+        self.col(0) * v.x + self.col(1) * v.y + self.col(2) * v.z
+    }
+}
+
+impl From<Mat3> for [[f32; 3]; 3] {
+    fn from(m: Mat3) -> [[f32; 3]; 3] {
+        m.0
+    }
+}
+
+
+/// Column-major 4x4 matrix: `self.0[column][row]`, the same layout as a
+/// GLSL `mat4`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4(pub [[f32; 4]; 4]);
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::identity()
+    }
+}
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        Mat4([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn from_cols(x: Vec4, y: Vec4, z: Vec4, w: Vec4) -> Self {
+        Mat4([x.into(), y.into(), z.into(), w.into()])
+    }
+
+    pub fn from_mat3(m: Mat3) -> Self {
+        Mat4::from_cols(m.col(0).extend(0.0), m.col(1).extend(0.0), m.col(2).extend(0.0), Vec4::new(0.0, 0.0, 0.0, 1.0))
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        let mut m = Mat4::identity();
+        m.0[3] = translation.extend(1.0).into();
+        m
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Mat4::from_mat3(Mat3::from_scale(scale))
+    }
+
+    pub fn from_quat(rotation: Quat) -> Self {
+        Mat4::from_mat3(Mat3::from_quat(rotation))
+    }
+
+    /// Scale, then rotate, then translate.
+    pub fn from_trs(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        let r = Mat3::from_quat(rotation);
+        Mat4::from_cols((r.col(0) * scale.x).extend(0.0),
+                        (r.col(1) * scale.y).extend(0.0),
+                        (r.col(2) * scale.z).extend(0.0),
+                        translation.extend(1.0))
+    }
+
+    /// View matrix for a camera at `eye` looking towards `target`.
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        let forward = (target - eye).normalize();
+        let right = forward.cross(up).normalize();
+        let up = right.cross(forward);
+        Mat4([
+            [right.x, up.x, -forward.x, 0.0],
+            [right.y, up.y, -forward.y, 0.0],
+            [right.z, up.z, -forward.z, 0.0],
+            [-right.dot(eye), -up.dot(eye), forward.dot(eye), 1.0],
+        ])
+    }
+
+    /// Perspective projection with a vertical field of view in radians and
+    /// `aspect` = width / height. Maps view-space -Z into depth 0 (`near`)
+    /// to 1 (`far`), with +Y pointing up the screen.
+    pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+        let f = 1.0 / (fov_y * 0.5).tan();
+        Mat4([
+            [f / aspect, 0.0, 0.0, 0.0],
+            [0.0, -f, 0.0, 0.0],
+            [0.0, 0.0, far / (near - far), -1.0],
+            [0.0, 0.0, near * far / (near - far), 0.0],
+        ])
+    }
+
+    /// Orthographic projection of the given view-space box, with `top`
+    /// appearing at the top of the screen and depth as for `perspective`.
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+        Mat4([
+            [2.0 / (right - left), 0.0, 0.0, 0.0],
+            [0.0, -2.0 / (top - bottom), 0.0, 0.0],
+            [0.0, 0.0, -1.0 / (far - near), 0.0],
+            [-(right + left) / (right - left), (top + bottom) / (top - bottom), -near / (far - near), 1.0],
+        ])
+    }
+
+    pub fn col(&self, index: usize) -> Vec4 {
+        self.0[index].into()
+    }
+
+    /// The upper-left 3x3: rotation and scale, without translation.
+    pub fn to_mat3(&self) -> Mat3 {
+        Mat3::from_cols(self.col(0).truncate(), self.col(1).truncate(), self.col(2).truncate())
+    }
+
+    /// Matrix for transforming normals, which stay perpendicular to surfaces
+    /// under non-uniform scale.
+    pub fn normal_matrix(&self) -> Mat3 {
+        self.to_mat3().inverse().unwrap_or_else(Mat3::identity).transpose()
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (c, column) in m.iter_mut().enumerate() {
+            for (r, value) in column.iter_mut().enumerate() {
+                *value = self.0[r][c];
+            }
+        }
+        Mat4(m)
+    }
+
+    /// The inverse, or `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        // Cofactor expansion via 2x2 sub-determinants of the top and bottom
+        // row pairs.
+        let m = &self.0;
+        let s0 = m[0][0] * m[1][1] - m[1][0] * m[0][1];
+        let s1 = m[0][0] * m[1][2] - m[1][0] * m[0][2];
+        let s2 = m[0][0] * m[1][3] - m[1][0] * m[0][3];
+        let s3 = m[0][1] * m[1][2] - m[1][1] * m[0][2];
+        let s4 = m[0][1] * m[1][3] - m[1][1] * m[0][3];
+        let s5 = m[0][2] * m[1][3] - m[1][2] * m[0][3];
+
+        let c5 = m[2][2] * m[3][3] - m[3][2] * m[2][3];
+        let c4 = m[2][1] * m[3][3] - m[3][1] * m[2][3];
+        let c3 = m[2][1] * m[3][2] - m[3][1] * m[2][2];
+        let c2 = m[2][0] * m[3][3] - m[3][0] * m[2][3];
+        let c1 = m[2][0] * m[3][2] - m[3][0] * m[2][2];
+        let c0 = m[2][0] * m[3][1] - m[3][0] * m[2][1];
+
+        let det = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+        if det == 0.0 {
+            return None;
+        }
+        let inv = 1.0 / det;
+
+        Some(Mat4([
+            [
+                (m[1][1] * c5 - m[1][2] * c4 + m[1][3] * c3) * inv,
+                (-m[0][1] * c5 + m[0][2] * c4 - m[0][3] * c3) * inv,
+                (m[3][1] * s5 - m[3][2] * s4 + m[3][3] * s3) * inv,
+                (-m[2][1] * s5 + m[2][2] * s4 - m[2][3] * s3) * inv,
+            ],
+            [
+                (-m[1][0] * c5 + m[1][2] * c2 - m[1][3] * c1) * inv,
+                (m[0][0] * c5 - m[0][2] * c2 + m[0][3] * c1) * inv,
+                (-m[3][0] * s5 + m[3][2] * s2 - m[3][3] * s1) * inv,
+                (m[2][0] * s5 - m[2][2] * s2 + m[2][3] * s1) * inv,
+            ],
+            [
+                (m[1][0] * c4 - m[1][1] * c2 + m[1][3] * c0) * inv,
+                (-m[0][0] * c4 + m[0][1] * c2 - m[0][3] * c0) * inv,
+                (m[3][0] * s4 - m[3][1] * s2 + m[3][3] * s0) * inv,
+                (-m[2][0] * s4 + m[2][1] * s2 - m[2][3] * s0) * inv,
+            ],
+            [
+                (-m[1][0] * c3 + m[1][1] * c1 - m[1][2] * c0) * inv,
+                (m[0][0] * c3 - m[0][1] * c1 + m[0][2] * c0) * inv,
+                (-m[3][0] * s3 + m[3][1] * s1 - m[3][2] * s0) * inv,
+                (m[2][0] * s3 - m[2][1] * s1 + m[2][2] * s0) * inv,
+            ],
+        ]))
+    }
+
+    /// Transform a point, including translation and perspective divide.
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let v = *self * p.extend(1.0);
+        v.truncate() / v.w
+    }
+
+    /// Transform a direction, ignoring translation.
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        (*self * v.extend(0.0)).truncate()
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        Mat4::from_cols(self * other.col(0), self * other.col(1), self * other.col(2), self * other.col(3))
+    }
+}
+
+impl Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+    fn mul(self, v: Vec4) -> Vec4 {
+        self.col(0) * v.x + self.col(1) * v.y + self.col(2) * v.z + self.col(3) * v.w
+    }
+}
+
+impl Index<usize> for Mat4 {
+    type Output = [f32; 4];
+    fn index(&self, column: usize) -> &[f32; 4] {
+        &self.0[column]
+    }
+}
+
+impl IndexMut<usize> for Mat4 {
+    fn index_mut(&mut self, column: usize) -> &mut [f32; 4] {
+        &mut self.0[column]
+    }
+}
+
+impl From<Mat4> for [[f32; 4]; 4] {
+    fn from(m: Mat4) -> [[f32; 4]; 4] {
+        m.0
+    }
+}
+
+impl From<[[f32; 4]; 4]> for Mat4 {
+    fn from(m: [[f32; 4]; 4]) -> Mat4 {
+        Mat4(m)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f32::consts::PI;
+
+    fn approx_eq(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    fn assert_vec3(a: Vec3, b: Vec3) {
+        assert!(approx_eq(a.x, b.x) && approx_eq(a.y, b.y) && approx_eq(a.z, b.z),
+                "{:?} != {:?}",
+                a,
+                b);
+    }
+
+    fn assert_mat4(a: Mat4, b: Mat4) {
+        for c in 0..4 {
+            for r in 0..4 {
+                assert!(approx_eq(a[c][r], b[c][r]), "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn perspective_maps_near_and_far_to_zero_and_one() {
+        let projection = Mat4::perspective(PI / 2.0, 2.0, 0.5, 100.0);
+        assert!(approx_eq(projection.transform_point(Vec3::new(0.0, 0.0, -0.5)).z, 0.0));
+        assert!(approx_eq(projection.transform_point(Vec3::new(0.0, 0.0, -100.0)).z, 1.0));
+
+        // A 90 degree field of view reaches the top edge at y = -z, and
+        // +Y in view space is up, which is -Y in clip space.
+        let top = projection.transform_point(Vec3::new(0.0, 10.0, -10.0));
+        assert!(approx_eq(top.y, -1.0));
+        let right = projection.transform_point(Vec3::new(20.0, 0.0, -10.0));
+        assert!(approx_eq(right.x, 1.0));
+    }
+
+    #[test]
+    fn orthographic_maps_the_box_to_clip_space() {
+        let projection = Mat4::orthographic(-4.0, 4.0, -2.0, 2.0, 1.0, 11.0);
+        assert_vec3(projection.transform_point(Vec3::new(-4.0, 2.0, -1.0)), Vec3::new(-1.0, -1.0, 0.0));
+        assert_vec3(projection.transform_point(Vec3::new(4.0, -2.0, -11.0)), Vec3::new(1.0, 1.0, 1.0));
+
+        // Screen-space style: top at y = 0, so Y points down.
+        let screen = Mat4::orthographic(0.0, 640.0, 480.0, 0.0, -1.0, 1.0);
+        assert_vec3(screen.transform_point(Vec3::new(0.0, 0.0, 0.0)), Vec3::new(-1.0, -1.0, 0.5));
+        assert_vec3(screen.transform_point(Vec3::new(640.0, 480.0, 0.0)), Vec3::new(1.0, 1.0, 0.5));
+    }
+
+    #[test]
+    fn look_at_puts_the_target_on_negative_z() {
+        let eye = Vec3::new(3.0, 4.0, 5.0);
+        let target = Vec3::new(-1.0, 2.0, 0.0);
+        let view = Mat4::look_at(eye, target, Vec3::unit_y());
+
+        assert_vec3(view.transform_point(eye), Vec3::zero());
+        let distance = (target - eye).length();
+        assert_vec3(view.transform_point(target), Vec3::new(0.0, 0.0, -distance));
+        // World up stays up.
+        assert!(view.transform_vector(Vec3::unit_y()).y > 0.0);
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let m = Mat4::from_trs(Vec3::new(1.0, -2.0, 3.0),
+                               Quat::from_yaw_pitch_roll(0.3, -0.7, 1.1),
+                               Vec3::new(2.0, 0.5, 3.0));
+        let inverse = m.inverse().unwrap();
+        assert_mat4(inverse * m, Mat4::identity());
+        assert_mat4(m * inverse, Mat4::identity());
+
+        let projection = Mat4::perspective(1.0, 1.5, 0.1, 50.0);
+        assert_mat4(projection.inverse().unwrap() * projection, Mat4::identity());
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        assert_eq!(Mat4::from_scale(Vec3::new(1.0, 0.0, 1.0)).inverse(), None);
+        assert_eq!(Mat3::from_scale(Vec3::new(1.0, 1.0, 0.0)).inverse(), None);
+
+        // Two equal columns.
+        let mut m = Mat4::identity();
+        m[1] = m[0];
+        assert_eq!(m.inverse(), None);
+    }
+
+    #[test]
+    fn quat_round_trips_through_mat3() {
+        // Includes rotations near 180 degrees, which take the other
+        // branches of `from_mat3`.
+        let rotations = [Quat::identity(),
+                         Quat::from_axis_angle(Vec3::new(1.0, 2.0, 3.0), 0.8),
+                         Quat::from_axis_angle(Vec3::unit_x(), PI),
+                         Quat::from_axis_angle(Vec3::unit_y(), 3.1),
+                         Quat::from_axis_angle(Vec3::new(0.0, 1.0, 1.0), -3.0),
+                         Quat::from_yaw_pitch_roll(2.0, -1.0, 0.5)];
+        for &q in &rotations {
+            let back = Quat::from_mat3(&Mat3::from_quat(q));
+            // q and -q are the same rotation.
+            assert!(approx_eq(back.dot(q).abs(), 1.0), "{:?} came back as {:?}", q, back);
+
+            let v = Vec3::new(0.3, -1.0, 2.0);
+            assert_vec3(Mat3::from_quat(q) * v, q.rotate(v));
+            assert_vec3(Mat4::from_quat(q).to_mat3() * v, q.rotate(v));
+        }
+    }
+
+    #[test]
+    fn arrays_are_column_major() {
+        let m = Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0));
+        let array: [[f32; 4]; 4] = m.into();
+        // The translation is the last column, i.e. the last inner array.
+        assert_eq!(array[3], [1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(array[0], [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(Mat4::from(array), m);
+        assert_eq!(m.col(3), Vec4::new(1.0, 2.0, 3.0, 1.0));
+
+        let rows = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]];
+        let m3: [[f32; 3]; 3] = Mat3::from_cols(rows[0].into(), rows[1].into(), rows[2].into()).into();
+        assert_eq!(m3, rows);
+    }
+    #[test]
+    fn rects_overlap_only_when_they_share_area() {
+        let rect = Rect2::from_corners(Vec2::new(1.0, 2.0), Vec2::new(4.0, 6.0));
+        assert_eq!(rect, Rect2::new(1.0, 2.0, 3.0, 4.0));
+
+        assert!(rect.overlaps(Rect2::new(3.0, 5.0, 10.0, 10.0)));
+        assert!(rect.overlaps(Rect2::new(2.0, 3.0, 1.0, 1.0)));
+        // Touching edges, and rectangles that are apart on only one axis.
+        assert!(!rect.overlaps(Rect2::new(4.0, 2.0, 1.0, 4.0)));
+        assert!(!rect.overlaps(Rect2::new(1.0, -1.0, 3.0, 3.0)));
+        assert!(!rect.overlaps(Rect2::new(2.0, 7.0, 1.0, 1.0)));
+    }
+}
@@ -0,0 +1,140 @@
+use gfx_hal::Backend;
+use prelude::*;
+use utils;
+
+pub mod normals;
+pub mod obj;
+pub mod primitives;
+pub mod tangents;
+mod vector;
+
+
+/// The vertex layout used by loaded and generated meshes.
+///
+/// `tangent.xyz` points along increasing U; `w` is the sign that makes
+/// `cross(normal, tangent.xyz) * w` point along increasing V. Meshes that
+/// don't provide tangents leave it zeroed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub tangent: [f32; 4],
+}
+
+impl Vertex {
+    /// Attribute descriptions for a pipeline reading this layout from the
+    /// given vertex buffer binding: position, normal, uv, then tangent.
+    pub fn attributes(binding: u32) -> Vec<AttributeDesc> {
+        vec![
+            AttributeDesc {
+                location: 0,
+                binding,
+                element: Element {
+                    format: Format::Rgb32Float,
+                    offset: 0,
+                },
+            },
+            AttributeDesc {
+                location: 1,
+                binding,
+                element: Element {
+                    format: Format::Rgb32Float,
+                    offset: 12,
+                },
+            },
+            AttributeDesc {
+                location: 2,
+                binding,
+                element: Element {
+                    format: Format::Rg32Float,
+                    offset: 24,
+                },
+            },
+            AttributeDesc {
+                location: 3,
+                binding,
+                element: Element {
+                    format: Format::Rgba32Float,
+                    offset: 32,
+                },
+            },
+        ]
+    }
+}
+
+
+/// Indexed triangle list.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new() -> Self {
+        Mesh::default()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Append another mesh, offsetting its indices.
+    pub fn append(&mut self, other: &Mesh) {
+        let base = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|index| index + base));
+    }
+
+    pub fn create_buffers<B: Backend>(&self,
+                                      device: &B::Device,
+                                      memory_types: &[MemoryType]) -> MeshBuffers<B> {
+        let (vertex_buffer, vertex_memory) = utils::create_buffer::<B, Vertex>(device,
+                                                                               memory_types,
+                                                                               Properties::CPU_VISIBLE,
+                                                                               buffer::Usage::VERTEX,
+                                                                               &self.vertices);
+        let (index_buffer, index_memory) = utils::create_buffer::<B, u32>(device,
+                                                                          memory_types,
+                                                                          Properties::CPU_VISIBLE,
+                                                                          buffer::Usage::INDEX,
+                                                                          &self.indices);
+        MeshBuffers {
+            vertex_buffer,
+            vertex_memory,
+            index_buffer,
+            index_memory,
+            index_count: self.indices.len() as u32,
+        }
+    }
+}
+
+
+/// A mesh's vertex and index buffers on the device. Bind the vertex buffer
+/// and `index_buffer_view()`, then `draw_indexed(0..index_count, 0, 0..1)`.
+pub struct MeshBuffers<B: Backend> {
+    pub vertex_buffer: B::Buffer,
+    pub vertex_memory: B::Memory,
+    pub index_buffer: B::Buffer,
+    pub index_memory: B::Memory,
+    pub index_count: u32,
+}
+
+impl<B: Backend> MeshBuffers<B> {
+    pub fn index_buffer_view(&self) -> IndexBufferView<B> {
+        IndexBufferView {
+            buffer: &self.index_buffer,
+            offset: 0,
+            index_type: IndexType::U32,
+        }
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        device.destroy_buffer(self.vertex_buffer);
+        device.free_memory(self.vertex_memory);
+        device.destroy_buffer(self.index_buffer);
+        device.free_memory(self.index_memory);
+    }
+}
@@ -0,0 +1,220 @@
+// Normal generation for meshes that arrive without them.
+//
+// Normals are worked out per triangle corner and then written back into the
+// vertex list, splitting a vertex wherever the corners that share it end up
+// with different normals (as happens along hard edges, or everywhere for
+// flat shading). Corners are matched by position rather than by index, so
+// vertices split by UV seams are still smoothed across; positions closer
+// than a small fraction of the mesh's size count as the same.
+
+use super::{Mesh, Vertex};
+use super::vector::{add, angle_between, cross, dot, normalize, scale, sub};
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+
+/// Smooth normals everywhere, averaging over every face at a position.
+pub fn smooth_normals(mesh: &mut Mesh) {
+    normals_with_threshold(mesh, PI);
+}
+
+/// One normal per face, giving a faceted look.
+pub fn flat_normals(mesh: &mut Mesh) {
+    normals_with_threshold(mesh, -1.0);
+}
+
+/// Smooth normals, except across edges where adjacent faces meet at more
+/// than `threshold` radians, which are kept hard.
+pub fn normals_with_threshold(mesh: &mut Mesh, threshold: f32) {
+    let triangle_count = mesh.indices.len() / 3;
+    let position = |mesh: &Mesh, corner: usize| mesh.vertices[mesh.indices[corner] as usize].position;
+
+    // Unit face normals, plus the angle each triangle makes at each of its
+    // corners, which is how much it contributes to that corner's normal.
+    let mut face_normals = Vec::with_capacity(triangle_count);
+    let mut corner_angles = Vec::with_capacity(triangle_count * 3);
+    for triangle in 0..triangle_count {
+        let p = [position(mesh, triangle * 3), position(mesh, triangle * 3 + 1), position(mesh, triangle * 3 + 2)];
+        face_normals.push(normalize(cross(sub(p[1], p[0]), sub(p[2], p[0]))));
+        for corner in 0..3 {
+            let here = p[corner];
+            let next = p[(corner + 1) % 3];
+            let prev = p[(corner + 2) % 3];
+            corner_angles.push(angle_between(sub(next, here), sub(prev, here)));
+        }
+    }
+
+    // Bucket corners into cells of the weld distance, so nearby corners can
+    // be found by checking neighbouring cells.
+    let extent = mesh.vertices.iter().fold(0.0f32, |extent, vertex| {
+        vertex.position.iter().fold(extent, |extent, x| extent.max(x.abs()))
+    });
+    let weld = (extent * 1e-5).max(::std::f32::MIN_POSITIVE);
+    let cell = |p: [f32; 3]| [(p[0] / weld).floor() as i64, (p[1] / weld).floor() as i64, (p[2] / weld).floor() as i64];
+    let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    for corner in 0..triangle_count * 3 {
+        cells.entry(cell(position(mesh, corner)))
+             .or_insert_with(Vec::new)
+             .push(corner);
+    }
+
+    let cos_threshold = threshold.cos();
+    let mut corner_normals = Vec::with_capacity(triangle_count * 3);
+    for corner in 0..triangle_count * 3 {
+        let face_normal = face_normals[corner / 3];
+        let mut normal = [0.0; 3];
+        if threshold >= 0.0 {
+            let here = position(mesh, corner);
+            let [x, y, z] = cell(here);
+            for neighbour in (x - 1..x + 2).flat_map(|x| (y - 1..y + 2).flat_map(move |y| (z - 1..z + 2).map(move |z| [x, y, z]))) {
+                for &other in cells.get(&neighbour).map(|corners| corners.as_slice()).unwrap_or(&[]) {
+                    let offset = sub(position(mesh, other), here);
+                    let other_normal = face_normals[other / 3];
+                    let same_face = other / 3 == corner / 3;
+                    if (same_face && other == corner) ||
+                       (!same_face && dot(offset, offset) <= weld * weld && dot(face_normal, other_normal) >= cos_threshold) {
+                        normal = add(normal, scale(other_normal, corner_angles[other]));
+                    }
+                }
+            }
+        }
+        corner_normals.push(if dot(normal, normal) > 0.0 { normalize(normal) } else { face_normal });
+    }
+
+    assign_corner_normals(mesh, &corner_normals);
+}
+
+
+// Write per-corner normals back, sharing a vertex between corners only if
+// they agree on its normal.
+fn assign_corner_normals(mesh: &mut Mesh, corner_normals: &[[f32; 3]]) {
+    let mut vertices = Vec::with_capacity(mesh.vertices.len());
+    let mut remap: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+    for (corner, &normal) in corner_normals.iter().enumerate() {
+        let original = mesh.indices[corner];
+        let index = *remap.entry((original, normal_key(normal))).or_insert_with(|| {
+            vertices.push(Vertex {
+                normal,
+                ..mesh.vertices[original as usize]
+            });
+            vertices.len() as u32 - 1
+        });
+        mesh.indices[corner] = index;
+    }
+    mesh.vertices = vertices;
+}
+
+fn normal_key(n: [f32; 3]) -> [u32; 3] {
+    // Treat -0.0 and 0.0 as the same.
+    [(n[0] + 0.0).to_bits(), (n[1] + 0.0).to_bits(), (n[2] + 0.0).to_bits()]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+        let d = sub(a, b);
+        dot(d, d) < EPSILON * EPSILON
+    }
+
+    // A unit cube with its eight corners shared between faces, wound
+    // counter-clockwise from outside, and no normals.
+    fn shared_cube() -> Mesh {
+        let vertices = (0..8).map(|i| Vertex {
+            position: [(i & 1) as f32 * 2.0 - 1.0, (i >> 1 & 1) as f32 * 2.0 - 1.0, (i >> 2 & 1) as f32 * 2.0 - 1.0],
+            ..Vertex::default()
+        }).collect();
+        let quads: [[u32; 4]; 6] = [
+            [1, 3, 7, 5], [0, 4, 6, 2],
+            [2, 6, 7, 3], [0, 1, 5, 4],
+            [4, 5, 7, 6], [0, 2, 3, 1],
+        ];
+        let indices = quads.iter().flat_map(|q| vec![q[0], q[1], q[2], q[0], q[2], q[3]]).collect();
+        Mesh { vertices, indices }
+    }
+
+    // Each corner's normal, in index order.
+    fn corner_normals(mesh: &Mesh) -> Vec<[f32; 3]> {
+        mesh.indices.iter().map(|&index| mesh.vertices[index as usize].normal).collect()
+    }
+
+    fn assert_outward_face_normals(mesh: &Mesh) {
+        assert_eq!(mesh.vertices.len(), 24);
+        for triangle in mesh.indices.chunks(3) {
+            let normals = triangle.iter().map(|&index| mesh.vertices[index as usize].normal).collect::<Vec<_>>();
+            let position = mesh.vertices[triangle[0] as usize].position;
+            assert!(normals.iter().all(|&normal| close(normal, normals[0])));
+            // Axis-aligned, and pointing the way of the face's position.
+            assert_eq!(normals[0].iter().filter(|x| x.abs() > 0.5).count(), 1);
+            assert!((dot(normals[0], position) - 1.0).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn smooth_normals_average_every_face() {
+        let mut mesh = shared_cube();
+        smooth_normals(&mut mesh);
+        assert_eq!(mesh.vertices.len(), 8);
+        for vertex in &mesh.vertices {
+            assert!(close(vertex.normal, normalize(vertex.position)), "{:?}", vertex);
+        }
+    }
+
+    #[test]
+    fn flat_normals_split_every_corner() {
+        let mut mesh = shared_cube();
+        flat_normals(&mut mesh);
+        assert_outward_face_normals(&mesh);
+    }
+
+    #[test]
+    fn threshold_keeps_sharp_edges_hard() {
+        // The cube's faces meet at 90 degrees.
+        let mut hard = shared_cube();
+        normals_with_threshold(&mut hard, 80.0f32.to_radians());
+        assert_outward_face_normals(&hard);
+
+        let mut soft = shared_cube();
+        normals_with_threshold(&mut soft, 100.0f32.to_radians());
+        assert_eq!(soft.vertices.len(), 8);
+        assert_eq!(corner_normals(&soft), {
+            let mut smooth = shared_cube();
+            smooth_normals(&mut smooth);
+            corner_normals(&smooth)
+        });
+    }
+
+    #[test]
+    fn smooths_across_uv_seams() {
+        // Two triangles folded 30 degrees along the Y axis, with the edge's
+        // vertices duplicated (as a UV seam would) rather than shared.
+        let (sin, cos) = 30.0f32.to_radians().sin_cos();
+        let vertex = |position: [f32; 3], u: f32| Vertex { position, uv: [u, 0.0], ..Vertex::default() };
+        let mut mesh = Mesh {
+            vertices: vec![
+                vertex([0.0, 0.0, 0.0], 0.0), vertex([-1.0, 0.0, 0.0], 0.0), vertex([0.0, 1.0, 0.0], 0.0),
+                vertex([0.0, 0.0, 0.0], 1.0), vertex([0.0, 1.0, 0.0], 1.0), vertex([cos, 0.0, sin], 1.0),
+            ],
+            indices: vec![0, 2, 1, 3, 5, 4],
+        };
+        normals_with_threshold(&mut mesh, 45.0f32.to_radians());
+
+        // Each seam position keeps both its vertices, but they agree on a
+        // normal halfway between the faces'.
+        assert_eq!(mesh.vertices.len(), 6);
+        let halfway = normalize(add([0.0, 0.0, 1.0], [-sin, 0.0, cos]));
+        for vertex in &mesh.vertices {
+            let expected = match vertex.position[0] {
+                x if x < 0.0 => [0.0, 0.0, 1.0],
+                x if x > 0.0 => [-sin, 0.0, cos],
+                _ => halfway,
+            };
+            assert!(close(vertex.normal, expected), "{:?}", vertex);
+        }
+    }
+}
@@ -0,0 +1,627 @@
+// Wavefront OBJ and MTL loading.
+//
+// Faces with more than three corners are fan-triangulated, negative indices
+// count back from the most recently defined element, and identical
+// position/uv/normal triples are merged into a single vertex. A new group is
+// started whenever `o`, `g` or `usemtl` changes after faces have been added.
+//
+// OBJ texture coordinates have V pointing up; they're flipped on load so they
+// match the top-down image layout textures are uploaded in.
+
+use super::{Mesh, Vertex};
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(err) => write!(f, "I/O error: {}", err),
+            ObjError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+impl From<io::Error> for ObjError {
+    fn from(err: io::Error) -> Self {
+        ObjError::Io(err)
+    }
+}
+
+fn parse_error<T, S: Into<String>>(line: usize, message: S) -> Result<T, ObjError> {
+    Err(ObjError::Parse {
+        line,
+        message: message.into(),
+    })
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub emissive: [f32; 3],
+    pub shininess: f32,
+    pub dissolve: f32,
+    pub diffuse_map: Option<String>,
+    pub specular_map: Option<String>,
+    pub normal_map: Option<String>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> Self {
+        ObjMaterial {
+            name: name.to_owned(),
+            ambient: [0.0; 3],
+            diffuse: [0.8, 0.8, 0.8],
+            specular: [0.0; 3],
+            emissive: [0.0; 3],
+            shininess: 0.0,
+            dissolve: 1.0,
+            diffuse_map: None,
+            specular_map: None,
+            normal_map: None,
+        }
+    }
+}
+
+
+/// A run of faces sharing an object, group and material.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjGroup {
+    pub object: String,
+    pub group: String,
+    pub material: Option<String>,
+    pub mesh: Mesh,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjModel {
+    pub groups: Vec<ObjGroup>,
+    pub materials: Vec<ObjMaterial>,
+    /// `mtllib` file names, as written in the OBJ.
+    pub material_libs: Vec<String>,
+}
+
+impl ObjModel {
+    pub fn material(&self, name: &str) -> Option<&ObjMaterial> {
+        self.materials.iter().find(|material| material.name == name)
+    }
+
+    /// Merge every group into a single mesh, dropping material assignments.
+    pub fn to_mesh(&self) -> Mesh {
+        let mut mesh = Mesh::new();
+        for group in &self.groups {
+            mesh.append(&group.mesh);
+        }
+        mesh
+    }
+}
+
+
+/// Load an OBJ file along with any MTL libraries it references, which are
+/// looked up relative to the OBJ file.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let mut model = parse_obj(&fs::read_to_string(path)?)?;
+
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    for lib in &model.material_libs {
+        let source = fs::read_to_string(base.join(lib))?;
+        model.materials.extend(parse_mtl(&source)?);
+    }
+
+    Ok(model)
+}
+
+
+// Vertex lookup key: position, uv and normal indices into the OBJ's arrays.
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+struct GroupBuilder {
+    group: ObjGroup,
+    lookup: HashMap<VertexKey, u32>,
+}
+
+impl GroupBuilder {
+    fn new(object: &str, group: &str, material: Option<&str>) -> Self {
+        GroupBuilder {
+            group: ObjGroup {
+                object: object.to_owned(),
+                group: group.to_owned(),
+                material: material.map(|name| name.to_owned()),
+                mesh: Mesh::new(),
+            },
+            lookup: HashMap::new(),
+        }
+    }
+}
+
+pub fn parse_obj(source: &str) -> Result<ObjModel, ObjError> {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+
+    let mut model = ObjModel::default();
+    let mut object = String::new();
+    let mut group = String::new();
+    let mut material: Option<String> = None;
+    let mut current = GroupBuilder::new("", "", None);
+    let mut corners = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => positions.push(parse_floats(line_number, words, 3)?.as_vec3()),
+            "vn" => normals.push(parse_floats(line_number, words, 3)?.as_vec3()),
+            "vt" => {
+                let uv = parse_floats(line_number, words, 1)?;
+                uvs.push([uv.0[0], 1.0 - uv.0.get(1).cloned().unwrap_or(0.0)]);
+            }
+            "f" => {
+                corners.clear();
+                for word in words {
+                    corners.push(parse_corner(line_number,
+                                              word,
+                                              positions.len(),
+                                              uvs.len(),
+                                              normals.len())?);
+                }
+                if corners.len() < 3 {
+                    return parse_error(line_number, "face needs at least three vertices");
+                }
+
+                let mut indices = Vec::with_capacity(corners.len());
+                for &key in &corners {
+                    let mesh = &mut current.group.mesh;
+                    let index = *current.lookup.entry(key).or_insert_with(|| {
+                        let (position, uv, normal) = key;
+                        mesh.vertices.push(Vertex {
+                            position: positions[position],
+                            normal: normal.map(|n| normals[n]).unwrap_or([0.0; 3]),
+                            uv: uv.map(|t| uvs[t]).unwrap_or([0.0; 2]),
+                            tangent: [0.0; 4],
+                        });
+                        mesh.vertices.len() as u32 - 1
+                    });
+                    indices.push(index);
+                }
+
+                for i in 1..indices.len() - 1 {
+                    current.group.mesh.indices.extend_from_slice(&[indices[0], indices[i], indices[i + 1]]);
+                }
+            }
+            "o" | "g" | "usemtl" => {
+                let name = words.collect::<Vec<_>>().join(" ");
+                match keyword {
+                    "o" => object = name,
+                    "g" => group = name,
+                    _ => material = Some(name),
+                }
+
+                if current.group.mesh.indices.is_empty() {
+                    current.group.object = object.clone();
+                    current.group.group = group.clone();
+                    current.group.material = material.clone();
+                } else {
+                    let next = GroupBuilder::new(&object, &group, material.as_ref().map(|m| m.as_str()));
+                    model.groups.push(::std::mem::replace(&mut current, next).group);
+                }
+            }
+            "mtllib" => model.material_libs.extend(words.map(|word| word.to_owned())),
+            // Smoothing groups, lines, points and anything else we don't
+            // draw are skipped.
+            _ => {}
+        }
+    }
+
+    if !current.group.mesh.indices.is_empty() {
+        model.groups.push(current.group);
+    }
+
+    Ok(model)
+}
+
+pub fn parse_mtl(source: &str) -> Result<Vec<ObjMaterial>, ObjError> {
+    let mut materials = Vec::new();
+    let mut current: Option<ObjMaterial> = None;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = words.collect::<Vec<_>>().join(" ");
+            if let Some(material) = current.take() {
+                materials.push(material);
+            }
+            current = Some(ObjMaterial::new(&name));
+            continue;
+        }
+
+        let material = match current.as_mut() {
+            Some(material) => material,
+            None => return parse_error(line_number, format!("'{}' before any newmtl", keyword)),
+        };
+
+        // Texture maps can carry options before the file name; the file name
+        // is always last.
+        let map_name = |words: ::std::str::SplitWhitespace| -> Result<String, ObjError> {
+            match words.last() {
+                Some(name) => Ok(name.to_owned()),
+                None => parse_error(line_number, "missing texture file name"),
+            }
+        };
+
+        match keyword {
+            "Ka" => material.ambient = parse_floats(line_number, words, 3)?.as_vec3(),
+            "Kd" => material.diffuse = parse_floats(line_number, words, 3)?.as_vec3(),
+            "Ks" => material.specular = parse_floats(line_number, words, 3)?.as_vec3(),
+            "Ke" => material.emissive = parse_floats(line_number, words, 3)?.as_vec3(),
+            "Ns" => material.shininess = parse_floats(line_number, words, 1)?.0[0],
+            "d" => material.dissolve = parse_floats(line_number, words, 1)?.0[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats(line_number, words, 1)?.0[0],
+            "map_Kd" => material.diffuse_map = Some(map_name(words)?),
+            "map_Ks" => material.specular_map = Some(map_name(words)?),
+            "map_Bump" | "map_bump" | "bump" | "norm" => material.normal_map = Some(map_name(words)?),
+            _ => {}
+        }
+    }
+
+    if let Some(material) = current {
+        materials.push(material);
+    }
+
+    Ok(materials)
+}
+
+
+struct Floats(Vec<f32>);
+
+impl Floats {
+    fn as_vec3(&self) -> [f32; 3] {
+        [self.0[0], self.0[1], self.0[2]]
+    }
+}
+
+// Parse at least `min` floats; extras (such as the optional w in `v`) are
+// kept but generally ignored.
+fn parse_floats<'a, I>(line: usize, words: I, min: usize) -> Result<Floats, ObjError>
+    where I: Iterator<Item = &'a str>
+{
+    let mut values = Vec::with_capacity(min);
+    for word in words {
+        match word.parse::<f32>() {
+            Ok(value) => values.push(value),
+            Err(_) => return parse_error(line, format!("invalid number '{}'", word)),
+        }
+    }
+    if values.len() < min {
+        return parse_error(line, format!("expected {} numbers, found {}", min, values.len()));
+    }
+    Ok(Floats(values))
+}
+
+// Resolve a single OBJ index (1-based, or negative for relative) against the
+// number of elements defined so far.
+fn resolve_index(line: usize, word: &str, count: usize, what: &str) -> Result<usize, ObjError> {
+    let index = match word.parse::<i64>() {
+        Ok(index) => index,
+        Err(_) => return parse_error(line, format!("invalid {} index '{}'", what, word)),
+    };
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return parse_error(line, format!("{} index {} out of range", what, index));
+    }
+    Ok(resolved as usize)
+}
+
+fn parse_corner(line: usize,
+                word: &str,
+                position_count: usize,
+                uv_count: usize,
+                normal_count: usize) -> Result<VertexKey, ObjError> {
+    let mut parts = word.split('/');
+    let position = resolve_index(line, parts.next().unwrap_or(""), position_count, "position")?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(part) => Some(resolve_index(line, part, uv_count, "texture coordinate")?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(part) => Some(resolve_index(line, part, normal_count, "normal")?),
+    };
+    if parts.next().is_some() {
+        return parse_error(line, format!("malformed face vertex '{}'", word));
+    }
+    Ok((position, uv, normal))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_message(source: &str) -> (usize, String) {
+        match parse_obj(source) {
+            Err(ObjError::Parse { line, message }) => (line, message),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    const SQUARE: &'static str = "
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+";
+
+    #[test]
+    fn resolves_negative_indices() {
+        let relative = parse_obj(&format!("{}f -4 -3 -2\n", SQUARE)).unwrap();
+        let absolute = parse_obj(&format!("{}f 1 2 3\n", SQUARE)).unwrap();
+        assert_eq!(relative, absolute);
+
+        // Relative to the vertices defined so far, not the whole file.
+        let model = parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 5 5 5\n").unwrap();
+        let positions = model.groups[0].mesh.vertices.iter().map(|v| v.position).collect::<Vec<_>>();
+        assert_eq!(positions, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+    }
+
+    #[test]
+    fn fan_triangulates_polygons() {
+        let model = parse_obj("
+v 0 0 0
+v 1 0 0
+v 2 1 0
+v 1 2 0
+v 0 1 0
+f 1 2 3 4 5
+").unwrap();
+        let mesh = &model.groups[0].mesh;
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3, 0, 3, 4]);
+    }
+
+    #[test]
+    fn reads_index_combinations() {
+        let model = parse_obj(&format!("{}
+vt 0 0
+vt 1 0.25
+vn 0 0 1
+f 1 2 3
+f 1/1 2/2 3/1
+f 1//1 2//1 3//1
+f 1/2/1 2/1/1 3/2/1
+", SQUARE)).unwrap();
+        let vertices = &model.groups[0].mesh.vertices;
+
+        // Position only: no UV or normal.
+        assert_eq!(vertices[0], Vertex { position: [0.0; 3], ..Vertex::default() });
+        // V is flipped.
+        assert_eq!(vertices[4].uv, [1.0, 0.75]);
+        assert_eq!(vertices[4].normal, [0.0; 3]);
+        // Normal without UV.
+        assert_eq!(vertices[6].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(vertices[6].uv, [0.0, 0.0]);
+        // All three.
+        assert_eq!(vertices[9].uv, [1.0, 0.75]);
+        assert_eq!(vertices[9].normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn merges_identical_corners() {
+        let model = parse_obj(&format!("{}
+vt 0 0
+vt 1 0
+f 1/1 2/2 3/2
+f 1/1 3/2 4/1
+f 1/2 3/2 4/1
+", SQUARE)).unwrap();
+        let mesh = &model.groups[0].mesh;
+        // The second face reuses 1/1 and 3/2; the third only differs from
+        // it in the first corner's UV, which makes a new vertex.
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3, 4, 2, 3]);
+    }
+
+    #[test]
+    fn splits_groups_and_materials() {
+        let model = parse_obj(&format!("{}
+mtllib scene.mtl
+o thing
+g top
+usemtl red
+f 1 2 3
+usemtl blue
+f 1 3 4
+g bottom
+f 3 2 1
+", SQUARE)).unwrap();
+        assert_eq!(model.material_libs, vec!["scene.mtl".to_owned()]);
+
+        let groups = model.groups
+                          .iter()
+                          .map(|group| {
+                              (group.object.as_str(), group.group.as_str(), group.material.as_ref().map(|m| m.as_str()))
+                          })
+                          .collect::<Vec<_>>();
+        assert_eq!(groups, vec![("thing", "top", Some("red")),
+                                ("thing", "top", Some("blue")),
+                                ("thing", "bottom", Some("blue"))]);
+
+        // Each group has its own vertices and indices.
+        for group in &model.groups {
+            assert_eq!(group.mesh.vertices.len(), 3);
+            assert_eq!(group.mesh.indices, vec![0, 1, 2]);
+        }
+        assert_eq!(model.to_mesh().triangle_count(), 3);
+    }
+
+    #[test]
+    fn skips_empty_groups() {
+        let model = parse_obj(&format!("{}g a\ng b\nusemtl m\nf 1 2 3\n", SQUARE)).unwrap();
+        assert_eq!(model.groups.len(), 1);
+        assert_eq!(model.groups[0].group, "b");
+        assert_eq!(model.groups[0].material, Some("m".to_owned()));
+    }
+
+    #[test]
+    fn reports_bad_faces_with_line_numbers() {
+        let (line, message) = parse_message(&format!("{}f 1 2\n", SQUARE));
+        assert_eq!(line, 6);
+        assert!(message.contains("three vertices"), "{}", message);
+
+        let (line, message) = parse_message(&format!("{}f 1 2 5\n", SQUARE));
+        assert_eq!(line, 6);
+        assert!(message.contains("out of range"), "{}", message);
+
+        let (line, _) = parse_message(&format!("{}f 0 1 2\n", SQUARE));
+        assert_eq!(line, 6);
+
+        let (line, message) = parse_message(&format!("{}f 1/1 2 3\n", SQUARE));
+        assert_eq!(line, 6);
+        assert!(message.contains("texture coordinate"), "{}", message);
+
+        let (line, message) = parse_message(&format!("{}f 1/x 2 3\n", SQUARE));
+        assert_eq!(line, 6);
+        assert!(message.contains("invalid"), "{}", message);
+
+        let (line, message) = parse_message(&format!("{}f 1/// 2 3\n", SQUARE));
+        assert_eq!(line, 6);
+        assert!(message.contains("malformed"), "{}", message);
+    }
+
+    #[test]
+    fn reports_bad_vertices_with_line_numbers() {
+        let (line, message) = parse_message("v 0 0 0\n\n# comment\nv 1 nope 0\n");
+        assert_eq!(line, 4);
+        assert!(message.contains("'nope'"), "{}", message);
+
+        let (line, message) = parse_message("v 0 0\n");
+        assert_eq!(line, 1);
+        assert!(message.contains("expected 3"), "{}", message);
+    }
+
+    #[test]
+    fn parses_materials() {
+        let materials = parse_mtl("
+newmtl red
+Kd 1 0 0
+Ns 32
+d 0.5
+map_Kd -s 2 2 1 textures/red.png
+newmtl plain
+").unwrap();
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].diffuse, [1.0, 0.0, 0.0]);
+        assert_eq!(materials[0].shininess, 32.0);
+        assert_eq!(materials[0].dissolve, 0.5);
+        assert_eq!(materials[0].diffuse_map, Some("textures/red.png".to_owned()));
+        assert_eq!(materials[1], ObjMaterial::new("plain"));
+
+        match parse_mtl("Kd 1 1 1\n") {
+            Err(ObjError::Parse { line: 1, .. }) => {}
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    fn fixture(name: &str) -> ::std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("obj").join(name)
+    }
+
+    #[test]
+    fn loads_obj_with_materials() {
+        let model = load_obj(fixture("crate.obj")).unwrap();
+        assert_eq!(model.material_libs, vec!["materials/crate.mtl".to_owned()]);
+
+        let groups = model.groups
+                          .iter()
+                          .map(|group| {
+                              (group.object.as_str(), group.group.as_str(), group.material.as_ref().map(|m| m.as_str()))
+                          })
+                          .collect::<Vec<_>>();
+        assert_eq!(groups, vec![("crate", "sides", Some("wood")), ("crate", "caps", Some("metal"))]);
+
+        // Corners are only shared within a face, since each face has its
+        // own normal. Quads become two triangles.
+        let sides = &model.groups[0].mesh;
+        assert_eq!((sides.vertices.len(), sides.indices.len()), (16, 24));
+        let caps = &model.groups[1].mesh;
+        assert_eq!((caps.vertices.len(), caps.indices.len()), (8, 12));
+        assert_eq!(caps.vertices[0].position, [0.0, 0.0, 0.0]);
+        assert_eq!(caps.vertices[0].normal, [0.0, -1.0, 0.0]);
+        // "vt 1 1", with V flipped.
+        assert_eq!(caps.vertices[2].uv, [1.0, 0.0]);
+
+        let merged = model.to_mesh();
+        assert_eq!((merged.vertices.len(), merged.indices.len()), (24, 36));
+        assert!(merged.indices.iter().all(|&index| (index as usize) < merged.vertices.len()));
+
+        let wood = model.material("wood").unwrap();
+        assert_eq!(wood.ambient, [0.1, 0.1, 0.1]);
+        assert_eq!(wood.diffuse, [0.6, 0.4, 0.2]);
+        assert_eq!(wood.shininess, 8.0);
+        assert_eq!(wood.diffuse_map, Some("textures/wood.png".to_owned()));
+        let metal = model.material("metal").unwrap();
+        assert_eq!(metal.specular, [0.9, 0.9, 0.9]);
+        assert_eq!(metal.specular_map, Some("textures/metal_spec.png".to_owned()));
+        assert_eq!(metal.normal_map, Some("textures/metal_normal.png".to_owned()));
+        assert_eq!(metal.diffuse_map, None);
+    }
+
+    #[test]
+    fn finds_material_libs_next_to_the_obj() {
+        // The tests run from the crate root, where "materials/crate.mtl"
+        // doesn't exist, so this only loads if the path is taken relative
+        // to the OBJ.
+        assert!(!Path::new("materials/crate.mtl").exists());
+        let model = load_obj(fixture("crate.obj")).unwrap();
+        assert_eq!(model.materials.len(), 2);
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        match load_obj(fixture("missing_mtl.obj")) {
+            Err(ObjError::Io(ref err)) if err.kind() == io::ErrorKind::NotFound => {}
+            other => panic!("expected a missing file error, got {:?}", other),
+        }
+        match load_obj(fixture("nothing_here.obj")) {
+            Err(ObjError::Io(ref err)) if err.kind() == io::ErrorKind::NotFound => {}
+            other => panic!("expected a missing file error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,446 @@
+// Procedural meshes for common shapes.
+//
+// Everything is built in a right-handed, Y-up space and wound counter-
+// clockwise when seen from outside, which is what `Rasterizer::FILL`'s
+// default front face expects once the projection maps world-up to
+// screen-up. UVs run left-to-right and top-to-bottom across each surface,
+// and tangents follow the convention documented on `Vertex`.
+//
+// Most shapes are a (u, v) grid wrapped around something, so they share
+// `build_grid`; triangles that collapse to nothing (such as those touching
+// a sphere's poles) are dropped rather than emitted as degenerates.
+
+use super::{Mesh, Vertex};
+use super::vector::{cross, dot, normalize, scale, sub};
+
+use std::f32::consts::PI;
+
+// Triangles with less than this much (doubled) area are treated as
+// degenerate and skipped.
+const DEGENERATE_AREA: f32 = 1e-10;
+
+
+/// A `width` x `height` rectangle in the XY plane, facing +Z.
+pub fn quad(width: f32, height: f32) -> Mesh {
+    let mut mesh = Mesh::new();
+    build_grid(&mut mesh, 1, 1, |s, t| SurfacePoint {
+        position: [(s - 0.5) * width, (0.5 - t) * height, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        u_direction: [1.0, 0.0, 0.0],
+        v_direction: [0.0, -1.0, 0.0],
+    });
+    mesh
+}
+
+/// A `width` x `depth` rectangle in the XZ plane, facing +Y, split into
+/// `columns` x `rows` cells.
+pub fn plane(width: f32, depth: f32, columns: u32, rows: u32) -> Mesh {
+    let mut mesh = Mesh::new();
+    build_grid(&mut mesh, columns.max(1), rows.max(1), |s, t| SurfacePoint {
+        position: [(s - 0.5) * width, 0.0, (t - 0.5) * depth],
+        normal: [0.0, 1.0, 0.0],
+        u_direction: [1.0, 0.0, 0.0],
+        v_direction: [0.0, 0.0, 1.0],
+    });
+    mesh
+}
+
+/// An axis-aligned cube centred on the origin, with each face split into
+/// `subdivisions` x `subdivisions` cells.
+pub fn cube(size: f32, subdivisions: u32) -> Mesh {
+    // (normal, u direction, v direction) for each face.
+    const FACES: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ];
+
+    let half = size * 0.5;
+    let cells = subdivisions.max(1);
+    let mut mesh = Mesh::new();
+    for &(normal, u_direction, v_direction) in &FACES {
+        build_grid(&mut mesh, cells, cells, |s, t| {
+            let a = (s - 0.5) * size;
+            let b = (t - 0.5) * size;
+            SurfacePoint {
+                position: [
+                    normal[0] * half + u_direction[0] * a + v_direction[0] * b,
+                    normal[1] * half + u_direction[1] * a + v_direction[1] * b,
+                    normal[2] * half + u_direction[2] * a + v_direction[2] * b,
+                ],
+                normal,
+                u_direction,
+                v_direction,
+            }
+        });
+    }
+    mesh
+}
+
+/// A latitude/longitude sphere. `segments` runs around the equator and
+/// `rings` from pole to pole; the seam is duplicated so UVs wrap cleanly.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Mesh {
+    let mut mesh = Mesh::new();
+    build_grid(&mut mesh, segments.max(3), rings.max(2), |s, t| {
+        let (sin_phi, cos_phi) = (s * 2.0 * PI).sin_cos();
+        // Pin the poles exactly so their triangles are recognised as empty.
+        let (sin_theta, cos_theta) = match t {
+            t if t <= 0.0 => (0.0, 1.0),
+            t if t >= 1.0 => (0.0, -1.0),
+            t => (t * PI).sin_cos(),
+        };
+        let normal = [sin_theta * sin_phi, cos_theta, sin_theta * cos_phi];
+        SurfacePoint {
+            position: scale(normal, radius),
+            normal,
+            u_direction: [cos_phi, 0.0, -sin_phi],
+            v_direction: normalize([cos_theta * sin_phi, -sin_theta, cos_theta * cos_phi]),
+        }
+    });
+    mesh
+}
+
+/// A sphere made by repeatedly subdividing an icosahedron. Vertices are
+/// shared between faces, so the spherical UVs have a visible seam; use
+/// `uv_sphere` if the sphere needs to be textured.
+pub fn icosphere(radius: f32, subdivisions: u32) -> Mesh {
+    use std::collections::HashMap;
+
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut positions = vec![
+        [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+        [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+        [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0],
+    ].into_iter().map(normalize).collect::<Vec<_>>();
+    let mut faces: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, positions: &mut Vec<[f32; 3]>| -> u32 {
+            let key = (a.min(b), a.max(b));
+            *midpoints.entry(key).or_insert_with(|| {
+                let (pa, pb) = (positions[a as usize], positions[b as usize]);
+                positions.push(normalize([pa[0] + pb[0], pa[1] + pb[1], pa[2] + pb[2]]));
+                positions.len() as u32 - 1
+            })
+        };
+
+        let mut next = Vec::with_capacity(faces.len() * 4);
+        for &[a, b, c] in &faces {
+            let ab = midpoint(a, b, &mut positions);
+            let bc = midpoint(b, c, &mut positions);
+            let ca = midpoint(c, a, &mut positions);
+            next.extend_from_slice(&[[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]);
+        }
+        faces = next;
+    }
+
+    let mut mesh = Mesh::new();
+    mesh.vertices = positions.iter()
+                             .map(|&normal| {
+                                 let phi = normal[0].atan2(normal[2]);
+                                 let (sin_phi, cos_phi) = phi.sin_cos();
+                                 let theta = normal[1].max(-1.0).min(1.0).acos();
+                                 let (sin_theta, cos_theta) = theta.sin_cos();
+                                 let v_direction = normalize([cos_theta * sin_phi, -sin_theta, cos_theta * cos_phi]);
+                                 Vertex {
+                                     position: scale(normal, radius),
+                                     normal,
+                                     uv: [0.5 + phi / (2.0 * PI), theta / PI],
+                                     tangent: tangent(normal, [cos_phi, 0.0, -sin_phi], v_direction),
+                                 }
+                             })
+                             .collect();
+    for &[a, b, c] in &faces {
+        push_triangle(&mut mesh, a, b, c);
+    }
+    mesh
+}
+
+/// A capped cylinder along the Y axis, centred on the origin.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> Mesh {
+    let segments = segments.max(3);
+    let mut mesh = Mesh::new();
+    build_grid(&mut mesh, segments, 1, |s, t| {
+        let (sin_phi, cos_phi) = (s * 2.0 * PI).sin_cos();
+        SurfacePoint {
+            position: [radius * sin_phi, (0.5 - t) * height, radius * cos_phi],
+            normal: [sin_phi, 0.0, cos_phi],
+            u_direction: [cos_phi, 0.0, -sin_phi],
+            v_direction: [0.0, -1.0, 0.0],
+        }
+    });
+    build_cap(&mut mesh, radius, height * 0.5, segments, true);
+    build_cap(&mut mesh, radius, -height * 0.5, segments, false);
+    mesh
+}
+
+/// A cone along the Y axis with its apex at `height / 2` and a capped base
+/// at `-height / 2`.
+pub fn cone(radius: f32, height: f32, segments: u32) -> Mesh {
+    let segments = segments.max(3);
+    let slant = (radius * radius + height * height).sqrt();
+    let mut mesh = Mesh::new();
+    // One row from apex to base; the apex row collapses, so each segment
+    // contributes a single triangle with its own apex vertex (and normal).
+    build_grid(&mut mesh, segments, 1, |s, t| {
+        let (sin_phi, cos_phi) = (s * 2.0 * PI).sin_cos();
+        SurfacePoint {
+            position: [radius * t * sin_phi, (0.5 - t) * height, radius * t * cos_phi],
+            normal: [height * sin_phi / slant, radius / slant, height * cos_phi / slant],
+            u_direction: [cos_phi, 0.0, -sin_phi],
+            v_direction: [radius * sin_phi / slant, -height / slant, radius * cos_phi / slant],
+        }
+    });
+    build_cap(&mut mesh, radius, -height * 0.5, segments, false);
+    mesh
+}
+
+/// A torus around the Y axis. `major_radius` is the distance from the
+/// centre to the middle of the tube, `minor_radius` the tube's radius.
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> Mesh {
+    let mut mesh = Mesh::new();
+    build_grid(&mut mesh, major_segments.max(3), minor_segments.max(3), |s, t| {
+        let (sin_phi, cos_phi) = (s * 2.0 * PI).sin_cos();
+        let (sin_theta, cos_theta) = (t * 2.0 * PI).sin_cos();
+        let ring = major_radius + minor_radius * cos_theta;
+        SurfacePoint {
+            position: [ring * sin_phi, minor_radius * sin_theta, ring * cos_phi],
+            normal: [cos_theta * sin_phi, sin_theta, cos_theta * cos_phi],
+            u_direction: [cos_phi, 0.0, -sin_phi],
+            v_direction: [-sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi],
+        }
+    });
+    mesh
+}
+
+
+struct SurfacePoint {
+    position: [f32; 3],
+    normal: [f32; 3],
+    // Directions of increasing u and v on the surface, used for the tangent.
+    u_direction: [f32; 3],
+    v_direction: [f32; 3],
+}
+
+// Sample `surface` over a (columns + 1) x (rows + 1) grid of (s, t) values
+// in 0..=1, which also become the UVs.
+fn build_grid<F>(mesh: &mut Mesh, columns: u32, rows: u32, surface: F)
+    where F: Fn(f32, f32) -> SurfacePoint
+{
+    let base = mesh.vertices.len() as u32;
+    for row in 0..rows + 1 {
+        for column in 0..columns + 1 {
+            let s = column as f32 / columns as f32;
+            let t = row as f32 / rows as f32;
+            let point = surface(s, t);
+            mesh.vertices.push(Vertex {
+                position: point.position,
+                normal: point.normal,
+                uv: [s, t],
+                tangent: tangent(point.normal, point.u_direction, point.v_direction),
+            });
+        }
+    }
+
+    let stride = columns + 1;
+    for row in 0..rows {
+        for column in 0..columns {
+            let a = base + row * stride + column;
+            let b = a + 1;
+            let c = a + stride + 1;
+            let d = a + stride;
+            push_triangle(mesh, a, b, c);
+            push_triangle(mesh, a, c, d);
+        }
+    }
+}
+
+// A flat disc at height `y`, facing up or down.
+fn build_cap(mesh: &mut Mesh, radius: f32, y: f32, segments: u32, up: bool) {
+    let normal = if up { [0.0, 1.0, 0.0] } else { [0.0, -1.0, 0.0] };
+    let v_direction = if up { [0.0, 0.0, 1.0] } else { [0.0, 0.0, -1.0] };
+    let tangent = tangent(normal, [1.0, 0.0, 0.0], v_direction);
+    let planar_uv = |x: f32, z: f32| {
+        let v = if up { z } else { -z };
+        [0.5 + x / (2.0 * radius), 0.5 + v / (2.0 * radius)]
+    };
+
+    let centre = mesh.vertices.len() as u32;
+    mesh.vertices.push(Vertex {
+        position: [0.0, y, 0.0],
+        normal,
+        uv: [0.5, 0.5],
+        tangent,
+    });
+    for segment in 0..segments + 1 {
+        let (sin_phi, cos_phi) = (segment as f32 / segments as f32 * 2.0 * PI).sin_cos();
+        let (x, z) = (radius * sin_phi, radius * cos_phi);
+        mesh.vertices.push(Vertex {
+            position: [x, y, z],
+            normal,
+            uv: planar_uv(x, z),
+            tangent,
+        });
+    }
+    for segment in 0..segments {
+        push_triangle(mesh, centre, centre + 1 + segment, centre + 2 + segment);
+    }
+}
+
+// Add a triangle wound counter-clockwise around its vertices' normals,
+// skipping it if it has no area.
+fn push_triangle(mesh: &mut Mesh, a: u32, b: u32, c: u32) {
+    let (va, vb, vc) = (mesh.vertices[a as usize], mesh.vertices[b as usize], mesh.vertices[c as usize]);
+    let face = cross(sub(vb.position, va.position), sub(vc.position, va.position));
+    if dot(face, face) < DEGENERATE_AREA * DEGENERATE_AREA {
+        return;
+    }
+
+    let normal = [
+        va.normal[0] + vb.normal[0] + vc.normal[0],
+        va.normal[1] + vb.normal[1] + vc.normal[1],
+        va.normal[2] + vb.normal[2] + vc.normal[2],
+    ];
+    if dot(face, normal) >= 0.0 {
+        mesh.indices.extend_from_slice(&[a, b, c]);
+    } else {
+        mesh.indices.extend_from_slice(&[a, c, b]);
+    }
+}
+
+fn tangent(normal: [f32; 3], u_direction: [f32; 3], v_direction: [f32; 3]) -> [f32; 4] {
+    let t = normalize(u_direction);
+    let handedness = if dot(cross(normal, t), v_direction) < 0.0 { -1.0 } else { 1.0 };
+    [t[0], t[1], t[2], handedness]
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn length(a: [f32; 3]) -> f32 {
+        dot(a, a).sqrt()
+    }
+
+    // The checks every generator should pass.
+    fn check(mesh: &Mesh, vertices: usize, triangles: usize) {
+        assert_eq!(mesh.vertices.len(), vertices, "vertex count");
+        assert_eq!(mesh.indices.len(), triangles * 3, "index count");
+        assert!(mesh.indices.iter().all(|&index| (index as usize) < vertices), "index out of range");
+
+        for vertex in &mesh.vertices {
+            assert!((length(vertex.normal) - 1.0).abs() < EPSILON, "normal {:?}", vertex.normal);
+            assert!(vertex.uv.iter().all(|&x| x >= 0.0 && x <= 1.0), "uv {:?}", vertex.uv);
+
+            let tangent = [vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]];
+            assert!((length(tangent) - 1.0).abs() < EPSILON, "tangent {:?}", tangent);
+            assert!(dot(tangent, vertex.normal).abs() < EPSILON, "tangent not orthogonal to normal");
+            assert!(vertex.tangent[3] == 1.0 || vertex.tangent[3] == -1.0);
+        }
+
+        // Wound counter-clockwise around the normals, with no degenerates.
+        for triangle in mesh.indices.chunks(3) {
+            let v = [mesh.vertices[triangle[0] as usize],
+                     mesh.vertices[triangle[1] as usize],
+                     mesh.vertices[triangle[2] as usize]];
+            let face = cross(sub(v[1].position, v[0].position), sub(v[2].position, v[0].position));
+            assert!(length(face) > 0.0, "degenerate triangle {:?}", triangle);
+            for vertex in &v {
+                assert!(dot(face, vertex.normal) > 0.0, "triangle {:?} faces inwards", triangle);
+            }
+        }
+    }
+
+    fn check_radius(mesh: &Mesh, radius: f32) {
+        for vertex in &mesh.vertices {
+            assert!((length(vertex.position) - radius).abs() < EPSILON * radius);
+        }
+    }
+
+    #[test]
+    fn quad() {
+        let mesh = super::quad(2.0, 1.0);
+        check(&mesh, 4, 2);
+        assert!(mesh.vertices.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn plane() {
+        let mesh = super::plane(4.0, 2.0, 4, 3);
+        check(&mesh, 5 * 4, 4 * 3 * 2);
+        assert!(mesh.vertices.iter().all(|v| v.position[1] == 0.0 && v.normal == [0.0, 1.0, 0.0]));
+    }
+
+    #[test]
+    fn cube() {
+        for subdivisions in 1..4 {
+            let cells = subdivisions as usize;
+            let mesh = super::cube(2.0, subdivisions);
+            check(&mesh, 6 * (cells + 1) * (cells + 1), 6 * cells * cells * 2);
+            for vertex in &mesh.vertices {
+                // On the face its normal points out of.
+                let along_normal = dot(vertex.position, vertex.normal);
+                assert!((along_normal - 1.0).abs() < EPSILON);
+                assert!(vertex.position.iter().all(|x| x.abs() <= 1.0 + EPSILON));
+            }
+        }
+    }
+
+    #[test]
+    fn uv_sphere() {
+        let mesh = super::uv_sphere(2.0, 16, 8);
+        // The triangles touching each pole collapse and are dropped.
+        check(&mesh, 17 * 9, 16 * 8 * 2 - 2 * 16);
+        check_radius(&mesh, 2.0);
+    }
+
+    #[test]
+    fn icosphere() {
+        for subdivisions in 0..3 {
+            let mesh = super::icosphere(1.5, subdivisions);
+            let faces = 20 * 4usize.pow(subdivisions);
+            check(&mesh, faces / 2 + 2, faces);
+            check_radius(&mesh, 1.5);
+        }
+    }
+
+    #[test]
+    fn cylinder() {
+        let mesh = super::cylinder(1.0, 3.0, 12);
+        // The side, then a centre plus a ring for each cap.
+        check(&mesh, 13 * 2 + 2 * (1 + 13), 12 * 2 + 2 * 12);
+        assert!(mesh.vertices.iter().all(|v| v.position[1].abs() <= 1.5 + EPSILON));
+    }
+
+    #[test]
+    fn cone() {
+        let mesh = super::cone(1.0, 2.0, 12);
+        // The apex row collapses to one triangle per segment.
+        check(&mesh, 13 * 2 + 1 + 13, 12 + 12);
+    }
+
+    #[test]
+    fn torus() {
+        let mesh = super::torus(2.0, 0.5, 24, 8);
+        check(&mesh, 25 * 9, 24 * 8 * 2);
+        for vertex in &mesh.vertices {
+            // Every point is `minor_radius` from the ring through the tube.
+            let [x, y, z] = vertex.position;
+            let from_ring = (x * x + z * z).sqrt() - 2.0;
+            assert!(((from_ring * from_ring + y * y).sqrt() - 0.5).abs() < EPSILON);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// Tangent generation for normal mapping.
+//
+// This follows the rules MikkTSpace uses, so tangents line up with normal
+// maps baked by tools that use it: per-triangle tangents come from the UV
+// derivatives, are made orthogonal to the vertex normal, and are averaged
+// over the triangles sharing a vertex, weighted by the angle each triangle
+// makes at that corner. Triangles whose UVs are mirrored get the opposite
+// handedness, and a vertex shared by both kinds is split in two.
+//
+// Normals need to be in place first; see `normals`.
+
+use super::{Mesh, Vertex};
+use super::vector::{add, angle_between, cross, dot, normalize, scale, sub};
+
+use std::collections::HashMap;
+
+
+/// Fill in `tangent` for every vertex of the mesh.
+pub fn generate_tangents(mesh: &mut Mesh) {
+    let triangle_count = mesh.indices.len() / 3;
+
+    // Angle-weighted tangent sums per vertex and handedness.
+    let mut sums: HashMap<(u32, bool), [f32; 3]> = HashMap::new();
+    let mut corner_handedness = Vec::with_capacity(triangle_count * 3);
+
+    for triangle in 0..triangle_count {
+        let corners = [mesh.indices[triangle * 3], mesh.indices[triangle * 3 + 1], mesh.indices[triangle * 3 + 2]];
+        let v = [
+            mesh.vertices[corners[0] as usize],
+            mesh.vertices[corners[1] as usize],
+            mesh.vertices[corners[2] as usize],
+        ];
+
+        let edge1 = sub(v[1].position, v[0].position);
+        let edge2 = sub(v[2].position, v[0].position);
+        let du1 = v[1].uv[0] - v[0].uv[0];
+        let dv1 = v[1].uv[1] - v[0].uv[1];
+        let du2 = v[2].uv[0] - v[0].uv[0];
+        let dv2 = v[2].uv[1] - v[0].uv[1];
+
+        // Signed UV area; its sign says whether the mapping is mirrored.
+        let uv_area = du1 * dv2 - du2 * dv1;
+        let (face_tangent, face_bitangent) = if uv_area != 0.0 {
+            (scale(sub(scale(edge1, dv2), scale(edge2, dv1)), 1.0 / uv_area),
+             scale(sub(scale(edge2, du1), scale(edge1, du2)), 1.0 / uv_area))
+        } else {
+            ([0.0; 3], [0.0; 3])
+        };
+
+        for corner in 0..3 {
+            let vertex = &v[corner];
+            let tangent = orthogonalize(face_tangent, vertex.normal);
+            let positive = dot(cross(vertex.normal, tangent), face_bitangent) >= 0.0;
+            corner_handedness.push(positive);
+
+            let here = vertex.position;
+            let next = v[(corner + 1) % 3].position;
+            let prev = v[(corner + 2) % 3].position;
+            let weight = angle_between(sub(next, here), sub(prev, here));
+
+            let sum = sums.entry((corners[corner], positive)).or_insert([0.0; 3]);
+            *sum = add(*sum, scale(tangent, weight));
+        }
+    }
+
+    // Split vertices used with both handednesses, then write the results.
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(mesh.vertices.len());
+    let mut remap: HashMap<(u32, bool), u32> = HashMap::new();
+    for (corner, &positive) in corner_handedness.iter().enumerate() {
+        let original = mesh.indices[corner];
+        let index = *remap.entry((original, positive)).or_insert_with(|| {
+            let vertex = mesh.vertices[original as usize];
+            let tangent = finish_tangent(sums[&(original, positive)], vertex.normal);
+            vertices.push(Vertex {
+                tangent: [tangent[0], tangent[1], tangent[2], if positive { 1.0 } else { -1.0 }],
+                ..vertex
+            });
+            vertices.len() as u32 - 1
+        });
+        mesh.indices[corner] = index;
+    }
+    mesh.vertices = vertices;
+}
+
+
+// Remove the part of `v` along `normal`.
+fn orthogonalize(v: [f32; 3], normal: [f32; 3]) -> [f32; 3] {
+    normalize(sub(v, scale(normal, dot(v, normal))))
+}
+
+// Normalize an accumulated tangent, falling back to any direction
+// perpendicular to the normal when the UVs gave nothing usable.
+fn finish_tangent(sum: [f32; 3], normal: [f32; 3]) -> [f32; 3] {
+    let tangent = orthogonalize(sum, normal);
+    if dot(tangent, tangent) > 0.0 {
+        return tangent;
+    }
+    let axis = if normal[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    orthogonalize(axis, normal)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mesh::primitives;
+
+    const EPSILON: f32 = 1e-5;
+
+    // Regenerate a primitive's tangents and check them against the ones it
+    // was built with, which come straight from its UV directions.
+    fn check_against_primitive(mut mesh: Mesh) {
+        let expected = mesh.clone();
+        for vertex in &mut mesh.vertices {
+            vertex.tangent = [0.0; 4];
+        }
+        generate_tangents(&mut mesh);
+
+        assert_eq!(mesh.vertices.len(), expected.vertices.len());
+        for (vertex, expected) in mesh.vertices.iter().zip(&expected.vertices) {
+            let tangent = [vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]];
+            assert!((dot(tangent, tangent) - 1.0).abs() < EPSILON, "{:?}", vertex);
+            assert!(dot(tangent, vertex.normal).abs() < EPSILON, "{:?}", vertex);
+            for i in 0..4 {
+                assert!((vertex.tangent[i] - expected.tangent[i]).abs() < EPSILON, "{:?} != {:?}", vertex, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn quad_tangents_follow_u() {
+        let mut mesh = primitives::quad(2.0, 1.0);
+        // V runs down the quad, so the bitangent is left-handed.
+        assert_eq!(mesh.vertices[0].tangent, [1.0, 0.0, 0.0, -1.0]);
+        check_against_primitive(mesh.clone());
+
+        // Flipping V flips the handedness but not the tangent.
+        for vertex in &mut mesh.vertices {
+            vertex.uv[1] = 1.0 - vertex.uv[1];
+        }
+        generate_tangents(&mut mesh);
+        for vertex in &mesh.vertices {
+            assert!(dot([vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]], [1.0, 0.0, 0.0]) > 1.0 - EPSILON);
+            assert_eq!(vertex.tangent[3], 1.0);
+        }
+    }
+
+    #[test]
+    fn cube_tangents_follow_u() {
+        check_against_primitive(primitives::cube(2.0, 1));
+        check_against_primitive(primitives::cube(1.0, 3));
+    }
+
+    #[test]
+    fn mirrored_uvs_split_shared_vertices() {
+        // Two quads side by side, sharing the edge at x = 1, with U running
+        // away from the shared edge on both sides.
+        let vertex = |x: f32, y: f32, u: f32| Vertex {
+            position: [x, y, 0.0],
+            normal: [0.0, 0.0, 1.0],
+            uv: [u, y],
+            ..Vertex::default()
+        };
+        let mut mesh = Mesh {
+            vertices: vec![
+                vertex(0.0, 0.0, 1.0), vertex(1.0, 0.0, 0.0), vertex(1.0, 1.0, 0.0), vertex(0.0, 1.0, 1.0),
+                vertex(2.0, 0.0, 1.0), vertex(2.0, 1.0, 1.0),
+            ],
+            indices: vec![0, 1, 2, 0, 2, 3, 1, 4, 5, 1, 5, 2],
+        };
+        generate_tangents(&mut mesh);
+
+        // The two shared vertices each get a copy per side.
+        assert_eq!(mesh.vertices.len(), 8);
+        for (triangle, indices) in mesh.indices.chunks(3).enumerate() {
+            let (direction, handedness) = if triangle < 2 { (-1.0, -1.0) } else { (1.0, 1.0) };
+            for &index in indices {
+                let vertex = mesh.vertices[index as usize];
+                assert!((vertex.tangent[0] - direction).abs() < EPSILON, "{:?}", vertex);
+                assert_eq!(vertex.tangent[3], handedness);
+            }
+        }
+    }
+}
@@ -0,0 +1,464 @@
+// Full-screen post-processing.
+//
+// The scene is drawn into an offscreen `RenderTarget` with `draw_scene`,
+// then `apply` runs `passes` over it in order. Each pass is a fragment
+// shader drawn over one full-screen triangle, reading the previous pass's
+// output and writing the next: passes ping-pong between two intermediate
+// targets, and the last one writes into the caller's render pass - usually
+// the swapchain's.
+//
+// Every pass shader gets the same inputs: the UV at location 0, the source
+// image at set 0, binding 0, and a push constant block holding the
+// source's texel size and two vec4s of parameters:
+//
+//     layout(push_constant) uniform PushConstants {
+//         vec2 texel_size;
+//         vec4 params[2];
+//     } push_constants;
+//
+// Built-in shaders cover copying, colour grading, vignetting, FXAA and
+// (separable) gaussian blur. Others can be added with `add_shader`.
+//
+// `hdr` draws its bloom and tone mapping passes the same way, with the
+// pipeline and pass helpers here.
+
+use gfx_hal::command::CommandBuffer;
+use gfx_hal::Backend;
+use prelude::*;
+use render_target::{RenderTarget, TargetSize};
+
+#[cfg(windows)]
+pub(crate) static VERT_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\fullscreen.vert.spv");
+#[cfg(windows)]
+static COPY_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\post_copy.frag.spv");
+#[cfg(windows)]
+static COLOUR_GRADE_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\post_colour_grade.frag.spv");
+#[cfg(windows)]
+static VIGNETTE_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\post_vignette.frag.spv");
+#[cfg(windows)]
+static FXAA_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\post_fxaa.frag.spv");
+#[cfg(windows)]
+static BLUR_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\post_blur.frag.spv");
+
+#[cfg(all(unix))]
+pub(crate) static VERT_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/fullscreen.vert.spv");
+#[cfg(all(unix))]
+static COPY_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/post_copy.frag.spv");
+#[cfg(all(unix))]
+static COLOUR_GRADE_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/post_colour_grade.frag.spv");
+#[cfg(all(unix))]
+static VIGNETTE_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/post_vignette.frag.spv");
+#[cfg(all(unix))]
+static FXAA_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/post_fxaa.frag.spv");
+#[cfg(all(unix))]
+static BLUR_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/post_blur.frag.spv");
+
+// vec2 texel_size, padding to 16 bytes, then vec4 params[2].
+pub(crate) const NUM_PUSH_CONSTANTS: u32 = 12;
+
+// Intermediate targets are cleared to this, though every pass overwrites
+// the whole target anyway.
+pub(crate) const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+
+/// Handle to a pass shader registered with a `PostProcess`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderId(usize);
+
+impl ShaderId {
+    pub const COPY: ShaderId = ShaderId(0);
+    pub const COLOUR_GRADE: ShaderId = ShaderId(1);
+    pub const VIGNETTE: ShaderId = ShaderId(2);
+    pub const FXAA: ShaderId = ShaderId(3);
+    pub const BLUR: ShaderId = ShaderId(4);
+}
+
+
+/// One full-screen pass: a shader, and the eight floats it gets as
+/// `params[0]` and `params[1]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pass {
+    pub shader: ShaderId,
+    pub params: [f32; 8],
+}
+
+impl Pass {
+    pub fn new(shader: ShaderId, params: [f32; 8]) -> Self {
+        Pass { shader, params }
+    }
+
+    /// Copy the image unchanged.
+    pub fn copy() -> Self {
+        Pass::new(ShaderId::COPY, [0.0; 8])
+    }
+
+    /// Scale by `exposure` stops, then adjust contrast around mid-grey and
+    /// saturation (0 is greyscale, 1 unchanged), and multiply by `tint`.
+    pub fn colour_grade(exposure: f32, contrast: f32, saturation: f32, tint: [f32; 3]) -> Self {
+        Pass::new(ShaderId::COLOUR_GRADE,
+                  [exposure, contrast, saturation, 0.0, tint[0], tint[1], tint[2], 0.0])
+    }
+
+    /// Fade towards `colour` beyond `radius` from the centre, over
+    /// `softness`. Both are in units of the image's height, so the centre
+    /// to the top edge is 0.5. `strength` is how far to fade, from 0 to 1.
+    pub fn vignette(strength: f32, radius: f32, softness: f32, colour: [f32; 3]) -> Self {
+        Pass::new(ShaderId::VIGNETTE,
+                  [strength, radius, softness, 0.0, colour[0], colour[1], colour[2], 0.0])
+    }
+
+    /// FXAA with the usual settings.
+    pub fn fxaa() -> Self {
+        Pass::fxaa_with(8.0, 1.0 / 8.0, 1.0 / 128.0)
+    }
+
+    /// FXAA with a maximum search span in pixels, and the multiplier and
+    /// floor for reducing the span in dark or flat areas.
+    pub fn fxaa_with(span_max: f32, reduce_mul: f32, reduce_min: f32) -> Self {
+        Pass::new(ShaderId::FXAA, [span_max, reduce_mul, reduce_min, 0.0, 0.0, 0.0, 0.0, 0.0])
+    }
+
+    /// One direction of a gaussian blur. `direction` is in pixels per tap,
+    /// so (1, 0) blurs horizontally.
+    pub fn blur(direction: [f32; 2], sigma: f32) -> Self {
+        Pass::new(ShaderId::BLUR, [direction[0], direction[1], sigma, 0.0, 0.0, 0.0, 0.0, 0.0])
+    }
+
+    /// A full gaussian blur, as a horizontal then a vertical pass.
+    pub fn gaussian_blur(sigma: f32) -> [Pass; 2] {
+        [Pass::blur([1.0, 0.0], sigma), Pass::blur([0.0, 1.0], sigma)]
+    }
+}
+
+
+struct PassShader<B: Backend> {
+    module: B::ShaderModule,
+    // Writing into one of the intermediate targets...
+    target_pipeline: B::GraphicsPipeline,
+    // ...or into the output.
+    output_pipeline: B::GraphicsPipeline,
+}
+
+/// A pipeline drawing `fragment_shader_module` over one full-screen
+/// triangle from `fullscreen.vert`, in the first subpass of `render_pass`.
+pub(crate) fn fullscreen_pipeline<B: Backend>(device: &B::Device,
+                                              render_pass: &B::RenderPass,
+                                              pipeline_layout: &B::PipelineLayout,
+                                              vertex_shader_module: &B::ShaderModule,
+                                              fragment_shader_module: &B::ShaderModule) -> B::GraphicsPipeline {
+    let vs_entry = EntryPoint::<B> {
+        entry: "main",
+        module: vertex_shader_module,
+        specialization: Default::default(),
+    };
+
+    let fs_entry = EntryPoint::<B> {
+        entry: "main",
+        module: fragment_shader_module,
+        specialization: Default::default(),
+    };
+
+    let shader_entries = GraphicsShaderSet {
+        vertex: vs_entry,
+        hull: None,
+        domain: None,
+        geometry: None,
+        fragment: Some(fs_entry),
+    };
+
+    let subpass = Subpass {
+        index: 0,
+        main_pass: render_pass,
+    };
+
+    let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                      Primitive::TriangleList,
+                                                      Rasterizer::FILL,
+                                                      pipeline_layout,
+                                                      subpass);
+
+    // Every pixel is overwritten, so there's nothing to blend with.
+    pipeline_desc.blender
+                 .targets
+                 .push(ColorBlendDesc(ColorMask::ALL, BlendState::Off));
+
+    device.create_graphics_pipeline(&pipeline_desc, None)
+          .unwrap()
+}
+
+/// Record a render pass drawing one full-screen triangle with `pipeline`,
+/// reading `set`. `clear_values` has one entry for each of the render
+/// pass's attachments. `source_extent` is the size of the image the
+/// shader's texel size refers to, and `params` fill the rest of the push
+/// constants.
+pub(crate) fn draw_fullscreen_pass<B: Backend>(command_buffer: &mut CommandBuffer<B, Graphics>,
+                                               render_pass: &B::RenderPass,
+                                               framebuffer: &B::Framebuffer,
+                                               viewport: &Viewport,
+                                               clear_values: &[ClearValue],
+                                               pipeline_layout: &B::PipelineLayout,
+                                               pipeline: &B::GraphicsPipeline,
+                                               set: &B::DescriptorSet,
+                                               source_extent: Extent2D,
+                                               params: &[f32; 8]) {
+    let mut push_constants = [0u32; NUM_PUSH_CONSTANTS as usize];
+    push_constants[0] = (1.0 / source_extent.width as f32).to_bits();
+    push_constants[1] = (1.0 / source_extent.height as f32).to_bits();
+    for (constant, param) in push_constants[4..].iter_mut().zip(params.iter()) {
+        *constant = param.to_bits();
+    }
+
+    command_buffer.set_viewports(0, &[viewport.clone()]);
+    command_buffer.set_scissors(0, &[viewport.rect]);
+
+    let mut encoder = command_buffer.begin_render_pass_inline(render_pass, framebuffer, viewport.rect, clear_values);
+    encoder.bind_graphics_pipeline(pipeline);
+    encoder.bind_graphics_descriptor_sets(pipeline_layout, 0, vec![set], &[]);
+    encoder.push_graphics_constants(pipeline_layout,
+                                    ShaderStageFlags::FRAGMENT,
+                                    0,
+                                    &push_constants);
+    encoder.draw(0..3, 0..1);
+}
+
+
+pub struct PostProcess<B: Backend> {
+    /// The passes `apply` runs, in order.
+    pub passes: Vec<Pass>,
+    set_layout: B::DescriptorSetLayout,
+    pipeline_layout: B::PipelineLayout,
+    desc_pool: B::DescriptorPool,
+    sampler: B::Sampler,
+    vertex_shader_module: B::ShaderModule,
+    shaders: Vec<PassShader<B>>,
+    scene: RenderTarget<B>,
+    ping_pong: Vec<RenderTarget<B>>,
+    // Sampling the scene, and each of the intermediate targets.
+    scene_set: B::DescriptorSet,
+    ping_pong_sets: [B::DescriptorSet; 2],
+}
+
+impl<B: Backend> PostProcess<B> {
+    /// Create a chain with no passes for a window of `window` pixels. The
+    /// scene and intermediate targets are `format`, and the scene target
+    /// gets a depth image if `depth_format` is given. The final pass draws
+    /// in the first subpass of `output_render_pass`.
+    pub fn new(device: &B::Device,
+               memory_types: &[MemoryType],
+               window: Extent2D,
+               format: Format,
+               depth_format: Option<Format>,
+               output_render_pass: &B::RenderPass) -> Self {
+        let set_layout = device.create_descriptor_set_layout(
+            &[DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+                stage_flags: ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            }],
+            &[],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(
+            vec![&set_layout],
+            &[(ShaderStageFlags::FRAGMENT, 0..NUM_PUSH_CONSTANTS)]
+        );
+
+        let mut desc_pool = device.create_descriptor_pool(
+            3,
+            &[DescriptorRangeDesc {
+                ty: DescriptorType::CombinedImageSampler,
+                count: 3,
+            }]
+        );
+        let scene_set = desc_pool.allocate_set(&set_layout).unwrap();
+        let ping_pong_sets = [desc_pool.allocate_set(&set_layout).unwrap(),
+                              desc_pool.allocate_set(&set_layout).unwrap()];
+
+        // Clamped, so blurs and FXAA don't pull in the opposite edge.
+        let sampler = device.create_sampler(SamplerInfo::new(Filter::Linear, WrapMode::Clamp));
+
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+
+        let size = TargetSize::Relative(1.0);
+        let scene = RenderTarget::new(device, memory_types, size, window, format, depth_format);
+        let ping_pong = (0..2).map(|_| RenderTarget::new(device, memory_types, size, window, format, None))
+                              .collect();
+
+        let mut post_process = PostProcess {
+            passes: Vec::new(),
+            set_layout,
+            pipeline_layout,
+            desc_pool,
+            sampler,
+            vertex_shader_module,
+            shaders: Vec::new(),
+            scene,
+            ping_pong,
+            scene_set,
+            ping_pong_sets,
+        };
+
+        // In the same order as the `ShaderId` constants.
+        for spirv in &[COPY_SPIRV, COLOUR_GRADE_SPIRV, VIGNETTE_SPIRV, FXAA_SPIRV, BLUR_SPIRV] {
+            post_process.add_shader(device, output_render_pass, spirv);
+        }
+
+        post_process.write_sets(device);
+        post_process
+    }
+
+    /// Register another pass shader, which must take the inputs described
+    /// at the top of this file.
+    pub fn add_shader(&mut self, device: &B::Device, output_render_pass: &B::RenderPass, fragment_spirv: &[u8]) -> ShaderId {
+        let module = device.create_shader_module(fragment_spirv).unwrap();
+        let target_pipeline = fullscreen_pipeline::<B>(device,
+                                                       &self.ping_pong[0].render_pass,
+                                                       &self.pipeline_layout,
+                                                       &self.vertex_shader_module,
+                                                       &module);
+        let output_pipeline = fullscreen_pipeline::<B>(device,
+                                                       output_render_pass,
+                                                       &self.pipeline_layout,
+                                                       &self.vertex_shader_module,
+                                                       &module);
+        self.shaders.push(PassShader { module, target_pipeline, output_pipeline });
+        ShaderId(self.shaders.len() - 1)
+    }
+
+    /// The target `draw_scene` draws into. Pipelines for the scene should
+    /// be created against its render pass.
+    pub fn scene_target(&self) -> &RenderTarget<B> {
+        &self.scene
+    }
+
+    /// Call when the window has been resized to `window`.
+    ///
+    /// Nothing using the old targets may still be running on the GPU.
+    pub fn resize(&mut self, device: &B::Device, memory_types: &[MemoryType], window: Extent2D) {
+        let mut changed = self.scene.resize(device, memory_types, window);
+        for target in self.ping_pong.iter_mut() {
+            changed |= target.resize(device, memory_types, window);
+        }
+        if changed {
+            self.write_sets(device);
+        }
+    }
+
+    fn write_sets(&self, device: &B::Device) {
+        let sources = [(&self.scene_set, &self.scene),
+                       (&self.ping_pong_sets[0], &self.ping_pong[0]),
+                       (&self.ping_pong_sets[1], &self.ping_pong[1])];
+        device.write_descriptor_sets(sources.iter().map(|&(set, target)| DescriptorSetWrite {
+            set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::CombinedImageSampler(&target.texture().view,
+                                                               Layout::ShaderReadOnlyOptimal,
+                                                               &self.sampler)),
+        }));
+    }
+
+    /// Record a render pass drawing the scene into the scene target with
+    /// `draw`, after clearing it to `clear_colour`. The viewport and
+    /// scissor cover the whole target.
+    pub fn draw_scene<F>(&self, command_buffer: &mut CommandBuffer<B, Graphics>, clear_colour: [f32; 4], draw: F)
+        where F: FnOnce(&mut RenderPassInlineEncoder<B>)
+    {
+        let viewport = self.scene.viewport();
+        command_buffer.set_viewports(0, &[viewport.clone()]);
+        command_buffer.set_scissors(0, &[viewport.rect]);
+
+        let mut encoder = command_buffer.begin_render_pass_inline(&self.scene.render_pass,
+                                                                  self.scene.framebuffer(),
+                                                                  viewport.rect,
+                                                                  &self.scene.clear_values(clear_colour));
+        draw(&mut encoder);
+    }
+
+    /// Record every pass, the last one into `output_framebuffer` (which
+    /// must belong to the `output_render_pass` given to `new`) over
+    /// `output_viewport`, beginning the output pass with
+    /// `output_clear_values`. With no passes, the scene is copied across
+    /// unchanged. Call after `draw_scene`.
+    pub fn apply(&self,
+                 command_buffer: &mut CommandBuffer<B, Graphics>,
+                 output_render_pass: &B::RenderPass,
+                 output_framebuffer: &B::Framebuffer,
+                 output_viewport: &Viewport,
+                 output_clear_values: &[ClearValue]) {
+        let copy = [Pass::copy()];
+        let passes = if self.passes.is_empty() { &copy[..] } else { &self.passes[..] };
+
+        for (i, pass) in passes.iter().enumerate() {
+            // Pass 0 reads the scene and writes target 0, pass 1 reads
+            // target 0 and writes target 1, pass 2 reads target 1 and
+            // writes target 0, and so on.
+            let (source, source_set) = if i == 0 {
+                (&self.scene, &self.scene_set)
+            } else {
+                (&self.ping_pong[(i - 1) % 2], &self.ping_pong_sets[(i - 1) % 2])
+            };
+
+            let shader = &self.shaders[pass.shader.0];
+            let is_last = i == passes.len() - 1;
+            let (render_pass, framebuffer, pipeline, viewport, clear_values) = if is_last {
+                (output_render_pass,
+                 output_framebuffer,
+                 &shader.output_pipeline,
+                 output_viewport.clone(),
+                 output_clear_values.to_vec())
+            } else {
+                let target = &self.ping_pong[i % 2];
+                (&target.render_pass,
+                 target.framebuffer(),
+                 &shader.target_pipeline,
+                 target.viewport(),
+                 target.clear_values(BLACK))
+            };
+
+            draw_fullscreen_pass(command_buffer,
+                                 render_pass,
+                                 framebuffer,
+                                 &viewport,
+                                 &clear_values,
+                                 &self.pipeline_layout,
+                                 pipeline,
+                                 source_set,
+                                 source.extent(),
+                                 &pass.params);
+        }
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        let PostProcess {
+            set_layout,
+            pipeline_layout,
+            desc_pool,
+            sampler,
+            vertex_shader_module,
+            shaders,
+            scene,
+            ping_pong,
+            ..
+        } = self;
+
+        for shader in shaders {
+            device.destroy_graphics_pipeline(shader.target_pipeline);
+            device.destroy_graphics_pipeline(shader.output_pipeline);
+            device.destroy_shader_module(shader.module);
+        }
+        device.destroy_shader_module(vertex_shader_module);
+
+        scene.destroy(device);
+        for target in ping_pong {
+            target.destroy(device);
+        }
+
+        device.destroy_sampler(sampler);
+        device.destroy_descriptor_pool(desc_pool);
+        device.destroy_pipeline_layout(pipeline_layout);
+        device.destroy_descriptor_set_layout(set_layout);
+    }
+}
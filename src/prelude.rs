@@ -1,12 +1,14 @@
 // There are a lot of imports - best to just accept it.
 pub use gfx_hal::{
     adapter::MemoryTypeId,
-    buffer,
-    command::{BufferImageCopy, ClearColor, ClearDepthStencil, ClearValue},
+    buffer::{self, IndexBufferView},
+    command::{
+        BufferImageCopy, ClearColor, ClearDepthStencil, ClearValue, RenderPassInlineEncoder,
+    },
     format::{Aspects, ChannelType, Format, Swizzle},
     image::{
-        self as img, Access, Extent, Filter, Layout, Offset, SubresourceLayers, SubresourceRange,
-        ViewCapabilities, ViewKind, WrapMode,
+        self as img, Access, Anisotropic, Extent, Filter, Layout, Lod, Offset, PackedColor,
+        SamplerInfo, SubresourceLayers, SubresourceRange, ViewCapabilities, ViewKind, WrapMode,
     },
     memory::{Barrier, Dependencies, Properties},
     pass::{
@@ -22,7 +24,10 @@ pub use gfx_hal::{
     },
     queue::Submission,
     window::Extent2D,
-    Backbuffer, DescriptorPool, Device, FrameSync, Graphics, Instance, MemoryType, PhysicalDevice,
-    Primitive, Surface, SwapImageIndex, Swapchain, SwapchainConfig,
+    Backbuffer, Backend, CommandPool, CommandQueue, DescriptorPool, Device, FrameSync, Graphics,
+    IndexType, Instance, MemoryType, PhysicalDevice, Primitive, Surface, SwapImageIndex, Swapchain,
+    SwapchainConfig,
+};
+pub use winit::{
+    ElementState, Event, EventsLoop, KeyboardInput, VirtualKeyCode, WindowBuilder, WindowEvent,
 };
-pub use winit::{Event, EventsLoop, KeyboardInput, VirtualKeyCode, WindowBuilder, WindowEvent};
@@ -0,0 +1,220 @@
+// A software rasterizer, for checking what the tutorials draw without a GPU.
+//
+// `Raster` is a colour buffer that triangles are drawn into by the same
+// rules the tutorials' pipelines follow: clip-space positions are mapped to
+// a viewport covering the whole target (with y pointing down, as in
+// Vulkan), pixels are sampled at their centres with a top-left fill rule,
+// vertex colours are interpolated perspective-correctly, and fragments are
+// blended as `BlendState::ALPHA` does. Both windings are drawn, as with
+// `Rasterizer::FILL`.
+//
+// Colours are stored as linear floats, clamped to 0..1 like a UNORM target.
+// `to_image` encodes them as sRGB, so it matches what reading back an
+// `Rgba8Srgb` target gives. Triangles aren't clipped, so every vertex must
+// have a positive w.
+
+use image::RgbaImage;
+use math::Vec4;
+use prelude::*;
+use readback;
+
+
+/// A vertex after the vertex shader has run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RasterVertex {
+    /// Clip-space position.
+    pub position: Vec4,
+    pub colour: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Blend {
+    /// Fragments overwrite the target.
+    Replace,
+    /// `BlendState::ALPHA`: colour is blended by the fragment's alpha, and
+    /// alpha is added.
+    Alpha,
+}
+
+
+pub struct Raster {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+    scissor: Option<Rect>,
+    blend: Blend,
+}
+
+impl Raster {
+    /// A `width` by `height` target, cleared to transparent black, which
+    /// blends with `Blend::Alpha`.
+    pub fn new(width: u32, height: u32) -> Self {
+        Raster {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; width as usize * height as usize],
+            scissor: None,
+            blend: Blend::Alpha,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Fill the whole target, ignoring the scissor.
+    pub fn clear(&mut self, colour: [f32; 4]) {
+        let colour = clamp(colour);
+        for pixel in &mut self.pixels {
+            *pixel = colour;
+        }
+    }
+
+    /// Only draw inside `scissor`, or anywhere in the target if `None`.
+    pub fn set_scissor(&mut self, scissor: Option<Rect>) {
+        self.scissor = scissor;
+    }
+
+    pub fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [f32; 4] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Every pixel, a row at a time from the top.
+    pub fn pixels(&self) -> &[[f32; 4]] {
+        &self.pixels
+    }
+
+    /// Draw a triangle list, running `shader` on each vertex first. Any
+    /// vertices left over after the last whole triangle are ignored.
+    pub fn draw<V, F>(&mut self, vertices: &[V], mut shader: F)
+        where F: FnMut(&V) -> RasterVertex
+    {
+        for triangle in vertices.chunks(3).filter(|triangle| triangle.len() == 3) {
+            let triangle = [shader(&triangle[0]), shader(&triangle[1]), shader(&triangle[2])];
+            self.draw_triangle(triangle);
+        }
+    }
+
+    pub fn draw_triangle(&mut self, triangle: [RasterVertex; 3]) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let mut points = [[0.0f32; 3]; 3];
+        let mut colours = [[0.0f32; 4]; 3];
+        for (i, vertex) in triangle.iter().enumerate() {
+            let p = vertex.position;
+            if !(p.w > 0.0) {
+                return;
+            }
+            let inv_w = 1.0 / p.w;
+            points[i] = [(p.x * inv_w + 1.0) * 0.5 * width,
+                         (p.y * inv_w + 1.0) * 0.5 * height,
+                         inv_w];
+            colours[i] = vertex.colour;
+        }
+
+        // Put the triangle in the winding where the inside of each edge is
+        // where its edge function is positive.
+        let mut area = edge(points[0], points[1], points[2][0], points[2][1]);
+        if !area.is_finite() || area == 0.0 {
+            return;
+        }
+        if area < 0.0 {
+            points.swap(1, 2);
+            colours.swap(1, 2);
+            area = -area;
+        }
+
+        let (mut x0, mut y0, mut x1, mut y1) = (0, 0, self.width as i32, self.height as i32);
+        if let Some(scissor) = self.scissor {
+            x0 = x0.max(scissor.x as i32);
+            y0 = y0.max(scissor.y as i32);
+            x1 = x1.min(scissor.x as i32 + scissor.w as i32);
+            y1 = y1.min(scissor.y as i32 + scissor.h as i32);
+        }
+        let min = |i: usize| points[0][i].min(points[1][i]).min(points[2][i]);
+        let max = |i: usize| points[0][i].max(points[1][i]).max(points[2][i]);
+        x0 = x0.max(min(0).floor() as i32);
+        y0 = y0.max(min(1).floor() as i32);
+        x1 = x1.min(max(0).ceil() as i32);
+        y1 = y1.min(max(1).ceil() as i32);
+
+        let edges = [(points[1], points[2]), (points[2], points[0]), (points[0], points[1])];
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+
+                let mut weights = [0.0f32; 3];
+                let mut inside = true;
+                for (i, &(a, b)) in edges.iter().enumerate() {
+                    let value = edge(a, b, px, py);
+                    if value < 0.0 || (value == 0.0 && !is_top_left(a, b)) {
+                        inside = false;
+                        break;
+                    }
+                    // Weighted by 1/w for perspective-correct interpolation.
+                    weights[i] = value / area * points[i][2];
+                }
+                if !inside {
+                    continue;
+                }
+
+                let total = weights[0] + weights[1] + weights[2];
+                let mut colour = [0.0f32; 4];
+                for (channel, value) in colour.iter_mut().enumerate() {
+                    *value = (weights[0] * colours[0][channel] +
+                              weights[1] * colours[1][channel] +
+                              weights[2] * colours[2][channel]) / total;
+                }
+                self.blend_pixel(x as u32, y as u32, colour);
+            }
+        }
+    }
+
+    fn blend_pixel(&mut self, x: u32, y: u32, colour: [f32; 4]) {
+        let src = clamp(colour);
+        let dst = &mut self.pixels[(y * self.width + x) as usize];
+        *dst = match self.blend {
+            Blend::Replace => src,
+            Blend::Alpha => clamp([src[0] * src[3] + dst[0] * (1.0 - src[3]),
+                                   src[1] * src[3] + dst[1] * (1.0 - src[3]),
+                                   src[2] * src[3] + dst[2] * (1.0 - src[3]),
+                                   src[3] + dst[3]]),
+        };
+    }
+
+    /// The target as sRGB-encoded RGBA8.
+    pub fn to_image(&self) -> RgbaImage {
+        let mut data = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in &self.pixels {
+            data.extend_from_slice(&readback::encode_linear(*pixel));
+        }
+        RgbaImage::from_raw(self.width, self.height, data).unwrap()
+    }
+}
+
+
+// Twice the signed area of the triangle (a, b, p): positive when p is on
+// the inside of the edge from a to b, for a clockwise triangle with y down.
+fn edge(a: [f32; 3], b: [f32; 3], px: f32, py: f32) -> f32 {
+    (b[0] - a[0]) * (py - a[1]) - (b[1] - a[1]) * (px - a[0])
+}
+
+// Pixel centres exactly on an edge belong to the triangle only if it's a
+// top edge (horizontal, with the inside below) or a left edge, so that
+// triangles sharing an edge never both draw the same pixel.
+fn is_top_left(a: [f32; 3], b: [f32; 3]) -> bool {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    (dy == 0.0 && dx > 0.0) || dy < 0.0
+}
+
+fn clamp(colour: [f32; 4]) -> [f32; 4] {
+    let c = |v: f32| if v.is_nan() { 0.0 } else { v.max(0.0).min(1.0) };
+    [c(colour[0]), c(colour[1]), c(colour[2]), c(colour[3])]
+}
@@ -0,0 +1,371 @@
+// Copying images back from the GPU, and saving them as PNGs.
+//
+// `read_image` copies one colour image into a CPU-visible buffer and
+// converts it to RGBA8, whatever layout it's in: it's moved to
+// `TransferSrcOptimal` for the copy and back again afterwards. That works
+// for swapchain images too, as long as the swapchain was created with
+// `img::Usage::TRANSFER_SRC`.
+//
+// 8-bit images are returned as they're stored (so sRGB images stay
+// sRGB-encoded, and look the same as they do on screen); floating point
+// images are treated as linear and encoded as sRGB.
+
+use gfx_hal::Backend;
+use image::RgbaImage;
+use prelude::*;
+use utils;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+#[derive(Debug)]
+pub enum ReadbackError {
+    Io(io::Error),
+    /// There's no conversion from this format to RGBA8.
+    UnsupportedFormat(Format),
+    /// There's less pixel data than the image's dimensions need.
+    Truncated,
+}
+
+impl fmt::Display for ReadbackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadbackError::Io(err) => write!(f, "I/O error: {}", err),
+            ReadbackError::UnsupportedFormat(format) => write!(f, "cannot read back {:?} images", format),
+            ReadbackError::Truncated => write!(f, "not enough pixel data"),
+        }
+    }
+}
+
+impl Error for ReadbackError {}
+
+impl From<io::Error> for ReadbackError {
+    fn from(err: io::Error) -> Self {
+        ReadbackError::Io(err)
+    }
+}
+
+
+/// Bytes per pixel of the formats `to_rgba8` understands.
+pub fn bytes_per_pixel(format: Format) -> Option<u32> {
+    match format {
+        Format::Rgba8Unorm | Format::Rgba8Srgb | Format::Bgra8Unorm | Format::Bgra8Srgb => Some(4),
+        Format::Rgba16Float => Some(8),
+        Format::Rgba32Float => Some(16),
+        _ => None,
+    }
+}
+
+/// Convert tightly packed pixels in `format` to RGBA8.
+pub fn to_rgba8(format: Format, width: u32, height: u32, data: &[u8]) -> Result<RgbaImage, ReadbackError> {
+    let bytes_per_pixel = bytes_per_pixel(format).ok_or(ReadbackError::UnsupportedFormat(format))? as usize;
+    let pixel_count = width as usize * height as usize;
+    match pixel_count.checked_mul(bytes_per_pixel) {
+        Some(size) if size <= data.len() => {}
+        _ => return Err(ReadbackError::Truncated),
+    }
+
+    let mut pixels = Vec::with_capacity(pixel_count * 4);
+    for pixel in data.chunks(bytes_per_pixel).take(pixel_count) {
+        match format {
+            Format::Rgba8Unorm | Format::Rgba8Srgb => pixels.extend_from_slice(pixel),
+            Format::Bgra8Unorm | Format::Bgra8Srgb => {
+                pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]])
+            }
+            Format::Rgba16Float => {
+                let channel = |i: usize| half_to_f32(pixel[i * 2] as u16 | (pixel[i * 2 + 1] as u16) << 8);
+                pixels.extend_from_slice(&encode_linear([channel(0), channel(1), channel(2), channel(3)]));
+            }
+            Format::Rgba32Float => {
+                let channel = |i: usize| {
+                    let bits = pixel[i * 4] as u32 | (pixel[i * 4 + 1] as u32) << 8 |
+                        (pixel[i * 4 + 2] as u32) << 16 | (pixel[i * 4 + 3] as u32) << 24;
+                    f32::from_bits(bits)
+                };
+                pixels.extend_from_slice(&encode_linear([channel(0), channel(1), channel(2), channel(3)]));
+            }
+            _ => unreachable!(),
+        }
+    }
+    Ok(RgbaImage::from_raw(width, height, pixels).unwrap())
+}
+
+/// Linear colour to sRGB-encoded bytes, clamping each channel to 0..1.
+/// Alpha is never sRGB-encoded.
+pub fn encode_linear(colour: [f32; 4]) -> [u8; 4] {
+    let encode = |c: f32| {
+        let c = if c.is_nan() { 0.0 } else { c.max(0.0).min(1.0) };
+        if c <= 0.003_130_8 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    };
+    let byte = |c: f32| (c * 255.0 + 0.5) as u8;
+    [byte(encode(colour[0])),
+     byte(encode(colour[1])),
+     byte(encode(colour[2])),
+     byte(colour[3].max(0.0).min(1.0))]
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * (2.0f32).powi(-24),
+        31 => if mantissa == 0.0 { ::std::f32::INFINITY } else { ::std::f32::NAN },
+        _ => (1.0 + mantissa / 1024.0) * (2.0f32).powi(exponent - 15),
+    }
+}
+
+
+/// Copy a single-level 2D colour image, currently in `layout`, to the CPU
+/// as RGBA8, leaving it in `layout` again afterwards. Blocks until the copy
+/// has finished.
+///
+/// Work already submitted to `queue` that writes the image is waited for,
+/// so this can be called straight after submitting a frame, before
+/// presenting it.
+pub fn read_image<B: Backend>(device: &B::Device,
+                              physical_device: &B::PhysicalDevice,
+                              command_pool: &mut CommandPool<B, Graphics>,
+                              queue: &mut CommandQueue<B, Graphics>,
+                              image: &B::Image,
+                              format: Format,
+                              width: u32,
+                              height: u32,
+                              layout: Layout) -> Result<RgbaImage, ReadbackError> {
+    let bytes_per_pixel = bytes_per_pixel(format).ok_or(ReadbackError::UnsupportedFormat(format))? as u64;
+
+    let memory_types = physical_device.memory_properties().memory_types;
+    let pitch_alignment = physical_device.limits().min_buffer_copy_pitch_alignment.max(bytes_per_pixel);
+    let row_size = width as u64 * bytes_per_pixel;
+    let row_pitch = (row_size + pitch_alignment - 1) / pitch_alignment * pitch_alignment;
+    let buffer_size = row_pitch * height as u64;
+
+    let (buffer, memory) = utils::empty_buffer::<B, u8>(device,
+                                                        &memory_types,
+                                                        Properties::CPU_VISIBLE,
+                                                        buffer::Usage::TRANSFER_DST,
+                                                        buffer_size as usize);
+
+    let range = SubresourceRange {
+        aspects: Aspects::COLOR,
+        levels: 0..1,
+        layers: 0..1,
+    };
+
+    let finished_command_buffer = {
+        let mut command_buffer = command_pool.acquire_command_buffer(false);
+
+        if layout != Layout::TransferSrcOptimal {
+            command_buffer.pipeline_barrier(
+                PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                &[Barrier::Image {
+                    states: (Access::COLOR_ATTACHMENT_WRITE, layout)
+                        ..(Access::TRANSFER_READ, Layout::TransferSrcOptimal),
+                    target: image,
+                    range: range.clone(),
+                }],
+            );
+        }
+
+        command_buffer.copy_image_to_buffer(image,
+                                            Layout::TransferSrcOptimal,
+                                            &buffer,
+                                            &[BufferImageCopy {
+                                                buffer_offset: 0,
+                                                buffer_width: (row_pitch / bytes_per_pixel) as u32,
+                                                buffer_height: height,
+                                                image_layers: SubresourceLayers {
+                                                    aspects: Aspects::COLOR,
+                                                    level: 0,
+                                                    layers: 0..1,
+                                                },
+                                                image_offset: Offset { x: 0, y: 0, z: 0 },
+                                                image_extent: Extent {
+                                                    width,
+                                                    height,
+                                                    depth: 1,
+                                                },
+                                            }]);
+
+        if layout != Layout::TransferSrcOptimal {
+            command_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER..PipelineStage::BOTTOM_OF_PIPE,
+                Dependencies::empty(),
+                &[Barrier::Image {
+                    states: (Access::TRANSFER_READ, Layout::TransferSrcOptimal)..(Access::empty(), layout),
+                    target: image,
+                    range,
+                }],
+            );
+        }
+
+        command_buffer.finish()
+    };
+
+    let fence = device.create_fence(false);
+    queue.submit(Submission::new().submit(vec![finished_command_buffer]), Some(&fence));
+    device.wait_for_fence(&fence, !0);
+    device.destroy_fence(fence);
+
+    let mut data = Vec::with_capacity((row_size * height as u64) as usize);
+    {
+        let reader = device.acquire_mapping_reader::<u8>(&memory, 0..buffer_size)
+                           .unwrap();
+        for row in 0..height as usize {
+            let start = row * row_pitch as usize;
+            data.extend_from_slice(&reader[start..start + row_size as usize]);
+        }
+        device.release_mapping_reader(reader);
+    }
+
+    device.destroy_buffer(buffer);
+    device.free_memory(memory);
+
+    to_rgba8(format, width, height, &data)
+}
+
+
+/// A path for a new screenshot in the working directory, named after the
+/// current time.
+pub fn screenshot_path() -> PathBuf {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    PathBuf::from(format!("screenshot-{}-{:03}.png",
+                          since_epoch.as_secs(),
+                          since_epoch.subsec_millis()))
+}
+
+pub fn save_png<P: AsRef<Path>>(image: &RgbaImage, path: P) -> Result<(), ReadbackError> {
+    Ok(image.save(path)?)
+}
+
+/// Read back a presentable image with `read_image` and save it as a PNG at
+/// `screenshot_path()`, returning the path. The window is shown opaque, so
+/// the screenshot is too, whatever's in the alpha channel.
+pub fn save_screenshot<B: Backend>(device: &B::Device,
+                                   physical_device: &B::PhysicalDevice,
+                                   command_pool: &mut CommandPool<B, Graphics>,
+                                   queue: &mut CommandQueue<B, Graphics>,
+                                   image: &B::Image,
+                                   format: Format,
+                                   width: u32,
+                                   height: u32,
+                                   layout: Layout) -> Result<PathBuf, ReadbackError> {
+    let mut pixels = read_image(device, physical_device, command_pool, queue, image, format, width, height, layout)?;
+    for pixel in pixels.pixels_mut() {
+        pixel.data[3] = 255;
+    }
+    let path = screenshot_path();
+    save_png(&pixels, &path)?;
+    Ok(path)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Little-endian bytes for one pixel of each float format.
+    fn half_pixel(channels: [u16; 4]) -> Vec<u8> {
+        channels.iter().flat_map(|&c| vec![c as u8, (c >> 8) as u8]).collect()
+    }
+
+    fn float_pixel(channels: [f32; 4]) -> Vec<u8> {
+        channels.iter()
+                .flat_map(|&c| {
+                    let bits = c.to_bits();
+                    vec![bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8]
+                })
+                .collect()
+    }
+
+    fn pixels(format: Format, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+        to_rgba8(format, width, height, data).unwrap().into_raw()
+    }
+
+    #[test]
+    fn copies_rgba8() {
+        let data = [1, 2, 3, 4, 250, 251, 252, 253];
+        assert_eq!(pixels(Format::Rgba8Unorm, 2, 1, &data), data.to_vec());
+        // sRGB data is already encoded, so it's left alone.
+        assert_eq!(pixels(Format::Rgba8Srgb, 1, 2, &data), data.to_vec());
+    }
+
+    #[test]
+    fn swizzles_bgra8() {
+        let data = [1, 2, 3, 4, 10, 20, 30, 40];
+        let expected = vec![3, 2, 1, 4, 30, 20, 10, 40];
+        assert_eq!(pixels(Format::Bgra8Unorm, 2, 1, &data), expected);
+        assert_eq!(pixels(Format::Bgra8Srgb, 2, 1, &data), expected);
+    }
+
+    #[test]
+    fn encodes_half_floats() {
+        // 1.0, 0.5, 0.0 and an alpha of 0.5.
+        let data = half_pixel([0x3C00, 0x3800, 0x0000, 0x3800]);
+        assert_eq!(pixels(Format::Rgba16Float, 1, 1, &data), vec![255, 188, 0, 128]);
+
+        // Out of range values clamp; alpha isn't sRGB-encoded.
+        let data = half_pixel([0x4000, 0xBC00, 0x7E00, 0x3400]);
+        assert_eq!(pixels(Format::Rgba16Float, 1, 1, &data), vec![255, 0, 0, 64]);
+    }
+
+    #[test]
+    fn encodes_floats() {
+        let mut data = float_pixel([1.0, 0.5, 0.0, 0.5]);
+        data.extend(float_pixel([2.0, -1.0, ::std::f32::NAN, ::std::f32::INFINITY]));
+        assert_eq!(pixels(Format::Rgba32Float, 2, 1, &data), vec![255, 188, 0, 128, 255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn encodes_linear_colours_as_srgb() {
+        assert_eq!(encode_linear([0.0, 1.0, 0.5, 1.0]), [0, 255, 188, 255]);
+        // Either side of the linear segment's end.
+        assert_eq!(encode_linear([0.003, 0.0032, 0.0, 0.0])[..2], [10, 11]);
+    }
+
+    #[test]
+    fn converts_halves() {
+        assert_eq!(half_to_f32(0x3C00), 1.0);
+        assert_eq!(half_to_f32(0xC000), -2.0);
+        assert_eq!(half_to_f32(0x7BFF), 65504.0);
+        assert_eq!(half_to_f32(0x0000), 0.0);
+        assert!(half_to_f32(0x8000) == 0.0 && half_to_f32(0x8000).is_sign_negative());
+
+        // Subnormals: the smallest, the largest, and the smallest normal
+        // just above them.
+        assert_eq!(half_to_f32(0x0001), (2.0f32).powi(-24));
+        assert_eq!(half_to_f32(0x03FF), 1023.0 * (2.0f32).powi(-24));
+        assert_eq!(half_to_f32(0x0400), (2.0f32).powi(-14));
+        assert_eq!(half_to_f32(0x8001), -(2.0f32).powi(-24));
+
+        assert_eq!(half_to_f32(0x7C00), ::std::f32::INFINITY);
+        assert_eq!(half_to_f32(0xFC00), ::std::f32::NEG_INFINITY);
+        assert!(half_to_f32(0x7E00).is_nan());
+        assert!(half_to_f32(0xFC01).is_nan());
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        match to_rgba8(Format::Rgba8Unorm, 2, 2, &[0; 15]) {
+            Err(ReadbackError::Truncated) => {}
+            other => panic!("expected Truncated, got {:?}", other.map(|_| ())),
+        }
+        match to_rgba8(Format::Rgba16Float, 0x1_0000, 0x1_0000, &[]) {
+            Err(ReadbackError::Truncated) => {}
+            other => panic!("expected Truncated, got {:?}", other.map(|_| ())),
+        }
+        match to_rgba8(Format::D32Float, 1, 1, &[0; 4]) {
+            Err(ReadbackError::UnsupportedFormat(Format::D32Float)) => {}
+            other => panic!("expected UnsupportedFormat, got {:?}", other.map(|_| ())),
+        }
+    }
+}
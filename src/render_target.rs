@@ -0,0 +1,307 @@
+// Images to render into and then sample from.
+//
+// A `RenderTarget` bundles a colour image (plus an optional depth image), a
+// render pass and a framebuffer. Its render pass leaves the colour image in
+// `ShaderReadOnlyOptimal`, and waits for the pass to finish writing it
+// before any later fragment shader reads it, so once the pass has been
+// recorded the colour image can be bound like any other `Texture` in the
+// same command buffer: drawn as a sprite for a minimap, sampled for a
+// reflection, or fed through post-processing.
+//
+// A target's size is either fixed, or relative to the window. Relative
+// targets are recreated by `resize` when the window changes size. The
+// render pass is kept, so pipelines created for it stay valid, but the
+// colour image's view is new, so descriptor sets sampling it must be
+// rewritten (see `SpriteBatch::set_texture`).
+
+use gfx_hal::Backend;
+use prelude::*;
+use texture::Texture;
+use utils;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetSize {
+    /// A fixed size, in pixels.
+    Fixed(u32, u32),
+    /// A fraction of the window's size: 1.0 is full resolution, 0.5 half.
+    Relative(f32),
+}
+
+impl TargetSize {
+    /// The target's size for a window of `window` pixels. Never zero.
+    pub fn resolve(&self, window: Extent2D) -> Extent2D {
+        let (width, height) = match *self {
+            TargetSize::Fixed(width, height) => (width, height),
+            TargetSize::Relative(scale) => ((window.width as f32 * scale).round() as u32,
+                                            (window.height as f32 * scale).round() as u32),
+        };
+        Extent2D {
+            width: width.max(1),
+            height: height.max(1),
+        }
+    }
+}
+
+
+struct DepthImage<B: Backend> {
+    image: B::Image,
+    memory: B::Memory,
+    view: B::ImageView,
+}
+
+// The images and framebuffer, which are recreated on resize.
+struct Attachments<B: Backend> {
+    colour: Texture<B>,
+    depth: Option<DepthImage<B>>,
+    framebuffer: B::Framebuffer,
+}
+
+fn create_image<B: Backend>(device: &B::Device,
+                            memory_types: &[MemoryType],
+                            extent: Extent2D,
+                            format: Format,
+                            usage: img::Usage,
+                            aspects: Aspects) -> (B::Image, B::Memory, B::ImageView) {
+    let kind = img::Kind::D2(extent.width, extent.height, 1, 1);
+    let unbound_image = device.create_image(kind, 1, format, img::Tiling::Optimal, usage, ViewCapabilities::empty())
+                              .unwrap();
+    let req = device.get_image_requirements(&unbound_image);
+    let memory_type = utils::find_memory_type(memory_types, req.type_mask, Properties::DEVICE_LOCAL)
+                          .expect("Could not find appropriate render target memory type.");
+    let memory = device.allocate_memory(memory_type, req.size).unwrap();
+    let image = device.bind_image_memory(&memory, 0, unbound_image).unwrap();
+
+    let range = SubresourceRange {
+        aspects,
+        levels: 0..1,
+        layers: 0..1,
+    };
+    let view = device.create_image_view(&image, ViewKind::D2, format, Swizzle::NO, range)
+                     .unwrap();
+    (image, memory, view)
+}
+
+impl<B: Backend> Attachments<B> {
+    fn new(device: &B::Device,
+           memory_types: &[MemoryType],
+           render_pass: &B::RenderPass,
+           extent: Extent2D,
+           colour_format: Format,
+           depth_format: Option<Format>) -> Self {
+        // TRANSFER_SRC so targets can be read back with `readback::read_image`.
+        let (image, memory, view) = create_image::<B>(device,
+                                                      memory_types,
+                                                      extent,
+                                                      colour_format,
+                                                      img::Usage::COLOR_ATTACHMENT |
+                                                      img::Usage::SAMPLED |
+                                                      img::Usage::TRANSFER_SRC,
+                                                      Aspects::COLOR);
+        let colour = Texture {
+            image,
+            memory,
+            view,
+            format: colour_format,
+            extent: extent.to_extent(),
+            levels: 1,
+            layers: 1,
+            view_kind: ViewKind::D2,
+        };
+
+        let depth = depth_format.map(|format| {
+            let (image, memory, view) = create_image::<B>(device,
+                                                          memory_types,
+                                                          extent,
+                                                          format,
+                                                          img::Usage::DEPTH_STENCIL_ATTACHMENT,
+                                                          Aspects::DEPTH);
+            DepthImage { image, memory, view }
+        });
+
+        let framebuffer = {
+            let mut views = vec![&colour.view];
+            if let Some(ref depth) = depth {
+                views.push(&depth.view);
+            }
+            device.create_framebuffer(render_pass, views, extent.to_extent())
+                  .unwrap()
+        };
+
+        Attachments { colour, depth, framebuffer }
+    }
+
+    fn destroy(self, device: &B::Device) {
+        device.destroy_framebuffer(self.framebuffer);
+        self.colour.destroy(device);
+        if let Some(depth) = self.depth {
+            device.destroy_image_view(depth.view);
+            device.destroy_image(depth.image);
+            device.free_memory(depth.memory);
+        }
+    }
+}
+
+
+pub struct RenderTarget<B: Backend> {
+    pub render_pass: B::RenderPass,
+    pub colour_format: Format,
+    pub depth_format: Option<Format>,
+    size: TargetSize,
+    extent: Extent2D,
+    // Only `None` for the moment it takes to recreate them.
+    attachments: Option<Attachments<B>>,
+}
+
+impl<B: Backend> RenderTarget<B> {
+    /// Create a target of `size`, where `window` is the window's current
+    /// size.
+    pub fn new(device: &B::Device,
+               memory_types: &[MemoryType],
+               size: TargetSize,
+               window: Extent2D,
+               colour_format: Format,
+               depth_format: Option<Format>) -> Self {
+        let render_pass = {
+            let mut attachments = vec![Attachment {
+                format: Some(colour_format),
+                samples: 1,
+                ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+                stencil_ops: AttachmentOps::DONT_CARE,
+                layouts: Layout::Undefined..Layout::ShaderReadOnlyOptimal,
+            }];
+            if let Some(format) = depth_format {
+                attachments.push(Attachment {
+                    format: Some(format),
+                    samples: 1,
+                    ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::DontCare),
+                    stencil_ops: AttachmentOps::DONT_CARE,
+                    layouts: Layout::Undefined..Layout::DepthStencilAttachmentOptimal,
+                });
+            }
+
+            let depth_ref = (1, Layout::DepthStencilAttachmentOptimal);
+            let subpass = SubpassDesc {
+                colors: &[(0, Layout::ColorAttachmentOptimal)],
+                depth_stencil: if depth_format.is_some() { Some(&depth_ref) } else { None },
+                inputs: &[],
+                preserves: &[],
+                resolves: &[],
+            };
+
+            let dependencies = [
+                // Don't write the image until earlier reads of it are done...
+                SubpassDependency {
+                    passes: SubpassRef::External..SubpassRef::Pass(0),
+                    stages: PipelineStage::FRAGMENT_SHADER..PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                    accesses: Access::SHADER_READ..(Access::COLOR_ATTACHMENT_READ | Access::COLOR_ATTACHMENT_WRITE),
+                },
+                // ...and don't read it in later passes until it's written.
+                SubpassDependency {
+                    passes: SubpassRef::Pass(0)..SubpassRef::External,
+                    stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::FRAGMENT_SHADER,
+                    accesses: Access::COLOR_ATTACHMENT_WRITE..Access::SHADER_READ,
+                },
+            ];
+
+            device.create_render_pass(&attachments, &[subpass], &dependencies)
+        };
+
+        let extent = size.resolve(window);
+        let attachments = Attachments::new(device, memory_types, &render_pass, extent, colour_format, depth_format);
+
+        RenderTarget {
+            render_pass,
+            colour_format,
+            depth_format,
+            size,
+            extent,
+            attachments: Some(attachments),
+        }
+    }
+
+    pub fn size(&self) -> TargetSize {
+        self.size
+    }
+
+    /// The target's current size in pixels.
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    pub fn framebuffer(&self) -> &B::Framebuffer {
+        &self.attachments.as_ref().unwrap().framebuffer
+    }
+
+    /// The colour image, to sample from after the target has been rendered
+    /// to. It's replaced whenever the target is resized.
+    pub fn texture(&self) -> &Texture<B> {
+        &self.attachments.as_ref().unwrap().colour
+    }
+
+    /// A viewport covering the whole target. Set it, and its rect as the
+    /// scissor, before drawing into the target.
+    pub fn viewport(&self) -> Viewport {
+        Viewport {
+            rect: Rect {
+                x: 0,
+                y: 0,
+                w: self.extent.width as i16,
+                h: self.extent.height as i16,
+            },
+            depth: 0.0..1.0,
+        }
+    }
+
+    /// Clear values for beginning the render pass: `colour` for the colour
+    /// image, and 1.0 for the depth image if there is one.
+    pub fn clear_values(&self, colour: [f32; 4]) -> Vec<ClearValue> {
+        let mut values = vec![ClearValue::Color(ClearColor::Float(colour))];
+        if self.depth_format.is_some() {
+            values.push(ClearValue::DepthStencil(ClearDepthStencil(1.0, 0)));
+        }
+        values
+    }
+
+    /// Call when the window has been resized to `window`. Relative targets
+    /// are recreated at their new size. Returns whether the images changed.
+    ///
+    /// Nothing using the old images may still be running on the GPU.
+    pub fn resize(&mut self, device: &B::Device, memory_types: &[MemoryType], window: Extent2D) -> bool {
+        let size = self.size;
+        self.set_size(device, memory_types, size, window)
+    }
+
+    /// Change the target's size, recreating its images if their size
+    /// changes. Returns whether they did.
+    ///
+    /// Nothing using the old images may still be running on the GPU.
+    pub fn set_size(&mut self,
+                    device: &B::Device,
+                    memory_types: &[MemoryType],
+                    size: TargetSize,
+                    window: Extent2D) -> bool {
+        self.size = size;
+        let extent = size.resolve(window);
+        if extent == self.extent {
+            return false;
+        }
+
+        self.attachments.take().unwrap().destroy(device);
+        self.attachments = Some(Attachments::new(device,
+                                                 memory_types,
+                                                 &self.render_pass,
+                                                 extent,
+                                                 self.colour_format,
+                                                 self.depth_format));
+        self.extent = extent;
+        true
+    }
+
+    pub fn destroy(mut self, device: &B::Device) {
+        if let Some(attachments) = self.attachments.take() {
+            attachments.destroy(device);
+        }
+        device.destroy_render_pass(self.render_pass);
+    }
+}
@@ -0,0 +1,175 @@
+use gfx_hal::Backend;
+use prelude::*;
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+
+/// Everything needed to build a sampler, in a form we can hash.
+///
+/// LOD values and the border colour are floats, so equality and hashing
+/// go through their bit patterns rather than deriving.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerDesc {
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    pub mip_filter: Filter,
+    pub wrap: (WrapMode, WrapMode, WrapMode),
+    pub lod_bias: f32,
+    pub lod_range: (f32, f32),
+    pub anisotropy: Option<u8>,
+    pub border: [f32; 4],
+    pub comparison: Option<Comparison>,
+}
+
+impl SamplerDesc {
+    pub fn new(filter: Filter, wrap: WrapMode) -> Self {
+        SamplerDesc {
+            min_filter: filter,
+            mag_filter: filter,
+            mip_filter: filter,
+            wrap: (wrap, wrap, wrap),
+            lod_bias: 0.0,
+            lod_range: (0.0, 1000.0),
+            anisotropy: None,
+            border: [0.0, 0.0, 0.0, 0.0],
+            comparison: None,
+        }
+    }
+
+    pub fn linear() -> Self {
+        SamplerDesc::new(Filter::Linear, WrapMode::Tile)
+    }
+
+    pub fn nearest() -> Self {
+        SamplerDesc::new(Filter::Nearest, WrapMode::Tile)
+    }
+
+    pub fn with_wrap(mut self, u: WrapMode, v: WrapMode, w: WrapMode) -> Self {
+        self.wrap = (u, v, w);
+        self
+    }
+
+    pub fn with_lod(mut self, bias: f32, min: f32, max: f32) -> Self {
+        self.lod_bias = bias;
+        self.lod_range = (min, max);
+        self
+    }
+
+    pub fn with_anisotropy(mut self, max_anisotropy: u8) -> Self {
+        self.anisotropy = Some(max_anisotropy);
+        self
+    }
+
+    pub fn with_border(mut self, colour: [f32; 4]) -> Self {
+        self.border = colour;
+        self
+    }
+
+    pub fn with_comparison(mut self, comparison: Comparison) -> Self {
+        self.comparison = Some(comparison);
+        self
+    }
+
+    pub fn to_info(&self) -> SamplerInfo {
+        SamplerInfo {
+            min_filter: self.min_filter,
+            mag_filter: self.mag_filter,
+            mip_filter: self.mip_filter,
+            wrap_mode: self.wrap,
+            lod_bias: Lod::from(self.lod_bias),
+            lod_range: Lod::from(self.lod_range.0)..Lod::from(self.lod_range.1),
+            comparison: self.comparison,
+            border: PackedColor::from(self.border),
+            anisotropic: match self.anisotropy {
+                Some(max) => Anisotropic::On(max),
+                None => Anisotropic::Off,
+            },
+        }
+    }
+
+    fn float_bits(&self) -> [u32; 7] {
+        [
+            self.lod_bias.to_bits(),
+            self.lod_range.0.to_bits(),
+            self.lod_range.1.to_bits(),
+            self.border[0].to_bits(),
+            self.border[1].to_bits(),
+            self.border[2].to_bits(),
+            self.border[3].to_bits(),
+        ]
+    }
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        SamplerDesc::linear()
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &SamplerDesc) -> bool {
+        self.min_filter == other.min_filter
+            && self.mag_filter == other.mag_filter
+            && self.mip_filter == other.mip_filter
+            && self.wrap == other.wrap
+            && self.anisotropy == other.anisotropy
+            && self.comparison == other.comparison
+            && self.float_bits() == other.float_bits()
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.min_filter.hash(state);
+        self.mag_filter.hash(state);
+        self.mip_filter.hash(state);
+        self.wrap.hash(state);
+        self.anisotropy.hash(state);
+        self.comparison.hash(state);
+        self.float_bits().hash(state);
+    }
+}
+
+
+/// Lazily creates samplers, handing back the same one for identical
+/// descriptors. Call `destroy` before the device goes away.
+pub struct SamplerCache<B: Backend> {
+    samplers: HashMap<SamplerDesc, B::Sampler>,
+}
+
+impl<B: Backend> SamplerCache<B> {
+    pub fn new() -> Self {
+        SamplerCache {
+            samplers: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, device: &B::Device, desc: SamplerDesc) -> &B::Sampler {
+        self.samplers
+            .entry(desc)
+            .or_insert_with(|| device.create_sampler(desc.to_info()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samplers.is_empty()
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        for (_, sampler) in self.samplers {
+            device.destroy_sampler(sampler);
+        }
+    }
+}
+
+impl<B: Backend> Default for SamplerCache<B> {
+    fn default() -> Self {
+        SamplerCache::new()
+    }
+}
@@ -0,0 +1,582 @@
+// A scene graph: a hierarchy of nodes with local transforms.
+//
+// Each node caches its world matrix. Changing a node's transform or parent
+// marks it dirty, and `update` recomputes world matrices for dirty nodes and
+// everything below them, leaving the rest alone. Call `update` once per frame
+// after making changes and before reading world transforms or draw items.
+//
+// Meshes and materials are referred to by index, the same way
+// `gltf_import::GltfScene` does, so the renderer decides what they index.
+
+use gltf_import::GltfScene;
+use math::{Mat3, Mat4, Quat, Vec3};
+
+use std::error::Error;
+use std::fmt;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            translation: Vec3::zero(),
+            rotation: Quat::identity(),
+            scale: Vec3::splat(1.0),
+        }
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Transform {
+            translation,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_trs(self.translation, self.rotation, self.scale)
+    }
+
+    /// Split a matrix back into translation, rotation and scale. Shear has
+    /// no place in a `Transform`, so any in the matrix is lost.
+    pub fn from_matrix(m: &Mat4) -> Self {
+        let (x, y, z) = (m.col(0).truncate(), m.col(1).truncate(), m.col(2).truncate());
+        let mut scale = Vec3::new(x.length(), y.length(), z.length());
+        // A mirroring matrix needs one negative scale; put it on X.
+        if m.to_mat3().determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+        let rotation = if scale.x != 0.0 && scale.y != 0.0 && scale.z != 0.0 {
+            Quat::from_mat3(&Mat3::from_cols(x / scale.x, y / scale.y, z / scale.z))
+        } else {
+            Quat::identity()
+        };
+        Transform {
+            translation: m.col(3).truncate(),
+            rotation,
+            scale,
+        }
+    }
+}
+
+
+/// Handle to a node. Handles to removed nodes are never reused, so they
+/// stop resolving rather than pointing at some other node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SceneError {
+    /// The node handle doesn't refer to a node in this scene.
+    InvalidNode(NodeId),
+    /// The new parent is the node itself or one of its descendants.
+    Cycle,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::InvalidNode(id) => write!(f, "no such node: {:?}", id),
+            SceneError::Cycle => write!(f, "a node can't be parented to itself or its descendants"),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: Option<String>,
+    pub mesh: Option<usize>,
+    pub material: Option<usize>,
+    transform: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    world: Mat4,
+    dirty: bool,
+}
+
+impl Node {
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+        self.dirty = true;
+    }
+
+    /// Change the transform in place.
+    pub fn transform_mut(&mut self) -> &mut Transform {
+        self.dirty = true;
+        &mut self.transform
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    /// The world matrix as of the last `Scene::update`.
+    pub fn world_transform(&self) -> Mat4 {
+        self.world
+    }
+}
+
+
+/// Something to draw: a node's mesh and material at its world transform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawItem {
+    pub node: NodeId,
+    pub mesh: usize,
+    pub material: Option<usize>,
+    pub world: Mat4,
+}
+
+
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    roots: Vec<NodeId>,
+}
+
+#[derive(Clone, Debug)]
+struct Slot {
+    generation: u32,
+    node: Option<Node>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Scene::default()
+    }
+
+    /// Add a node under `parent`, or as a root if `parent` is `None`.
+    pub fn add(&mut self, parent: Option<NodeId>, transform: Transform) -> Result<NodeId, SceneError> {
+        if let Some(parent) = parent {
+            self.check(parent)?;
+        }
+
+        let node = Node {
+            name: None,
+            mesh: None,
+            material: None,
+            transform,
+            parent,
+            children: Vec::new(),
+            world: Mat4::identity(),
+            dirty: true,
+        };
+
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.node = Some(node);
+                NodeId { index, generation: slot.generation }
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, node: Some(node) });
+                NodeId { index: self.slots.len() as u32 - 1, generation: 0 }
+            }
+        };
+
+        match parent {
+            Some(parent) => self.slot_mut(parent).children.push(id),
+            None => self.roots.push(id),
+        }
+        Ok(id)
+    }
+
+    /// Remove a node along with all of its descendants.
+    pub fn remove(&mut self, id: NodeId) -> Result<(), SceneError> {
+        self.check(id)?;
+        self.detach(id);
+
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let slot = &mut self.slots[id.index as usize];
+            let node = slot.node.take().unwrap();
+            slot.generation += 1;
+            self.free.push(id.index);
+            stack.extend(node.children);
+        }
+        Ok(())
+    }
+
+    /// Move a node (and its subtree) under a new parent, or make it a root.
+    /// Its local transform is kept, so its world transform will generally
+    /// change.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), SceneError> {
+        self.check(id)?;
+        if let Some(parent) = parent {
+            self.check(parent)?;
+            // Walk up from the new parent; finding `id` means a cycle.
+            let mut ancestor = Some(parent);
+            while let Some(current) = ancestor {
+                if current == id {
+                    return Err(SceneError::Cycle);
+                }
+                ancestor = self.slot(current).parent;
+            }
+        }
+
+        self.detach(id);
+        match parent {
+            Some(parent) => self.slot_mut(parent).children.push(id),
+            None => self.roots.push(id),
+        }
+        let node = self.slot_mut(id);
+        node.parent = parent;
+        node.dirty = true;
+        Ok(())
+    }
+
+    /// Like `set_parent`, but adjust the node's local transform so that its
+    /// world transform stays where it is. If the new parent's world matrix
+    /// can't be inverted (it has a zero scale somewhere), the local
+    /// transform is kept instead. Under a non-uniformly scaled parent, a
+    /// rotated node may need shear to stay put, which a `Transform` can't
+    /// hold, so it only comes close.
+    pub fn set_parent_keeping_world(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), SceneError> {
+        self.check(id)?;
+        if let Some(parent) = parent {
+            self.check(parent)?;
+        }
+        // Worked out from the local transforms rather than the cached world
+        // matrices, which may be stale.
+        let world = self.current_world(id);
+        let parent_world = parent.map(|parent| self.current_world(parent)).unwrap_or_else(Mat4::identity);
+
+        self.set_parent(id, parent)?;
+        if let Some(inverse) = parent_world.inverse() {
+            self.slot_mut(id).set_transform(Transform::from_matrix(&(inverse * world)));
+        }
+        Ok(())
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.slots.get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.slots.get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Recompute world matrices for dirty nodes and their descendants.
+    pub fn update(&mut self) {
+        // (node, parent's world matrix, whether an ancestor changed)
+        let mut stack: Vec<(NodeId, Mat4, bool)> = self.roots
+            .iter()
+            .rev()
+            .map(|&root| (root, Mat4::identity(), false))
+            .collect();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = self.slot_mut(id);
+            let changed = parent_changed || node.dirty;
+            if changed {
+                node.world = parent_world * node.transform.matrix();
+                node.dirty = false;
+            }
+            let world = node.world;
+            stack.extend(node.children.iter().rev().map(|&child| (child, world, changed)));
+        }
+    }
+
+    /// Every node with a mesh, depth-first from the roots.
+    pub fn draw_items(&self) -> Vec<DrawItem> {
+        let mut items = Vec::new();
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().cloned().collect();
+        while let Some(id) = stack.pop() {
+            let node = self.slot(id);
+            if let Some(mesh) = node.mesh {
+                items.push(DrawItem {
+                    node: id,
+                    mesh,
+                    material: node.material,
+                    world: node.world,
+                });
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        items
+    }
+
+    /// Build a scene from an imported glTF file's node hierarchy. Returns
+    /// the scene and the node id for each glTF node, by index. Node meshes
+    /// index `GltfScene::meshes`; materials are left to the primitives.
+    pub fn from_gltf(gltf: &GltfScene) -> (Scene, Vec<NodeId>) {
+        let mut scene = Scene::new();
+        let mut ids: Vec<Option<NodeId>> = vec![None; gltf.nodes.len()];
+
+        for &root in &gltf.roots {
+            scene.add_gltf_subtree(gltf, root, &mut ids);
+        }
+        // Nodes outside the default scene still get ids, as extra roots.
+        for index in 0..gltf.nodes.len() {
+            if ids[index].is_none() && gltf.nodes[index].parent.is_none() {
+                scene.add_gltf_subtree(gltf, index, &mut ids);
+            }
+        }
+
+        scene.update();
+        (scene, ids.into_iter().map(|id| id.unwrap()).collect())
+    }
+
+
+    fn check(&self, id: NodeId) -> Result<(), SceneError> {
+        match self.node(id) {
+            Some(_) => Ok(()),
+            None => Err(SceneError::InvalidNode(id)),
+        }
+    }
+
+    // Look up a node already known to be valid.
+    fn slot(&self, id: NodeId) -> &Node {
+        self.slots[id.index as usize].node.as_ref().unwrap()
+    }
+
+    fn slot_mut(&mut self, id: NodeId) -> &mut Node {
+        self.slots[id.index as usize].node.as_mut().unwrap()
+    }
+
+    // A node's world matrix from its and its ancestors' current transforms.
+    fn current_world(&self, id: NodeId) -> Mat4 {
+        let mut world = Mat4::identity();
+        let mut current = Some(id);
+        while let Some(id) = current {
+            let node = self.slot(id);
+            world = node.transform.matrix() * world;
+            current = node.parent;
+        }
+        world
+    }
+
+    // Unlink a node from its parent's children, or from the roots.
+    fn detach(&mut self, id: NodeId) {
+        let parent = self.slot(id).parent;
+        let siblings = match parent {
+            Some(parent) => &mut self.slot_mut(parent).children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != id);
+    }
+
+    fn add_gltf_subtree(&mut self, gltf: &GltfScene, root: usize, ids: &mut [Option<NodeId>]) {
+        // Parents always come off the stack before their children.
+        let mut stack = vec![(root, None)];
+        while let Some((index, parent)) = stack.pop() {
+            let source = &gltf.nodes[index];
+            let [x, y, z, w] = source.rotation;
+            let id = self.add(parent, Transform {
+                translation: source.translation.into(),
+                rotation: Quat::new(x, y, z, w),
+                scale: source.scale.into(),
+            }).unwrap();
+            {
+                let node = self.slot_mut(id);
+                node.name = source.name.clone();
+                node.mesh = source.mesh;
+            }
+            ids[index] = Some(id);
+            stack.extend(source.children.iter().rev().map(|&child| (child, Some(id))));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_close(a: Mat4, b: Mat4) {
+        for (a_col, b_col) in a.0.iter().zip(&b.0) {
+            for (a, b) in a_col.iter().zip(b_col) {
+                assert!((a - b).abs() < EPSILON, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    fn world_position(scene: &Scene, id: NodeId) -> Vec3 {
+        scene.node(id).unwrap().world_transform().transform_point(Vec3::zero())
+    }
+
+    fn assert_position(scene: &Scene, id: NodeId, expected: Vec3) {
+        let position = world_position(scene, id);
+        assert!((position - expected).length() < EPSILON, "{:?} != {:?}", position, expected);
+    }
+
+    // root at (1, 0, 0) -> child at (0, 2, 0) -> grandchild at (0, 0, 3),
+    // plus an unrelated second root.
+    fn chain() -> (Scene, [NodeId; 4]) {
+        let mut scene = Scene::new();
+        let root = scene.add(None, Transform::from_translation(Vec3::new(1.0, 0.0, 0.0))).unwrap();
+        let child = scene.add(Some(root), Transform::from_translation(Vec3::new(0.0, 2.0, 0.0))).unwrap();
+        let grandchild = scene.add(Some(child), Transform::from_translation(Vec3::new(0.0, 0.0, 3.0))).unwrap();
+        let other = scene.add(None, Transform::from_translation(Vec3::new(-1.0, 0.0, 0.0))).unwrap();
+        scene.update();
+        (scene, [root, child, grandchild, other])
+    }
+
+    #[test]
+    fn world_matrices_compose_parents() {
+        let (scene, [root, child, grandchild, other]) = chain();
+        assert_position(&scene, root, Vec3::new(1.0, 0.0, 0.0));
+        assert_position(&scene, child, Vec3::new(1.0, 2.0, 0.0));
+        assert_position(&scene, grandchild, Vec3::new(1.0, 2.0, 3.0));
+        assert_position(&scene, other, Vec3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn moving_a_parent_moves_its_subtree() {
+        let (mut scene, [root, child, grandchild, other]) = chain();
+        scene.node_mut(root).unwrap().set_transform(Transform {
+            translation: Vec3::new(5.0, 0.0, 0.0),
+            rotation: Quat::from_axis_angle(Vec3::unit_z(), FRAC_PI_2),
+            scale: Vec3::splat(1.0),
+        });
+
+        // Nothing changes until the next update.
+        assert_position(&scene, grandchild, Vec3::new(1.0, 2.0, 3.0));
+        scene.update();
+
+        // The quarter turn about Z takes the child's +Y offset to -X.
+        assert_position(&scene, root, Vec3::new(5.0, 0.0, 0.0));
+        assert_position(&scene, child, Vec3::new(3.0, 0.0, 0.0));
+        assert_position(&scene, grandchild, Vec3::new(3.0, 0.0, 3.0));
+        assert_position(&scene, other, Vec3::new(-1.0, 0.0, 0.0));
+
+        // Changing a node in the middle leaves its parent alone.
+        scene.node_mut(child).unwrap().transform_mut().translation = Vec3::zero();
+        scene.update();
+        assert_position(&scene, root, Vec3::new(5.0, 0.0, 0.0));
+        assert_position(&scene, grandchild, Vec3::new(5.0, 0.0, 3.0));
+    }
+
+    #[test]
+    fn reparenting_keeps_the_local_transform() {
+        let (mut scene, [root, child, grandchild, other]) = chain();
+        scene.set_parent(child, Some(other)).unwrap();
+        scene.update();
+
+        assert!(scene.node(root).unwrap().children().is_empty());
+        assert_eq!(scene.node(other).unwrap().children(), &[child]);
+        assert_eq!(scene.node(child).unwrap().parent(), Some(other));
+        assert_position(&scene, child, Vec3::new(-1.0, 2.0, 0.0));
+        assert_position(&scene, grandchild, Vec3::new(-1.0, 2.0, 3.0));
+
+        scene.set_parent(child, None).unwrap();
+        scene.update();
+        assert_eq!(scene.roots(), &[root, other, child]);
+        assert_position(&scene, grandchild, Vec3::new(0.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn reparenting_can_keep_the_world_transform() {
+        let (mut scene, [_, child, grandchild, other]) = chain();
+        scene.node_mut(other).unwrap().set_transform(Transform {
+            translation: Vec3::new(-1.0, 4.0, 0.0),
+            rotation: Quat::from_yaw_pitch_roll(0.3, -0.7, 1.1),
+            scale: Vec3::splat(2.0),
+        });
+        let before = scene.node(grandchild).unwrap().world_transform();
+
+        // Both the old and new parents' transforms are out of date in the
+        // cached world matrices here, which mustn't matter.
+        scene.set_parent_keeping_world(grandchild, Some(other)).unwrap();
+        assert!(scene.node(child).unwrap().children().is_empty());
+        scene.update();
+        assert_close(scene.node(grandchild).unwrap().world_transform(), before);
+
+        scene.set_parent_keeping_world(grandchild, None).unwrap();
+        scene.update();
+        assert_close(scene.node(grandchild).unwrap().world_transform(), before);
+        assert_close(scene.node(grandchild).unwrap().transform().matrix(), before);
+    }
+
+    #[test]
+    fn reparenting_under_a_descendant_is_refused() {
+        let (mut scene, [root, child, grandchild, _]) = chain();
+        assert_eq!(scene.set_parent(root, Some(grandchild)), Err(SceneError::Cycle));
+        assert_eq!(scene.set_parent(child, Some(child)), Err(SceneError::Cycle));
+        assert_eq!(scene.set_parent_keeping_world(root, Some(child)), Err(SceneError::Cycle));
+        assert_eq!(scene.node(child).unwrap().parent(), Some(root));
+        assert_eq!(scene.node(root).unwrap().parent(), None);
+    }
+
+    #[test]
+    fn removing_a_node_removes_its_subtree() {
+        let (mut scene, [root, child, grandchild, other]) = chain();
+        for &(id, mesh) in &[(root, 0), (child, 1), (grandchild, 2), (other, 3)] {
+            scene.node_mut(id).unwrap().mesh = Some(mesh);
+        }
+        scene.remove(child).unwrap();
+
+        assert_eq!(scene.len(), 2);
+        assert!(scene.node(child).is_none());
+        assert!(scene.node(grandchild).is_none());
+        assert!(scene.node(root).unwrap().children().is_empty());
+        assert_eq!(scene.remove(grandchild), Err(SceneError::InvalidNode(grandchild)));
+        let meshes: Vec<usize> = scene.draw_items().iter().map(|item| item.mesh).collect();
+        assert_eq!(meshes, vec![0, 3]);
+
+        // New nodes reuse the slots, but not the old handles.
+        let new = scene.add(Some(root), Transform::identity()).unwrap();
+        assert!(new != child && new != grandchild);
+        assert!(scene.node(child).is_none() && scene.node(grandchild).is_none());
+        assert_eq!(scene.len(), 3);
+
+        scene.remove(root).unwrap();
+        scene.remove(other).unwrap();
+        assert!(scene.is_empty());
+        assert!(scene.roots().is_empty());
+    }
+
+    #[test]
+    fn transforms_survive_a_round_trip_through_matrices() {
+        let transform = Transform {
+            translation: Vec3::new(1.0, -2.0, 3.0),
+            rotation: Quat::from_yaw_pitch_roll(-0.4, 0.9, 2.5),
+            scale: Vec3::new(-2.0, 0.5, 1.5),
+        };
+        assert_close(Transform::from_matrix(&transform.matrix()).matrix(), transform.matrix());
+    }
+}
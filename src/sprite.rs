@@ -0,0 +1,447 @@
+// Batched 2D sprite drawing.
+//
+// Sprites are queued with `draw_sprite` over the course of a frame, then
+// `flush` sorts them by layer and texture, writes them all into one vertex
+// and index buffer, and records one draw call per run of sprites sharing a
+// texture. Within a layer, sprites using the same texture keep the order
+// they were drawn in; sprites using different textures may be reordered.
+
+use dynamic_buffer::DynamicBuffer;
+use gfx_hal::Backend;
+use math::{Mat4, Vec2};
+use prelude::*;
+use texture::Texture;
+use utils;
+
+#[cfg(windows)]
+static VERT_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\sprite.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\sprite.frag.spv");
+
+#[cfg(all(unix))]
+static VERT_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/sprite.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/sprite.frag.spv");
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct SpriteVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub colour: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct UniformBlock {
+    projection: [[f32; 4]; 4],
+}
+
+
+/// A region of a texture in UV space, from 0 to 1 across the whole
+/// texture. Geometry uses `math::Rect2` instead, and viewports and scissors
+/// the prelude's integer `Rect`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UvRect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl UvRect {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        UvRect { x, y, w, h }
+    }
+
+    /// The whole of a texture, in UV space.
+    pub fn full() -> Self {
+        UvRect::new(0.0, 0.0, 1.0, 1.0)
+    }
+}
+
+
+/// Where and how a sprite is placed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteTransform {
+    pub position: Vec2,
+    pub size: Vec2,
+    /// Radians, clockwise on screen when Y points down.
+    pub rotation: f32,
+    /// The point that's placed at `position` and rotated about, as a
+    /// fraction of the size: (0, 0) is the top-left corner, (0.5, 0.5) the
+    /// centre.
+    pub origin: Vec2,
+    /// Lower layers are drawn first, so higher layers appear on top.
+    pub layer: i32,
+}
+
+impl SpriteTransform {
+    /// An unrotated sprite with its top-left corner at `position`.
+    pub fn new(position: Vec2, size: Vec2) -> Self {
+        SpriteTransform {
+            position,
+            size,
+            rotation: 0.0,
+            origin: Vec2::zero(),
+            layer: 0,
+        }
+    }
+
+    pub fn with_rotation(self, rotation: f32, origin: Vec2) -> Self {
+        SpriteTransform {
+            rotation,
+            origin,
+            ..self
+        }
+    }
+
+    pub fn with_layer(self, layer: i32) -> Self {
+        SpriteTransform { layer, ..self }
+    }
+}
+
+
+/// Handle to a texture registered with a `SpriteBatch`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextureId(usize);
+
+
+struct QueuedSprite {
+    layer: i32,
+    texture: TextureId,
+    vertices: [SpriteVertex; 4],
+}
+
+
+pub struct SpriteBatch<B: Backend> {
+    set_layout: B::DescriptorSetLayout,
+    texture_set_layout: B::DescriptorSetLayout,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::GraphicsPipeline,
+    vertex_shader_module: B::ShaderModule,
+    fragment_shader_module: B::ShaderModule,
+    desc_pool: B::DescriptorPool,
+    desc_set: B::DescriptorSet,
+    texture_sets: Vec<B::DescriptorSet>,
+    max_textures: usize,
+    uniform_buffer: B::Buffer,
+    uniform_memory: B::Memory,
+    vertices: DynamicBuffer<B, SpriteVertex>,
+    indices: DynamicBuffer<B, u32>,
+    queue: Vec<QueuedSprite>,
+    vertex_data: Vec<SpriteVertex>,
+    index_data: Vec<u32>,
+}
+
+impl<B: Backend> SpriteBatch<B> {
+    /// Create a batch that draws in the first subpass of `render_pass`, with
+    /// room for up to `max_textures` textures.
+    pub fn new(device: &B::Device,
+               memory_types: &[MemoryType],
+               render_pass: &B::RenderPass,
+               max_textures: usize) -> Self {
+        SpriteBatch::with_fragment_shader(device, memory_types, render_pass, max_textures, FRAG_SPIRV)
+    }
+
+    /// Like `new`, but with a different fragment shader. It gets the same
+    /// inputs as `sprite.frag`: the UV at location 0, the colour at
+    /// location 1, and the texture at set 1, binding 0.
+    pub fn with_fragment_shader(device: &B::Device,
+                                memory_types: &[MemoryType],
+                                render_pass: &B::RenderPass,
+                                max_textures: usize,
+                                fragment_spirv: &[u8]) -> Self {
+        let set_layout = device.create_descriptor_set_layout(
+            &[DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::UniformBuffer,
+                count: 1,
+                stage_flags: ShaderStageFlags::VERTEX,
+                immutable_samplers: false,
+            }],
+            &[],
+        );
+
+        let texture_set_layout = device.create_descriptor_set_layout(
+            &[DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+                stage_flags: ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            }],
+            &[],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(vec![&set_layout, &texture_set_layout], &[]);
+
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+        let fragment_shader_module = device.create_shader_module(fragment_spirv).unwrap();
+
+        let pipeline = {
+            let vs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &vertex_shader_module,
+                specialization: Default::default(),
+            };
+
+            let fs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &fragment_shader_module,
+                specialization: Default::default(),
+            };
+
+            let shader_entries = GraphicsShaderSet {
+                vertex: vs_entry,
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(fs_entry),
+            };
+
+            let subpass = Subpass {
+                index: 0,
+                main_pass: render_pass,
+            };
+
+            let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                              Primitive::TriangleList,
+                                                              Rasterizer::FILL,
+                                                              &pipeline_layout,
+                                                              subpass);
+
+            pipeline_desc.blender
+                         .targets
+                         .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+            pipeline_desc.vertex_buffers.push(VertexBufferDesc {
+                binding: 0,
+                stride: ::std::mem::size_of::<SpriteVertex>() as u32,
+                rate: 0,
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 0,
+                binding: 0,
+                element: Element {
+                    format: Format::Rg32Float,
+                    offset: 0,
+                },
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 1,
+                binding: 0,
+                element: Element {
+                    format: Format::Rg32Float,
+                    offset: 8,
+                },
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 2,
+                binding: 0,
+                element: Element {
+                    format: Format::Rgba32Float,
+                    offset: 16,
+                },
+            });
+
+            device.create_graphics_pipeline(&pipeline_desc, None)
+                  .unwrap()
+        };
+
+        let mut desc_pool = device.create_descriptor_pool(
+            1 + max_textures,
+            &[
+                DescriptorRangeDesc {
+                    ty: DescriptorType::UniformBuffer,
+                    count: 1,
+                },
+                DescriptorRangeDesc {
+                    ty: DescriptorType::CombinedImageSampler,
+                    count: max_textures,
+                },
+            ],
+        );
+        let desc_set = desc_pool.allocate_set(&set_layout).unwrap();
+
+        let (uniform_buffer, uniform_memory) = utils::create_buffer::<B, UniformBlock>(
+            device,
+            memory_types,
+            Properties::CPU_VISIBLE,
+            buffer::Usage::UNIFORM,
+            &[UniformBlock {
+                projection: Mat4::identity().into(),
+            }],
+        );
+
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &desc_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::Buffer(&uniform_buffer, None..None)),
+        }]);
+
+        SpriteBatch {
+            set_layout,
+            texture_set_layout,
+            pipeline_layout,
+            pipeline,
+            vertex_shader_module,
+            fragment_shader_module,
+            desc_pool,
+            desc_set,
+            texture_sets: Vec::new(),
+            max_textures,
+            uniform_buffer,
+            uniform_memory,
+            vertices: DynamicBuffer::new(device, memory_types, buffer::Usage::VERTEX, 4 * 256),
+            indices: DynamicBuffer::new(device, memory_types, buffer::Usage::INDEX, 6 * 256),
+            queue: Vec::new(),
+            vertex_data: Vec::new(),
+            index_data: Vec::new(),
+        }
+    }
+
+    /// Make a texture available for drawing sprites. The texture and
+    /// sampler must outlive the batch (or at least its last flush).
+    ///
+    /// Panics if more than `max_textures` textures are added.
+    pub fn add_texture(&mut self, device: &B::Device, texture: &Texture<B>, sampler: &B::Sampler) -> TextureId {
+        assert!(self.texture_sets.len() < self.max_textures, "SpriteBatch texture limit reached");
+
+        let set = self.desc_pool.allocate_set(&self.texture_set_layout).unwrap();
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::CombinedImageSampler(&texture.view, Layout::ShaderReadOnlyOptimal, sampler)),
+        }]);
+        self.texture_sets.push(set);
+        TextureId(self.texture_sets.len() - 1)
+    }
+
+    /// Point an existing `TextureId` at a different texture, such as a
+    /// `RenderTarget`'s after it's been resized. Sprites already drawn with
+    /// the old texture must have finished on the GPU.
+    pub fn set_texture(&mut self, device: &B::Device, id: TextureId, texture: &Texture<B>, sampler: &B::Sampler) {
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &self.texture_sets[id.0],
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::CombinedImageSampler(&texture.view, Layout::ShaderReadOnlyOptimal, sampler)),
+        }]);
+    }
+
+    /// Queue a sprite showing the `uv` region of `texture`, tinted by
+    /// `colour`.
+    pub fn draw_sprite(&mut self, texture: TextureId, uv: UvRect, transform: SpriteTransform, colour: [f32; 4]) {
+        let (sin, cos) = transform.rotation.sin_cos();
+        let corner = |x: f32, y: f32, u: f32, v: f32| {
+            let local = (Vec2::new(x, y) - transform.origin) * transform.size;
+            let rotated = Vec2::new(local.x * cos - local.y * sin, local.x * sin + local.y * cos);
+            SpriteVertex {
+                position: (transform.position + rotated).into(),
+                uv: [u, v],
+                colour,
+            }
+        };
+
+        let (u0, v0, u1, v1) = (uv.x, uv.y, uv.x + uv.w, uv.y + uv.h);
+        self.queue.push(QueuedSprite {
+            layer: transform.layer,
+            texture,
+            vertices: [
+                corner(0.0, 0.0, u0, v0),
+                corner(1.0, 0.0, u1, v0),
+                corner(1.0, 1.0, u1, v1),
+                corner(0.0, 1.0, u0, v1),
+            ],
+        });
+    }
+
+    /// Number of sprites queued since the last flush.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// A projection for drawing in pixels, with (0, 0) at the top left and
+    /// Y pointing down the screen.
+    pub fn screen_projection(width: f32, height: f32) -> Mat4 {
+        Mat4::orthographic(0.0, width, height, 0.0, -1.0, 1.0)
+    }
+
+    /// Upload every queued sprite and record the draw calls, then clear the
+    /// queue. Returns the number of draw calls recorded.
+    ///
+    /// This rewrites buffers the GPU reads from, so flush at most once per
+    /// frame, after the previous frame using this batch has finished.
+    pub fn flush(&mut self,
+                 device: &B::Device,
+                 memory_types: &[MemoryType],
+                 encoder: &mut RenderPassInlineEncoder<B>,
+                 projection: Mat4) -> usize {
+        if self.queue.is_empty() {
+            return 0;
+        }
+
+        // Stable, so sprites sharing a layer and texture stay in order.
+        self.queue.sort_by_key(|sprite| (sprite.layer, sprite.texture));
+
+        self.vertex_data.clear();
+        self.index_data.clear();
+        // (texture, first index, index count) for each draw call.
+        let mut runs: Vec<(TextureId, u32, u32)> = Vec::new();
+        for sprite in &self.queue {
+            let base = self.vertex_data.len() as u32;
+            self.vertex_data.extend_from_slice(&sprite.vertices);
+            self.index_data.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+
+            match runs.last_mut() {
+                Some(run) if run.0 == sprite.texture => run.2 += 6,
+                _ => runs.push((sprite.texture, self.index_data.len() as u32 - 6, 6)),
+            }
+        }
+        self.queue.clear();
+
+        self.vertices.write(device, memory_types, &self.vertex_data);
+        self.indices.write(device, memory_types, &self.index_data);
+        utils::fill_buffer::<B, UniformBlock>(device,
+                                              &mut self.uniform_memory,
+                                              &[UniformBlock { projection: projection.into() }]);
+
+        encoder.bind_graphics_pipeline(&self.pipeline);
+        encoder.bind_graphics_descriptor_sets(&self.pipeline_layout, 0, vec![&self.desc_set], &[]);
+        encoder.bind_vertex_buffers(0, vec![(self.vertices.buffer(), 0)]);
+        encoder.bind_index_buffer(IndexBufferView {
+            buffer: self.indices.buffer(),
+            offset: 0,
+            index_type: IndexType::U32,
+        });
+
+        for &(texture, first, count) in &runs {
+            encoder.bind_graphics_descriptor_sets(&self.pipeline_layout, 1, vec![&self.texture_sets[texture.0]], &[]);
+            encoder.draw_indexed(first..first + count, 0, 0..1);
+        }
+
+        runs.len()
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        self.vertices.destroy(device);
+        self.indices.destroy(device);
+        device.destroy_buffer(self.uniform_buffer);
+        device.free_memory(self.uniform_memory);
+        device.destroy_descriptor_pool(self.desc_pool);
+        device.destroy_graphics_pipeline(self.pipeline);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+        device.destroy_descriptor_set_layout(self.set_layout);
+        device.destroy_descriptor_set_layout(self.texture_set_layout);
+        device.destroy_shader_module(self.vertex_shader_module);
+        device.destroy_shader_module(self.fragment_shader_module);
+    }
+}
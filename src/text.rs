@@ -0,0 +1,641 @@
+// Text rendering.
+//
+// A `FontAtlas` rasterises a set of characters from a TrueType/OpenType
+// font into a single atlas page, either as plain coverage or as a signed
+// distance field (SDF). Bitmap glyphs look best drawn at the size they were
+// rasterised at; SDF glyphs stay sharp when scaled up, at the cost of a
+// different fragment shader (see `sdf_sprite_batch`).
+//
+// Strings are laid out with kerning, line breaks, word wrapping and
+// alignment, and drawn as quads through a `SpriteBatch`.
+
+use atlas::{AtlasBuilder, AtlasError};
+use gfx_hal::Backend;
+use image::{Rgba, RgbaImage};
+use math::Vec2;
+use prelude::*;
+use rusttype::{self, point, GlyphId, Scale};
+use sprite::{SpriteBatch, SpriteTransform, TextureId, UvRect};
+use texture::TextureData;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+#[cfg(windows)]
+static SDF_FRAG_SPIRV: &'static [u8] = include_bytes!("..\\assets\\gen\\shaders\\text_sdf.frag.spv");
+
+#[cfg(all(unix))]
+static SDF_FRAG_SPIRV: &'static [u8] = include_bytes!("../assets/gen/shaders/text_sdf.frag.spv");
+
+/// The largest atlas page `FontAtlas::new` will try before giving up.
+const MAX_PAGE_SIZE: u32 = 4096;
+
+
+#[derive(Debug)]
+pub enum TextError {
+    Io(io::Error),
+    InvalidFont,
+    /// The glyphs don't fit on a single atlas page.
+    TooManyGlyphs,
+    Atlas(AtlasError),
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextError::Io(err) => write!(f, "I/O error: {}", err),
+            TextError::InvalidFont => write!(f, "not a valid TrueType or OpenType font"),
+            TextError::TooManyGlyphs => write!(f, "glyphs don't fit on a {0}x{0} atlas page", MAX_PAGE_SIZE),
+            TextError::Atlas(err) => write!(f, "atlas error: {}", err),
+        }
+    }
+}
+
+impl Error for TextError {}
+
+impl From<io::Error> for TextError {
+    fn from(err: io::Error) -> Self {
+        TextError::Io(err)
+    }
+}
+
+impl From<AtlasError> for TextError {
+    fn from(err: AtlasError) -> Self {
+        TextError::Atlas(err)
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GlyphMode {
+    /// Anti-aliased coverage; draw with a plain `SpriteBatch`.
+    Bitmap,
+    /// Signed distance to the glyph outline, out to `spread` pixels either
+    /// side of it; draw with `sdf_sprite_batch`.
+    Sdf { spread: u32 },
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Centre,
+    Right,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextStyle {
+    /// Height in pixels from the font's ascent to its descent.
+    pub size: f32,
+    pub colour: [f32; 4],
+    pub align: Align,
+    /// Wrap lines at word boundaries to fit this width, if set.
+    pub max_width: Option<f32>,
+    /// Multiplier for the font's own line height.
+    pub line_spacing: f32,
+    pub layer: i32,
+}
+
+impl TextStyle {
+    pub fn new(size: f32) -> Self {
+        TextStyle {
+            size,
+            colour: [1.0, 1.0, 1.0, 1.0],
+            align: Align::Left,
+            max_width: None,
+            line_spacing: 1.0,
+            layer: 0,
+        }
+    }
+
+    pub fn with_colour(self, colour: [f32; 4]) -> Self {
+        TextStyle { colour, ..self }
+    }
+
+    pub fn with_align(self, align: Align) -> Self {
+        TextStyle { align, ..self }
+    }
+
+    pub fn with_max_width(self, max_width: f32) -> Self {
+        TextStyle {
+            max_width: Some(max_width),
+            ..self
+        }
+    }
+
+    pub fn with_line_spacing(self, line_spacing: f32) -> Self {
+        TextStyle { line_spacing, ..self }
+    }
+
+    pub fn with_layer(self, layer: i32) -> Self {
+        TextStyle { layer, ..self }
+    }
+}
+
+
+/// One rasterised glyph. Metrics are in pixels at the atlas's size.
+#[derive(Clone, Copy, Debug)]
+struct GlyphInfo {
+    id: GlyphId,
+    uv: UvRect,
+    /// From the pen position on the baseline to the quad's top-left corner.
+    offset: Vec2,
+    size: Vec2,
+    advance: f32,
+}
+
+
+/// A glyph quad produced by layout, relative to the text's top-left corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlyphQuad {
+    pub position: Vec2,
+    pub size: Vec2,
+    pub uv: UvRect,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextLayout {
+    pub quads: Vec<GlyphQuad>,
+    /// The widest line, or `max_width` if that was set.
+    pub width: f32,
+    pub height: f32,
+    pub lines: usize,
+}
+
+
+pub struct FontAtlas {
+    font: rusttype::Font<'static>,
+    px_size: f32,
+    mode: GlyphMode,
+    glyphs: HashMap<char, GlyphInfo>,
+    image: RgbaImage,
+    ascent: f32,
+    line_height: f32,
+}
+
+impl FontAtlas {
+    /// Rasterise `chars` from `font_data`, `px_size` pixels high.
+    /// Characters the font has no glyph for are skipped, and drawn as the
+    /// replacement character (or '?') if that was included.
+    pub fn new<I>(font_data: Vec<u8>, px_size: f32, mode: GlyphMode, chars: I) -> Result<Self, TextError>
+        where I: IntoIterator<Item = char>
+    {
+        let font = rusttype::Font::from_bytes(font_data).map_err(|_| TextError::InvalidFont)?;
+        let scale = Scale::uniform(px_size);
+        let spread = match mode {
+            GlyphMode::Bitmap => 0,
+            GlyphMode::Sdf { spread } => spread,
+        };
+
+        let mut glyphs = HashMap::new();
+        let mut images = Vec::new();
+        for c in chars {
+            if glyphs.contains_key(&c) {
+                continue;
+            }
+            let glyph = font.glyph(c);
+            if glyph.id().0 == 0 {
+                continue;
+            }
+            let glyph = glyph.scaled(scale);
+            let advance = glyph.h_metrics().advance_width;
+            let glyph = glyph.positioned(point(0.0, 0.0));
+
+            let mut info = GlyphInfo {
+                id: glyph.id(),
+                uv: UvRect::default(),
+                offset: Vec2::zero(),
+                size: Vec2::zero(),
+                advance,
+            };
+            if let Some(bounds) = glyph.pixel_bounding_box() {
+                let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+                let mut coverage = vec![0.0; (width * height) as usize];
+                glyph.draw(|x, y, value| coverage[(y * width + x) as usize] = value);
+                let image = match mode {
+                    GlyphMode::Bitmap => {
+                        RgbaImage::from_fn(width, height, |x, y| {
+                            let alpha = coverage[(y * width + x) as usize];
+                            Rgba([255, 255, 255, (alpha * 255.0).round() as u8])
+                        })
+                    }
+                    GlyphMode::Sdf { spread } => distance_field(&coverage, width, height, spread),
+                };
+                info.offset = Vec2::new((bounds.min.x - spread as i32) as f32,
+                                        (bounds.min.y - spread as i32) as f32);
+                info.size = Vec2::new(image.width() as f32, image.height() as f32);
+                images.push((c, image));
+            }
+            glyphs.insert(c, info);
+        }
+
+        // Find the smallest square page that holds everything.
+        let mut page_size = 128;
+        let atlas = loop {
+            let mut builder = AtlasBuilder::new(page_size, page_size).with_padding(1);
+            for (c, image) in &images {
+                builder.add(c.to_string(), image.clone());
+            }
+            match builder.build() {
+                Ok(ref atlas) if atlas.pages.len() > 1 => {}
+                Ok(atlas) => break atlas,
+                Err(AtlasError::TooLarge(_)) => {}
+                Err(err) => return Err(err.into()),
+            }
+            page_size *= 2;
+            if page_size > MAX_PAGE_SIZE {
+                return Err(TextError::TooManyGlyphs);
+            }
+        };
+
+        for (c, _) in &images {
+            if let Some((_, uv)) = atlas.uv(&c.to_string()) {
+                glyphs.get_mut(c).unwrap().uv = uv;
+            }
+        }
+
+        let v_metrics = font.v_metrics(scale);
+        let image = atlas.pages
+                         .into_iter()
+                         .next()
+                         .unwrap_or_else(|| RgbaImage::new(1, 1));
+        Ok(FontAtlas {
+            font,
+            px_size,
+            mode,
+            glyphs,
+            image,
+            ascent: v_metrics.ascent,
+            line_height: v_metrics.ascent - v_metrics.descent + v_metrics.line_gap,
+        })
+    }
+
+    /// Rasterise printable ASCII, plus the replacement character.
+    pub fn ascii(font_data: Vec<u8>, px_size: f32, mode: GlyphMode) -> Result<Self, TextError> {
+        FontAtlas::new(font_data, px_size, mode, (0x20u8..0x7f).map(char::from).chain(Some('\u{FFFD}')))
+    }
+
+    /// Load a font file and rasterise printable ASCII from it.
+    pub fn load<P: AsRef<Path>>(path: P, px_size: f32, mode: GlyphMode) -> Result<Self, TextError> {
+        FontAtlas::ascii(::std::fs::read(path)?, px_size, mode)
+    }
+
+    pub fn mode(&self) -> GlyphMode {
+        self.mode
+    }
+
+    /// The size glyphs were rasterised at; see `TextStyle::size`.
+    pub fn px_size(&self) -> f32 {
+        self.px_size
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// The atlas page, ready for `Texture::upload`. It holds coverage or
+    /// distance rather than colour, so it's never sRGB.
+    pub fn texture_data(&self) -> TextureData {
+        TextureData::from_rgba8(&self.image, false)
+    }
+
+    /// Distance between baselines for text of the given size.
+    pub fn line_height(&self, size: f32) -> f32 {
+        self.line_height * size / self.px_size
+    }
+
+    fn glyph(&self, c: char) -> Option<&GlyphInfo> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&'\u{FFFD}'))
+            .or_else(|| self.glyphs.get(&'?'))
+    }
+
+    /// Position every glyph of `text`.
+    pub fn layout(&self, text: &str, style: &TextStyle) -> TextLayout {
+        let scale = style.size / self.px_size;
+        let line_height = self.line_height(style.size) * style.line_spacing;
+
+        // Each line is its glyphs (pen x, glyph) and its width.
+        let mut lines: Vec<(Vec<(f32, &GlyphInfo)>, f32)> = Vec::new();
+        for paragraph in text.split('\n') {
+            let mut line: Vec<(f32, &GlyphInfo)> = Vec::new();
+            let mut pen = 0.0;
+            let mut previous: Option<GlyphId> = None;
+            // Index of the first glyph after the most recent space.
+            let mut word_start: Option<usize> = None;
+
+            for c in paragraph.trim_end_matches('\r').chars() {
+                let glyph = match self.glyph(c) {
+                    Some(glyph) => glyph,
+                    None => continue,
+                };
+                if let Some(previous) = previous {
+                    pen += self.font.pair_kerning(Scale::uniform(self.px_size), previous, glyph.id) * scale;
+                }
+                previous = Some(glyph.id);
+
+                if c.is_whitespace() {
+                    line.push((pen, glyph));
+                    pen += glyph.advance * scale;
+                    word_start = Some(line.len());
+                    continue;
+                }
+
+                let overflows = style.max_width
+                                     .map_or(false, |max_width| pen + glyph.advance * scale > max_width);
+                if overflows && !line.is_empty() {
+                    // Wrap at the last space if there was one, or break the
+                    // word here if it's too long for a line of its own.
+                    let rest = line.split_off(word_start.unwrap_or(line.len()));
+                    let shift = rest.first().map_or(pen, |&(x, _)| x);
+                    lines.push((line, 0.0));
+                    line = rest.into_iter().map(|(x, glyph)| (x - shift, glyph)).collect();
+                    pen -= shift;
+                    word_start = None;
+                }
+
+                line.push((pen, glyph));
+                pen += glyph.advance * scale;
+            }
+            lines.push((line, 0.0));
+        }
+
+        // Trailing spaces don't count towards a line's width.
+        for (line, width) in &mut lines {
+            *width = line.iter()
+                         .rev()
+                         .find(|(_, glyph)| glyph.size.x > 0.0)
+                         .map_or(0.0, |&(x, glyph)| x + glyph.advance * scale);
+        }
+
+        let widest = lines.iter().fold(0.0f32, |widest, &(_, width)| widest.max(width));
+        let box_width = style.max_width.unwrap_or(widest);
+
+        let mut quads = Vec::new();
+        for (index, (line, width)) in lines.iter().enumerate() {
+            let x = match style.align {
+                Align::Left => 0.0,
+                Align::Centre => (box_width - width) * 0.5,
+                Align::Right => box_width - width,
+            };
+            let baseline = index as f32 * line_height + self.ascent * scale;
+            for &(pen, glyph) in line {
+                if glyph.size.x == 0.0 {
+                    continue;
+                }
+                quads.push(GlyphQuad {
+                    position: Vec2::new(x + pen, baseline) + glyph.offset * scale,
+                    size: glyph.size * scale,
+                    uv: glyph.uv,
+                });
+            }
+        }
+
+        TextLayout {
+            quads,
+            width: box_width,
+            height: lines.len() as f32 * line_height,
+            lines: lines.len(),
+        }
+    }
+
+    /// The width and height `text` would take up.
+    pub fn measure(&self, text: &str, style: &TextStyle) -> Vec2 {
+        let layout = self.layout(text, style);
+        Vec2::new(layout.width, layout.height)
+    }
+
+    /// Lay out `text` with its top-left corner at `position` and queue it
+    /// in `batch`, where `texture` is this atlas's page. Returns the size
+    /// of the text.
+    pub fn draw_text<B: Backend>(&self,
+                                 batch: &mut SpriteBatch<B>,
+                                 texture: TextureId,
+                                 text: &str,
+                                 position: Vec2,
+                                 style: &TextStyle) -> Vec2 {
+        let layout = self.layout(text, style);
+        for quad in &layout.quads {
+            let mut corner = position + quad.position;
+            // Bitmap glyphs blur if they're not on pixel boundaries.
+            if self.mode == GlyphMode::Bitmap {
+                corner = Vec2::new(corner.x.round(), corner.y.round());
+            }
+            batch.draw_sprite(texture,
+                              quad.uv,
+                              SpriteTransform::new(corner, quad.size).with_layer(style.layer),
+                              style.colour);
+        }
+        Vec2::new(layout.width, layout.height)
+    }
+}
+
+
+/// A sprite batch whose fragment shader draws SDF glyphs.
+pub fn sdf_sprite_batch<B: Backend>(device: &B::Device,
+                                    memory_types: &[MemoryType],
+                                    render_pass: &B::RenderPass,
+                                    max_textures: usize) -> SpriteBatch<B> {
+    SpriteBatch::with_fragment_shader(device, memory_types, render_pass, max_textures, SDF_FRAG_SPIRV)
+}
+
+
+// Turn glyph coverage into a signed distance field, `spread` pixels bigger
+// on every side. The distance is stored in alpha, mapped so 0.5 is the
+// outline, 1.0 is `spread` pixels inside and 0.0 `spread` pixels outside.
+fn distance_field(coverage: &[f32], width: u32, height: u32, spread: u32) -> RgbaImage {
+    let (w, h, s) = (width as i32, height as i32, spread as i32);
+    let inside = |x: i32, y: i32| {
+        x >= 0 && y >= 0 && x < w && y < h && coverage[(y * w + x) as usize] >= 0.5
+    };
+
+    RgbaImage::from_fn(width + spread * 2, height + spread * 2, |out_x, out_y| {
+        let (x, y) = (out_x as i32 - s, out_y as i32 - s);
+        let here = inside(x, y);
+
+        // Brute force: glyphs are small, and this only runs at load time.
+        let mut nearest_squared = (s * s + 1) as f32;
+        for dy in -s..=s {
+            for dx in -s..=s {
+                let distance_squared = (dx * dx + dy * dy) as f32;
+                if distance_squared < nearest_squared && inside(x + dx, y + dy) != here {
+                    nearest_squared = distance_squared;
+                }
+            }
+        }
+
+        // The outline lies about halfway between the two pixel centres.
+        let distance = (nearest_squared.sqrt() - 0.5).min(spread as f32);
+        let signed = if here { distance } else { -distance };
+        let alpha = (0.5 + signed / (2.0 * spread.max(1) as f32)).max(0.0).min(1.0);
+        Rgba([255, 255, 255, (alpha * 255.0).round() as u8])
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scale 1, so layout positions are in the atlas's pixels.
+    const SIZE: f32 = 32.0;
+
+    fn font_data() -> Vec<u8> {
+        ::std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/fonts/DejaVuSansMono.ttf")).unwrap()
+    }
+
+    fn atlas() -> FontAtlas {
+        FontAtlas::ascii(font_data(), SIZE, GlyphMode::Bitmap).unwrap()
+    }
+
+    fn advance(atlas: &FontAtlas) -> f32 {
+        atlas.glyphs[&'a'].advance
+    }
+
+    fn shifted(quads: &[GlyphQuad], dx: f32, dy: f32) -> Vec<GlyphQuad> {
+        quads.iter()
+             .map(|quad| GlyphQuad { position: quad.position + Vec2::new(dx, dy), ..*quad })
+             .collect()
+    }
+
+    fn assert_quads_eq(a: &[GlyphQuad], b: &[GlyphQuad]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert!((a.position - b.position).length() < 1e-3, "{:?} != {:?}", a, b);
+            assert_eq!((a.size, a.uv), (b.size, b.uv));
+        }
+    }
+
+    #[test]
+    fn applies_kerning() {
+        // DejaVu Sans Mono has no kerning pairs, so this checks that pairs
+        // are looked up between every glyph without disturbing its fixed
+        // advances.
+        let atlas = atlas();
+        let style = TextStyle::new(SIZE);
+        let text = "AVAWTo";
+        let layout = atlas.layout(text, &style);
+        let mut pen = 0.0;
+        let mut previous: Option<GlyphId> = None;
+        for (c, quad) in text.chars().zip(&layout.quads) {
+            let glyph = &atlas.glyphs[&c];
+            if let Some(previous) = previous {
+                pen += atlas.font.pair_kerning(Scale::uniform(SIZE), previous, glyph.id);
+            }
+            previous = Some(glyph.id);
+            assert!((quad.position.x - (pen + glyph.offset.x)).abs() < 1e-3);
+            pen += glyph.advance;
+        }
+        assert!((layout.width - 6.0 * advance(&atlas)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        let atlas = atlas();
+        let line_height = atlas.line_height(SIZE);
+        // Room for "hello " but not "hello w".
+        let style = TextStyle::new(SIZE).with_max_width(6.5 * advance(&atlas));
+        let layout = atlas.layout("hello world", &style);
+        assert_eq!(layout.lines, 2);
+        assert!((layout.height - 2.0 * line_height).abs() < 1e-3);
+        assert_eq!(layout.width, 6.5 * advance(&atlas));
+
+        let plain = TextStyle::new(SIZE);
+        assert_quads_eq(&layout.quads[..5], &atlas.layout("hello", &plain).quads);
+        assert_quads_eq(&layout.quads[5..], &shifted(&atlas.layout("world", &plain).quads, 0.0, line_height));
+    }
+
+    #[test]
+    fn breaks_words_longer_than_a_line() {
+        let atlas = atlas();
+        let line_height = atlas.line_height(SIZE);
+        let style = TextStyle::new(SIZE).with_max_width(4.5 * advance(&atlas));
+        let layout = atlas.layout("abcdefghij", &style);
+        assert_eq!(layout.lines, 3);
+
+        let plain = TextStyle::new(SIZE);
+        assert_quads_eq(&layout.quads[4..8], &shifted(&atlas.layout("efgh", &plain).quads, 0.0, line_height));
+        assert_quads_eq(&layout.quads[8..], &shifted(&atlas.layout("ij", &plain).quads, 0.0, 2.0 * line_height));
+    }
+
+    #[test]
+    fn splits_lines() {
+        let atlas = atlas();
+        let style = TextStyle::new(SIZE);
+        let layout = atlas.layout("ab\ncd\n\nef", &style);
+        assert_eq!(layout.lines, 4);
+        assert_eq!(atlas.layout("ab\r\ncd\r\n\r\nef", &style), layout);
+        assert_eq!(atlas.layout("", &style).lines, 1);
+
+        let double = atlas.layout("ab\ncd", &style.with_line_spacing(2.0));
+        let second = shifted(&atlas.layout("cd", &style).quads, 0.0, 2.0 * atlas.line_height(SIZE));
+        assert_quads_eq(&double.quads[2..], &second);
+    }
+
+    #[test]
+    fn ignores_trailing_spaces_in_width() {
+        let atlas = atlas();
+        let style = TextStyle::new(SIZE);
+        let width = atlas.layout("ab", &style).width;
+        assert_eq!(atlas.layout("ab   ", &style).width, width);
+        assert!(atlas.layout("  ab", &style).width > width);
+        assert_eq!(atlas.layout("   ", &style).width, 0.0);
+    }
+
+    #[test]
+    fn aligns_lines() {
+        let atlas = atlas();
+        let advance = advance(&atlas);
+        let left = atlas.layout("a\nabc", &TextStyle::new(SIZE));
+        let (short, long) = (&left.quads[..1], &left.quads[1..]);
+
+        // Without a max width, lines align within the widest one.
+        let right = atlas.layout("a\nabc", &TextStyle::new(SIZE).with_align(Align::Right));
+        assert_quads_eq(&right.quads[..1], &shifted(short, 2.0 * advance, 0.0));
+        assert_quads_eq(&right.quads[1..], long);
+
+        let centre = atlas.layout("a\nabc", &TextStyle::new(SIZE).with_align(Align::Centre));
+        assert_quads_eq(&centre.quads[..1], &shifted(short, advance, 0.0));
+        assert_quads_eq(&centre.quads[1..], long);
+
+        // With one, they align within it.
+        let boxed = TextStyle::new(SIZE).with_max_width(10.0 * advance);
+        let right = atlas.layout("abc", &boxed.with_align(Align::Right));
+        assert_quads_eq(&right.quads, &shifted(&atlas.layout("abc", &boxed).quads, 7.0 * advance, 0.0));
+        let centre = atlas.layout("abc", &boxed.with_align(Align::Centre));
+        assert_quads_eq(&centre.quads, &shifted(&atlas.layout("abc", &boxed).quads, 3.5 * advance, 0.0));
+    }
+
+    #[test]
+    fn scales_with_size() {
+        let atlas = atlas();
+        let small = atlas.layout("ab", &TextStyle::new(SIZE / 2.0));
+        let full = atlas.layout("ab", &TextStyle::new(SIZE));
+        assert!((small.width * 2.0 - full.width).abs() < 1e-3);
+        assert_eq!(small.quads[1].size * 2.0, full.quads[1].size);
+    }
+
+    #[test]
+    fn falls_back_for_missing_characters() {
+        let style = TextStyle::new(SIZE);
+
+        // Not in the atlas: drawn as the replacement character.
+        let atlas = atlas();
+        assert_eq!(atlas.layout("é", &style), atlas.layout("\u{FFFD}", &style));
+
+        // Without one, as '?'.
+        let question = FontAtlas::new(font_data(), SIZE, GlyphMode::Bitmap, "ab?".chars()).unwrap();
+        assert_eq!(question.layout("aéb", &style), question.layout("a?b", &style));
+
+        // Without either, skipped. The font has no glyph for CJK, so it's
+        // never added.
+        let bare = FontAtlas::new(font_data(), SIZE, GlyphMode::Bitmap, "ab\u{4E2D}".chars()).unwrap();
+        assert!(!bare.glyphs.contains_key(&'\u{4E2D}'));
+        assert_eq!(bare.layout("a\u{4E2D}b", &style), bare.layout("ab", &style));
+    }
+}
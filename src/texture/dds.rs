@@ -0,0 +1,327 @@
+// DirectDraw Surface parsing, including the DX10 header extension.
+//
+// DDS stores each array element (layer or cube face) with its whole mip
+// chain before moving on to the next, so the images are shuffled into the
+// level-major order `TextureData` expects.
+
+use prelude::*;
+
+use super::{image_size, mip_extent, read_u32, MipLevel, TextureData, TextureError};
+
+const MAGIC: u32 = 0x2053_4444; // "DDS "
+const HEADER_SIZE: usize = 124;
+const DX10_HEADER_SIZE: usize = 20;
+
+const DDSD_DEPTH: u32 = 0x0080_0000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xFC00;
+const DDSCAPS2_VOLUME: u32 = 0x0020_0000;
+const DX10_MISC_TEXTURECUBE: u32 = 0x4;
+const DX10_DIMENSION_TEXTURE3D: u32 = 4;
+
+
+fn four_cc(code: &[u8; 4]) -> u32 {
+    code[0] as u32 | (code[1] as u32) << 8 | (code[2] as u32) << 16 | (code[3] as u32) << 24
+}
+
+pub fn is_dds(bytes: &[u8]) -> bool {
+    read_u32(bytes, 0).map(|magic| magic == MAGIC).unwrap_or(false)
+}
+
+pub fn parse(bytes: &[u8]) -> Result<TextureData, TextureError> {
+    if !is_dds(bytes) {
+        return Err(TextureError::InvalidHeader("missing DDS magic"));
+    }
+    if read_u32(bytes, 4)? as usize != HEADER_SIZE {
+        return Err(TextureError::InvalidHeader("unexpected DDS header size"));
+    }
+
+    // Offsets below are relative to the start of the file, i.e. after the
+    // four magic bytes.
+    let flags = read_u32(bytes, 8)?;
+    let height = read_u32(bytes, 12)?.max(1);
+    let width = read_u32(bytes, 16)?.max(1);
+    let depth = if flags & DDSD_DEPTH != 0 { read_u32(bytes, 24)?.max(1) } else { 1 };
+    let level_count = read_u32(bytes, 28)?.max(1);
+    let pf_flags = read_u32(bytes, 80)?;
+    let pf_four_cc = read_u32(bytes, 84)?;
+    let caps2 = read_u32(bytes, 112)?;
+
+    let mut data_offset = 4 + HEADER_SIZE;
+    let mut layers = 1;
+    let mut faces = 1;
+    let mut depth = depth;
+
+    let format = if pf_flags & DDPF_FOURCC != 0 && pf_four_cc == four_cc(b"DX10") {
+        let dxgi_format = read_u32(bytes, data_offset)?;
+        let dimension = read_u32(bytes, data_offset + 4)?;
+        let misc_flags = read_u32(bytes, data_offset + 8)?;
+        layers = read_u32(bytes, data_offset + 12)?.max(1);
+        data_offset += DX10_HEADER_SIZE;
+
+        if misc_flags & DX10_MISC_TEXTURECUBE != 0 {
+            faces = 6;
+        }
+        if dimension != DX10_DIMENSION_TEXTURE3D {
+            depth = 1;
+        }
+        format_from_dxgi(dxgi_format)
+            .ok_or_else(|| TextureError::UnknownFormat(format!("DXGI format {}", dxgi_format)))?
+    } else {
+        if caps2 & DDSCAPS2_CUBEMAP != 0 {
+            if caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+                return Err(TextureError::Unsupported("partial cubemaps"));
+            }
+            faces = 6;
+        }
+        if caps2 & DDSCAPS2_VOLUME == 0 {
+            depth = 1;
+        }
+        legacy_format(bytes, pf_flags, pf_four_cc)?
+    };
+
+    if faces == 6 && width != height {
+        return Err(TextureError::InvalidHeader("cube faces must be square"));
+    }
+    if layers > u16::max_value() as u32 || level_count > 32 {
+        return Err(TextureError::InvalidHeader("layer or level count out of range"));
+    }
+
+    let image_count = (layers * faces) as usize;
+    let level_sizes = (0..level_count)
+        .map(|level| {
+            image_size(format,
+                       mip_extent(width, level),
+                       mip_extent(height, level),
+                       mip_extent(depth, level))
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or(TextureError::InvalidHeader("level size overflows"))?;
+    let chain_size = level_sizes.iter()
+                                .try_fold(0usize, |total, &size| total.checked_add(size))
+                                .ok_or(TextureError::InvalidHeader("level size overflows"))?;
+    match image_count.checked_mul(chain_size).and_then(|size| size.checked_add(data_offset)) {
+        Some(end) if end <= bytes.len() => {}
+        _ => return Err(TextureError::Truncated),
+    }
+
+    let mut levels = (0..level_count)
+        .map(|level| MipLevel {
+            width: mip_extent(width, level),
+            height: mip_extent(height, level),
+            depth: mip_extent(depth, level),
+            data: Vec::with_capacity(level_sizes[level as usize] * image_count),
+        })
+        .collect::<Vec<_>>();
+
+    for image in 0..image_count {
+        let mut offset = data_offset + image * chain_size;
+        for (level, size) in levels.iter_mut().zip(&level_sizes) {
+            level.data.extend_from_slice(&bytes[offset..offset + size]);
+            offset += size;
+        }
+    }
+
+    Ok(TextureData {
+        format,
+        width,
+        height,
+        depth,
+        layers: layers as u16,
+        faces: faces as u16,
+        levels,
+    })
+}
+
+
+// Pre-DX10 files describe their format with a FourCC or with bit masks.
+fn legacy_format(bytes: &[u8], pf_flags: u32, pf_four_cc: u32) -> Result<Format, TextureError> {
+    if pf_flags & DDPF_FOURCC != 0 {
+        let format = match pf_four_cc {
+            x if x == four_cc(b"DXT1") => Format::Bc1RgbaUnorm,
+            x if x == four_cc(b"DXT2") || x == four_cc(b"DXT3") => Format::Bc2Unorm,
+            x if x == four_cc(b"DXT4") || x == four_cc(b"DXT5") => Format::Bc3Unorm,
+            x if x == four_cc(b"ATI1") || x == four_cc(b"BC4U") => Format::Bc4Unorm,
+            x if x == four_cc(b"BC4S") => Format::Bc4Inorm,
+            x if x == four_cc(b"ATI2") || x == four_cc(b"BC5U") => Format::Bc5Unorm,
+            x if x == four_cc(b"BC5S") => Format::Bc5Inorm,
+            _ => {
+                let code = pf_four_cc.to_le_bytes();
+                return Err(TextureError::UnknownFormat(
+                    format!("FourCC {}", String::from_utf8_lossy(&code))));
+            }
+        };
+        return Ok(format);
+    }
+
+    if pf_flags & DDPF_RGB != 0 {
+        let bit_count = read_u32(bytes, 88)?;
+        let masks = (read_u32(bytes, 92)?, read_u32(bytes, 96)?, read_u32(bytes, 100)?);
+        match (bit_count, masks) {
+            (32, (0x0000_00FF, 0x0000_FF00, 0x00FF_0000)) => return Ok(Format::Rgba8Unorm),
+            (32, (0x00FF_0000, 0x0000_FF00, 0x0000_00FF)) => return Ok(Format::Bgra8Unorm),
+            _ => {}
+        }
+    }
+
+    Err(TextureError::UnknownFormat("unrecognised DDS pixel format".to_owned()))
+}
+
+/// Map a `DXGI_FORMAT` value onto the matching gfx-hal format.
+pub fn format_from_dxgi(dxgi_format: u32) -> Option<Format> {
+    let format = match dxgi_format {
+        2 => Format::Rgba32Float,
+        10 => Format::Rgba16Float,
+        28 => Format::Rgba8Unorm,
+        29 => Format::Rgba8Srgb,
+        49 => Format::Rg8Unorm,
+        61 => Format::R8Unorm,
+        71 => Format::Bc1RgbaUnorm,
+        72 => Format::Bc1RgbaSrgb,
+        74 => Format::Bc2Unorm,
+        75 => Format::Bc2Srgb,
+        77 => Format::Bc3Unorm,
+        78 => Format::Bc3Srgb,
+        80 => Format::Bc4Unorm,
+        81 => Format::Bc4Inorm,
+        83 => Format::Bc5Unorm,
+        84 => Format::Bc5Inorm,
+        87 => Format::Bgra8Unorm,
+        91 => Format::Bgra8Srgb,
+        95 => Format::Bc6hUfloat,
+        96 => Format::Bc6hFloat,
+        98 => Format::Bc7Unorm,
+        99 => Format::Bc7Srgb,
+        _ => return None,
+    };
+    Some(format)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every byte of level N of image M is (N << 4) | M.
+    //
+    // 8x8 DXT1 with a full mip chain.
+    static BC1_MIPS: &'static [u8] = include_bytes!("../../tests/fixtures/bc1_mips.dds");
+    // 2x2 RGBA8 with a DX10 header: three array layers with two levels each.
+    static DX10_ARRAY: &'static [u8] = include_bytes!("../../tests/fixtures/dx10_array.dds");
+    // 2x2 BGRA8 cubemap described by bit masks, one level.
+    static BGRA_CUBE: &'static [u8] = include_bytes!("../../tests/fixtures/bgra_cube.dds");
+
+    fn expect_error(result: Result<TextureData, TextureError>, expected: &str) {
+        match result {
+            Err(ref err) if format!("{:?}", err).starts_with(expected) => {}
+            other => panic!("expected {}, got {:?}", expected, other),
+        }
+    }
+
+    fn assert_filled(data: &[u8], level: usize, images: usize) {
+        let image_size = data.len() / images;
+        for (image, chunk) in data.chunks(image_size).enumerate() {
+            let expected = (level << 4 | image) as u8;
+            assert!(chunk.iter().all(|&byte| byte == expected), "level {}, image {}", level, image);
+        }
+    }
+
+    #[test]
+    fn reads_legacy_header() {
+        let texture = parse(BC1_MIPS).unwrap();
+        assert_eq!(texture.format, Format::Bc1RgbaUnorm);
+        assert_eq!((texture.width, texture.height, texture.depth), (8, 8, 1));
+        assert_eq!((texture.layers, texture.faces), (1, 1));
+        assert!(texture.is_compressed());
+    }
+
+    #[test]
+    fn slices_compressed_mips() {
+        let texture = parse(BC1_MIPS).unwrap();
+        let extents = texture.levels.iter().map(|level| (level.width, level.height)).collect::<Vec<_>>();
+        assert_eq!(extents, vec![(8, 8), (4, 4), (2, 2), (1, 1)]);
+
+        // Levels smaller than a block still take up a whole block.
+        let sizes = texture.levels.iter().map(|level| level.data.len()).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![32, 8, 8, 8]);
+        for (i, level) in texture.levels.iter().enumerate() {
+            assert_filled(&level.data, i, 1);
+        }
+    }
+
+    #[test]
+    fn reorders_array_layers_by_level() {
+        let texture = parse(DX10_ARRAY).unwrap();
+        assert_eq!(texture.format, Format::Rgba8Unorm);
+        assert_eq!((texture.layers, texture.faces), (3, 1));
+        assert_eq!(texture.view_kind(), ViewKind::D2Array);
+
+        assert_eq!(texture.levels.len(), 2);
+        assert_eq!(texture.levels[0].data.len(), 3 * 16);
+        assert_eq!(texture.levels[1].data.len(), 3 * 4);
+        for (i, level) in texture.levels.iter().enumerate() {
+            assert_filled(&level.data, i, 3);
+        }
+    }
+
+    #[test]
+    fn reads_cubemap_faces() {
+        let texture = parse(BGRA_CUBE).unwrap();
+        assert_eq!(texture.format, Format::Bgra8Unorm);
+        assert_eq!((texture.layers, texture.faces), (1, 6));
+        assert_eq!(texture.view_kind(), ViewKind::Cube);
+        assert_filled(&texture.levels[0].data, 0, 6);
+    }
+
+    #[test]
+    fn maps_formats() {
+        assert_eq!(format_from_dxgi(28), Some(Format::Rgba8Unorm));
+        assert_eq!(format_from_dxgi(29), Some(Format::Rgba8Srgb));
+        assert_eq!(format_from_dxgi(71), Some(Format::Bc1RgbaUnorm));
+        assert_eq!(format_from_dxgi(99), Some(Format::Bc7Srgb));
+        assert_eq!(format_from_dxgi(0), None);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(!is_dds(b"\xABKTX 20\xBB"));
+        expect_error(parse(b"not a texture"), "InvalidHeader");
+    }
+
+    #[test]
+    fn rejects_unknown_four_cc() {
+        let mut bytes = BC1_MIPS.to_vec();
+        bytes[84..88].copy_from_slice(b"ABCD");
+        expect_error(parse(&bytes), "UnknownFormat");
+    }
+
+    #[test]
+    fn rejects_partial_cubemaps() {
+        let mut bytes = BGRA_CUBE.to_vec();
+        // Drop the -Z face from caps2.
+        bytes[113] &= !0x80;
+        expect_error(parse(&bytes), "Unsupported");
+    }
+
+    #[test]
+    fn rejects_oversized_dimensions() {
+        // A width of 0xFFFF_FFFF overflows when rounded up to BC1 blocks.
+        let mut bytes = BC1_MIPS.to_vec();
+        for byte in &mut bytes[16..20] {
+            *byte = 0xFF;
+        }
+        expect_error(parse(&bytes), "InvalidHeader");
+    }
+
+    #[test]
+    fn reports_truncation() {
+        // Inside the header, inside the DX10 header, and inside the data.
+        expect_error(parse(&BC1_MIPS[..60]), "Truncated");
+        expect_error(parse(&DX10_ARRAY[..136]), "Truncated");
+        for fixture in &[BC1_MIPS, DX10_ARRAY, BGRA_CUBE] {
+            expect_error(parse(&fixture[..fixture.len() - 1]), "Truncated");
+        }
+    }
+}
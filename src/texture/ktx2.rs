@@ -0,0 +1,289 @@
+// KTX 2.0 container parsing.
+//
+// Only the parts of the format we need are read: the header, the level index
+// and the level data itself. The data format descriptor and key/value data
+// are skipped, and supercompressed files (Basis, zstd) are rejected.
+
+use prelude::*;
+
+use super::{image_size, mip_extent, read_u32, read_u64, MipLevel, TextureData, TextureError};
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+
+pub fn is_ktx2(bytes: &[u8]) -> bool {
+    bytes.len() >= IDENTIFIER.len() && bytes[..IDENTIFIER.len()] == IDENTIFIER
+}
+
+pub fn parse(bytes: &[u8]) -> Result<TextureData, TextureError> {
+    if !is_ktx2(bytes) {
+        return Err(TextureError::InvalidHeader("missing KTX2 identifier"));
+    }
+    if bytes.len() < HEADER_SIZE {
+        return Err(TextureError::Truncated);
+    }
+
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?.max(1);
+    let depth = read_u32(bytes, 28)?.max(1);
+    let layers = read_u32(bytes, 32)?.max(1);
+    let faces = read_u32(bytes, 36)?;
+    let level_count = read_u32(bytes, 40)?.max(1);
+    let supercompression = read_u32(bytes, 44)?;
+
+    if width == 0 {
+        return Err(TextureError::InvalidHeader("zero width"));
+    }
+    if faces != 1 && faces != 6 {
+        return Err(TextureError::InvalidHeader("face count must be 1 or 6"));
+    }
+    if faces == 6 && (width != height || depth != 1) {
+        return Err(TextureError::InvalidHeader("cube faces must be square and 2D"));
+    }
+    if supercompression != 0 {
+        return Err(TextureError::Unsupported("supercompressed KTX2"));
+    }
+    if layers > u16::max_value() as u32 || level_count > 32 {
+        return Err(TextureError::InvalidHeader("layer or level count out of range"));
+    }
+
+    let format = format_from_vk(vk_format)
+        .ok_or_else(|| TextureError::UnknownFormat(format!("VkFormat {}", vk_format)))?;
+
+    let images_per_level = (layers * faces) as usize;
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+        let entry = HEADER_SIZE + level as usize * LEVEL_INDEX_ENTRY_SIZE;
+        let offset = read_u64(bytes, entry)?;
+        let length = read_u64(bytes, entry + 8)?;
+
+        let level_width = mip_extent(width, level);
+        let level_height = mip_extent(height, level);
+        let level_depth = mip_extent(depth, level);
+        let expected = image_size(format, level_width, level_height, level_depth)
+            .and_then(|size| size.checked_mul(images_per_level))
+            .ok_or(TextureError::InvalidHeader("level size overflows"))?;
+        if length < expected as u64 {
+            return Err(TextureError::InvalidHeader("level smaller than its dimensions"));
+        }
+
+        // The offset comes straight from the file, so don't trust it not to
+        // overflow.
+        let end = offset.checked_add(expected as u64).ok_or(TextureError::Truncated)?;
+        if end > bytes.len() as u64 {
+            return Err(TextureError::Truncated);
+        }
+        let data = &bytes[offset as usize..end as usize];
+        levels.push(MipLevel {
+            width: level_width,
+            height: level_height,
+            depth: level_depth,
+            data: data.to_vec(),
+        });
+    }
+
+    Ok(TextureData {
+        format,
+        width,
+        height,
+        depth,
+        layers: layers as u16,
+        faces: faces as u16,
+        levels,
+    })
+}
+
+
+/// Map a `VkFormat` value onto the matching gfx-hal format, for the formats
+/// we expect to see in texture files.
+pub fn format_from_vk(vk_format: u32) -> Option<Format> {
+    let format = match vk_format {
+        9 => Format::R8Unorm,
+        16 => Format::Rg8Unorm,
+        37 => Format::Rgba8Unorm,
+        43 => Format::Rgba8Srgb,
+        44 => Format::Bgra8Unorm,
+        50 => Format::Bgra8Srgb,
+        97 => Format::Rgba16Float,
+        109 => Format::Rgba32Float,
+        131 => Format::Bc1RgbUnorm,
+        132 => Format::Bc1RgbSrgb,
+        133 => Format::Bc1RgbaUnorm,
+        134 => Format::Bc1RgbaSrgb,
+        135 => Format::Bc2Unorm,
+        136 => Format::Bc2Srgb,
+        137 => Format::Bc3Unorm,
+        138 => Format::Bc3Srgb,
+        139 => Format::Bc4Unorm,
+        140 => Format::Bc4Inorm,
+        141 => Format::Bc5Unorm,
+        142 => Format::Bc5Inorm,
+        143 => Format::Bc6hUfloat,
+        144 => Format::Bc6hFloat,
+        145 => Format::Bc7Unorm,
+        146 => Format::Bc7Srgb,
+        147 => Format::Etc2R8g8b8Unorm,
+        148 => Format::Etc2R8g8b8Srgb,
+        149 => Format::Etc2R8g8b8a1Unorm,
+        150 => Format::Etc2R8g8b8a1Srgb,
+        151 => Format::Etc2R8g8b8a8Unorm,
+        152 => Format::Etc2R8g8b8a8Srgb,
+        153 => Format::EacR11Unorm,
+        154 => Format::EacR11Inorm,
+        155 => Format::EacR11g11Unorm,
+        156 => Format::EacR11g11Inorm,
+        157 => Format::Astc4x4Unorm,
+        158 => Format::Astc4x4Srgb,
+        159 => Format::Astc5x4Unorm,
+        160 => Format::Astc5x4Srgb,
+        161 => Format::Astc5x5Unorm,
+        162 => Format::Astc5x5Srgb,
+        163 => Format::Astc6x5Unorm,
+        164 => Format::Astc6x5Srgb,
+        165 => Format::Astc6x6Unorm,
+        166 => Format::Astc6x6Srgb,
+        167 => Format::Astc8x5Unorm,
+        168 => Format::Astc8x5Srgb,
+        169 => Format::Astc8x6Unorm,
+        170 => Format::Astc8x6Srgb,
+        171 => Format::Astc8x8Unorm,
+        172 => Format::Astc8x8Srgb,
+        173 => Format::Astc10x5Unorm,
+        174 => Format::Astc10x5Srgb,
+        175 => Format::Astc10x6Unorm,
+        176 => Format::Astc10x6Srgb,
+        177 => Format::Astc10x8Unorm,
+        178 => Format::Astc10x8Srgb,
+        179 => Format::Astc10x10Unorm,
+        180 => Format::Astc10x10Srgb,
+        181 => Format::Astc12x10Unorm,
+        182 => Format::Astc12x10Srgb,
+        183 => Format::Astc12x12Unorm,
+        184 => Format::Astc12x12Srgb,
+        _ => return None,
+    };
+    Some(format)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 4x2 RGBA8 with three mip levels. Every byte of level N is N << 4.
+    static RGBA8_MIPS: &'static [u8] = include_bytes!("../../tests/fixtures/rgba8_mips.ktx2");
+    // 2x2 sRGB RGBA8, two layers of cube faces, one level. Every byte of
+    // image N (layer-major, then face) is N.
+    static CUBE_ARRAY: &'static [u8] = include_bytes!("../../tests/fixtures/cube_array.ktx2");
+
+    fn expect_error(result: Result<TextureData, TextureError>, expected: &str) {
+        match result {
+            Err(ref err) if format!("{:?}", err).starts_with(expected) => {}
+            other => panic!("expected {}, got {:?}", expected, other),
+        }
+    }
+
+    #[test]
+    fn reads_header() {
+        let texture = parse(RGBA8_MIPS).unwrap();
+        assert_eq!(texture.format, Format::Rgba8Unorm);
+        assert_eq!((texture.width, texture.height, texture.depth), (4, 2, 1));
+        assert_eq!((texture.layers, texture.faces), (1, 1));
+        assert_eq!(texture.levels.len(), 3);
+    }
+
+    #[test]
+    fn slices_mip_levels() {
+        let texture = parse(RGBA8_MIPS).unwrap();
+        let extents = texture.levels.iter().map(|level| (level.width, level.height)).collect::<Vec<_>>();
+        assert_eq!(extents, vec![(4, 2), (2, 1), (1, 1)]);
+
+        // The file stores the smallest level first; the level index still
+        // has to put them back in order.
+        for (i, level) in texture.levels.iter().enumerate() {
+            assert_eq!(level.data.len(), (level.width * level.height * 4) as usize);
+            assert!(level.data.iter().all(|&byte| byte == (i << 4) as u8), "level {}", i);
+        }
+    }
+
+    #[test]
+    fn slices_layers_and_faces() {
+        let texture = parse(CUBE_ARRAY).unwrap();
+        assert_eq!(texture.format, Format::Rgba8Srgb);
+        assert_eq!((texture.layers, texture.faces), (2, 6));
+        assert!(texture.is_cube());
+        assert_eq!(texture.view_kind(), ViewKind::CubeArray);
+        assert_eq!(texture.image_count(), 12);
+
+        let data = &texture.levels[0].data;
+        assert_eq!(data.len(), 12 * 16);
+        for (image, chunk) in data.chunks(16).enumerate() {
+            assert!(chunk.iter().all(|&byte| byte == image as u8), "image {}", image);
+        }
+    }
+
+    #[test]
+    fn maps_formats() {
+        assert_eq!(format_from_vk(37), Some(Format::Rgba8Unorm));
+        assert_eq!(format_from_vk(43), Some(Format::Rgba8Srgb));
+        assert_eq!(format_from_vk(97), Some(Format::Rgba16Float));
+        assert_eq!(format_from_vk(133), Some(Format::Bc1RgbaUnorm));
+        assert_eq!(format_from_vk(146), Some(Format::Bc7Srgb));
+        assert_eq!(format_from_vk(0), None);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(!is_ktx2(b"DDS "));
+        expect_error(parse(b"not a texture"), "InvalidHeader");
+    }
+
+    #[test]
+    fn rejects_unknown_format() {
+        let mut bytes = RGBA8_MIPS.to_vec();
+        bytes[12] = 1;
+        expect_error(parse(&bytes), "UnknownFormat");
+    }
+
+    #[test]
+    fn reports_truncation() {
+        // Inside the header, inside the level index, and inside the data.
+        for &len in &[40, 90, RGBA8_MIPS.len() - 1] {
+            expect_error(parse(&RGBA8_MIPS[..len]), "Truncated");
+        }
+    }
+
+    #[test]
+    fn reports_overflowing_offsets() {
+        let mut bytes = RGBA8_MIPS.to_vec();
+        for byte in &mut bytes[HEADER_SIZE..HEADER_SIZE + 8] {
+            *byte = 0xFF;
+        }
+        expect_error(parse(&bytes), "Truncated");
+    }
+
+    #[test]
+    fn rejects_oversized_dimensions() {
+        // Width, height and depth all 0xFFFF_FFFF: the level size doesn't
+        // fit in a usize.
+        let mut bytes = RGBA8_MIPS.to_vec();
+        for byte in &mut bytes[20..32] {
+            *byte = 0xFF;
+        }
+        expect_error(parse(&bytes), "InvalidHeader");
+    }
+
+    #[test]
+    fn rejects_short_levels() {
+        let mut bytes = RGBA8_MIPS.to_vec();
+        // Level 0's byte length.
+        bytes[HEADER_SIZE + 8] = 1;
+        expect_error(parse(&bytes), "InvalidHeader");
+    }
+}
@@ -0,0 +1,500 @@
+use gfx_hal::format::ImageFeature;
+use gfx_hal::Backend;
+use image::{self, RgbaImage};
+use prelude::*;
+use utils;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+pub mod dds;
+pub mod ktx2;
+
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(io::Error),
+    Image(image::ImageError),
+    /// The file doesn't look like the container it claims to be.
+    InvalidHeader(&'static str),
+    /// The file ended before all the data its header describes.
+    Truncated,
+    /// The container stores a pixel format we have no mapping for.
+    UnknownFormat(String),
+    /// A valid file using a container feature we don't handle.
+    Unsupported(&'static str),
+    /// The device can't sample from images in this format.
+    DeviceUnsupported(Format),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Io(err) => write!(f, "I/O error: {}", err),
+            TextureError::Image(err) => write!(f, "image error: {}", err),
+            TextureError::InvalidHeader(what) => write!(f, "invalid header: {}", what),
+            TextureError::Truncated => write!(f, "texture data is truncated"),
+            TextureError::UnknownFormat(what) => write!(f, "unknown pixel format: {}", what),
+            TextureError::Unsupported(what) => write!(f, "unsupported: {}", what),
+            TextureError::DeviceUnsupported(format) => {
+                write!(f, "device cannot sample {:?} images", format)
+            }
+        }
+    }
+}
+
+impl Error for TextureError {}
+
+impl From<io::Error> for TextureError {
+    fn from(err: io::Error) -> Self {
+        TextureError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(err: image::ImageError) -> Self {
+        TextureError::Image(err)
+    }
+}
+
+
+/// One mip level. `data` holds every layer and face of the level back to
+/// back (layer-major, then face, then depth slice), with tightly packed rows.
+#[derive(Clone, Debug)]
+pub struct MipLevel {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub data: Vec<u8>,
+}
+
+/// Texture data on the CPU, ready for upload.
+#[derive(Clone, Debug)]
+pub struct TextureData {
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub layers: u16,
+    pub faces: u16,
+    pub levels: Vec<MipLevel>,
+}
+
+impl TextureData {
+    pub fn from_rgba8(image: &RgbaImage, srgb: bool) -> Self {
+        let (width, height) = image.dimensions();
+        TextureData {
+            format: if srgb { Format::Rgba8Srgb } else { Format::Rgba8Unorm },
+            width,
+            height,
+            depth: 1,
+            layers: 1,
+            faces: 1,
+            levels: vec![MipLevel {
+                width,
+                height,
+                depth: 1,
+                data: image.clone().into_raw(),
+            }],
+        }
+    }
+
+    /// Load a texture, picking the parser from the file extension. Anything
+    /// that isn't KTX2 or DDS goes through the `image` crate as sRGB RGBA8.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TextureError> {
+        let path = path.as_ref();
+        let extension = path.extension()
+                            .map(|ext| ext.to_string_lossy().to_lowercase())
+                            .unwrap_or_default();
+        match extension.as_str() {
+            "ktx2" => ktx2::parse(&::std::fs::read(path)?),
+            "dds" => dds::parse(&::std::fs::read(path)?),
+            _ => {
+                let image = image::open(path)?.to_rgba();
+                Ok(TextureData::from_rgba8(&image, true))
+            }
+        }
+    }
+
+    /// Build a cubemap from six equally-sized square faces, in the order
+    /// +X, -X, +Y, -Y, +Z, -Z.
+    pub fn cube_from_faces(faces: &[RgbaImage], srgb: bool) -> Result<Self, TextureError> {
+        if faces.len() != 6 {
+            return Err(TextureError::InvalidHeader("a cubemap needs exactly six faces"));
+        }
+        let mut data = TextureData::array_from_layers(faces, srgb)?;
+        if data.width != data.height {
+            return Err(TextureError::InvalidHeader("cube faces must be square"));
+        }
+        data.layers = 1;
+        data.faces = 6;
+        Ok(data)
+    }
+
+    /// Build a 2D array texture from equally-sized layers.
+    pub fn array_from_layers(layers: &[RgbaImage], srgb: bool) -> Result<Self, TextureError> {
+        let (width, height) = match layers.first() {
+            Some(layer) => layer.dimensions(),
+            None => return Err(TextureError::InvalidHeader("no layers given")),
+        };
+        if layers.iter().any(|layer| layer.dimensions() != (width, height)) {
+            return Err(TextureError::InvalidHeader("layers differ in size"));
+        }
+        if layers.len() > u16::max_value() as usize {
+            return Err(TextureError::InvalidHeader("too many layers"));
+        }
+
+        let mut data = Vec::with_capacity(width as usize * height as usize * 4 * layers.len());
+        for layer in layers {
+            data.extend_from_slice(&**layer);
+        }
+
+        Ok(TextureData {
+            format: if srgb { Format::Rgba8Srgb } else { Format::Rgba8Unorm },
+            width,
+            height,
+            depth: 1,
+            layers: layers.len() as u16,
+            faces: 1,
+            levels: vec![MipLevel {
+                width,
+                height,
+                depth: 1,
+                data,
+            }],
+        })
+    }
+
+    pub fn load_cube<P: AsRef<Path>>(face_paths: &[P]) -> Result<Self, TextureError> {
+        let faces = face_paths.iter()
+                              .map(|path| image::open(path).map(|image| image.to_rgba()))
+                              .collect::<Result<Vec<_>, _>>()?;
+        TextureData::cube_from_faces(&faces, true)
+    }
+
+    pub fn load_array<P: AsRef<Path>>(layer_paths: &[P]) -> Result<Self, TextureError> {
+        let layers = layer_paths.iter()
+                                .map(|path| image::open(path).map(|image| image.to_rgba()))
+                                .collect::<Result<Vec<_>, _>>()?;
+        TextureData::array_from_layers(&layers, true)
+    }
+
+    pub fn view_kind(&self) -> ViewKind {
+        if self.depth > 1 {
+            ViewKind::D3
+        } else if self.is_cube() && self.layers > 1 {
+            ViewKind::CubeArray
+        } else if self.is_cube() {
+            ViewKind::Cube
+        } else if self.layers > 1 {
+            ViewKind::D2Array
+        } else {
+            ViewKind::D2
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        let (block_width, block_height, _) = block_info(self.format);
+        block_width > 1 || block_height > 1
+    }
+
+    pub fn is_cube(&self) -> bool {
+        self.faces == 6
+    }
+
+    /// Number of 2D images (array layers times cube faces) in each level.
+    pub fn image_count(&self) -> u32 {
+        self.layers as u32 * self.faces as u32
+    }
+}
+
+
+/// Block width, block height and bytes per block. Uncompressed formats are
+/// treated as 1x1 blocks.
+pub fn block_info(format: Format) -> (u32, u32, u32) {
+    let desc = format.surface_desc();
+    (desc.dim.0 as u32, desc.dim.1 as u32, desc.bits as u32 / 8)
+}
+
+/// Size in bytes of a single image of the given dimensions, tightly packed.
+/// Returns `None` if the size doesn't fit in a `usize`.
+pub fn image_size(format: Format, width: u32, height: u32, depth: u32) -> Option<usize> {
+    let (block_width, block_height, block_bytes) = block_info(format);
+    let blocks_x = width.checked_add(block_width - 1)? / block_width;
+    let blocks_y = height.checked_add(block_height - 1)? / block_height;
+    (blocks_x as usize).checked_mul(blocks_y as usize)?
+                       .checked_mul(depth as usize)?
+                       .checked_mul(block_bytes as usize)
+}
+
+pub fn mip_extent(extent: u32, level: u32) -> u32 {
+    (extent >> level).max(1)
+}
+
+pub fn is_sampleable<B: Backend>(physical_device: &B::PhysicalDevice, format: Format) -> bool {
+    physical_device.format_properties(Some(format))
+                   .optimal_tiling
+                   .contains(ImageFeature::SAMPLED)
+}
+
+
+pub struct Texture<B: Backend> {
+    pub image: B::Image,
+    pub memory: B::Memory,
+    pub view: B::ImageView,
+    pub format: Format,
+    pub extent: Extent,
+    pub levels: u8,
+    pub layers: u16,
+    pub view_kind: ViewKind,
+}
+
+impl<B: Backend> Texture<B> {
+    /// Upload every mip level of `data` to a new device-local image, leaving
+    /// it in `ShaderReadOnlyOptimal`. Blocks until the copy has finished.
+    pub fn upload(device: &B::Device,
+                  physical_device: &B::PhysicalDevice,
+                  command_pool: &mut CommandPool<B, Graphics>,
+                  queue: &mut CommandQueue<B, Graphics>,
+                  data: &TextureData) -> Result<Self, TextureError> {
+        if data.depth > 1 && data.image_count() > 1 {
+            return Err(TextureError::Unsupported("3D texture arrays"));
+        }
+        if !is_sampleable::<B>(physical_device, data.format) {
+            return Err(TextureError::DeviceUnsupported(data.format));
+        }
+        for level in &data.levels {
+            let size = image_size(data.format, level.width, level.height, level.depth)
+                .and_then(|size| size.checked_mul(data.image_count() as usize));
+            match size {
+                Some(size) if size <= level.data.len() => {}
+                _ => return Err(TextureError::InvalidHeader("level smaller than its dimensions")),
+            }
+        }
+
+        // Cube faces are just array layers as far as the image is concerned;
+        // the view decides how they're interpreted.
+        let image_count = data.image_count() as u16;
+        let kind = if data.depth > 1 {
+            img::Kind::D3(data.width, data.height, data.depth)
+        } else {
+            img::Kind::D2(data.width, data.height, image_count, 1)
+        };
+        let view_caps = if data.is_cube() {
+            ViewCapabilities::KIND_CUBE
+        } else {
+            ViewCapabilities::empty()
+        };
+
+        let memory_types = physical_device.memory_properties().memory_types;
+        let limits = physical_device.limits();
+        let level_count = data.levels.len() as u8;
+
+        let unbound_image = device.create_image(kind,
+                                                level_count,
+                                                data.format,
+                                                img::Tiling::Optimal,
+                                                img::Usage::TRANSFER_DST | img::Usage::SAMPLED,
+                                                view_caps)
+                                  .unwrap();
+        let req = device.get_image_requirements(&unbound_image);
+        let memory_type = utils::find_memory_type(&memory_types, req.type_mask, Properties::DEVICE_LOCAL)
+                              .expect("Could not find appropriate texture memory type.");
+        let memory = device.allocate_memory(memory_type, req.size).unwrap();
+        let image = device.bind_image_memory(&memory, 0, unbound_image).unwrap();
+
+        let range = SubresourceRange {
+            aspects: Aspects::COLOR,
+            levels: 0..level_count,
+            layers: 0..image_count,
+        };
+
+        upload_levels::<B>(device,
+                           &memory_types,
+                           limits.min_buffer_copy_offset_alignment,
+                           limits.min_buffer_copy_pitch_alignment,
+                           command_pool,
+                           queue,
+                           &image,
+                           range.clone(),
+                           data);
+
+        let view_kind = data.view_kind();
+        let view = device.create_image_view(&image, view_kind, data.format, Swizzle::NO, range)
+                         .unwrap();
+
+        Ok(Texture {
+            image,
+            memory,
+            view,
+            format: data.format,
+            extent: Extent {
+                width: data.width,
+                height: data.height,
+                depth: data.depth,
+            },
+            levels: level_count,
+            layers: image_count,
+            view_kind,
+        })
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        device.destroy_image_view(self.view);
+        device.destroy_image(self.image);
+        device.free_memory(self.memory);
+    }
+}
+
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    if alignment <= 1 {
+        value
+    } else {
+        (value + alignment - 1) / alignment * alignment
+    }
+}
+
+fn lcm(a: u64, b: u64) -> u64 {
+    let (mut x, mut y) = (a.max(1), b.max(1));
+    while y != 0 {
+        let t = y;
+        y = x % y;
+        x = t;
+    }
+    a.max(1) / x * b.max(1)
+}
+
+// Copy all levels of `data` into a staging buffer, respecting the device's
+// copy alignment rules, then record and wait on the transfer into `image`.
+fn upload_levels<B: Backend>(device: &B::Device,
+                             memory_types: &[MemoryType],
+                             offset_alignment: u64,
+                             pitch_alignment: u64,
+                             command_pool: &mut CommandPool<B, Graphics>,
+                             queue: &mut CommandQueue<B, Graphics>,
+                             image: &B::Image,
+                             range: SubresourceRange,
+                             data: &TextureData) {
+    let (block_width, block_height, block_bytes) = block_info(data.format);
+    let offset_alignment = lcm(lcm(offset_alignment, block_bytes as u64), 4);
+    let image_count = data.image_count() as usize;
+
+    // Work out where every (level, image) pair lives in the staging buffer.
+    let mut regions = Vec::new();
+    let mut staging_size = 0;
+    for (level_index, level) in data.levels.iter().enumerate() {
+        // Texture::upload has already checked none of this overflows.
+        let blocks_x = (level.width + block_width - 1) / block_width;
+        let blocks_y = (level.height + block_height - 1) / block_height;
+        let row_size = blocks_x as u64 * block_bytes as u64;
+        let row_pitch = align_up(row_size, pitch_alignment);
+        let image_size = image_size(data.format, level.width, level.height, level.depth).unwrap();
+
+        for image_index in 0..image_count {
+            staging_size = align_up(staging_size, offset_alignment);
+            regions.push((level_index, image_index, staging_size, row_size, row_pitch, blocks_y, image_size));
+            staging_size += row_pitch * blocks_y as u64 * level.depth as u64;
+        }
+    }
+
+    let (staging_buffer, staging_memory) = utils::empty_buffer::<B, u8>(device,
+                                                                        memory_types,
+                                                                        Properties::CPU_VISIBLE,
+                                                                        buffer::Usage::TRANSFER_SRC,
+                                                                        staging_size as usize);
+    {
+        let mut dest = device.acquire_mapping_writer::<u8>(&staging_memory, 0..staging_size)
+                             .unwrap();
+        for &(level_index, image_index, offset, row_size, row_pitch, blocks_y, image_size) in &regions {
+            let level = &data.levels[level_index];
+            let source = &level.data[image_index * image_size..(image_index + 1) * image_size];
+            let rows = blocks_y as usize * level.depth as usize;
+            for row in 0..rows {
+                let src_start = row * row_size as usize;
+                let dst_start = (offset + row as u64 * row_pitch) as usize;
+                dest[dst_start..dst_start + row_size as usize]
+                    .copy_from_slice(&source[src_start..src_start + row_size as usize]);
+            }
+        }
+        device.release_mapping_writer(dest);
+    }
+
+    let copies = regions.iter()
+                        .map(|&(level_index, image_index, offset, _, row_pitch, blocks_y, _)| {
+                            let level = &data.levels[level_index];
+                            BufferImageCopy {
+                                buffer_offset: offset,
+                                buffer_width: (row_pitch / block_bytes as u64) as u32 * block_width,
+                                buffer_height: blocks_y * block_height,
+                                image_layers: SubresourceLayers {
+                                    aspects: Aspects::COLOR,
+                                    level: level_index as u8,
+                                    layers: image_index as u16..image_index as u16 + 1,
+                                },
+                                image_offset: Offset { x: 0, y: 0, z: 0 },
+                                image_extent: Extent {
+                                    width: level.width,
+                                    height: level.height,
+                                    depth: level.depth,
+                                },
+                            }
+                        })
+                        .collect::<Vec<_>>();
+
+    let finished_command_buffer = {
+        let mut command_buffer = command_pool.acquire_command_buffer(false);
+
+        command_buffer.pipeline_barrier(
+            PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
+            Dependencies::empty(),
+            &[Barrier::Image {
+                states: (Access::empty(), Layout::Undefined)
+                    ..(Access::TRANSFER_WRITE, Layout::TransferDstOptimal),
+                target: image,
+                range: range.clone(),
+            }],
+        );
+
+        command_buffer.copy_buffer_to_image(&staging_buffer,
+                                            image,
+                                            Layout::TransferDstOptimal,
+                                            &copies);
+
+        command_buffer.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+            Dependencies::empty(),
+            &[Barrier::Image {
+                states: (Access::TRANSFER_WRITE, Layout::TransferDstOptimal)
+                    ..(Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+                target: image,
+                range,
+            }],
+        );
+
+        command_buffer.finish()
+    };
+
+    let fence = device.create_fence(false);
+    queue.submit(Submission::new().submit(vec![finished_command_buffer]), Some(&fence));
+    device.wait_for_fence(&fence, !0);
+
+    device.destroy_fence(fence);
+    device.destroy_buffer(staging_buffer);
+    device.free_memory(staging_memory);
+}
+
+
+// Little-endian readers shared by the container parsers.
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, TextureError> {
+    let b = bytes.get(offset..offset + 4).ok_or(TextureError::Truncated)?;
+    Ok(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, TextureError> {
+    let low = read_u32(bytes, offset)? as u64;
+    let high = read_u32(bytes, offset + 4)? as u64;
+    Ok(low | high << 32)
+}
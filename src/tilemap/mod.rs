@@ -0,0 +1,836 @@
+// Tile maps, drawn in chunks.
+//
+// A map is split into square chunks of tiles, and each layer of each chunk
+// gets its own static vertex and index buffer, with UVs into the tileset's
+// texture. Changing a tile only marks its chunk dirty; `update` rebuilds
+// just the dirty chunks, and `draw` skips chunks outside the view.
+//
+// Map coordinates are pixels, with tile (0, 0) at the origin and Y pointing
+// down, as in Tiled. Use a nearest-neighbour sampler, or a tileset with
+// spacing between tiles, to stop neighbouring tiles bleeding in at the edges.
+
+use gfx_hal::Backend;
+use math::{Mat4, Rect2, Vec2, Vec3};
+use prelude::*;
+use serde_json;
+use sprite::{SpriteVertex, UvRect};
+use texture::Texture;
+use utils;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+pub mod tiled;
+
+#[cfg(windows)]
+static VERT_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\tilemap.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\tilemap.frag.spv");
+
+#[cfg(all(unix))]
+static VERT_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/tilemap.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/tilemap.frag.spv");
+
+/// Flags Tiled stores in the top bits of a tile's global ID.
+pub const FLIP_HORIZONTAL: u32 = 0x8000_0000;
+pub const FLIP_VERTICAL: u32 = 0x4000_0000;
+pub const FLIP_DIAGONAL: u32 = 0x2000_0000;
+/// The bits of a global ID that identify the tile. Zero means no tile.
+pub const TILE_ID_MASK: u32 = 0x0fff_ffff;
+
+
+#[derive(Debug)]
+pub enum TileMapError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Parse(String),
+    /// A valid map using a feature we don't handle.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for TileMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TileMapError::Io(err) => write!(f, "I/O error: {}", err),
+            TileMapError::Json(err) => write!(f, "JSON error: {}", err),
+            TileMapError::Parse(message) => write!(f, "parse error: {}", message),
+            TileMapError::Unsupported(what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+impl Error for TileMapError {}
+
+impl From<io::Error> for TileMapError {
+    fn from(err: io::Error) -> Self {
+        TileMapError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for TileMapError {
+    fn from(err: serde_json::Error) -> Self {
+        TileMapError::Json(err)
+    }
+}
+
+
+/// A grid of tiles, each a global ID: zero for no tile, otherwise an index
+/// into one of the map's tilesets, plus the flip flags.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileLayer {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub visible: bool,
+    pub opacity: f32,
+    tiles: Vec<u32>,
+}
+
+impl TileLayer {
+    /// An empty layer.
+    pub fn new<S: Into<String>>(name: S, width: u32, height: u32) -> Self {
+        TileLayer {
+            name: name.into(),
+            width,
+            height,
+            visible: true,
+            opacity: 1.0,
+            tiles: vec![0; (width * height) as usize],
+        }
+    }
+
+    fn from_tiles(name: String,
+                  width: u32,
+                  height: u32,
+                  tiles: Vec<u32>,
+                  visible: bool,
+                  opacity: f32) -> Result<Self, TileMapError> {
+        if tiles.len() != (width * height) as usize {
+            return Err(TileMapError::Parse(format!("layer '{}' should have {} tiles, but has {}",
+                                                   name,
+                                                   width * height,
+                                                   tiles.len())));
+        }
+        Ok(TileLayer {
+            name,
+            width,
+            height,
+            visible,
+            opacity,
+            tiles,
+        })
+    }
+
+    /// The tile at `(x, y)`, or zero if that's outside the layer.
+    pub fn tile(&self, x: u32, y: u32) -> u32 {
+        if x < self.width && y < self.height {
+            self.tiles[(y * self.width + x) as usize]
+        } else {
+            0
+        }
+    }
+
+    /// Change the tile at `(x, y)`, returning whether it changed. Tiles
+    /// outside the layer are ignored.
+    pub fn set_tile(&mut self, x: u32, y: u32, gid: u32) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        let index = (y * self.width + x) as usize;
+        let changed = self.tiles[index] != gid;
+        self.tiles[index] = gid;
+        changed
+    }
+
+    /// Row by row, from the top left.
+    pub fn tiles(&self) -> &[u32] {
+        &self.tiles
+    }
+}
+
+
+/// A grid of tiles cut from one image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tileset {
+    /// The global ID of this tileset's first tile.
+    pub first_gid: u32,
+    pub name: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub tile_count: u32,
+    /// Pixels around the edge of the image.
+    pub margin: u32,
+    /// Pixels between tiles.
+    pub spacing: u32,
+    pub image: Option<PathBuf>,
+    pub image_width: u32,
+    pub image_height: u32,
+}
+
+impl Tileset {
+    pub fn contains(&self, gid: u32) -> bool {
+        let id = gid & TILE_ID_MASK;
+        id >= self.first_gid && id - self.first_gid < self.tile_count
+    }
+
+    /// Where a tile is in the image, in UV space, ignoring flip flags.
+    pub fn uv(&self, gid: u32) -> Option<UvRect> {
+        if !self.contains(gid) || self.columns == 0 || self.image_width == 0 || self.image_height == 0 {
+            return None;
+        }
+        let index = (gid & TILE_ID_MASK) - self.first_gid;
+        let x = self.margin + index % self.columns * (self.tile_width + self.spacing);
+        let y = self.margin + index / self.columns * (self.tile_height + self.spacing);
+        let (w, h) = (self.image_width as f32, self.image_height as f32);
+        Some(UvRect::new(x as f32 / w,
+                         y as f32 / h,
+                         self.tile_width as f32 / w,
+                         self.tile_height as f32 / h))
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileMap {
+    /// Size in tiles.
+    pub width: u32,
+    pub height: u32,
+    /// Size of each grid cell in pixels.
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// Drawn in order, so later layers appear on top.
+    pub layers: Vec<TileLayer>,
+    pub tilesets: Vec<Tileset>,
+}
+
+impl TileMap {
+    /// Load a map saved by Tiled. See the `tiled` module for what's
+    /// supported.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TileMapError> {
+        tiled::load(path)
+    }
+
+    /// The tileset a tile comes from.
+    pub fn tileset_for(&self, gid: u32) -> Option<&Tileset> {
+        self.tilesets.iter().find(|tileset| tileset.contains(gid))
+    }
+
+    /// The tile containing a point in map pixels.
+    pub fn tile_at(&self, point: Vec2) -> Option<(u32, u32)> {
+        if point.x < 0.0 || point.y < 0.0 || self.tile_width == 0 || self.tile_height == 0 {
+            return None;
+        }
+        let x = (point.x / self.tile_width as f32) as u32;
+        let y = (point.y / self.tile_height as f32) as u32;
+        if x < self.width && y < self.height {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+}
+
+
+struct ChunkBuffers<B: Backend> {
+    vertex_buffer: B::Buffer,
+    vertex_memory: B::Memory,
+    index_buffer: B::Buffer,
+    index_memory: B::Memory,
+    index_count: u32,
+    // Covers every tile in the chunk, in map pixels.
+    bounds: Rect2,
+}
+
+impl<B: Backend> ChunkBuffers<B> {
+    fn destroy(self, device: &B::Device) {
+        device.destroy_buffer(self.vertex_buffer);
+        device.free_memory(self.vertex_memory);
+        device.destroy_buffer(self.index_buffer);
+        device.free_memory(self.index_memory);
+    }
+}
+
+// Which chunks each layer is split into, and which need rebuilding. This
+// is kept apart from the buffers so it doesn't need a device.
+struct ChunkGrid {
+    chunk_size: u32,
+    chunks_x: u32,
+    chunks_y: u32,
+    // Layer by layer, then row by row.
+    dirty: Vec<bool>,
+}
+
+impl ChunkGrid {
+    // Starts with every chunk dirty.
+    fn new(map: &TileMap, chunk_size: u32) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let chunks_x = (map.width + chunk_size - 1) / chunk_size;
+        let chunks_y = (map.height + chunk_size - 1) / chunk_size;
+        ChunkGrid {
+            chunk_size,
+            chunks_x,
+            chunks_y,
+            dirty: vec![true; map.layers.len() * (chunks_x * chunks_y) as usize],
+        }
+    }
+
+    fn per_layer(&self) -> usize {
+        (self.chunks_x * self.chunks_y) as usize
+    }
+
+    fn index(&self, layer: usize, chunk_x: u32, chunk_y: u32) -> usize {
+        layer * self.per_layer() + (chunk_y * self.chunks_x + chunk_x) as usize
+    }
+
+    fn mark_tile(&mut self, layer: usize, x: u32, y: u32) {
+        let index = self.index(layer, x / self.chunk_size, y / self.chunk_size);
+        self.dirty[index] = true;
+    }
+
+    fn mark_layer(&mut self, layer: usize) {
+        let per_layer = self.per_layer();
+        for dirty in &mut self.dirty[layer * per_layer..(layer + 1) * per_layer] {
+            *dirty = true;
+        }
+    }
+
+    // The tiles a chunk covers, clipped to the layer.
+    fn tiles(&self, layer: &TileLayer, chunk_x: u32, chunk_y: u32) -> (Range<u32>, Range<u32>) {
+        let (x0, y0) = (chunk_x * self.chunk_size, chunk_y * self.chunk_size);
+        (x0..(x0 + self.chunk_size).min(layer.width), y0..(y0 + self.chunk_size).min(layer.height))
+    }
+}
+
+
+/// Draws one tileset's tiles from a map's layers. Tiles from any other
+/// tileset are left out.
+pub struct TileMapRenderer<B: Backend> {
+    set_layout: B::DescriptorSetLayout,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::GraphicsPipeline,
+    vertex_shader_module: B::ShaderModule,
+    fragment_shader_module: B::ShaderModule,
+    desc_pool: B::DescriptorPool,
+    desc_set: B::DescriptorSet,
+    map: TileMap,
+    tileset: Tileset,
+    grid: ChunkGrid,
+    // In the same order as `grid.dirty`; `None` if the chunk has no tiles.
+    chunks: Vec<Option<ChunkBuffers<B>>>,
+}
+
+impl<B: Backend> TileMapRenderer<B> {
+    /// Create a renderer for the first subpass of `render_pass`, drawing
+    /// tiles from `map.tilesets[tileset]` with `texture`, in chunks of
+    /// `chunk_size` by `chunk_size` tiles. The texture and sampler must
+    /// outlive the renderer.
+    ///
+    /// Nothing is built until the first `update`.
+    pub fn new(device: &B::Device,
+               render_pass: &B::RenderPass,
+               map: TileMap,
+               tileset: usize,
+               texture: &Texture<B>,
+               sampler: &B::Sampler,
+               chunk_size: u32) -> Self {
+        let set_layout = device.create_descriptor_set_layout(
+            &[DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+                stage_flags: ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            }],
+            &[],
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(
+            vec![&set_layout],
+            &[(ShaderStageFlags::VERTEX, 0..16)],
+        );
+
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+        let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
+
+        let pipeline = {
+            let vs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &vertex_shader_module,
+                specialization: Default::default(),
+            };
+
+            let fs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &fragment_shader_module,
+                specialization: Default::default(),
+            };
+
+            let shader_entries = GraphicsShaderSet {
+                vertex: vs_entry,
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(fs_entry),
+            };
+
+            let subpass = Subpass {
+                index: 0,
+                main_pass: render_pass,
+            };
+
+            let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                              Primitive::TriangleList,
+                                                              Rasterizer::FILL,
+                                                              &pipeline_layout,
+                                                              subpass);
+
+            pipeline_desc.blender
+                         .targets
+                         .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+            pipeline_desc.vertex_buffers.push(VertexBufferDesc {
+                binding: 0,
+                stride: ::std::mem::size_of::<SpriteVertex>() as u32,
+                rate: 0,
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 0,
+                binding: 0,
+                element: Element {
+                    format: Format::Rg32Float,
+                    offset: 0,
+                },
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 1,
+                binding: 0,
+                element: Element {
+                    format: Format::Rg32Float,
+                    offset: 8,
+                },
+            });
+            pipeline_desc.attributes.push(AttributeDesc {
+                location: 2,
+                binding: 0,
+                element: Element {
+                    format: Format::Rgba32Float,
+                    offset: 16,
+                },
+            });
+
+            device.create_graphics_pipeline(&pipeline_desc, None)
+                  .unwrap()
+        };
+
+        let mut desc_pool = device.create_descriptor_pool(
+            1,
+            &[DescriptorRangeDesc {
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+            }],
+        );
+        let desc_set = desc_pool.allocate_set(&set_layout).unwrap();
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &desc_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::CombinedImageSampler(&texture.view, Layout::ShaderReadOnlyOptimal, sampler)),
+        }]);
+
+        let grid = ChunkGrid::new(&map, chunk_size);
+        let tileset = map.tilesets[tileset].clone();
+
+        TileMapRenderer {
+            set_layout,
+            pipeline_layout,
+            pipeline,
+            vertex_shader_module,
+            fragment_shader_module,
+            desc_pool,
+            desc_set,
+            chunks: grid.dirty.iter().map(|_| None).collect(),
+            map,
+            tileset,
+            grid,
+        }
+    }
+
+    pub fn map(&self) -> &TileMap {
+        &self.map
+    }
+
+    /// Change one tile, marking its chunk for rebuilding. Tiles outside the
+    /// layer are ignored.
+    pub fn set_tile(&mut self, layer: usize, x: u32, y: u32, gid: u32) {
+        if self.map.layers[layer].set_tile(x, y, gid) {
+            self.grid.mark_tile(layer, x, y);
+        }
+    }
+
+    pub fn set_layer_visible(&mut self, layer: usize, visible: bool) {
+        self.map.layers[layer].visible = visible;
+    }
+
+    /// Opacity is baked into the vertices, so this rebuilds the whole
+    /// layer on the next update.
+    pub fn set_layer_opacity(&mut self, layer: usize, opacity: f32) {
+        self.map.layers[layer].opacity = opacity;
+        self.grid.mark_layer(layer);
+    }
+
+    /// Rebuild the buffers of every chunk that's changed since the last
+    /// update. Returns how many were rebuilt.
+    ///
+    /// This replaces buffers the GPU reads from, so call it after the
+    /// previous frame has finished, before recording `draw`.
+    pub fn update(&mut self, device: &B::Device, memory_types: &[MemoryType]) -> usize {
+        let mut rebuilt = 0;
+        for layer in 0..self.map.layers.len() {
+            for chunk_y in 0..self.grid.chunks_y {
+                for chunk_x in 0..self.grid.chunks_x {
+                    let index = self.grid.index(layer, chunk_x, chunk_y);
+                    if !self.grid.dirty[index] {
+                        continue;
+                    }
+                    let buffers = self.build_chunk(device, memory_types, layer, chunk_x, chunk_y);
+                    if let Some(old) = ::std::mem::replace(&mut self.chunks[index], buffers) {
+                        old.destroy(device);
+                    }
+                    self.grid.dirty[index] = false;
+                    rebuilt += 1;
+                }
+            }
+        }
+        rebuilt
+    }
+
+    fn build_chunk(&self,
+                   device: &B::Device,
+                   memory_types: &[MemoryType],
+                   layer: usize,
+                   chunk_x: u32,
+                   chunk_y: u32) -> Option<ChunkBuffers<B>> {
+        let (xs, ys) = self.grid.tiles(&self.map.layers[layer], chunk_x, chunk_y);
+        let (vertices, indices, bounds) = chunk_geometry(&self.map, &self.tileset, layer, xs, ys)?;
+
+        let (vertex_buffer, vertex_memory) = utils::create_buffer::<B, SpriteVertex>(device,
+                                                                                     memory_types,
+                                                                                     Properties::CPU_VISIBLE,
+                                                                                     buffer::Usage::VERTEX,
+                                                                                     &vertices);
+        let (index_buffer, index_memory) = utils::create_buffer::<B, u32>(device,
+                                                                          memory_types,
+                                                                          Properties::CPU_VISIBLE,
+                                                                          buffer::Usage::INDEX,
+                                                                          &indices);
+        Some(ChunkBuffers {
+            vertex_buffer,
+            vertex_memory,
+            index_buffer,
+            index_memory,
+            index_count: indices.len() as u32,
+            bounds,
+        })
+    }
+
+    /// Record draw calls for the visible layers' chunks that overlap the
+    /// view. Returns the number of chunks drawn.
+    pub fn draw(&self, encoder: &mut RenderPassInlineEncoder<B>, view_projection: Mat4) -> usize {
+        let view = visible_rect(view_projection);
+        let matrix: [[f32; 4]; 4] = view_projection.into();
+        let push_constants = unsafe { ::std::slice::from_raw_parts(matrix.as_ptr() as *const u32, 16) };
+
+        encoder.bind_graphics_pipeline(&self.pipeline);
+        encoder.bind_graphics_descriptor_sets(&self.pipeline_layout, 0, vec![&self.desc_set], &[]);
+        encoder.push_graphics_constants(&self.pipeline_layout, ShaderStageFlags::VERTEX, 0, push_constants);
+
+        let per_layer = self.grid.per_layer();
+        let mut drawn = 0;
+        for (layer, tile_layer) in self.map.layers.iter().enumerate() {
+            if !tile_layer.visible || tile_layer.opacity <= 0.0 {
+                continue;
+            }
+            for chunk in &self.chunks[layer * per_layer..(layer + 1) * per_layer] {
+                let buffers = match *chunk {
+                    Some(ref buffers) => buffers,
+                    None => continue,
+                };
+                if let Some(view) = view {
+                    if !view.overlaps(buffers.bounds) {
+                        continue;
+                    }
+                }
+                encoder.bind_vertex_buffers(0, vec![(&buffers.vertex_buffer, 0)]);
+                encoder.bind_index_buffer(IndexBufferView {
+                    buffer: &buffers.index_buffer,
+                    offset: 0,
+                    index_type: IndexType::U32,
+                });
+                encoder.draw_indexed(0..buffers.index_count, 0, 0..1);
+                drawn += 1;
+            }
+        }
+        drawn
+    }
+
+    pub fn destroy(self, device: &B::Device) {
+        for chunk in self.chunks {
+            if let Some(buffers) = chunk {
+                buffers.destroy(device);
+            }
+        }
+        device.destroy_descriptor_pool(self.desc_pool);
+        device.destroy_graphics_pipeline(self.pipeline);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+        device.destroy_descriptor_set_layout(self.set_layout);
+        device.destroy_shader_module(self.vertex_shader_module);
+        device.destroy_shader_module(self.fragment_shader_module);
+    }
+}
+
+
+// Two triangles for each of a layer's tiles from `tileset` in the given
+// range, and the rectangle they cover in map pixels. `None` if there are no
+// such tiles.
+fn chunk_geometry(map: &TileMap,
+                  tileset: &Tileset,
+                  layer: usize,
+                  xs: Range<u32>,
+                  ys: Range<u32>) -> Option<(Vec<SpriteVertex>, Vec<u32>, Rect2)> {
+    let tile_layer = &map.layers[layer];
+    let colour = [1.0, 1.0, 1.0, tile_layer.opacity];
+    // Tiles larger than a grid cell overhang up and to the right, as in
+    // Tiled.
+    let size = Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32);
+    let cell = Vec2::new(map.tile_width as f32, map.tile_height as f32);
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for y in ys.clone() {
+        for x in xs.clone() {
+            let gid = tile_layer.tile(x, y);
+            let uv = match tileset.uv(gid) {
+                Some(uv) => uv,
+                None => continue,
+            };
+            let top_left = Vec2::new(x as f32 * cell.x, (y + 1) as f32 * cell.y - size.y);
+
+            let corner = |cx: f32, cy: f32| {
+                // Undo the flips to find which part of the tile's image
+                // shows at this corner.
+                let (mut s, mut t) = (cx, cy);
+                if gid & FLIP_HORIZONTAL != 0 {
+                    s = 1.0 - s;
+                }
+                if gid & FLIP_VERTICAL != 0 {
+                    t = 1.0 - t;
+                }
+                if gid & FLIP_DIAGONAL != 0 {
+                    ::std::mem::swap(&mut s, &mut t);
+                }
+                SpriteVertex {
+                    position: (top_left + Vec2::new(cx, cy) * size).into(),
+                    uv: [uv.x + s * uv.w, uv.y + t * uv.h],
+                    colour,
+                }
+            };
+
+            let base = vertices.len() as u32;
+            vertices.extend_from_slice(&[corner(0.0, 0.0), corner(1.0, 0.0), corner(1.0, 1.0), corner(0.0, 1.0)]);
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+    }
+
+    if indices.is_empty() {
+        return None;
+    }
+
+    let left = xs.start as f32 * cell.x;
+    let top = (ys.start + 1) as f32 * cell.y - size.y;
+    let bounds = Rect2::new(left,
+                            top,
+                            (xs.end - 1) as f32 * cell.x + size.x - left,
+                            ys.end as f32 * cell.y - top);
+    Some((vertices, indices, bounds))
+}
+
+// The part of the Z = 0 plane that an orthographic `view_projection` shows,
+// as a bounding rectangle, or `None` if the matrix can't be inverted.
+fn visible_rect(view_projection: Mat4) -> Option<Rect2> {
+    let inverse = view_projection.inverse()?;
+    let mut min = Vec2::splat(::std::f32::INFINITY);
+    let mut max = Vec2::splat(::std::f32::NEG_INFINITY);
+    for &(x, y) in &[(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+        let point = inverse.transform_point(Vec3::new(x, y, 0.0));
+        min = min.min(Vec2::new(point.x, point.y));
+        max = max.max(Vec2::new(point.x, point.y));
+    }
+    Some(Rect2::from_corners(min, max))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx_eq(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 1e-5 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    // 16x16 tiles in 4 columns and 2 rows, with a 2 pixel margin and 1
+    // pixel between tiles.
+    fn tileset() -> Tileset {
+        Tileset {
+            first_gid: 1,
+            name: "tiles".to_owned(),
+            tile_width: 16,
+            tile_height: 16,
+            columns: 4,
+            tile_count: 8,
+            margin: 2,
+            spacing: 1,
+            image: None,
+            image_width: 2 + 4 * 16 + 3 + 2,
+            image_height: 2 + 2 * 16 + 1 + 2,
+        }
+    }
+
+    fn map(width: u32, height: u32) -> TileMap {
+        TileMap {
+            width,
+            height,
+            tile_width: 16,
+            tile_height: 16,
+            layers: vec![TileLayer::new("ground", width, height)],
+            tilesets: vec![tileset()],
+        }
+    }
+
+    #[test]
+    fn uv_skips_margin_and_spacing() {
+        let tileset = tileset();
+        let (w, h) = (tileset.image_width as f32, tileset.image_height as f32);
+
+        let first = tileset.uv(1).unwrap();
+        assert!(approx_eq(first.x, 2.0 / w) && approx_eq(first.y, 2.0 / h));
+        assert!(approx_eq(first.w, 16.0 / w) && approx_eq(first.h, 16.0 / h));
+
+        // The second tile of the second row.
+        let sixth = tileset.uv(6).unwrap();
+        assert!(approx_eq(sixth.x, 19.0 / w) && approx_eq(sixth.y, 19.0 / h));
+
+        // Flip flags don't change which tile it is.
+        assert_eq!(tileset.uv(6 | FLIP_HORIZONTAL | FLIP_DIAGONAL), Some(sixth));
+        assert_eq!(tileset.uv(0), None);
+        assert_eq!(tileset.uv(9), None);
+    }
+
+    #[test]
+    fn set_tile_marks_its_chunk_dirty() {
+        let mut map = map(10, 6);
+        let mut grid = ChunkGrid::new(&map, 4);
+        assert_eq!((grid.chunks_x, grid.chunks_y), (3, 2));
+        assert!(grid.dirty.iter().all(|&dirty| dirty));
+        for dirty in &mut grid.dirty {
+            *dirty = false;
+        }
+
+        assert!(map.layers[0].set_tile(9, 5, 3));
+        grid.mark_tile(0, 9, 5);
+        assert_eq!(map.layers[0].tile(9, 5), 3);
+        let dirty = grid.dirty.iter().enumerate().filter(|&(_, &dirty)| dirty).map(|(i, _)| i).collect::<Vec<_>>();
+        assert_eq!(dirty, vec![grid.index(0, 2, 1)]);
+
+        // Nothing changes when the tile is already set, or off the layer.
+        assert!(!map.layers[0].set_tile(9, 5, 3));
+        assert!(!map.layers[0].set_tile(10, 0, 3));
+        assert_eq!(map.layers[0].tile(10, 0), 0);
+
+        // The edge chunk is clipped to the layer.
+        assert_eq!(grid.tiles(&map.layers[0], 2, 1), (8..10, 4..6));
+    }
+
+    #[test]
+    fn builds_chunk_geometry() {
+        let mut map = map(4, 4);
+        assert_eq!(chunk_geometry(&map, &tileset(), 0, 0..4, 0..4), None);
+
+        map.layers[0].set_tile(1, 2, 1);
+        map.layers[0].set_tile(3, 3, 2);
+        let (vertices, indices, bounds) = chunk_geometry(&map, &tileset(), 0, 0..4, 0..4).unwrap();
+        assert_eq!(vertices.len(), 8);
+        assert_eq!(indices, vec![0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7]);
+        assert_eq!(vertices[0].position, [16.0, 32.0]);
+        assert_eq!(vertices[2].position, [32.0, 48.0]);
+        // The bounds cover the whole chunk, not just the tiles in it.
+        assert_eq!(bounds, Rect2::new(0.0, 0.0, 64.0, 64.0));
+    }
+
+    #[test]
+    fn flips_tile_uvs() {
+        let tileset = tileset();
+        let uv = tileset.uv(1).unwrap();
+        let corner_uvs = |gid: u32| {
+            let mut map = map(1, 1);
+            map.layers[0].set_tile(0, 0, gid);
+            let (vertices, _, _) = chunk_geometry(&map, &tileset, 0, 0..1, 0..1).unwrap();
+            vertices.iter().map(|vertex| vertex.uv).collect::<Vec<_>>()
+        };
+        let (left, top, right, bottom) = (uv.x, uv.y, uv.x + uv.w, uv.y + uv.h);
+
+        // Corners go top left, top right, bottom right, bottom left.
+        assert_eq!(corner_uvs(1), vec![[left, top], [right, top], [right, bottom], [left, bottom]]);
+        assert_eq!(corner_uvs(1 | FLIP_HORIZONTAL),
+                   vec![[right, top], [left, top], [left, bottom], [right, bottom]]);
+        assert_eq!(corner_uvs(1 | FLIP_VERTICAL),
+                   vec![[left, bottom], [right, bottom], [right, top], [left, top]]);
+        // Diagonal flips swap the axes, turning the tile's top edge into
+        // its left edge.
+        assert_eq!(corner_uvs(1 | FLIP_DIAGONAL),
+                   vec![[left, top], [left, bottom], [right, bottom], [right, top]]);
+    }
+
+    #[test]
+    fn large_tiles_overhang_upwards() {
+        let mut tall = tileset();
+        tall.tile_height = 32;
+        let mut map = map(2, 2);
+        map.layers[0].set_tile(0, 0, 1);
+        let (vertices, _, bounds) = chunk_geometry(&map, &tall, 0, 0..2, 0..2).unwrap();
+        assert_eq!(vertices[0].position, [0.0, -16.0]);
+        assert_eq!(bounds, Rect2::new(0.0, -16.0, 32.0, 48.0));
+    }
+
+    #[test]
+    fn culls_chunks_outside_the_view() {
+        // A 100x50 pixel view of the map, scrolled 200 pixels right.
+        let projection = Mat4::orthographic(0.0, 100.0, 50.0, 0.0, -1.0, 1.0);
+        let view = Mat4::from_trs(Vec3::new(-200.0, 0.0, 0.0), Default::default(), Vec3::splat(1.0));
+        let visible = visible_rect(projection * view).unwrap();
+        assert!(approx_eq(visible.x, 200.0) && approx_eq(visible.y, 0.0));
+        assert!(approx_eq(visible.w, 100.0) && approx_eq(visible.h, 50.0));
+
+        assert!(visible.overlaps(Rect2::new(150.0, 0.0, 64.0, 64.0)));
+        assert!(visible.overlaps(Rect2::new(290.0, 40.0, 64.0, 64.0)));
+        assert!(!visible.overlaps(Rect2::new(0.0, 0.0, 64.0, 64.0)));
+        assert!(!visible.overlaps(Rect2::new(200.0, 50.0, 64.0, 64.0)));
+        assert!(!visible.overlaps(Rect2::new(300.0, 0.0, 64.0, 64.0)));
+
+        assert_eq!(visible_rect(Mat4([[0.0; 4]; 4])), None);
+    }
+
+    #[test]
+    fn finds_tiles_and_tilesets() {
+        let map = map(4, 3);
+        assert_eq!(map.tile_at(Vec2::new(17.0, 40.0)), Some((1, 2)));
+        assert_eq!(map.tile_at(Vec2::new(64.0, 0.0)), None);
+        assert_eq!(map.tile_at(Vec2::new(-1.0, 0.0)), None);
+        assert_eq!(map.tileset_for(8 | FLIP_VERTICAL).map(|tileset| tileset.first_gid), Some(1));
+        assert_eq!(map.tileset_for(9), None);
+    }
+}
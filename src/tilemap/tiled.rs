@@ -0,0 +1,634 @@
+// Loading maps made with the Tiled editor (https://www.mapeditor.org).
+//
+// Both the JSON (`.json`/`.tmj`) and XML (`.tmx`) formats are read, along
+// with external tilesets in either format (`.tsj`/`.tsx`). Only orthogonal,
+// finite maps are supported. Tile layer data must be uncompressed: plain
+// arrays in JSON, or CSV or `<tile>` elements in XML. Group layers are
+// flattened into their children; object and image layers are skipped.
+//
+// There's no XML crate among our dependencies, so a small reader below
+// handles the subset of XML that Tiled writes.
+
+use super::{TileLayer, TileMap, TileMapError, Tileset};
+
+use serde_json::{self, Value};
+
+use std::fs;
+use std::path::Path;
+
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<TileMap, TileMapError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    if is_xml(path) {
+        from_tmx(&source, base_dir)
+    } else {
+        from_json(&source, base_dir)
+    }
+}
+
+fn is_xml(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tmx") | Some("tsx") | Some("xml") => true,
+        _ => false,
+    }
+}
+
+fn check_orientation(orientation: &str, infinite: bool) -> Result<(), TileMapError> {
+    if orientation != "orthogonal" {
+        return Err(TileMapError::Unsupported("non-orthogonal map"));
+    }
+    if infinite {
+        return Err(TileMapError::Unsupported("infinite map"));
+    }
+    Ok(())
+}
+
+
+fn default_orientation() -> String {
+    "orthogonal".to_owned()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default = "default_orientation")]
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    width: u32,
+    #[serde(default)]
+    height: u32,
+    #[serde(default = "default_true")]
+    visible: bool,
+    #[serde(default = "default_opacity")]
+    opacity: f32,
+    #[serde(default)]
+    data: Option<Value>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    #[serde(default)]
+    firstgid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+    #[serde(default)]
+    image: Option<String>,
+    #[serde(default)]
+    imagewidth: u32,
+    #[serde(default)]
+    imageheight: u32,
+}
+
+impl JsonTileset {
+    fn into_tileset(self, first_gid: u32, base_dir: &Path) -> Tileset {
+        Tileset {
+            first_gid,
+            name: self.name,
+            tile_width: self.tilewidth,
+            tile_height: self.tileheight,
+            columns: self.columns,
+            tile_count: self.tilecount,
+            margin: self.margin,
+            spacing: self.spacing,
+            image: self.image.map(|image| base_dir.join(image)),
+            image_width: self.imagewidth,
+            image_height: self.imageheight,
+        }
+    }
+}
+
+/// Read a map in Tiled's JSON format. Paths in it are relative to
+/// `base_dir`.
+pub fn from_json(source: &str, base_dir: &Path) -> Result<TileMap, TileMapError> {
+    let map: JsonMap = serde_json::from_str(source)?;
+    check_orientation(&map.orientation, map.infinite)?;
+
+    let mut tilesets = Vec::new();
+    for tileset in map.tilesets {
+        let first_gid = tileset.firstgid;
+        tilesets.push(match tileset.source.clone() {
+            Some(source) => load_tileset(&base_dir.join(source), first_gid)?,
+            None => tileset.into_tileset(first_gid, base_dir),
+        });
+    }
+
+    let mut layers = Vec::new();
+    flatten_json_layers(map.layers, true, 1.0, &mut layers)?;
+
+    Ok(TileMap {
+        width: map.width,
+        height: map.height,
+        tile_width: map.tilewidth,
+        tile_height: map.tileheight,
+        layers,
+        tilesets,
+    })
+}
+
+fn flatten_json_layers(layers: Vec<JsonLayer>,
+                       visible: bool,
+                       opacity: f32,
+                       out: &mut Vec<TileLayer>) -> Result<(), TileMapError> {
+    for layer in layers {
+        let visible = visible && layer.visible;
+        let opacity = opacity * layer.opacity;
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                let tiles = match layer.data {
+                    Some(Value::Array(values)) => {
+                        values.iter()
+                              .map(|value| {
+                                  value.as_u64()
+                                       .map(|gid| gid as u32)
+                                       .ok_or_else(|| TileMapError::Parse("tile data must be numbers".to_owned()))
+                              })
+                              .collect::<Result<Vec<_>, _>>()?
+                    }
+                    Some(Value::String(_)) => return Err(TileMapError::Unsupported("base64 tile data")),
+                    _ => return Err(TileMapError::Parse(format!("layer '{}' has no tile data", layer.name))),
+                };
+                out.push(TileLayer::from_tiles(layer.name, layer.width, layer.height, tiles, visible, opacity)?);
+            }
+            "group" => flatten_json_layers(layer.layers, visible, opacity, out)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+// An external tileset, in either format.
+fn load_tileset(path: &Path, first_gid: u32) -> Result<Tileset, TileMapError> {
+    let source = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    if is_xml(path) {
+        let root = parse_xml(&source)?;
+        tmx_tileset(&root, first_gid, base_dir)
+    } else {
+        let tileset: JsonTileset = serde_json::from_str(&source)?;
+        Ok(tileset.into_tileset(first_gid, base_dir))
+    }
+}
+
+
+/// Read a map in Tiled's XML format. Paths in it are relative to
+/// `base_dir`.
+pub fn from_tmx(source: &str, base_dir: &Path) -> Result<TileMap, TileMapError> {
+    let root = parse_xml(source)?;
+    if root.name != "map" {
+        return Err(TileMapError::Parse("expected a <map> element".to_owned()));
+    }
+    check_orientation(root.attribute("orientation").unwrap_or("orthogonal"),
+                      root.attribute("infinite") == Some("1"))?;
+
+    let mut tilesets = Vec::new();
+    for element in root.children_named("tileset") {
+        let first_gid: u32 = element.number("firstgid", 1)?;
+        tilesets.push(match element.attribute("source") {
+            Some(source) => load_tileset(&base_dir.join(source), first_gid)?,
+            None => tmx_tileset(element, first_gid, base_dir)?,
+        });
+    }
+
+    let mut layers = Vec::new();
+    flatten_tmx_layers(&root, true, 1.0, &mut layers)?;
+
+    Ok(TileMap {
+        width: root.number("width", 0)?,
+        height: root.number("height", 0)?,
+        tile_width: root.number("tilewidth", 0)?,
+        tile_height: root.number("tileheight", 0)?,
+        layers,
+        tilesets,
+    })
+}
+
+fn tmx_tileset(element: &XmlElement, first_gid: u32, base_dir: &Path) -> Result<Tileset, TileMapError> {
+    let image = element.children_named("image").next();
+    Ok(Tileset {
+        first_gid,
+        name: element.attribute("name").unwrap_or("").to_owned(),
+        tile_width: element.number("tilewidth", 0)?,
+        tile_height: element.number("tileheight", 0)?,
+        columns: element.number("columns", 0)?,
+        tile_count: element.number("tilecount", 0)?,
+        margin: element.number("margin", 0)?,
+        spacing: element.number("spacing", 0)?,
+        image: image.and_then(|image| image.attribute("source")).map(|source| base_dir.join(source)),
+        image_width: match image {
+            Some(image) => image.number("width", 0)?,
+            None => 0,
+        },
+        image_height: match image {
+            Some(image) => image.number("height", 0)?,
+            None => 0,
+        },
+    })
+}
+
+fn flatten_tmx_layers(parent: &XmlElement,
+                      visible: bool,
+                      opacity: f32,
+                      out: &mut Vec<TileLayer>) -> Result<(), TileMapError> {
+    for element in &parent.children {
+        let visible = visible && element.attribute("visible") != Some("0");
+        let opacity = opacity * element.number("opacity", 1.0f32)?;
+        match element.name.as_str() {
+            "layer" => {
+                let name = element.attribute("name").unwrap_or("").to_owned();
+                let data = element.children_named("data")
+                                  .next()
+                                  .ok_or_else(|| TileMapError::Parse(format!("layer '{}' has no tile data", name)))?;
+                if data.attribute("compression").is_some() {
+                    return Err(TileMapError::Unsupported("compressed tile data"));
+                }
+                let tiles = match data.attribute("encoding") {
+                    Some("csv") => {
+                        data.text
+                            .split(',')
+                            .map(|gid| {
+                                gid.trim()
+                                   .parse()
+                                   .map_err(|_| TileMapError::Parse(format!("bad tile '{}'", gid.trim())))
+                            })
+                            .collect::<Result<Vec<u32>, _>>()?
+                    }
+                    None => {
+                        data.children_named("tile")
+                            .map(|tile| tile.number("gid", 0))
+                            .collect::<Result<Vec<u32>, _>>()?
+                    }
+                    Some(_) => return Err(TileMapError::Unsupported("base64 tile data")),
+                };
+                out.push(TileLayer::from_tiles(name,
+                                               element.number("width", 0)?,
+                                               element.number("height", 0)?,
+                                               tiles,
+                                               visible,
+                                               opacity)?);
+            }
+            "group" => flatten_tmx_layers(element, visible, opacity, out)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+
+struct XmlElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|&&(ref key, _)| key == name)
+            .map(|&(_, ref value)| value.as_str())
+    }
+
+    fn number<T: ::std::str::FromStr>(&self, name: &str, default: T) -> Result<T, TileMapError> {
+        match self.attribute(name) {
+            Some(value) => {
+                value.parse()
+                     .map_err(|_| TileMapError::Parse(format!("bad value '{}' for '{}'", value, name)))
+            }
+            None => Ok(default),
+        }
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+// Parse a document into its root element. Declarations, comments and
+// doctypes are skipped, and text (including CDATA sections) is only kept
+// for elements that have it.
+fn parse_xml(source: &str) -> Result<XmlElement, TileMapError> {
+    let error = |message: &str| TileMapError::Parse(format!("XML: {}", message));
+    let mut stack: Vec<XmlElement> = Vec::new();
+    let mut rest = source;
+
+    loop {
+        let open = match rest.find('<') {
+            Some(open) => open,
+            None => break,
+        };
+        if let Some(element) = stack.last_mut() {
+            element.text.push_str(&unescape(&rest[..open])?);
+        }
+        rest = &rest[open..];
+
+        if rest.starts_with("<![CDATA[") {
+            let end = rest.find("]]>").ok_or_else(|| error("unterminated CDATA section"))?;
+            if let Some(element) = stack.last_mut() {
+                element.text.push_str(&rest[9..end]);
+            }
+            rest = &rest[end + 3..];
+        } else if rest.starts_with("<?") {
+            let end = rest.find("?>").ok_or_else(|| error("unterminated declaration"))?;
+            rest = &rest[end + 2..];
+        } else if rest.starts_with("<!--") {
+            let end = rest.find("-->").ok_or_else(|| error("unterminated comment"))?;
+            rest = &rest[end + 3..];
+        } else if rest.starts_with("<!") {
+            let end = rest.find('>').ok_or_else(|| error("unterminated doctype"))?;
+            rest = &rest[end + 1..];
+        } else if rest.starts_with("</") {
+            let end = rest.find('>').ok_or_else(|| error("unterminated closing tag"))?;
+            let name = rest[2..end].trim();
+            rest = &rest[end + 1..];
+            let element = stack.pop().ok_or_else(|| error("unexpected closing tag"))?;
+            if element.name != name {
+                return Err(error(&format!("<{}> closed by </{}>", element.name, name)));
+            }
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(element),
+                None => return Ok(element),
+            }
+        } else {
+            let (element, self_closing, remainder) = parse_tag(&rest[1..])?;
+            rest = remainder;
+            if self_closing {
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            } else {
+                stack.push(element);
+            }
+        }
+    }
+    Err(error("unexpected end of document"))
+}
+
+// Parse an opening tag after its `<`, returning the element, whether it
+// closed itself, and the text after the tag.
+fn parse_tag(source: &str) -> Result<(XmlElement, bool, &str), TileMapError> {
+    let malformed = || TileMapError::Parse("XML: malformed tag".to_owned());
+    let is_name_char = |c: char| !c.is_whitespace() && c != '/' && c != '>' && c != '=';
+    let name_end = source.find(|c: char| !is_name_char(c)).ok_or_else(malformed)?;
+    let mut element = XmlElement {
+        name: source[..name_end].to_owned(),
+        attributes: Vec::new(),
+        children: Vec::new(),
+        text: String::new(),
+    };
+    let mut rest = &source[name_end..];
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with("/>") {
+            return Ok((element, true, &rest[2..]));
+        }
+        if rest.starts_with('>') {
+            return Ok((element, false, &rest[1..]));
+        }
+
+        let key_end = rest.find(|c: char| !is_name_char(c)).ok_or_else(malformed)?;
+        if key_end == 0 {
+            return Err(malformed());
+        }
+        let key = rest[..key_end].to_owned();
+        rest = rest[key_end..].trim_start();
+        if !rest.starts_with('=') {
+            return Err(malformed());
+        }
+        rest = rest[1..].trim_start();
+        let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'').ok_or_else(malformed)?;
+        let value_end = rest[1..].find(quote).ok_or_else(malformed)? + 1;
+        element.attributes.push((key, unescape(&rest[1..value_end])?));
+        rest = &rest[value_end + 1..];
+    }
+}
+
+// Replace the predefined entities and numeric character references.
+fn unescape(text: &str) -> Result<String, TileMapError> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let end = rest.find(';')
+                      .ok_or_else(|| TileMapError::Parse("XML: unterminated entity".to_owned()))?;
+        let entity = &rest[..end];
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "amp" => Some('&'),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(::std::char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(::std::char::from_u32),
+            _ => None,
+        };
+        unescaped.push(c.ok_or_else(|| TileMapError::Parse(format!("XML: unknown entity '&{};'", entity)))?);
+        rest = &rest[end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tilemap::{FLIP_DIAGONAL, FLIP_HORIZONTAL, FLIP_VERTICAL};
+
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+    }
+
+    fn expect_parse_error(result: Result<XmlElement, TileMapError>) {
+        match result {
+            Err(TileMapError::Parse(_)) => (),
+            Err(err) => panic!("expected a parse error, got {}", err),
+            Ok(element) => panic!("expected a parse error, got <{}>", element.name),
+        }
+    }
+
+    fn check_fixture_map(map: &TileMap) {
+        let dir = fixture("");
+        assert_eq!((map.width, map.height, map.tile_width, map.tile_height), (4, 3, 16, 16));
+
+        // The external tileset, with its image relative to the .tsx.
+        assert_eq!(map.tilesets.len(), 2);
+        let terrain = &map.tilesets[0];
+        assert_eq!((terrain.first_gid, terrain.name.as_str()), (1, "Terrain & Water"));
+        assert_eq!((terrain.tile_width, terrain.tile_height, terrain.columns, terrain.tile_count), (16, 16, 4, 8));
+        assert_eq!((terrain.margin, terrain.spacing), (2, 1));
+        assert_eq!(terrain.image, Some(dir.join("terrain.png")));
+        assert_eq!((terrain.image_width, terrain.image_height), (71, 37));
+
+        let props = &map.tilesets[1];
+        assert_eq!((props.first_gid, props.tile_height, props.tile_count), (9, 32, 2));
+        assert_eq!(props.image, Some(dir.join("images").join("props.png")));
+
+        // The group is flattened into its child, passing its opacity down,
+        // and the object layer is skipped.
+        assert_eq!(map.layers.len(), 2);
+        let ground = &map.layers[0];
+        assert_eq!((ground.name.as_str(), ground.width, ground.height), ("ground", 4, 3));
+        assert_eq!(&ground.tiles()[..8], &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(ground.tile(0, 2), 1 | FLIP_HORIZONTAL);
+        assert_eq!(ground.tile(3, 2), 1 | FLIP_VERTICAL);
+        assert_eq!(map.tileset_for(ground.tile(0, 2)).map(|tileset| tileset.first_gid), Some(1));
+
+        let decor = &map.layers[1];
+        assert_eq!(decor.name, "props");
+        assert!(decor.visible);
+        assert!((decor.opacity - 0.25).abs() < 1e-6);
+        assert_eq!(decor.tile(0, 0), 9);
+        assert_eq!(decor.tile(3, 0), 10);
+        assert_eq!(decor.tile(1, 2), 9 | FLIP_DIAGONAL);
+        assert_eq!(decor.tiles().iter().filter(|&&gid| gid != 0).count(), 3);
+    }
+
+    #[test]
+    fn loads_tmx() {
+        let map = load(fixture("tilemap.tmx")).unwrap();
+        check_fixture_map(&map);
+    }
+
+    #[test]
+    fn loads_json() {
+        let map = load(fixture("tilemap.json")).unwrap();
+        check_fixture_map(&map);
+        assert_eq!(map, load(fixture("tilemap.tmx")).unwrap());
+    }
+
+    #[test]
+    fn hides_layers_in_hidden_groups() {
+        let source = r#"<map width="1" height="1" tilewidth="8" tileheight="8">
+                          <group visible="0">
+                            <layer name="inner" width="1" height="1"><data encoding="csv">1</data></layer>
+                          </group>
+                        </map>"#;
+        let map = from_tmx(source, Path::new("")).unwrap();
+        assert!(!map.layers[0].visible);
+    }
+
+    #[test]
+    fn rejects_unsupported_maps() {
+        let expect_unsupported = |source: &str| match from_tmx(source, Path::new("")) {
+            Err(TileMapError::Unsupported(_)) => (),
+            other => panic!("expected Unsupported, got {:?}", other.map(|_| ())),
+        };
+        expect_unsupported(r#"<map orientation="isometric"/>"#);
+        expect_unsupported(r#"<map infinite="1"/>"#);
+        expect_unsupported(r#"<map width="1" height="1">
+                                <layer width="1" height="1"><data encoding="base64">AQAAAA==</data></layer>
+                              </map>"#);
+        expect_unsupported(r#"<map width="1" height="1">
+                                <layer width="1" height="1"><data encoding="csv" compression="zlib">1</data></layer>
+                              </map>"#);
+    }
+
+    #[test]
+    fn checks_layer_sizes() {
+        let source = r#"<map width="2" height="1">
+                          <layer name="short" width="2" height="1"><data encoding="csv">1</data></layer>
+                        </map>"#;
+        match from_tmx(source, Path::new("")) {
+            Err(TileMapError::Parse(_)) => (),
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn parses_nested_elements() {
+        let root = parse_xml("<?xml version=\"1.0\"?>\n\
+                              <!DOCTYPE map>\n\
+                              <a x='1' y = \"two\"><!-- <b> --><b/><c>text</c></a>").unwrap();
+        assert_eq!(root.name, "a");
+        assert_eq!(root.attribute("x"), Some("1"));
+        assert_eq!(root.attribute("y"), Some("two"));
+        assert_eq!(root.children.iter().map(|child| child.name.as_str()).collect::<Vec<_>>(), vec!["b", "c"]);
+        assert_eq!(root.children[1].text, "text");
+    }
+
+    #[test]
+    fn unescapes_entities() {
+        let root = parse_xml("<a name=\"&lt;&quot;&apos;&amp;lt;&gt;\">&#65;&#x42;&amp;</a>").unwrap();
+        assert_eq!(root.attribute("name"), Some("<\"'&lt;>"));
+        assert_eq!(root.text, "AB&");
+        assert_eq!(parse_xml("<a>&#10;</a>").unwrap().text, "\n");
+
+        expect_parse_error(parse_xml("<a>&nbsp;</a>"));
+        expect_parse_error(parse_xml("<a>&amp</a>"));
+        expect_parse_error(parse_xml("<a>&#xD800;</a>"));
+    }
+
+    #[test]
+    fn keeps_cdata_as_text() {
+        let root = parse_xml("<data>1,<![CDATA[2,<3>&amp;]]>4</data>").unwrap();
+        assert_eq!(root.text, "1,2,<3>&amp;4");
+        expect_parse_error(parse_xml("<data><![CDATA[1,2</data>"));
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        for &source in &["<a></b>",
+                         "<a><b></a></b>",
+                         "<a>",
+                         "<a><b>",
+                         "</a>",
+                         "<a x=\"1></a>",
+                         "<a x=1></a>",
+                         "<a x></a>",
+                         "<a",
+                         "<a></a",
+                         "<a><!-- </a>",
+                         "<?xml version=\"1.0\"",
+                         ""] {
+            expect_parse_error(parse_xml(source));
+        }
+    }
+}
@@ -0,0 +1,53 @@
+// The scenes drawn by the numbered tutorials.
+//
+// Each tutorial binary owns its window, swapchain and main loop, and hands
+// the drawing itself to one of these. Keeping the scenes here means the
+// same frame can be rendered somewhere other than a window: given a time
+// `t` and a target size, a scene always draws exactly the same thing, which
+// is what the golden-image tests in `tests/golden.rs` rely on.
+
+pub mod push_constants;
+pub mod triangle;
+pub mod uniforms;
+pub mod vertex_buffers;
+
+pub use self::push_constants::PushConstantScene;
+pub use self::triangle::TriangleScene;
+pub use self::uniforms::UniformScene;
+pub use self::vertex_buffers::VertexBufferScene;
+
+use gfx_hal::Backend;
+use math::{Mat4, Vec3};
+use prelude::*;
+
+
+pub trait TutorialScene<B: Backend>: Sized {
+    /// Create the scene's pipeline and buffers for the first subpass of
+    /// `render_pass`.
+    fn new(device: &B::Device, memory_types: &[MemoryType], render_pass: &B::RenderPass) -> Self;
+
+    /// Update everything that depends on time (in seconds) or on the size of
+    /// the target. This writes buffers the GPU reads from, so the previous
+    /// frame drawn with the scene must have finished.
+    fn update(&mut self, device: &B::Device, t: f32, extent: Extent2D);
+
+    /// Bind the scene's state and record its draw calls.
+    fn draw(&self, encoder: &mut RenderPassInlineEncoder<B>);
+
+    fn destroy(self, device: &B::Device);
+}
+
+
+/// The zoom the uniform and push constant tutorials apply at time `t`.
+pub fn zoom(t: f32) -> f32 {
+    t.cos() * 0.33 + 0.67
+}
+
+/// A projection which scales by `zoom` while keeping squares square in a
+/// `width` by `height` target.
+pub fn zoom_projection(zoom: f32, width: u32, height: u32) -> [[f32; 4]; 4] {
+    let aspect_corrected_x = height as f32 / width as f32;
+    // Matrices in `math` are column-major like GLSL's, so they convert
+    // straight into the `[[f32; 4]; 4]` the shaders expect.
+    Mat4::from_scale(Vec3::new(aspect_corrected_x * zoom, zoom, 1.0)).into()
+}
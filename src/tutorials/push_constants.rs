@@ -0,0 +1,273 @@
+// Part 04: four tinted copies of the diamond, each positioned and coloured
+// by push constants, all sharing part 03's zooming projection.
+
+use gfx_hal::Backend;
+use math::{Mat4, Vec4};
+use prelude::*;
+use raster::{Raster, RasterVertex};
+use tutorials::uniforms::UniformBlock;
+use tutorials::vertex_buffers::{self, Vertex, MESH};
+use tutorials::TutorialScene;
+use utils;
+
+#[cfg(windows)]
+static VERT_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part04.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part04.frag.spv");
+
+#[cfg(all(unix))]
+static VERT_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/part04.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/part04.frag.spv");
+
+// Push constants are a simpler and faster way of providing data
+// to shaders than through the more complex bindings introduced
+// thus far. The traadeoff is limited size: the spec only mandates
+// that each shader stage should support 128 bytes of push constant
+// data (TODO: check this).
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct PushConstants {
+    pub tint: [f32; 4],
+    pub position: [f32; 3],
+}
+
+/// One push constant for each draw call.
+pub const DIAMONDS: &[PushConstants] = &[
+    PushConstants {
+        position: [-1.0, -1.0, 0.0],
+        tint: [1.0, 0.0, 0.0, 1.0],
+    },
+    PushConstants {
+        position: [1.0, -1.0, 0.0],
+        tint: [0.0, 1.0, 0.0, 1.0],
+    },
+    PushConstants {
+        position: [-1.0, 1.0, 0.0],
+        tint: [0.0, 0.0, 1.0, 1.0],
+    },
+    PushConstants {
+        position: [1.0, 1.0, 0.0],
+        tint: [1.0, 1.0, 1.0, 1.0],
+    },
+];
+
+/// Draw a diamond for each of `diamonds` into `raster` the way the shaders
+/// do, with `uniforms`.
+pub fn draw_reference(raster: &mut Raster, uniforms: &UniformBlock, diamonds: &[PushConstants]) {
+    let projection = Mat4::from(uniforms.projection);
+    for diamond in diamonds {
+        raster.draw(MESH, |vertex| {
+            let position = Vec4::new(vertex.position[0] + diamond.position[0],
+                                     vertex.position[1] + diamond.position[1],
+                                     vertex.position[2] + diamond.position[2],
+                                     1.0);
+            let mut colour = vertex.colour;
+            for (channel, tint) in colour.iter_mut().zip(diamond.tint.iter()) {
+                *channel *= tint;
+            }
+            RasterVertex {
+                position: projection * position,
+                colour,
+            }
+        });
+    }
+}
+
+// We need to do a little transmutation later; push constants
+// are u32, and we need to work out the size in 'push constants'
+// both here for the pipeline layout, and later to unsafe-cast
+// and upload the data for the draw-call.
+fn num_push_constants() -> usize {
+    let size_in_bytes = ::std::mem::size_of::<PushConstants>();
+    let size_of_push_constant = ::std::mem::size_of::<u32>();
+    size_in_bytes / size_of_push_constant
+}
+
+
+pub struct PushConstantScene<B: Backend> {
+    /// The diamonds to draw, starting as `DIAMONDS`.
+    pub diamonds: Vec<PushConstants>,
+    set_layout: B::DescriptorSetLayout,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::GraphicsPipeline,
+    vertex_shader_module: B::ShaderModule,
+    fragment_shader_module: B::ShaderModule,
+    desc_pool: B::DescriptorPool,
+    desc_set: B::DescriptorSet,
+    vertex_buffer: B::Buffer,
+    vertex_buffer_memory: B::Memory,
+    uniform_buffer: B::Buffer,
+    uniform_memory: B::Memory,
+}
+
+impl<B: Backend> TutorialScene<B> for PushConstantScene<B> {
+    fn new(device: &B::Device, memory_types: &[MemoryType], render_pass: &B::RenderPass) -> Self {
+        let set_layout = device.create_descriptor_set_layout(
+            &[DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::UniformBuffer,
+                count: 1,
+                stage_flags: ShaderStageFlags::VERTEX,
+                immutable_samplers: false,
+            }],
+            &[],
+        );
+
+        // As with the descriptor set layout, we add our push constants
+        // to the pipeline layout.
+        let pipeline_layout = device.create_pipeline_layout(
+            vec![&set_layout],
+            &[(ShaderStageFlags::VERTEX, 0..(num_push_constants() as u32))]
+        );
+
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+        let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
+
+        let pipeline = {
+            let vs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &vertex_shader_module,
+                specialization: Default::default(),
+            };
+
+            let fs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &fragment_shader_module,
+                specialization: Default::default(),
+            };
+
+            let shader_entries = GraphicsShaderSet {
+                vertex: vs_entry,
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(fs_entry),
+            };
+
+            let subpass = Subpass {
+                index: 0,
+                main_pass: render_pass,
+            };
+
+            let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                              Primitive::TriangleList,
+                                                              Rasterizer::FILL,
+                                                              &pipeline_layout,
+                                                              subpass);
+
+            pipeline_desc.blender
+                         .targets
+                         .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+            vertex_buffers::describe_vertices(&mut pipeline_desc);
+
+            device.create_graphics_pipeline(&pipeline_desc, None)
+                  .unwrap()
+        };
+
+        let mut desc_pool = device.create_descriptor_pool(
+            1,
+            &[DescriptorRangeDesc {
+                ty: DescriptorType::UniformBuffer,
+                count: 1
+            }]
+        );
+
+        let desc_set = desc_pool.allocate_set(&set_layout).unwrap();
+
+        let (vertex_buffer, vertex_buffer_memory) = utils::create_buffer::<B, Vertex>(
+            device,
+            memory_types,
+            Properties::CPU_VISIBLE,
+            buffer::Usage::VERTEX,
+            MESH
+        );
+
+        let (uniform_buffer, uniform_memory) = utils::create_buffer::<B, UniformBlock>(
+            device,
+            memory_types,
+            Properties::CPU_VISIBLE,
+            buffer::Usage::UNIFORM,
+            &[UniformBlock {
+                projection: Default::default()
+            }]
+        );
+
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &desc_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::Buffer(&uniform_buffer, None..None))
+        }]);
+
+        PushConstantScene {
+            diamonds: DIAMONDS.to_vec(),
+            set_layout,
+            pipeline_layout,
+            pipeline,
+            vertex_shader_module,
+            fragment_shader_module,
+            desc_pool,
+            desc_set,
+            vertex_buffer,
+            vertex_buffer_memory,
+            uniform_buffer,
+            uniform_memory,
+        }
+    }
+
+    /// Here `t` is the zoom phase, which the tutorial advances at an
+    /// adjustable speed rather than in step with the clock.
+    fn update(&mut self, device: &B::Device, t: f32, extent: Extent2D) {
+        utils::fill_buffer::<B, UniformBlock>(
+            device,
+            &mut self.uniform_memory,
+            &[UniformBlock::at(t, extent.width, extent.height)]
+        );
+    }
+
+    fn draw(&self, encoder: &mut RenderPassInlineEncoder<B>) {
+        encoder.bind_graphics_pipeline(&self.pipeline);
+        encoder.bind_vertex_buffers(0, vec![(&self.vertex_buffer, 0)]);
+        encoder.bind_graphics_descriptor_sets(&self.pipeline_layout, 0, vec![&self.desc_set], &[]);
+
+        let num_vertices = MESH.len() as u32;
+
+        // now, for each of our push constants
+        // - cast the PushConstant data to &[u32]
+        // - upload the data to the vertex shader
+        // - draw our mesh with the vertex shader making use of the push constant data
+        for diamond in &self.diamonds {
+            let push_constants = {
+                let start_ptr = diamond as *const PushConstants as *const u32;
+                unsafe {
+                    ::std::slice::from_raw_parts(start_ptr, num_push_constants())
+                }
+            };
+            encoder.push_graphics_constants(
+                &self.pipeline_layout,
+                ShaderStageFlags::VERTEX,
+                0,
+                push_constants,
+            );
+            encoder.draw(0..num_vertices, 0..1);
+        }
+    }
+
+    fn destroy(self, device: &B::Device) {
+        device.destroy_graphics_pipeline(self.pipeline);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+
+        device.destroy_descriptor_pool(self.desc_pool);
+        device.destroy_descriptor_set_layout(self.set_layout);
+        device.destroy_buffer(self.uniform_buffer);
+        device.free_memory(self.uniform_memory);
+
+        device.destroy_buffer(self.vertex_buffer);
+        device.free_memory(self.vertex_buffer_memory);
+
+        device.destroy_shader_module(self.vertex_shader_module);
+        device.destroy_shader_module(self.fragment_shader_module);
+    }
+}
@@ -0,0 +1,134 @@
+// Parts 00 and 01: a single triangle, with its vertices written into the
+// shader itself.
+
+use gfx_hal::Backend;
+use math::Vec4;
+use prelude::*;
+use raster::{Raster, RasterVertex};
+use tutorials::TutorialScene;
+
+#[cfg(windows)]
+static VERT_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part00.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part00.frag.spv");
+
+#[cfg(all(unix))]
+static VERT_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/part00.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/part00.frag.spv");
+
+/// The triangle's corners, as written in `part00.vert`.
+pub const POSITIONS: [[f32; 3]; 3] = [
+    [0.0, -0.5, 0.0],
+    [0.5, 0.5, 0.0],
+    [-0.5, 0.5, 0.0],
+];
+
+/// The colour `part00.frag` fills the triangle with.
+pub const COLOUR: [f32; 4] = [0.5, 0.5, 1.0, 1.0];
+
+/// Draw the triangle into `raster` the way the shaders do.
+pub fn draw_reference(raster: &mut Raster) {
+    raster.draw(&POSITIONS[..], |position| RasterVertex {
+        position: Vec4::new(position[0], position[1], position[2], 1.0),
+        colour: COLOUR,
+    });
+}
+
+
+pub struct TriangleScene<B: Backend> {
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::GraphicsPipeline,
+    vertex_shader_module: B::ShaderModule,
+    fragment_shader_module: B::ShaderModule,
+}
+
+impl<B: Backend> TutorialScene<B> for TriangleScene<B> {
+    fn new(device: &B::Device, _memory_types: &[MemoryType], render_pass: &B::RenderPass) -> Self {
+        // The pipeline layout defines the shape of the data you can send to a
+        // shader.
+        // This includes the number of uniforms and push constants. We don't need
+        // them for now.
+        let pipeline_layout = device.create_pipeline_layout(Vec::<&B::DescriptorSetLayout>::new(), &[]);
+
+        // Shader modules are needed to create the pipeline definition.
+        // The shaders are loaded from SPIR-V binary files.
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+        let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
+
+        // A pipeline object encodes almost all the state you need in order to draw
+        // geometry on screen.
+        // For now, that's really only which shaders to use, what kind of blending
+        // to do, and what kind of primitives to draw.
+        let pipeline = {
+            let vs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &vertex_shader_module,
+                specialization: Default::default(),
+            };
+
+            let fs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &fragment_shader_module,
+                specialization: Default::default(),
+            };
+
+            let shader_entries = GraphicsShaderSet {
+                vertex: vs_entry,
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(fs_entry),
+            };
+
+            let subpass = Subpass {
+                index: 0,
+                main_pass: render_pass,
+            };
+
+            let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                              Primitive::TriangleList,
+                                                              Rasterizer::FILL,
+                                                              &pipeline_layout,
+                                                              subpass);
+
+            pipeline_desc.blender
+                         .targets
+                         .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+            device.create_graphics_pipeline(&pipeline_desc, None)
+                  .unwrap()
+        };
+
+        TriangleScene {
+            pipeline_layout,
+            pipeline,
+            vertex_shader_module,
+            fragment_shader_module,
+        }
+    }
+
+    fn update(&mut self, _device: &B::Device, _t: f32, _extent: Extent2D) {}
+
+    fn draw(&self, encoder: &mut RenderPassInlineEncoder<B>) {
+        // Choose a pipeline.
+        encoder.bind_graphics_pipeline(&self.pipeline);
+
+        // Draw the geometry. In this case 0..3 indicates the range of
+        // vertices to be drawn. We have no vertex buffer as yet, so
+        // this really just tells our shader to draw one triangle. The
+        // specific vertices to draw at this point are encoded in the
+        // shader itself.
+        //
+        // The 0..1 is the range of instances to draw. This is
+        // irrelevant unless we're using instanced rendering.
+        encoder.draw(0..3, 0..1);
+    }
+
+    fn destroy(self, device: &B::Device) {
+        device.destroy_graphics_pipeline(self.pipeline);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+        device.destroy_shader_module(self.vertex_shader_module);
+        device.destroy_shader_module(self.fragment_shader_module);
+    }
+}
@@ -0,0 +1,233 @@
+// Part 03: the diamond from part 02, zooming in and out with a projection
+// matrix read from a uniform buffer.
+
+use gfx_hal::Backend;
+use math::{Mat4, Vec4};
+use prelude::*;
+use raster::{Raster, RasterVertex};
+use tutorials::vertex_buffers::{self, Vertex, MESH};
+use tutorials::{self, TutorialScene};
+use utils;
+
+#[cfg(windows)]
+static VERT_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part03.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part03.frag.spv");
+
+#[cfg(all(unix))]
+static VERT_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/part03.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/part03.frag.spv");
+
+// A struct to upload to a uniform buffer; this is a 4x4 projection matrix.
+// As before, repr(C) for deterministic layout.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct UniformBlock {
+    pub projection: [[f32; 4]; 4],
+}
+
+impl UniformBlock {
+    /// The uniforms part 03 uses at time `t` in a `width` by `height`
+    /// target.
+    pub fn at(t: f32, width: u32, height: u32) -> Self {
+        UniformBlock {
+            projection: tutorials::zoom_projection(tutorials::zoom(t), width, height),
+        }
+    }
+}
+
+/// Draw `MESH` into `raster` the way the shaders do, with `uniforms`.
+pub fn draw_reference(raster: &mut Raster, uniforms: &UniformBlock) {
+    let projection = Mat4::from(uniforms.projection);
+    raster.draw(MESH, |vertex| RasterVertex {
+        position: projection * Vec4::new(vertex.position[0], vertex.position[1], vertex.position[2], 1.0),
+        colour: vertex.colour,
+    });
+}
+
+
+pub struct UniformScene<B: Backend> {
+    set_layout: B::DescriptorSetLayout,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::GraphicsPipeline,
+    vertex_shader_module: B::ShaderModule,
+    fragment_shader_module: B::ShaderModule,
+    desc_pool: B::DescriptorPool,
+    desc_set: B::DescriptorSet,
+    vertex_buffer: B::Buffer,
+    vertex_buffer_memory: B::Memory,
+    uniform_buffer: B::Buffer,
+    uniform_memory: B::Memory,
+}
+
+impl<B: Backend> TutorialScene<B> for UniformScene<B> {
+    fn new(device: &B::Device, memory_types: &[MemoryType], render_pass: &B::RenderPass) -> Self {
+        // Shaders access resources such as buffers and images by using special variables which are indirectly bound to
+        // buffer and image views by the API.
+        //
+        // Variables are organised into sets, where each set of bindings is represented by a 'descriptor set' object
+        // in the API, and a discriptor set is bound all at once. A 'descriptor' is an opaque data structure representing
+        // a shader resource. The content of each set is determined by its 'descriptor set layout', which we define here.
+        //
+        // We pass in a list of bindings - just one for now, representing our uniform buffer - along with an array size
+        // 'count' indicating the number of descriptors in the binding, a set of shader stages permitted to access the
+        // binding, and (if using immutable samplers) an array of sampler descriptors. We want our uniform, a projection
+        // matrix, to be available to the Vertex shader.
+        //
+        let set_layout = device.create_descriptor_set_layout(
+            &[DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::UniformBuffer,
+                count: 1,
+                stage_flags: ShaderStageFlags::VERTEX,
+                immutable_samplers: false,
+            }],
+            &[],
+        );
+
+        // For the first time, we're passing something create_pipeline_layout - an
+        // array of descriptor set layouts. We pass in our new
+        // descriptor set layout to indicate that it should be accessible from within
+        // the pipeline. cumulatively, the set of layouts (and push constants, which are
+        // covered in the next tutorial) describe the interface between shader stages
+        // and shader resources.
+        let pipeline_layout = device.create_pipeline_layout(vec![&set_layout], &[]);
+
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+        let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
+
+        let pipeline = {
+            let vs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &vertex_shader_module,
+                specialization: Default::default(),
+            };
+
+            let fs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &fragment_shader_module,
+                specialization: Default::default(),
+            };
+
+            let shader_entries = GraphicsShaderSet {
+                vertex: vs_entry,
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(fs_entry),
+            };
+
+            let subpass = Subpass {
+                index: 0,
+                main_pass: render_pass,
+            };
+
+            let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                              Primitive::TriangleList,
+                                                              Rasterizer::FILL,
+                                                              &pipeline_layout,
+                                                              subpass);
+
+            pipeline_desc.blender
+                         .targets
+                         .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+            vertex_buffers::describe_vertices(&mut pipeline_desc);
+
+            device.create_graphics_pipeline(&pipeline_desc, None)
+                  .unwrap()
+        };
+
+        // Descriptor sets can't be created directly, and like command buffers,
+        // must be allocated from a pool.
+        let mut desc_pool = device.create_descriptor_pool(
+            1, // maximum number of descriptor sets
+            &[DescriptorRangeDesc {
+                ty: DescriptorType::UniformBuffer,
+                count: 1 // amount of space
+            }]
+        );
+
+        // Allocate our previously-specified descriptor set from the pool.
+        let desc_set = desc_pool.allocate_set(&set_layout).unwrap();
+
+        // Using our new utility functions in place of last tutorial's boilerplate...
+        let (vertex_buffer, vertex_buffer_memory) = utils::create_buffer::<B, Vertex>(
+            device,
+            memory_types,
+            Properties::CPU_VISIBLE,
+            buffer::Usage::VERTEX,
+            MESH
+        );
+
+        // ... and also here, to create our uniform buffer.
+        let (uniform_buffer, uniform_memory) = utils::create_buffer::<B, UniformBlock>(
+            device,
+            memory_types,
+            Properties::CPU_VISIBLE,
+            buffer::Usage::UNIFORM,
+            &[UniformBlock {
+                projection: Default::default()
+            }]
+        );
+
+        // Point the descriptor set's only binding at the uniform buffer. The
+        // `None..None` range means the whole buffer.
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &desc_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(Descriptor::Buffer(&uniform_buffer, None..None))
+        }]);
+
+        UniformScene {
+            set_layout,
+            pipeline_layout,
+            pipeline,
+            vertex_shader_module,
+            fragment_shader_module,
+            desc_pool,
+            desc_set,
+            vertex_buffer,
+            vertex_buffer_memory,
+            uniform_buffer,
+            uniform_memory,
+        }
+    }
+
+    fn update(&mut self, device: &B::Device, t: f32, extent: Extent2D) {
+        utils::fill_buffer::<B, UniformBlock>(
+            device,
+            &mut self.uniform_memory,
+            &[UniformBlock::at(t, extent.width, extent.height)]
+        );
+    }
+
+    fn draw(&self, encoder: &mut RenderPassInlineEncoder<B>) {
+        encoder.bind_graphics_pipeline(&self.pipeline);
+        encoder.bind_vertex_buffers(0, vec![(&self.vertex_buffer, 0)]);
+
+        // Bind the descriptor set as set 0 of the pipeline layout, so the
+        // shader's uniform block reads from our uniform buffer.
+        encoder.bind_graphics_descriptor_sets(&self.pipeline_layout, 0, vec![&self.desc_set], &[]);
+
+        encoder.draw(0..MESH.len() as u32, 0..1);
+    }
+
+    fn destroy(self, device: &B::Device) {
+        device.destroy_graphics_pipeline(self.pipeline);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+
+        device.destroy_descriptor_pool(self.desc_pool);
+        device.destroy_descriptor_set_layout(self.set_layout);
+        device.destroy_buffer(self.uniform_buffer);
+        device.free_memory(self.uniform_memory);
+
+        device.destroy_buffer(self.vertex_buffer);
+        device.free_memory(self.vertex_buffer_memory);
+
+        device.destroy_shader_module(self.vertex_shader_module);
+        device.destroy_shader_module(self.fragment_shader_module);
+    }
+}
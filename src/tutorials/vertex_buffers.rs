@@ -0,0 +1,214 @@
+// Part 02: a quad built from two triangles in a vertex buffer, with a colour
+// at each corner.
+
+use gfx_hal::Backend;
+use math::Vec4;
+use prelude::*;
+use raster::{Raster, RasterVertex};
+use tutorials::TutorialScene;
+use utils;
+
+#[cfg(windows)]
+static VERT_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part02.vert.spv");
+#[cfg(windows)]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("..\\..\\assets\\gen\\shaders\\part02.frag.spv");
+
+#[cfg(all(unix))]
+static VERT_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/part02.vert.spv");
+#[cfg(all(unix))]
+static FRAG_SPIRV: &'static [u8] = include_bytes!("../../assets/gen/shaders/part02.frag.spv");
+
+// repr(C) ensures deterministic layout in memory.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub colour: [f32; 4],
+}
+
+/// The diamond drawn by parts 02 to 04.
+pub const MESH: &[Vertex] = &[
+    Vertex {
+        position: [0.0, -1.0, 0.0],
+        colour: [1.0, 0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [-1.0, 0.0, 0.0],
+        colour: [0.0, 0.0, 1.0, 1.0],
+    },
+    Vertex {
+        position: [0.0, 1.0, 0.0],
+        colour: [0.0, 1.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [0.0, -1.0, 0.0],
+        colour: [1.0, 0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [0.0, 1.0, 0.0],
+        colour: [0.0, 1.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [1.0, 0.0, 0.0],
+        colour: [1.0, 1.0, 0.0, 1.0],
+    },
+];
+
+/// Draw `MESH` into `raster` the way the shaders do.
+pub fn draw_reference(raster: &mut Raster) {
+    raster.draw(MESH, |vertex| RasterVertex {
+        position: Vec4::new(vertex.position[0], vertex.position[1], vertex.position[2], 1.0),
+        colour: vertex.colour,
+    });
+}
+
+
+/// Add the `Vertex` buffer layout, and its position and colour attributes,
+/// to a pipeline description. Parts 03 and 04 use the same vertices.
+pub fn describe_vertices<B: Backend>(pipeline_desc: &mut GraphicsPipelineDesc<B>) {
+    // We need to let the pipeline know about all the different formats
+    // of vertex buffer we're going to use.
+    // - The `binding` number is an ID for this entry.
+    // - The `stride` indicates the size of each vertex in bytes.
+    // - The `rate` is used for instanced rendering, and can be ignored at
+    // this point.
+    pipeline_desc.vertex_buffers.push(VertexBufferDesc {
+        binding: 0,
+        stride: ::std::mem::size_of::<Vertex>() as u32,
+        rate: 0,
+    });
+
+    // We have to declare two vertex attributes: position, and colour.
+    // Note that their locaitons have to match the locations in the shader,
+    // and their format has to be appropriate for the data type in the
+    // shader.
+    //
+    // vec3 = Rgb32Float
+    // vec4 = Rgba32Float
+    //
+    // Additionally, the second attribute must have an offset of 12 bytes
+    // in the vertex, because this refers to the size of the first field.
+    // The `binding` parameter refers back to the ID we gave in the
+    // VertexBufferDesc above.
+    pipeline_desc.attributes.push(AttributeDesc {
+        location: 0,
+        binding: 0,
+        element: Element {
+            format: Format::Rgb32Float,
+            offset: 0,
+        },
+    });
+    pipeline_desc.attributes.push(AttributeDesc {
+        location: 1,
+        binding: 0,
+        element: Element {
+            format: Format::Rgba32Float,
+            offset: 12,
+        },
+    });
+}
+
+
+pub struct VertexBufferScene<B: Backend> {
+    pipeline_layout: B::PipelineLayout,
+    pipeline: B::GraphicsPipeline,
+    vertex_shader_module: B::ShaderModule,
+    fragment_shader_module: B::ShaderModule,
+    vertex_buffer: B::Buffer,
+    vertex_buffer_memory: B::Memory,
+}
+
+impl<B: Backend> TutorialScene<B> for VertexBufferScene<B> {
+    fn new(device: &B::Device, memory_types: &[MemoryType], render_pass: &B::RenderPass) -> Self {
+        let pipeline_layout = device.create_pipeline_layout(Vec::<&B::DescriptorSetLayout>::new(), &[]);
+
+        let vertex_shader_module = device.create_shader_module(VERT_SPIRV).unwrap();
+        let fragment_shader_module = device.create_shader_module(FRAG_SPIRV).unwrap();
+
+        let pipeline = {
+            let vs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &vertex_shader_module,
+                specialization: Default::default(),
+            };
+
+            let fs_entry = EntryPoint::<B> {
+                entry: "main",
+                module: &fragment_shader_module,
+                specialization: Default::default(),
+            };
+
+            let shader_entries = GraphicsShaderSet {
+                vertex: vs_entry,
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(fs_entry),
+            };
+
+            let subpass = Subpass {
+                index: 0,
+                main_pass: render_pass,
+            };
+
+            let mut pipeline_desc = GraphicsPipelineDesc::new(shader_entries,
+                                                              Primitive::TriangleList,
+                                                              Rasterizer::FILL,
+                                                              &pipeline_layout,
+                                                              subpass);
+
+            pipeline_desc.blender
+                         .targets
+                         .push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+
+            describe_vertices(&mut pipeline_desc);
+
+            device.create_graphics_pipeline(&pipeline_desc, None)
+                  .unwrap()
+        };
+
+        // Here's where we create the buffer itself, and the memory it uses.
+        let (vertex_buffer, vertex_buffer_memory) = utils::create_buffer::<B, Vertex>(
+            device,
+            memory_types,
+            Properties::CPU_VISIBLE,
+            buffer::Usage::VERTEX,
+            MESH
+        );
+
+        VertexBufferScene {
+            pipeline_layout,
+            pipeline,
+            vertex_shader_module,
+            fragment_shader_module,
+            vertex_buffer,
+            vertex_buffer_memory,
+        }
+    }
+
+    fn update(&mut self, _device: &B::Device, _t: f32, _extent: Extent2D) {}
+
+    fn draw(&self, encoder: &mut RenderPassInlineEncoder<B>) {
+        encoder.bind_graphics_pipeline(&self.pipeline);
+
+        // This is where we tell our pipeline to use a specific vertex
+        // buffer.
+        // The first argument again refers to the vertex buffer `binding`.
+        // The second argument is a Vec of buffers in the form
+        //  (buffer, offset)
+        // where offset is relative to the binding number.
+        encoder.bind_vertex_buffers(0, vec![(&self.vertex_buffer, 0)]);
+
+        // Draw the number of vertices in our mesh.
+        encoder.draw(0..MESH.len() as u32, 0..1);
+    }
+
+    fn destroy(self, device: &B::Device) {
+        device.destroy_graphics_pipeline(self.pipeline);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+        device.destroy_buffer(self.vertex_buffer);
+        device.free_memory(self.vertex_buffer_memory);
+        device.destroy_shader_module(self.vertex_shader_module);
+        device.destroy_shader_module(self.fragment_shader_module);
+    }
+}
@@ -2,6 +2,19 @@ use gfx_hal::Backend;
 use prelude::*;
 
 
+pub fn find_memory_type(memory_types: &[MemoryType],
+                        type_mask: u64,
+                        properties: Properties) -> Option<MemoryTypeId> {
+    memory_types.iter()
+                .enumerate()
+                .find(|(id, ty)| {
+                    let type_supported = type_mask & (1_u64 << id) != 0;
+                    type_supported && ty.properties.contains(properties)
+                })
+                .map(|(id, _ty)| MemoryTypeId(id))
+}
+
+
 pub fn empty_buffer<B: Backend, Item>(device: &B::Device,
                                       memory_types: &[MemoryType],
                                       properties: Properties,
@@ -12,14 +25,8 @@ pub fn empty_buffer<B: Backend, Item>(device: &B::Device,
     let unbound_buffer = device.create_buffer(buffer_len, usage).unwrap();
     let req = device.get_buffer_requirements(&unbound_buffer);
 
-    let upload_type = memory_types.iter()
-                                  .enumerate()
-                                  .find(|(id, ty)| {
-                                      let type_supported = req.type_mask & (1_u64 << id) != 0;
-                                      type_supported && ty.properties.contains(properties)
-                                  })
-                                  .map(|(id, _ty)| MemoryTypeId(id))
-                                  .expect("Could not find appropriate vertex buffer memory type.");
+    let upload_type = find_memory_type(memory_types, req.type_mask, properties)
+                          .expect("Could not find appropriate vertex buffer memory type.");
     let buffer_memory = device.allocate_memory(upload_type, req.size).unwrap();
     let buffer = device
         .bind_buffer_memory(&buffer_memory, 0, unbound_buffer)
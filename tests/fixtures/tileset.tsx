@@ -0,0 +1,4 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" tiledversion="1.10.2" name="Terrain &amp; Water" tilewidth="16" tileheight="16" spacing="1" margin="2" tilecount="8" columns="4">
+ <image source="terrain.png" width="71" height="37"/>
+</tileset>
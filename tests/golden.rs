@@ -0,0 +1,180 @@
+// Golden-image tests for the tutorial scenes.
+//
+// Each test renders one frame of a tutorial offscreen at a fixed time and
+// compares it with a reference PNG in `tests/golden`. Pixels may differ by
+// up to `TOLERANCE` in each channel, to allow for small differences between
+// GPUs and drivers. When a test fails, the rendered frame and a diff image
+// (mismatched pixels in red over a darkened copy of the reference) are
+// written to `target/golden`.
+//
+// To regenerate the references after an intended change, run the tests with
+// `HALTUT_BLESS=1` set and check the new PNGs in.
+//
+// The references were first drawn with the tutorials' `draw_reference`
+// functions and the software rasterizer in `raster`, which follows the same
+// coverage and interpolation rules as the GPU. If an adapter disagrees by
+// more than `TOLERANCE`, look at the diff before re-blessing: it may be a
+// driver quirk, or a real difference between the scene and its reference.
+//
+// Machines without a usable graphics adapter skip these tests.
+
+extern crate haltut;
+extern crate image;
+
+use haltut::backend;
+use haltut::headless::{HeadlessContext, OffscreenTarget};
+use haltut::prelude::*;
+use haltut::tutorials::{PushConstantScene, TriangleScene, TutorialScene, UniformScene, VertexBufferScene};
+use image::{Rgba, RgbaImage};
+
+use std::env;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+
+const TOLERANCE: u8 = 3;
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+
+
+// `None` when there's no adapter to render with. Creating an instance can
+// panic if the graphics API isn't available at all, so that counts too.
+fn context() -> Option<HeadlessContext> {
+    match panic::catch_unwind(|| HeadlessContext::new("haltut golden tests")) {
+        Ok(Ok(context)) => Some(context),
+        Ok(Err(err)) => {
+            eprintln!("skipping golden-image test: {}", err);
+            None
+        }
+        Err(_) => {
+            eprintln!("skipping golden-image test: graphics API unavailable");
+            None
+        }
+    }
+}
+
+fn render<S: TutorialScene<backend::Backend>>(context: &mut HeadlessContext, t: f32, width: u32, height: u32) -> RgbaImage {
+    let target = OffscreenTarget::<backend::Backend>::new(&context.device,
+                                                          &context.memory_types,
+                                                          width,
+                                                          height,
+                                                          Format::Rgba8Srgb,
+                                                          None);
+    let mut scene = S::new(&context.device, &context.memory_types, target.render_pass());
+    scene.update(&context.device, t, target.extent());
+
+    target.render(&context.device,
+                  &mut context.command_pool,
+                  &mut context.queue_group.queues[0],
+                  [0.0, 0.0, 0.0, 1.0],
+                  |encoder| scene.draw(encoder));
+    let pixels = target.read_pixels(&context.device,
+                                    &context.adapter.physical_device,
+                                    &mut context.command_pool,
+                                    &mut context.queue_group.queues[0])
+                       .unwrap();
+
+    scene.destroy(&context.device);
+    target.destroy(&context.device);
+    pixels
+}
+
+fn output_dir() -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target").join("golden");
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// The diff image, and how many pixels are out of tolerance.
+fn diff(actual: &RgbaImage, expected: &RgbaImage) -> (RgbaImage, usize) {
+    let mut image = RgbaImage::new(expected.width(), expected.height());
+    let mut mismatches = 0;
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let a = actual.get_pixel(x, y).data;
+        let e = expected.get_pixel(x, y).data;
+        let worst = (0..4).map(|i| (a[i] as i16 - e[i] as i16).abs() as u8).max().unwrap();
+        *pixel = if worst > TOLERANCE {
+            mismatches += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([e[0] / 3, e[1] / 3, e[2] / 3, 255])
+        };
+    }
+    (image, mismatches)
+}
+
+fn check(name: &str, actual: &RgbaImage) {
+    let reference = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")
+                                                             .join("golden")
+                                                             .join(format!("{}.png", name));
+    if env::var_os("HALTUT_BLESS").is_some() {
+        fs::create_dir_all(reference.parent().unwrap()).unwrap();
+        actual.save(&reference).unwrap();
+        return;
+    }
+
+    let actual_path = output_dir().join(format!("{}.actual.png", name));
+    let expected = match image::open(&reference) {
+        Ok(image) => image.to_rgba(),
+        Err(err) => {
+            actual.save(&actual_path).unwrap();
+            panic!("{}: could not load {} ({}); run with HALTUT_BLESS=1 to create it. \
+                    The rendered frame is at {}",
+                   name, reference.display(), err, actual_path.display());
+        }
+    };
+
+    if expected.dimensions() != actual.dimensions() {
+        actual.save(&actual_path).unwrap();
+        panic!("{}: rendered {:?}, but the reference is {:?}. The rendered frame is at {}",
+               name, actual.dimensions(), expected.dimensions(), actual_path.display());
+    }
+
+    let (diff_image, mismatches) = diff(actual, &expected);
+    if mismatches > 0 {
+        let diff_path = output_dir().join(format!("{}.diff.png", name));
+        actual.save(&actual_path).unwrap();
+        diff_image.save(&diff_path).unwrap();
+        panic!("{}: {} pixels differ from the reference by more than {}. See {} and {}",
+               name, mismatches, TOLERANCE, actual_path.display(), diff_path.display());
+    }
+}
+
+fn golden<S: TutorialScene<backend::Backend>>(name: &str, t: f32, width: u32, height: u32) {
+    let mut context = match context() {
+        Some(context) => context,
+        None => return,
+    };
+    let pixels = render::<S>(&mut context, t, width, height);
+    context.destroy();
+    check(name, &pixels);
+}
+
+
+#[test]
+fn part00_draw_a_triangle() {
+    golden::<TriangleScene<backend::Backend>>("00_draw_a_triangle", 0.0, WIDTH, HEIGHT);
+}
+
+// The same triangle, in a target with a different aspect ratio.
+#[test]
+fn part01_resizing() {
+    golden::<TriangleScene<backend::Backend>>("01_resizing", 0.0, 240, 100);
+}
+
+#[test]
+fn part02_vertex_buffers() {
+    golden::<VertexBufferScene<backend::Backend>>("02_vertex_buffers", 0.0, WIDTH, HEIGHT);
+}
+
+// Part way through zooming out.
+#[test]
+fn part03_uniform() {
+    golden::<UniformScene<backend::Backend>>("03_uniform", 1.0, WIDTH, HEIGHT);
+}
+
+#[test]
+fn part04_push_constants() {
+    golden::<PushConstantScene<backend::Backend>>("04_push_constants", 1.0, WIDTH, HEIGHT);
+}
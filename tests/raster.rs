@@ -0,0 +1,203 @@
+// Tests for the software rasterizer, and for the tutorial scenes drawn with
+// it. These don't need a GPU.
+
+extern crate haltut;
+
+use haltut::math::Vec4;
+use haltut::prelude::*;
+use haltut::raster::{Blend, Raster, RasterVertex};
+use haltut::tutorials::{push_constants, triangle, uniforms, vertex_buffers};
+use haltut::tutorials::uniforms::UniformBlock;
+
+const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+const TRANSPARENT: [f32; 4] = [0.0; 4];
+
+
+fn vertex(x: f32, y: f32, colour: [f32; 4]) -> RasterVertex {
+    RasterVertex {
+        position: Vec4::new(x, y, 0.0, 1.0),
+        colour,
+    }
+}
+
+// A quad covering the whole target, as two triangles sharing a diagonal.
+fn full_screen_quad(colour: [f32; 4]) -> Vec<RasterVertex> {
+    vec![
+        vertex(-1.0, -1.0, colour), vertex(1.0, -1.0, colour), vertex(1.0, 1.0, colour),
+        vertex(-1.0, -1.0, colour), vertex(1.0, 1.0, colour), vertex(-1.0, 1.0, colour),
+    ]
+}
+
+// Drawn pixels, when the target was cleared to `TRANSPARENT`. Some of the
+// tutorials draw black, so colour alone doesn't say what was drawn.
+fn covered(raster: &Raster) -> usize {
+    raster.pixels().iter().filter(|pixel| pixel[3] > 0.0).count()
+}
+
+
+#[test]
+fn shared_edges_are_drawn_once() {
+    // The diagonal passes exactly through pixel centres, so each of those
+    // pixels must go to exactly one of the two triangles.
+    let mut raster = Raster::new(8, 8);
+    raster.clear(BLACK);
+    raster.draw(&full_screen_quad([1.0, 0.0, 0.0, 0.5]), |v| *v);
+    for pixel in raster.pixels() {
+        assert_eq!(*pixel, [0.5, 0.0, 0.0, 1.0]);
+    }
+}
+
+#[test]
+fn both_windings_are_drawn() {
+    let mut raster = Raster::new(8, 8);
+    raster.clear(TRANSPARENT);
+    let white = [1.0; 4];
+    raster.draw_triangle([vertex(-1.0, -1.0, white), vertex(1.0, -1.0, white), vertex(-1.0, 1.0, white)]);
+    let clockwise = covered(&raster);
+
+    raster.clear(TRANSPARENT);
+    raster.draw_triangle([vertex(-1.0, -1.0, white), vertex(-1.0, 1.0, white), vertex(1.0, -1.0, white)]);
+    assert_eq!(covered(&raster), clockwise);
+
+    // Pixel centres on the diagonal are on a right edge, so aren't drawn.
+    assert_eq!(clockwise, 7 * 8 / 2);
+}
+
+#[test]
+fn colours_are_interpolated() {
+    let (red, green, blue) = ([1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]);
+    let mut raster = Raster::new(32, 32);
+    raster.set_blend(Blend::Replace);
+    raster.draw_triangle([vertex(-1.0, -1.0, red), vertex(1.0, -1.0, green), vertex(-1.0, 1.0, blue)]);
+
+    // Barycentric weights always add up to one.
+    for pixel in raster.pixels().iter().filter(|pixel| pixel[3] > 0.0) {
+        assert!((pixel[0] + pixel[1] + pixel[2] - 1.0).abs() < 1.0e-5, "{:?}", pixel);
+    }
+
+    // Green increases to the right and blue downwards, evaluated at pixel
+    // centres.
+    for y in 0..32 {
+        for x in 0..32 {
+            let pixel = raster.pixel(x, y);
+            if pixel[3] > 0.0 {
+                assert!((pixel[1] - (x as f32 + 0.5) / 32.0).abs() < 1.0e-5, "{:?} at {}, {}", pixel, x, y);
+                assert!((pixel[2] - (y as f32 + 0.5) / 32.0).abs() < 1.0e-5, "{:?} at {}, {}", pixel, x, y);
+            }
+        }
+    }
+    assert_eq!(raster.pixel(31, 31)[3], 0.0);
+}
+
+#[test]
+fn alpha_blending_matches_blend_state_alpha() {
+    let mut raster = Raster::new(4, 4);
+    raster.clear([0.0, 0.0, 1.0, 1.0]);
+    raster.draw(&full_screen_quad([1.0, 1.0, 1.0, 0.25]), |v| *v);
+    assert_eq!(raster.pixel(1, 1), [0.25, 0.25, 1.0, 1.0]);
+
+    raster.clear(TRANSPARENT);
+    raster.draw(&full_screen_quad([1.0, 0.5, 0.0, 0.5]), |v| *v);
+    assert_eq!(raster.pixel(2, 3), [0.5, 0.25, 0.0, 0.5]);
+}
+
+#[test]
+fn scissor_limits_drawing() {
+    let mut raster = Raster::new(8, 8);
+    raster.clear(TRANSPARENT);
+    raster.set_scissor(Some(Rect { x: 2, y: 3, w: 4, h: 2 }));
+    raster.draw(&full_screen_quad([1.0; 4]), |v| *v);
+    assert_eq!(covered(&raster), 8);
+    assert_eq!(raster.pixel(2, 3), [1.0; 4]);
+    assert_eq!(raster.pixel(6, 3), TRANSPARENT);
+    assert_eq!(raster.pixel(2, 5), TRANSPARENT);
+}
+
+#[test]
+fn triangles_behind_the_camera_are_skipped() {
+    let mut raster = Raster::new(4, 4);
+    raster.clear(TRANSPARENT);
+    let mut quad = full_screen_quad([1.0; 4]);
+    quad[0].position.w = -1.0;
+    raster.draw(&quad, |v| *v);
+
+    // The skipped triangle had the pixels on the diagonal.
+    assert_eq!(covered(&raster), 4 * 3 / 2);
+}
+
+
+#[test]
+fn part00_triangle() {
+    let mut raster = Raster::new(64, 64);
+    raster.clear(TRANSPARENT);
+    triangle::draw_reference(&mut raster);
+
+    let image = raster.to_image();
+    assert_eq!(image.get_pixel(32, 32).data, [188, 188, 255, 255]);
+    assert_eq!(image.get_pixel(32, 12).data, [0, 0, 0, 0]);
+    assert_eq!(image.get_pixel(10, 40).data, [0, 0, 0, 0]);
+
+    // Corners at (32, 16), (48, 48) and (16, 48): area 512, and with the
+    // fill rule, exactly as many pixels.
+    assert_eq!(covered(&raster), 512);
+}
+
+#[test]
+fn part02_vertex_buffers() {
+    let mut raster = Raster::new(64, 64);
+    raster.clear(TRANSPARENT);
+    vertex_buffers::draw_reference(&mut raster);
+
+    // The diamond touches the middle of each edge, and covers half the
+    // target.
+    assert_eq!(covered(&raster), 64 * 64 / 2);
+    assert_eq!(raster.pixel(0, 0), TRANSPARENT);
+    assert_eq!(raster.pixel(63, 63), TRANSPARENT);
+
+    // Red at the top, green at the bottom, blue on the left and yellow on
+    // the right.
+    let top = raster.pixel(32, 1);
+    assert!(top[0] > 0.9 && top[1] < 0.1 && top[2] < 0.1, "{:?}", top);
+    let bottom = raster.pixel(32, 62);
+    assert!(bottom[1] > 0.9 && bottom[0] < 0.1, "{:?}", bottom);
+    let left = raster.pixel(1, 32);
+    assert!(left[2] > 0.9, "{:?}", left);
+    let right = raster.pixel(62, 32);
+    assert!(right[0] > 0.9 && right[1] > 0.9 && right[2] < 0.1, "{:?}", right);
+}
+
+#[test]
+fn part03_uniform_zooms_over_time() {
+    // At t = 0 the diamond is full size, squashed horizontally to keep its
+    // shape in a wide target.
+    let mut raster = Raster::new(64, 48);
+    raster.clear(TRANSPARENT);
+    uniforms::draw_reference(&mut raster, &UniformBlock::at(0.0, 64, 48));
+    assert_eq!(covered(&raster), 48 * 48 / 2);
+    assert!(raster.pixel(32, 1) != TRANSPARENT);
+    assert_eq!(raster.pixel(5, 24), TRANSPARENT);
+
+    // At t = pi it's at its smallest, 0.34 of the size.
+    raster.clear(TRANSPARENT);
+    uniforms::draw_reference(&mut raster, &UniformBlock::at(::std::f32::consts::PI, 64, 48));
+    assert!(raster.pixel(32, 24) != TRANSPARENT);
+    assert_eq!(raster.pixel(32, 12), TRANSPARENT);
+}
+
+#[test]
+fn part04_push_constants_tints_each_diamond() {
+    let mut raster = Raster::new(64, 64);
+    raster.clear(TRANSPARENT);
+    push_constants::draw_reference(&mut raster, &UniformBlock::at(0.0, 64, 64), push_constants::DIAMONDS);
+
+    // Each diamond is centred on a corner, so a quarter of each is visible.
+    assert_eq!(covered(&raster), 4 * 32 * 32 / 2);
+    assert_eq!(raster.pixel(32, 32), TRANSPARENT);
+
+    // The top-left diamond is tinted red, so only has red in it.
+    let top_left = raster.pixel(2, 2);
+    assert!(top_left[0] > 0.0 && top_left[1] == 0.0 && top_left[2] == 0.0, "{:?}", top_left);
+    // The bottom-right one isn't tinted at all.
+    let bottom_right = raster.pixel(61, 61);
+    assert!(bottom_right[0] > 0.0 && bottom_right[1] > 0.0, "{:?}", bottom_right);
+}